@@ -0,0 +1,108 @@
+use regex::{Regex, RegexBuilder};
+
+/// Parses a regular expression given via `--filter`
+pub fn parse_regex(s: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(s).case_insensitive(true).build()
+}
+
+/// Parses a glob pattern given via `--glob` into an equivalent regular expression. `*` matches
+/// any number of characters, including dots, and `?` matches exactly one. The pattern must match
+/// the whole name
+pub fn parse_glob(s: &str) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    for c in s.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    RegexBuilder::new(&pattern).case_insensitive(true).build()
+}
+
+/// Selects the certificate names that match any of the patterns. Without patterns, all names are
+/// selected
+#[derive(Debug, Default)]
+pub struct NameFilter {
+    patterns: Vec<Regex>,
+}
+
+impl NameFilter {
+    pub fn new(patterns: impl IntoIterator<Item = Regex>) -> Self {
+        NameFilter {
+            patterns: patterns.into_iter().collect(),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|pattern| pattern.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected<'a>(filter: &NameFilter, names: &[&'a str]) -> Vec<&'a str> {
+        names
+            .iter()
+            .copied()
+            .filter(|name| filter.matches(name))
+            .collect()
+    }
+
+    const NAMES: &[&str] = &[
+        "www.example.com",
+        "billing-api.example.com",
+        "Search-API.example.com",
+        "db.internal.example.com",
+        "internal.example.com",
+        "api.example.com",
+    ];
+
+    #[test]
+    fn globs_match_the_whole_name() {
+        let filter = NameFilter::new([parse_glob("*-api.example.com").unwrap()]);
+        assert_eq!(
+            selected(&filter, NAMES),
+            ["billing-api.example.com", "Search-API.example.com"]
+        );
+
+        let filter = NameFilter::new([parse_glob("??.internal.example.com").unwrap()]);
+        assert_eq!(selected(&filter, NAMES), ["db.internal.example.com"]);
+
+        // The dot is matched literally rather than as any character
+        let filter = NameFilter::new([parse_glob("api.example.com").unwrap()]);
+        assert!(!filter.matches("apixexample.com"));
+    }
+
+    #[test]
+    fn regular_expressions_match_anywhere_in_the_name() {
+        let filter = NameFilter::new([parse_regex(r"(^|\.)internal\.").unwrap()]);
+        assert_eq!(
+            selected(&filter, NAMES),
+            ["db.internal.example.com", "internal.example.com"]
+        );
+    }
+
+    #[test]
+    fn names_matching_any_pattern_are_selected() {
+        let filter = NameFilter::new([
+            parse_glob("www.*").unwrap(),
+            parse_regex("^api\\.").unwrap(),
+        ]);
+        assert_eq!(
+            selected(&filter, NAMES),
+            ["www.example.com", "api.example.com"]
+        );
+        assert_eq!(selected(&NameFilter::default(), NAMES), NAMES);
+    }
+
+    #[test]
+    fn invalid_regular_expressions_are_rejected() {
+        assert!(parse_regex("(unclosed").is_err());
+        assert!(parse_regex("[a-").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+
+use grimoire::IpAddrOrFqdn;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime};
+use tracing::debug;
+
+use crate::{normalize_name, CertName};
+
+/// A single certificate as returned by the crt.sh JSON API
+#[derive(Debug, Deserialize)]
+struct CertEntry {
+    /// The common name and subject alternative names of the certificate, separated by newlines
+    name_value: String,
+    issuer_name: Option<String>,
+    /// The start of the validity period in UTC, without an offset
+    not_before: Option<String>,
+    /// The end of the validity period in UTC, without an offset
+    not_after: Option<String>,
+}
+
+/// The base URL of the JSON API of the certificate transparency log service at `ct_host`
+pub fn ct_url(ct_host: &IpAddrOrFqdn) -> anyhow::Result<Url> {
+    let mut url = Url::parse("https://localhost/")?;
+    match ct_host {
+        IpAddrOrFqdn::IpAddr(ip_addr) => url
+            .set_ip_host(*ip_addr)
+            .map_err(|_| anyhow::anyhow!("cannot use '{ip_addr}' as the CT host"))?,
+        IpAddrOrFqdn::Fqdn(fqdn) => url.set_host(Some(&fqdn.to_string()))?,
+    }
+
+    Ok(url)
+}
+
+/// Queries the JSON API at `ct_url` for certificates issued to subdomains of `domain`. Returns the
+/// distinct names found across all certificates, in order of their first appearance, along with
+/// the details of the certificate they first appeared in
+#[tracing::instrument(skip(client))]
+pub async fn fetch_cert_names(
+    client: &Client,
+    ct_url: &Url,
+    domain: &str,
+) -> anyhow::Result<Vec<CertName>> {
+    let mut url = ct_url.clone();
+    url.query_pairs_mut()
+        .append_pair("q", &format!("%.{domain}"))
+        .append_pair("output", "json");
+
+    debug!("Requesting {url}");
+    let entries: Vec<CertEntry> = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(extract_cert_names(&entries, domain))
+}
+
+/// Splits the newline-separated names of each certificate and keeps those below `domain`, just
+/// like the Certwatch query does. Names that differ only in case or in a trailing dot are only
+/// returned once
+fn extract_cert_names(entries: &[CertEntry], domain: &str) -> Vec<CertName> {
+    let suffix = normalize_name(&format!(".{domain}"));
+    let mut seen = HashSet::new();
+
+    entries
+        .iter()
+        .flat_map(|entry| entry.name_value.lines().map(move |name| (entry, name)))
+        .map(|(entry, name)| (entry, name.trim()))
+        .filter(|(_, name)| normalize_name(name).ends_with(&suffix))
+        .filter(|(_, name)| seen.insert(normalize_name(name)))
+        .map(|(entry, name)| CertName {
+            name_value: name.to_string(),
+            issuer: entry.issuer_name.clone(),
+            not_before: entry.not_before.as_deref().and_then(parse_timestamp),
+            not_after: entry.not_after.as_deref().and_then(parse_timestamp),
+        })
+        .collect()
+}
+
+/// Parses a UTC timestamp of the form `2024-07-28T09:30:15`, as used by crt.sh
+fn parse_timestamp(s: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(
+        s,
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+    )
+    .map(PrimitiveDateTime::assume_utc)
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::http::read_request_head;
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+
+    /// An abridged response of crt.sh to the query '%.example.com'
+    const RESPONSE: &str = r#"[
+        {"issuer_ca_id":185756,"issuer_name":"C=US, O=DigiCert Inc, CN=DigiCert TLS RSA SHA256 2020 CA1","common_name":"www.example.org","name_value":"example.com\nwww.example.com","id":12337892544,"entry_timestamp":"2024-03-01T04:05:11.512","not_before":"2024-01-30T00:00:00","not_after":"2025-03-01T23:59:59","serial_number":"075bcef30689c8addf13e51af4afe187","result_count":3},
+        {"issuer_ca_id":185756,"issuer_name":"C=US, O=DigiCert Inc, CN=DigiCert TLS RSA SHA256 2020 CA1","common_name":"WWW.example.com","name_value":"WWW.example.com\nmail.example.com.\nhostmaster@example.com","id":11920183465,"entry_timestamp":"2024-01-30T19:22:50.288","not_before":"2024-01-29T00:00:00","not_after":"2025-02-28T23:59:59","serial_number":"0aa1b2c3d4e5f60718293a4b5c6d7e8f","result_count":3}
+    ]"#;
+
+    #[test]
+    fn names_below_the_domain_are_extracted_once() {
+        let entries: Vec<CertEntry> = serde_json::from_str(RESPONSE).unwrap();
+
+        let names = extract_cert_names(&entries, "example.com");
+
+        // Just like with the Certwatch query, the domain itself and email addresses are not names
+        // below the domain
+        let name_values: Vec<_> = names.iter().map(|name| name.name_value.as_str()).collect();
+        assert_eq!(name_values, ["www.example.com", "mail.example.com."]);
+        assert_eq!(
+            names[0].issuer.as_deref(),
+            Some("C=US, O=DigiCert Inc, CN=DigiCert TLS RSA SHA256 2020 CA1")
+        );
+        assert_eq!(names[0].not_before, parse_timestamp("2024-01-30T00:00:00"));
+        assert_eq!(names[1].not_after, parse_timestamp("2025-02-28T23:59:59"));
+    }
+
+    #[test]
+    fn timestamps_are_parsed_as_utc() {
+        assert_eq!(
+            parse_timestamp("2024-07-28T09:30:15"),
+            Some(time::macros::datetime!(2024-07-28 09:30:15 UTC))
+        );
+        assert_eq!(parse_timestamp("2024-07-28"), None);
+    }
+
+    #[tokio::test]
+    async fn the_api_is_queried_for_subdomains() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request_head(&mut stream).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{RESPONSE}",
+                RESPONSE.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+
+        let names = fetch_cert_names(&Client::new(), &url, "example.com")
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("GET /?q=%25.example.com&output=json "),
+            "{request}"
+        );
+        assert_eq!(names.len(), 2);
+    }
+}
@@ -0,0 +1,244 @@
+pub mod filter;
+pub mod json_api;
+pub mod resolve;
+pub mod state;
+
+use std::{collections::HashSet, str::FromStr, time::Duration};
+
+use grimoire::{records::CertReconRecord, Fqdn};
+use sqlx::{query, PgPool};
+use time::OffsetDateTime;
+
+/// A certificate name along with the details of the certificate it was found in
+#[derive(Debug)]
+pub struct CertName {
+    pub name_value: String,
+    pub issuer: Option<String>,
+    pub not_before: Option<OffsetDateTime>,
+    pub not_after: Option<OffsetDateTime>,
+}
+
+/// The delay before the first retry of a failed query, doubled for every subsequent retry
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Whether a query failed due to the connection to the CT service, rather than due to the query
+/// itself, such that it may succeed when retried
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<sqlx::Error>() {
+        matches!(error, sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut)
+    } else if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        error.is_connect() || error.is_timeout()
+    } else {
+        false
+    }
+}
+
+/// Extracts the FQDNs from a certificate name, which may hold several newline-separated SANs.
+/// Wildcards are reduced to their base domain and entries that are not host names, such as email
+/// addresses, are dropped
+pub fn cert_name_fqdns(cert_name: &str) -> impl Iterator<Item = Fqdn> + '_ {
+    cert_name
+        .lines()
+        .map(str::trim)
+        .map(|name| name.strip_prefix("*.").unwrap_or(name))
+        .filter(|name| !name.contains('@'))
+        .filter_map(|name| Fqdn::from_str(name).ok())
+}
+
+/// The names emitted for a certificate name, which are either the name as is if `raw` is set, or
+/// the FQDNs it holds. Names already in `seen` are skipped, and the others are added to it
+pub fn unseen_names(cert_name: &str, raw: bool, seen: &mut HashSet<String>) -> Vec<String> {
+    if raw {
+        seen.insert(normalize_name(cert_name))
+            .then(|| cert_name.to_string())
+            .into_iter()
+            .collect()
+    } else {
+        cert_name_fqdns(cert_name)
+            .map(|fqdn| fqdn.to_string())
+            .filter(|name| seen.insert(normalize_name(name)))
+            .collect()
+    }
+}
+
+/// The form of a certificate name used to recognise duplicates, which differ only in case or in a
+/// trailing dot
+pub fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Stores a certificate name. Names are compared case-insensitively. If the name is already known,
+/// the details of the more recently issued certificate are kept, and the name is marked as updated
+/// either way
+#[tracing::instrument(skip(pg_pool, record))]
+pub async fn submit_cert_recon_results(
+    pg_pool: &PgPool,
+    record: &CertReconRecord,
+) -> Result<(), sqlx::Error> {
+    query!(
+        r#"
+        INSERT INTO "cert-recon" (id, domain, "cert-name", issuer, "not-before", "not-after")
+        VALUES (DEFAULT, $1, $2, $3, $4, $5)
+        ON CONFLICT (lower("cert-name")) DO UPDATE
+        SET issuer = CASE WHEN "cert-recon"."not-before" IS NULL OR "cert-recon"."not-before" < EXCLUDED."not-before"
+                THEN EXCLUDED.issuer ELSE "cert-recon".issuer END,
+            "not-before" = CASE WHEN "cert-recon"."not-before" IS NULL OR "cert-recon"."not-before" < EXCLUDED."not-before"
+                THEN EXCLUDED."not-before" ELSE "cert-recon"."not-before" END,
+            "not-after" = CASE WHEN "cert-recon"."not-before" IS NULL OR "cert-recon"."not-before" < EXCLUDED."not-before"
+                THEN EXCLUDED."not-after" ELSE "cert-recon"."not-after" END,
+            updated_at = now()
+        "#,
+        record.domain,
+        record.cert_name,
+        record.issuer.as_deref(),
+        record.not_before,
+        record.not_after,
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fqdns(cert_name: &str) -> Vec<String> {
+        cert_name_fqdns(cert_name)
+            .map(|fqdn| fqdn.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn wildcards_are_reduced_to_their_base() {
+        assert_eq!(fqdns("*.a.example.com"), ["a.example.com"]);
+    }
+
+    #[test]
+    fn multi_san_names_yield_one_fqdn_each() {
+        assert_eq!(
+            fqdns("example.com\nwww.example.com\r\n *.api.example.com"),
+            ["example.com", "www.example.com", "api.example.com"]
+        );
+    }
+
+    #[test]
+    fn email_sans_are_dropped() {
+        assert_eq!(
+            fqdns("hostmaster@example.com\nmail.example.com"),
+            ["mail.example.com"]
+        );
+    }
+
+    #[test]
+    fn names_are_emitted_once() {
+        let mut seen = HashSet::new();
+
+        assert_eq!(
+            unseen_names("*.example.com\nwww.example.com", false, &mut seen),
+            ["example.com", "www.example.com"]
+        );
+        assert_eq!(
+            unseen_names("WWW.example.com\nmail.example.com", false, &mut seen),
+            ["mail.example.com"]
+        );
+    }
+
+    #[test]
+    fn names_differing_in_case_or_a_trailing_dot_are_emitted_once() {
+        let mut seen = HashSet::new();
+
+        assert_eq!(
+            unseen_names("Www.Example.Com", false, &mut seen),
+            ["Www.Example.Com"]
+        );
+        assert!(unseen_names("www.example.com", false, &mut seen).is_empty());
+        assert!(unseen_names("www.example.com.", false, &mut seen).is_empty());
+        assert_eq!(normalize_name("Www.Example.Com."), "www.example.com");
+    }
+
+    #[test]
+    fn raw_names_are_emitted_as_is() {
+        let mut seen = HashSet::new();
+
+        assert_eq!(
+            unseen_names("*.example.com\nhostmaster@example.com", true, &mut seen),
+            ["*.example.com\nhostmaster@example.com"]
+        );
+        assert!(unseen_names("*.EXAMPLE.com\nhostmaster@example.com", true, &mut seen).is_empty());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn certificate_details_round_trip(pg_pool: PgPool) {
+        let record = CertReconRecord {
+            domain: "example.com".to_string(),
+            cert_name: "www.example.com".to_string(),
+            issuer: Some("C=US, O=Example CA".to_string()),
+            not_before: Some(time::macros::datetime!(2024-07-01 00:00:00 UTC)),
+            not_after: Some(time::macros::datetime!(2025-07-01 12:30:00 UTC)),
+            updated_at: OffsetDateTime::now_utc(),
+        };
+        submit_cert_recon_results(&pg_pool, &record).await.unwrap();
+
+        let stored: (
+            Option<String>,
+            Option<OffsetDateTime>,
+            Option<OffsetDateTime>,
+        ) = sqlx::query_as(r#"SELECT issuer, "not-before", "not-after" FROM "cert-recon""#)
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, (record.issuer, record.not_before, record.not_after));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn names_differing_in_case_are_stored_once(pg_pool: PgPool) {
+        for cert_name in ["Www.Example.Com", "www.example.com"] {
+            let record = CertReconRecord {
+                domain: "example.com".to_string(),
+                cert_name: cert_name.to_string(),
+                issuer: None,
+                not_before: None,
+                not_after: None,
+                updated_at: OffsetDateTime::now_utc(),
+            };
+            submit_cert_recon_results(&pg_pool, &record).await.unwrap();
+        }
+
+        let names: Vec<String> = sqlx::query_scalar(r#"SELECT "cert-name" FROM "cert-recon""#)
+            .fetch_all(&pg_pool)
+            .await
+            .unwrap();
+        assert_eq!(names, ["Www.Example.Com"]);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn re_inserting_a_name_advances_updated_at(pg_pool: PgPool) {
+        let record = CertReconRecord {
+            domain: "example.com".to_string(),
+            cert_name: "www.example.com".to_string(),
+            issuer: None,
+            not_before: None,
+            not_after: None,
+            updated_at: OffsetDateTime::now_utc(),
+        };
+        let timestamps = || async {
+            sqlx::query_as::<_, (OffsetDateTime, OffsetDateTime)>(
+                r#"SELECT created_at, updated_at FROM "cert-recon""#,
+            )
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap()
+        };
+
+        submit_cert_recon_results(&pg_pool, &record).await.unwrap();
+        let (created_at, updated_at) = timestamps().await;
+        assert_eq!(created_at, updated_at);
+
+        submit_cert_recon_results(&pg_pool, &record).await.unwrap();
+        let (created_at_again, updated_at_again) = timestamps().await;
+        assert_eq!(created_at_again, created_at);
+        assert!(updated_at_again > updated_at);
+    }
+}
@@ -1,96 +1,186 @@
-use std::time::Duration;
+use std::{
+    collections::HashSet, net::SocketAddr, num::NonZeroUsize, path::PathBuf, pin::pin,
+    process::ExitCode, str::FromStr, time::Duration,
+};
 
-use clap::Parser;
-use futures::StreamExt;
-use grimoire::{create_recon_db_pool, Fqdn, IpAddrOrFqdn};
+use anyhow::bail;
+use clap::{Parser, ValueEnum};
+use futures::{stream, StreamExt};
+use grimoire::{
+    build_info,
+    cli::{parse_with_config, ConfigArgs, LogArgs, OutputArgs, ReconDbArgs, RuntimeArgs},
+    exit::{report_with, ExitStatus, RunError, EXIT_STATUS_HELP},
+    open_input,
+    output::OutputFormat,
+    records::CertReconRecord,
+    resolver::{dns_server_addr, udp_resolver_config},
+    shutdown_on_ctrl_c, Fqdn, IpAddrOrFqdn,
+};
+use hickory_resolver::{config::ResolverOpts, TokioAsyncResolver};
+use regex::Regex;
+use reqwest::Url;
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
-    query, raw_sql, ConnectOptions, PgPool, Row,
+    raw_sql, ConnectOptions, Row,
+};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime,
+    PrimitiveDateTime, UtcOffset,
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{debug, info, warn};
+
+use cert_recon::{
+    filter::{parse_glob, parse_regex, NameFilter},
+    is_transient, json_api,
+    resolve::resolve_names,
+    state::StateFile,
+    submit_cert_recon_results, unseen_names, CertName, RETRY_BACKOFF,
 };
-use tracing::debug;
-use tracing_subscriber::EnvFilter;
 
 /// Queries certificate transparency logs for subdomains of a domain
 #[derive(Debug, Parser)]
-#[command(version, name = "dns-recon", about, long_about = None)]
+#[command(version, long_version = build_info(), about, long_about = None, after_help = EXIT_STATUS_HELP)]
 struct Args {
-    /// The IPv4 or IPv6 address or the host name of the recon database service
-    #[arg(long, default_value = "localhost", env = "RECON_DB_HOST")]
-    recon_db_host: String,
-    /// The username used for authenticating with the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_USERNAME")]
-    recon_db_username: String,
-    /// The password used for authenticating with the recon database service
-    #[arg(long, env = "RECON_DB_PASSWORD")]
-    recon_db_password: Option<String>,
-    /// The database to connect to when using the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_DATABASE")]
-    recon_db_database: String,
-    /// If enabled, store the results in the recon database
-    #[arg(short, long)]
-    enable_db_storage: bool,
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+    /// The interface used to query the certificate transparency log (CT) service
+    #[arg(long, value_enum, default_value_t = Backend::Postgres)]
+    backend: Backend,
     /// The IPv4 or IPv6 address or the FQDN of the certificate transparency log (CT) service
     #[arg(long, default_value = "crt.sh", env = "CT_HOST")]
     ct_host: IpAddrOrFqdn,
+    /// The base URL of the JSON API of the CT service, e.g. of a mirror, instead of HTTPS on the
+    /// CT host
+    #[arg(long, value_name = "URL", env = "CT_URL")]
+    ct_url: Option<Url>,
     /// The username used for the PostgreSQL connection to the CT service
     #[arg(long, default_value = "guest", env = "CT_USERNAME")]
     ct_username: String,
     /// The PostgreSQL database to connect to when using the CT service
     #[arg(long, default_value = "certwatch", env = "CT_DATABASE")]
     ct_database: String,
-    /// Disable output to stdout
-    #[arg(short, long)]
-    quiet: bool,
-    /// The domain to query
-    domain: Fqdn,
+    /// Retry the query to the CT service at most this many times if the connection fails
+    #[arg(long, default_value_t = 3_u32)]
+    retries: u32,
+    /// Only consider certificates issued on or after this date, given as YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    issued_after: Option<Date>,
+    /// Only consider certificates issued at or after this time, given in RFC 3339 format, e.g.
+    /// '2024-08-01T00:00:00Z'
+    #[arg(long, value_name = "TIMESTAMP", value_parser = parse_since)]
+    since: Option<OffsetDateTime>,
+    /// Remember the most recent issuance date seen for each domain in this file, and only consider
+    /// certificates issued after it on subsequent runs. The mark of a domain only advances once
+    /// its query completes
+    #[arg(long, value_name = "PATH")]
+    state_file: Option<PathBuf>,
+    /// Abort the query to the CT service if it has not completed after this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    query_timeout_secs: Option<u64>,
+    /// Emit the certificate names exactly as returned by the CT service, instead of one FQDN per
+    /// line. Raw names may contain multiple newline-separated SANs, wildcards and email addresses
+    #[arg(long)]
+    raw: bool,
+    /// Only emit and store names matching this case-insensitive regular expression, e.g.
+    /// '^[^.]+-api\.'. May be specified multiple times, in which case names matching any of the
+    /// patterns are kept
+    #[arg(long, value_name = "REGEX", value_parser = parse_regex)]
+    filter: Vec<Regex>,
+    /// Only emit and store names matching this case-insensitive glob pattern, e.g.
+    /// '*-api.example.com' or '*.internal.example.com', where '*' also matches dots. May be
+    /// specified multiple times and combined with '--filter'
+    #[arg(long, value_name = "PATTERN", value_parser = parse_glob)]
+    glob: Vec<Regex>,
+    /// Resolve the names found for each domain right away and store their IP addresses in the
+    /// 'dns-recon' table, as if they were piped into dns-recon. Requires the recon database
+    /// integration and a DNS server
+    #[arg(long, requires_all = ["enable_db_storage", "dns_server"], conflicts_with = "raw")]
+    resolve: bool,
+    /// The IP address or fully qualified domain name of the DNS server used to resolve the names
+    #[arg(long, env = "DNS_SERVER")]
+    dns_server: Option<IpAddrOrFqdn>,
+    /// The port used to connect to the DNS server
+    #[arg(long, env = "DNS_PORT", default_value_t = 53)]
+    dns_port: u16,
+    /// The maximum number of DNS lookups that may be in flight at the same time
+    #[arg(long, env = "DNS_CONCURRENCY", default_value = "50")]
+    dns_concurrency: NonZeroUsize,
+    #[command(flatten)]
+    output: OutputArgs,
+    /// Read the domains from this file instead of Stdin if no domain is given. Use '-' to read
+    /// from Stdin explicitly
+    #[arg(short, long, value_name = "PATH", conflicts_with = "domain")]
+    input: Option<PathBuf>,
+    /// The domain to query. If omitted, the domains are read from Stdin, one per line
+    domain: Option<Fqdn>,
 }
 
-#[tracing::instrument(skip(pg_pool))]
-async fn submit_cert_recon_results(
-    pg_pool: &PgPool,
-    domain: &str,
-    cert_name: &str,
-) -> Result<(), sqlx::Error> {
-    query!(
-        r#"
-        INSERT INTO "cert-recon" (id, domain, "cert-name") 
-        VALUES (DEFAULT, $1, $2)
-        ON CONFLICT ON CONSTRAINT "cert-recon_pkey" DO NOTHING
-        "#,
-        domain,
-        cert_name
+/// The interface of the CT service. crt.sh offers both, but its PostgreSQL interface is frequently
+/// overloaded and may be firewalled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// Query the Certwatch database directly
+    Postgres,
+    /// Query the JSON HTTP API
+    JsonApi,
+}
+
+fn parse_date(s: &str) -> Result<Date, time::error::Parse> {
+    Date::parse(s, format_description!("[year]-[month]-[day]"))
+}
+
+fn parse_since(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    OffsetDateTime::parse(s, &Rfc3339)
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report_with(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+        classify_requests,
     )
-    .execute(pg_pool)
-    .await?;
+}
 
-    Ok(())
+/// Classifies the failed requests to the JSON API of the CT service, which are not classified
+/// where they occur, as network errors
+fn classify_requests(error: &anyhow::Error) -> Option<ExitStatus> {
+    error
+        .chain()
+        .any(|e| e.is::<reqwest::Error>())
+        .then_some(ExitStatus::Network)
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(std::io::stderr)
-        .init();
+async fn run(args: Args) -> anyhow::Result<()> {
+    args.log.init();
 
-    debug!("Parsing command line arguments");
-    let args = Args::parse();
+    let shutdown = shutdown_on_ctrl_c();
 
-    let recon_pg_pool = if args.enable_db_storage {
+    let recon_pg_pool = if args.recon_db.enable_db_storage {
         debug!("Establishing a connection to the recon database");
-        Some(
-            create_recon_db_pool(
-                &args.recon_db_host,
-                &args.recon_db_username,
-                args.recon_db_password.as_deref(),
-                &args.recon_db_database,
-            )
-            .await?,
-        )
+        Some(args.recon_db.connect().await?)
     } else {
         None
     };
 
+    let query_cancelled = shutdown.child_token();
+    if let Some(query_timeout_secs) = args.query_timeout_secs {
+        let query_cancelled = query_cancelled.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(query_timeout_secs)).await;
+            query_cancelled.cancel();
+        });
+    }
+
     debug!("Defining PostgreSQL connection settings for Certwatch");
     let ct_pg_connect_opts = PgConnectOptions::new_without_pgpass()
         .log_slow_statements(log::LevelFilter::Debug, Duration::from_secs(10))
@@ -105,36 +195,542 @@ async fn main() -> anyhow::Result<()> {
         .max_connections(1)
         .connect_lazy_with(ct_pg_connect_opts);
 
-    debug!("Creating the SQL query for Certwatch");
-    let domain = args.domain.to_string();
-    let raw_query = format!(
-        r#"
-        SELECT DISTINCT cai.NAME_VALUE
-        FROM certificate_and_identities AS cai
-        WHERE
-            plainto_tsquery('certwatch', '{0}') @@ identities(cai.certificate)
-            AND (cai.NAME_TYPE = '2.5.4.3' OR cai.NAME_TYPE LIKE 'san:%')
-            AND cai.NAME_VALUE LIKE '%.{0}'
-    "#,
-        &domain
-    );
-
-    debug!("Fetching SQL query results");
-    let mut data_stream = raw_sql(&raw_query).fetch(&ct_pg_pool);
-
-    debug!("Evaluating SQL query results");
-    while let Some(data) = data_stream.next().await {
-        let row = data?;
-        let cert_name_or_san = row.get::<&str, _>(0);
-
-        if !args.quiet {
-            println!("{}", &cert_name_or_san);
+    let domains = match args.domain {
+        Some(domain) => vec![domain],
+        None => {
+            info!("Lines that don't parse as FQDNs are silently ignored");
+            let mut domains = Vec::new();
+            let mut lines = BufReader::new(open_input(args.input.as_deref()).await?).lines();
+            while let Some(line) = lines.next_line().await? {
+                match Fqdn::from_str(line.trim()) {
+                    Ok(domain) => domains.push(domain),
+                    Err(e) => warn!("{e}"),
+                }
+            }
+            domains
+        }
+    };
+
+    let resolver = match &args.dns_server {
+        Some(dns_server) if args.resolve => {
+            debug!("Creating the resolver");
+            let dns_server = dns_server_addr(dns_server).await?;
+            Some(TokioAsyncResolver::tokio(
+                udp_resolver_config(SocketAddr::new(dns_server, args.dns_port)),
+                ResolverOpts::default(),
+            ))
+        }
+        _ => None,
+    };
+
+    let mut state = match args.state_file.clone() {
+        Some(path) => Some(StateFile::load(path).await?),
+        None => None,
+    };
+
+    let name_filter = NameFilter::new(args.filter.iter().chain(&args.glob).cloned());
+    let writer = args.output.writer().await?;
+    let client = reqwest::Client::new();
+    let ct_url = match args.ct_url.clone() {
+        Some(ct_url) => ct_url,
+        None => json_api::ct_url(&args.ct_host)?,
+    };
+
+    let mut seen = HashSet::new();
+    let mut queried = 0_usize;
+    let mut processed = 0_usize;
+    let mut skipped = 0_usize;
+    let mut filtered = 0_usize;
+    let mut emitted = 0_usize;
+    let mut stored = 0_usize;
+    let mut resolved = 0_usize;
+    for domain in domains {
+        if query_cancelled.is_cancelled() {
+            break;
+        }
+        queried += 1;
+
+        info!("Querying certificates for '{domain}'");
+        let domain = domain.to_string();
+        let since = args
+            .since
+            .into_iter()
+            .chain(state.as_ref().and_then(|state| state.since(&domain)))
+            .max();
+        if let Some(since) = since {
+            debug!("Only considering certificates issued since {since}");
+        }
+
+        debug!("Creating the SQL query for Certwatch");
+        let issued_after_clause = args
+            .issued_after
+            .map(|date| format!("AND x509_notBefore(cai.CERTIFICATE) >= '{date}'"))
+            .unwrap_or_default();
+        // The Certwatch timestamps are in UTC without an offset
+        let since_clause = since
+            .map(|since| {
+                let since = since.to_offset(UtcOffset::UTC).format(format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second]"
+                ))?;
+                anyhow::Ok(format!("AND x509_notBefore(cai.CERTIFICATE) >= '{since}'"))
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let raw_query = format!(
+            r#"
+            SELECT DISTINCT
+                cai.NAME_VALUE,
+                x509_issuerName(cai.CERTIFICATE),
+                x509_notBefore(cai.CERTIFICATE),
+                x509_notAfter(cai.CERTIFICATE)
+            FROM certificate_and_identities AS cai
+            WHERE
+                plainto_tsquery('certwatch', '{0}') @@ identities(cai.certificate)
+                AND (cai.NAME_TYPE = '2.5.4.3' OR cai.NAME_TYPE LIKE 'san:%')
+                AND cai.NAME_VALUE LIKE '%.{0}'
+                {1}
+                {2}
+        "#,
+            &domain, issued_after_clause, since_clause
+        );
+
+        let mut found = Vec::new();
+        let mut latest_not_before: Option<OffsetDateTime> = None;
+        let mut attempt = 0_u32;
+        loop {
+            let result = async {
+                let cert_names = match args.backend {
+                    Backend::Postgres => {
+                        debug!("Fetching SQL query results");
+                        raw_sql(&raw_query)
+                            .fetch(&ct_pg_pool)
+                            .map(|data| {
+                                let row = data?;
+                                anyhow::Ok(CertName {
+                                    name_value: row.try_get(0)?,
+                                    issuer: row.try_get(1)?,
+                                    not_before: row
+                                        .try_get::<Option<PrimitiveDateTime>, _>(2)?
+                                        .map(PrimitiveDateTime::assume_utc),
+                                    not_after: row
+                                        .try_get::<Option<PrimitiveDateTime>, _>(3)?
+                                        .map(PrimitiveDateTime::assume_utc),
+                                })
+                            })
+                            .boxed()
+                    }
+                    Backend::JsonApi => {
+                        debug!("Fetching JSON API results");
+                        let cert_names = tokio::select! {
+                            cert_names = json_api::fetch_cert_names(&client, &ct_url, &domain) => cert_names?,
+                            _ = query_cancelled.cancelled() => Vec::new(),
+                        };
+                        stream::iter(cert_names).map(anyhow::Ok).boxed()
+                    }
+                };
+                let mut data_stream = pin!(cert_names.take_until(query_cancelled.cancelled()));
+
+                debug!("Evaluating the results");
+                while let Some(data) = data_stream.next().await {
+                    let cert = data?;
+                    latest_not_before = latest_not_before.max(cert.not_before);
+                    if let Some(issued_after) = args.issued_after {
+                        if cert.not_before.is_none_or(|t| t.date() < issued_after) {
+                            processed += 1;
+                            skipped += 1;
+                            continue;
+                        }
+                    }
+                    if let Some(since) = since {
+                        if cert.not_before.is_none_or(|t| t < since) {
+                            processed += 1;
+                            skipped += 1;
+                            continue;
+                        }
+                    }
+
+                    for name in unseen_names(&cert.name_value, args.raw, &mut seen) {
+                        if !name_filter.matches(&name) {
+                            filtered += 1;
+                            continue;
+                        }
+
+                        let record = CertReconRecord {
+                            domain: domain.clone(),
+                            cert_name: name,
+                            issuer: cert.issuer.clone(),
+                            not_before: cert.not_before,
+                            not_after: cert.not_after,
+                            updated_at: OffsetDateTime::now_utc(),
+                        };
+                        if writer.format() == OutputFormat::Text {
+                            writer.write_line(&record.cert_name).await?;
+                        } else {
+                            writer.write_record(&record).await?;
+                        }
+                        emitted += 1;
+
+                        if let Some(recon_pg_pool) = &recon_pg_pool {
+                            submit_cert_recon_results(recon_pg_pool, &record)
+                                .await
+                                .map_err(|e| RunError::ReconDb(e.into()))?;
+                            stored += 1;
+                        }
+                        if resolver.is_some() {
+                            found.extend(Fqdn::from_str(&record.cert_name).ok());
+                        }
+                    }
+
+                    processed += 1;
+                }
+
+                anyhow::Ok(())
+            }
+            .await;
+
+            match result {
+                Ok(()) => break,
+                Err(e) if attempt < args.retries && is_transient(&e) => {
+                    let backoff = RETRY_BACKOFF * 2_u32.pow(attempt);
+                    attempt += 1;
+                    warn!(
+                        "Querying certificates for '{domain}' failed, retrying in {backoff:?}: {e}"
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        _ = query_cancelled.cancelled() => break,
+                    }
+                }
+                Err(e) => {
+                    let e = e.context(format!(
+                        "Querying certificates for '{domain}' failed after {} attempts",
+                        attempt + 1
+                    ));
+                    // The CT service and the recon database are both PostgreSQL databases, so the
+                    // failures of the former are classified here and those of the latter where
+                    // they occur
+                    if e.is::<sqlx::Error>() {
+                        return Err(RunError::Network(e).into());
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        if let (Some(resolver), Some(recon_pg_pool)) = (&resolver, &recon_pg_pool) {
+            info!("Resolving {} names found for '{domain}'", found.len());
+            resolved +=
+                resolve_names(resolver, recon_pg_pool, found, args.dns_concurrency.get()).await?;
         }
 
-        if let Some(recon_pg_pool) = &recon_pg_pool {
-            submit_cert_recon_results(recon_pg_pool, &domain, cert_name_or_san).await?;
+        // An interrupted query may have missed certificates older than the latest one seen
+        if let (Some(state), Some(latest_not_before)) = (&mut state, latest_not_before) {
+            if !query_cancelled.is_cancelled() {
+                state.advance(&domain, latest_not_before);
+                state.save().await?;
+            }
         }
     }
 
+    writer.finish().await?;
+
+    eprintln!("Domains:       {queried}");
+    eprintln!("Processed:     {processed}");
+    eprintln!("Skipped old:   {skipped}");
+    eprintln!("Filtered out:  {filtered}");
+    eprintln!("Emitted:       {emitted}");
+    eprintln!("Stored:        {stored}");
+    if args.resolve {
+        eprintln!("Resolved:      {resolved}");
+    }
+
+    if shutdown.is_cancelled() {
+        eprintln!("Interrupted after processing {processed} certificate names");
+    } else if query_cancelled.is_cancelled() {
+        bail!(
+            "The query timed out after {} seconds, having processed {processed} certificate names",
+            args.query_timeout_secs.unwrap_or_default()
+        );
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use anyhow::Context as _;
+    use grimoire_test::{
+        http::{read_request_head, serve},
+        temp_dir,
+    };
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Answers each query of the JSON API on `listener` with a certificate for the 'www' subdomain
+    /// of the queried domain issued in 2024, and one for the 'mail' subdomain issued in 2023
+    async fn serve_ct(listener: TcpListener) {
+        serve(listener, |request| {
+            let request_line = request.lines().next().unwrap_or_default();
+            let domain = request_line
+                .split_once("q=%25.")
+                .and_then(|(_, query)| query.split_once('&'))
+                .map(|(domain, _)| domain)
+                .unwrap_or_default();
+            let body = format!(
+                r#"[
+                    {{"issuer_name":"C=US, O=Example CA","name_value":"www.{domain}","not_before":"2024-07-01T00:00:00","not_after":"2025-07-01T12:30:00"}},
+                    {{"issuer_name":"C=US, O=Example CA","name_value":"mail.{domain}","not_before":"2023-01-15T00:00:00","not_after":"2024-01-15T00:00:00"}}
+                ]"#
+            );
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            )
+        })
+        .await;
+    }
+
+    /// Runs cert-recon with `args` against the JSON API on `addr`, and returns the results it
+    /// writes in NDJSON format
+    async fn run_against(addr: SocketAddr, args: &[&str]) -> Vec<serde_json::Value> {
+        let ct_url = format!("http://{addr}/");
+        let dir = temp_dir();
+        let output = dir.path().join("results.ndjson");
+        let args = Args::try_parse_from(
+            [
+                "cert-recon",
+                "--quiet",
+                "--backend",
+                "json-api",
+                "--ct-url",
+                &ct_url,
+                "--output",
+                "ndjson",
+                "--output-file",
+                output.to_str().unwrap(),
+            ]
+            .iter()
+            .chain(args),
+        )
+        .unwrap();
+
+        run(args).await.unwrap();
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        use clap::CommandFactory;
+
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn the_version_is_reported_under_the_name_of_the_binary() {
+        use clap::CommandFactory;
+
+        let version = Args::command().render_long_version();
+
+        assert!(version.starts_with("cert-recon "), "{version}");
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected_at_startup() {
+        let error =
+            Args::try_parse_from(["cert-recon", "--filter", "(api", "example.com"]).unwrap_err();
+
+        assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[tokio::test]
+    async fn slow_queries_are_aborted_after_the_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ct_url = format!("http://{}/", listener.local_addr().unwrap());
+        // Receives the request, but never answers it
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    read_request_head(&mut stream).await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+        let args = Args::try_parse_from([
+            "cert-recon",
+            "--quiet",
+            "--backend",
+            "json-api",
+            "--ct-url",
+            &ct_url,
+            "--query-timeout-secs",
+            "1",
+            "example.com",
+        ])
+        .unwrap();
+
+        let start = Instant::now();
+        let error = run(args).await.unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(
+            error.to_string().contains("timed out after 1 seconds"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_names_of_each_domain_are_labeled_with_it() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_ct(listener));
+        let dir = temp_dir();
+        let input = dir.path().join("domains.txt");
+        std::fs::write(
+            &input,
+            "example.com\nnot a domain\nexample.org\nexample.net\n",
+        )
+        .unwrap();
+
+        let results = run_against(addr, &["--input", input.to_str().unwrap()]).await;
+
+        let labeled: Vec<_> = results
+            .iter()
+            .map(|result| {
+                (
+                    result["domain"].as_str().unwrap(),
+                    result["cert-name"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            labeled,
+            [
+                ("example.com", "www.example.com"),
+                ("example.com", "mail.example.com"),
+                ("example.org", "www.example.org"),
+                ("example.org", "mail.example.org"),
+                ("example.net", "www.example.net"),
+                ("example.net", "mail.example.net"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_retried() {
+        // The port is only listened on after the first connection has been refused
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(RETRY_BACKOFF / 2).await;
+            serve_ct(TcpListener::bind(addr).await.unwrap()).await;
+        });
+
+        let results = run_against(addr, &["--retries", "1", "example.com"]).await;
+
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn queries_fail_once_the_retries_are_exhausted() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let args = Args::try_parse_from([
+            "cert-recon",
+            "--quiet",
+            "--backend",
+            "json-api",
+            "--ct-url",
+            &format!("http://{addr}/"),
+            "--retries",
+            "0",
+            "example.com",
+        ])
+        .unwrap();
+
+        let error = run(args).await.unwrap_err();
+
+        assert!(
+            error.to_string().contains("failed after 1 attempts"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn certificates_issued_before_the_date_are_skipped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_ct(listener));
+
+        let results = run_against(addr, &["--issued-after", "2024-01-01", "example.com"]).await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["cert-name"], "www.example.com");
+        assert_eq!(results[0]["issuer"], "C=US, O=Example CA");
+        assert_eq!(results[0]["not-before"], "2024-07-01T00:00:00Z");
+        assert_eq!(results[0]["not-after"], "2025-07-01T12:30:00Z");
+    }
+
+    #[tokio::test]
+    async fn certificates_issued_before_the_time_are_skipped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_ct(listener));
+
+        let results = run_against(addr, &["--since", "2024-07-01T00:00:00Z", "example.com"]).await;
+        let excluded = run_against(addr, &["--since", "2024-07-01T00:00:01Z", "example.com"]).await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["cert-name"], "www.example.com");
+        assert!(excluded.is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_high_water_mark_advances_between_runs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_ct(listener));
+        let dir = temp_dir();
+        let state_file = dir.path().join("state.json");
+        let args = ["--state-file", state_file.to_str().unwrap(), "example.com"];
+
+        let first = run_against(addr, &args).await;
+        let state: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&state_file).unwrap()).unwrap();
+        let second = run_against(addr, &args).await;
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(state["example.com"], "2024-07-01T00:00:01Z");
+        assert!(second.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_to_the_ct_service_are_network_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let error = reqwest::get(format!("http://127.0.0.1:{port}/"))
+            .await
+            .context("Querying certificates for 'example.com' failed after 1 attempts")
+            .unwrap_err();
+
+        assert_eq!(
+            RunError::classify_with(error, classify_requests).status(),
+            ExitStatus::Network
+        );
+    }
+}
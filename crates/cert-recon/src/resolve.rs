@@ -0,0 +1,131 @@
+use std::net::IpAddr;
+
+use futures::{stream, StreamExt};
+use grimoire::Fqdn;
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+use sqlx::{query, types::ipnetwork::IpNetwork, PgPool};
+use tracing::debug;
+
+/// Stores the IP addresses of a name in the 'dns-recon' table, merging them with the known ones
+#[tracing::instrument(skip(pg_pool))]
+async fn submit_ips(pg_pool: &PgPool, fqdn: &Fqdn, ips: &[IpAddr]) -> Result<(), sqlx::Error> {
+    let ip_networks: Vec<_> = ips.iter().map(|ip| IpNetwork::from(*ip)).collect();
+    query!(
+        r#"
+        INSERT INTO "dns-recon" (fqdn, ips, domain)
+        VALUES ($1, $2, $3)
+        ON CONFLICT ON CONSTRAINT "dns-recon_pkey" DO
+        UPDATE SET ips = (SELECT ARRAY(SELECT DISTINCT UNNEST("dns-recon".ips || EXCLUDED.ips))),
+            updated_at = now()
+        "#,
+        fqdn.to_string(),
+        &ip_networks,
+        fqdn.domain(),
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Looks up the A and AAAA records of the names, `concurrency` at a time, and stores the results
+/// just like dns-recon does. Names without records are stored without addresses, whereas names
+/// whose lookup fails are skipped. Returns the number of names that resolved to an address
+pub async fn resolve_names(
+    resolver: &TokioAsyncResolver,
+    pg_pool: &PgPool,
+    fqdns: Vec<Fqdn>,
+    concurrency: usize,
+) -> anyhow::Result<usize> {
+    let mut lookups = stream::iter(fqdns)
+        .map(|fqdn| async move {
+            let lookup_result = resolver.lookup_ip(format!("{fqdn}.")).await;
+            (fqdn, lookup_result)
+        })
+        .buffer_unordered(concurrency);
+
+    let mut resolved = 0_usize;
+    while let Some((fqdn, lookup_result)) = lookups.next().await {
+        let ips: Vec<IpAddr> = match lookup_result {
+            Ok(lookup_ip) => lookup_ip.iter().collect(),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Vec::new(),
+            Err(e) => {
+                debug!("Cannot resolve '{}': {}", fqdn, e);
+                continue;
+            }
+        };
+        if !ips.is_empty() {
+            resolved += 1;
+        }
+
+        submit_ips(pg_pool, &fqdn, &ips).await?;
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire::resolver::udp_resolver_config;
+    use grimoire_test::dns::{StubServer, UnknownNames};
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{rdata::A, Name, RData, Record},
+    };
+    use sqlx::query_as;
+
+    use super::*;
+    use crate::cert_name_fqdns;
+
+    /// Spawns a DNS server that answers the A queries for 'www.example.com' with 192.0.2.1. All
+    /// other names exist, but have no records
+    async fn spawn_dns() -> StubServer {
+        StubServer::builder(vec![Record::from_rdata(
+            Name::from_ascii("www.example.com.").unwrap(),
+            60,
+            RData::A(A::new(192, 0, 2, 1)),
+        )])
+        .unknown_names(UnknownNames::NoRecords)
+        .spawn()
+        .await
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn the_names_of_a_certificate_are_resolved_and_stored(pg_pool: PgPool) {
+        let server = spawn_dns().await;
+        let resolver =
+            TokioAsyncResolver::tokio(udp_resolver_config(server.addr()), ResolverOpts::default());
+        sqlx::query(
+            r#"INSERT INTO "dns-recon" (fqdn, ips, domain) VALUES ('www.example.com', '{198.51.100.1}', 'example.com')"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+
+        let fqdns = cert_name_fqdns("www.example.com\n*.mail.example.com").collect();
+        let resolved = resolve_names(&resolver, &pg_pool, fqdns, 2).await.unwrap();
+
+        let mut rows: Vec<(String, Vec<IpNetwork>)> =
+            query_as(r#"SELECT fqdn, ips FROM "dns-recon" ORDER BY fqdn"#)
+                .fetch_all(&pg_pool)
+                .await
+                .unwrap();
+        rows[1].1.sort();
+        assert_eq!(resolved, 1);
+        assert_eq!(
+            rows,
+            [
+                ("mail.example.com".to_string(), Vec::new()),
+                (
+                    "www.example.com".to_string(),
+                    vec![
+                        IpNetwork::from_str("192.0.2.1/32").unwrap(),
+                        IpNetwork::from_str("198.51.100.1/32").unwrap(),
+                    ]
+                ),
+            ]
+        );
+    }
+}
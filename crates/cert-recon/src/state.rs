@@ -0,0 +1,101 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use grimoire::save_atomically;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tracing::debug;
+
+/// The time from which the certificates of a domain are queried on the next run
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct HighWaterMark(#[serde(with = "time::serde::rfc3339")] OffsetDateTime);
+
+/// Persists a high-water mark per domain between runs, such that subsequent runs only query
+/// certificates issued after the most recent one seen so far
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    marks: HashMap<String, HighWaterMark>,
+}
+
+impl StateFile {
+    /// Loads the state file. A missing file yields an empty state
+    pub async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let marks = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| {
+                anyhow::anyhow!("Cannot parse the state file '{}': {}", path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(StateFile { path, marks })
+    }
+
+    /// Returns the time from which the certificates of `domain` are to be queried, if the domain
+    /// was queried before
+    pub fn since(&self, domain: &str) -> Option<OffsetDateTime> {
+        self.marks.get(domain).map(|mark| mark.0)
+    }
+
+    /// Advances the high-water mark of `domain` past the most recent issuance date seen. The mark
+    /// never moves backwards
+    pub fn advance(&mut self, domain: &str, latest_not_before: OffsetDateTime) {
+        let next = latest_not_before + Duration::SECOND;
+        if self.since(domain).is_none_or(|since| since < next) {
+            self.marks.insert(domain.to_string(), HighWaterMark(next));
+        }
+    }
+
+    /// Writes the state file, replacing it atomically
+    pub async fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_vec_pretty(&self.marks)?;
+
+        debug!("Saving the state file '{}'", self.path.display());
+        save_atomically(&self.path, contents).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn the_mark_only_advances() {
+        let dir = temp_dir();
+        let mut state = StateFile::load(dir.path().join("missing.json"))
+            .await
+            .unwrap();
+        assert_eq!(state.since("example.com"), None);
+
+        state.advance("example.com", datetime!(2024-07-01 00:00:00 UTC));
+        assert_eq!(
+            state.since("example.com"),
+            Some(datetime!(2024-07-01 00:00:01 UTC))
+        );
+        state.advance("example.com", datetime!(2023-01-15 00:00:00 UTC));
+        assert_eq!(
+            state.since("example.com"),
+            Some(datetime!(2024-07-01 00:00:01 UTC))
+        );
+        assert_eq!(state.since("example.org"), None);
+    }
+
+    #[tokio::test]
+    async fn the_marks_are_kept_between_runs() {
+        let dir = temp_dir();
+        let path = dir.path().join("state.json");
+        let mut state = StateFile::load(path.clone()).await.unwrap();
+        state.advance("example.com", datetime!(2024-07-01 00:00:00 UTC));
+        state.save().await.unwrap();
+
+        let loaded = StateFile::load(path.clone()).await.unwrap();
+
+        assert_eq!(
+            loaded.since("example.com"),
+            Some(datetime!(2024-07-01 00:00:01 UTC))
+        );
+    }
+}
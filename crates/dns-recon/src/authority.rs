@@ -0,0 +1,312 @@
+use std::{fmt::Display, net::IpAddr};
+
+use anyhow::Context;
+use grimoire::Fqdn;
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    AsyncResolver,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::{query, types::ipnetwork::IpNetwork, PgPool};
+use tracing::warn;
+
+use crate::edns::EdnsConnectionProvider;
+
+/// A name server of a zone along with its IP addresses, if they were resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NsRecord {
+    pub nameserver: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ips: Vec<IpAddr>,
+}
+
+impl Display for NsRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.nameserver)?;
+        for ip in &self.ips {
+            write!(f, " {ip}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The SOA record of a zone. The durations are given in seconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SoaRecord {
+    /// The primary name server of the zone
+    pub mname: String,
+    /// The mailbox of the person responsible for the zone, with the '@' replaced by a '.'
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: i32,
+    pub retry: i32,
+    pub expire: i32,
+    /// The TTL of negative responses
+    pub minimum: u32,
+}
+
+impl Display for SoaRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.mname,
+            self.rname,
+            self.serial,
+            self.refresh,
+            self.retry,
+            self.expire,
+            self.minimum
+        )
+    }
+}
+
+/// Looks up the NS records of `fqdn`, ordered by name. If `resolve` is set, the IP addresses of
+/// each name server are looked up in a follow-up pass, and name servers that cannot be resolved are
+/// kept without addresses. Names without NS records yield an empty list
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_ns(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+    resolve: bool,
+) -> Result<Vec<NsRecord>, ResolveError> {
+    let lookup = match resolver.ns_lookup(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut records: Vec<_> = lookup
+        .iter()
+        .map(|ns| NsRecord {
+            nameserver: Fqdn::from(&ns.0).to_string(),
+            ips: Vec::new(),
+        })
+        .sorted_by(|a, b| a.nameserver.cmp(&b.nameserver))
+        .dedup()
+        .collect();
+
+    if resolve {
+        for record in &mut records {
+            match resolver.lookup_ip(format!("{}.", record.nameserver)).await {
+                Ok(lookup_ip) => record.ips = lookup_ip.iter().sorted().collect(),
+                Err(e) => warn!(
+                    "Error resolving the name server '{}' of '{}': {}",
+                    record.nameserver, fqdn, e
+                ),
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Looks up the SOA record of `fqdn`, which only exists at the apex of a zone
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_soa(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+) -> Result<Option<SoaRecord>, ResolveError> {
+    let lookup = match resolver.soa_lookup(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    Ok(lookup.iter().next().map(|soa| SoaRecord {
+        mname: Fqdn::from(soa.mname()).to_string(),
+        rname: Fqdn::from(soa.rname()).to_string(),
+        serial: soa.serial(),
+        refresh: soa.refresh(),
+        retry: soa.retry(),
+        expire: soa.expire(),
+        minimum: soa.minimum(),
+    }))
+}
+
+#[tracing::instrument(skip(pg_pool, records))]
+pub async fn submit_ns_recon_results(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    records: &[NsRecord],
+) -> anyhow::Result<()> {
+    for record in records {
+        let ips: Vec<_> = record.ips.iter().copied().map(IpNetwork::from).collect();
+        query!(
+            r#"
+            INSERT INTO "ns-recon" (domain, fqdn, nameserver, ips)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT ON CONSTRAINT "ns-recon_pkey" DO
+            UPDATE SET ips = CASE WHEN CARDINALITY(EXCLUDED.ips) > 0 THEN EXCLUDED.ips ELSE "ns-recon".ips END
+            "#,
+            fqdn.domain(),
+            fqdn.to_string(),
+            record.nameserver,
+            &ips,
+        )
+        .execute(pg_pool)
+        .await
+        .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(pg_pool, record))]
+pub async fn submit_soa_recon_result(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    record: &SoaRecord,
+) -> anyhow::Result<()> {
+    query!(
+        r#"
+        INSERT INTO "soa-recon" (domain, fqdn, mname, rname, serial, refresh, retry, expire, minimum)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT ON CONSTRAINT "soa-recon_pkey" DO
+        UPDATE SET mname = EXCLUDED.mname, rname = EXCLUDED.rname, serial = EXCLUDED.serial,
+            refresh = EXCLUDED.refresh, retry = EXCLUDED.retry, expire = EXCLUDED.expire,
+            minimum = EXCLUDED.minimum
+        "#,
+        fqdn.domain(),
+        fqdn.to_string(),
+        record.mname,
+        record.rname,
+        i64::from(record.serial),
+        record.refresh,
+        record.retry,
+        record.expire,
+        i64::from(record.minimum),
+    )
+    .execute(pg_pool)
+    .await
+    .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{
+            rdata::{A, NS, SOA},
+            Name, RData, Record,
+        },
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    fn name(s: &str) -> Name {
+        Name::from_ascii(s).unwrap()
+    }
+
+    async fn spawn_zone() -> StubServer {
+        StubServer::spawn(vec![
+            Record::from_rdata(
+                name("example.com."),
+                3600,
+                RData::SOA(SOA::new(
+                    name("ns1.example.com."),
+                    name("hostmaster.example.com."),
+                    2024080501,
+                    7200,
+                    3600,
+                    1209600,
+                    300,
+                )),
+            ),
+            Record::from_rdata(
+                name("example.com."),
+                3600,
+                RData::NS(NS(name("ns2.example.com."))),
+            ),
+            Record::from_rdata(
+                name("example.com."),
+                3600,
+                RData::NS(NS(name("ns1.example.com."))),
+            ),
+            Record::from_rdata(
+                name("ns1.example.com."),
+                3600,
+                RData::A(A::new(192, 0, 2, 53)),
+            ),
+        ])
+        .await
+    }
+
+    #[tokio::test]
+    async fn the_ns_set_is_ordered_by_name() {
+        let server = spawn_zone().await;
+        let resolver = server.resolver(ResolverOpts::default());
+        let fqdn = "example.com".parse().unwrap();
+
+        let records = lookup_ns(&resolver, &fqdn, false).await.unwrap();
+
+        assert_eq!(
+            records,
+            [
+                NsRecord {
+                    nameserver: "ns1.example.com".to_string(),
+                    ips: Vec::new(),
+                },
+                NsRecord {
+                    nameserver: "ns2.example.com".to_string(),
+                    ips: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn name_servers_are_resolved_on_request() {
+        let server = spawn_zone().await;
+        let resolver = server.resolver(ResolverOpts::default());
+        let fqdn = "example.com".parse().unwrap();
+
+        let records = lookup_ns(&resolver, &fqdn, true).await.unwrap();
+
+        // The second name server has no addresses, so it is kept without any
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.to_string())
+                .collect::<Vec<_>>(),
+            ["ns1.example.com 192.0.2.53", "ns2.example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn all_fields_of_the_soa_record_are_parsed() {
+        let server = spawn_zone().await;
+        let resolver = server.resolver(ResolverOpts::default());
+
+        let soa = lookup_soa(&resolver, &"example.com".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            soa,
+            Some(SoaRecord {
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2024080501,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            })
+        );
+        assert_eq!(
+            lookup_soa(&resolver, &"ns1.example.com".parse().unwrap())
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
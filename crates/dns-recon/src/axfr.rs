@@ -0,0 +1,180 @@
+use std::{net::SocketAddr, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use futures::StreamExt;
+use grimoire::Fqdn;
+use hickory_client::{
+    client::{AsyncClient, ClientHandle},
+    op::ResponseCode,
+    proto::iocompat::AsyncIoTokioAsStd,
+    rr::{Name, Record, RecordType},
+    tcp::TcpClientStream,
+};
+use sqlx::{query, PgPool};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+use crate::output::Output;
+
+#[tracing::instrument(skip(pg_pool, record))]
+async fn submit_axfr_recon_results(
+    pg_pool: &PgPool,
+    zone: &Fqdn,
+    record: &Record,
+) -> anyhow::Result<()> {
+    let fqdn = Fqdn::from(record.name());
+
+    query!(
+        r#"
+        INSERT INTO "axfr-recon" (id, domain, fqdn, "record-type", ttl, value)
+        VALUES (DEFAULT, $1, $2, $3, $4, $5)
+        ON CONFLICT ON CONSTRAINT "axfr-recon_pkey" DO
+        UPDATE SET ttl = EXCLUDED.ttl
+        "#,
+        zone.to_string(),
+        fqdn.to_string(),
+        record.record_type().to_string(),
+        record.ttl() as i32,
+        record.data().map(|d| d.to_string()).unwrap_or_default(),
+    )
+    .execute(pg_pool)
+    .await
+    .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+
+    Ok(())
+}
+
+/// Attempts a full zone transfer (AXFR) of `zone` from the DNS server at `server`
+///
+/// Servers that refuse the transfer (REFUSED or NOTAUTH) are not treated as an error, because that
+/// is the expected outcome for a correctly configured authoritative server.
+#[tracing::instrument(skip(pg_pool, output))]
+pub async fn zone_transfer(
+    server: SocketAddr,
+    zone: &Fqdn,
+    pg_pool: Option<&PgPool>,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let zone_name = Name::from_str(&format!("{zone}."))?;
+
+    debug!("Connecting to the DNS server via TCP");
+    let (stream, sender) = TcpClientStream::<AsyncIoTokioAsStd<TcpStream>>::new(server);
+    let (mut client, background) = AsyncClient::new(stream, sender, None).await?;
+    tokio::spawn(background);
+
+    info!("Requesting a zone transfer of '{zone}' from {server}");
+    let mut responses = client.zone_transfer(zone_name, None);
+    let mut soa_seen = false;
+    while let Some(response) = responses.next().await {
+        let response = response?;
+        match response.response_code() {
+            ResponseCode::NoError => (),
+            code @ (ResponseCode::Refused | ResponseCode::NotAuth) => {
+                warn!("The DNS server {server} refused the zone transfer of '{zone}': {code}");
+                return Ok(());
+            }
+            code => return Err(anyhow!("the zone transfer of '{zone}' failed: {code}")),
+        }
+
+        for record in response.answers() {
+            // An AXFR response is framed by the zone's SOA record, which is repeated at the end
+            if record.record_type() == RecordType::SOA {
+                if soa_seen {
+                    continue;
+                }
+                soa_seen = true;
+            }
+
+            output.write_zone_record(record).await?;
+
+            if let Some(pg_pool) = pg_pool {
+                submit_axfr_recon_results(pg_pool, zone, record).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire::output::{OutputFormat, ResultWriter, Writer};
+    use grimoire_test::{dns::StubServer, temp_dir};
+    use hickory_client::rr::{
+        rdata::{A, NS, SOA},
+        RData,
+    };
+
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        Name::from_ascii(s).unwrap()
+    }
+
+    #[tokio::test]
+    async fn permitted_zone_transfers_yield_each_record_once() {
+        let zone = name("example.com.");
+        let server = StubServer::spawn(vec![
+            Record::from_rdata(
+                zone.clone(),
+                3600,
+                RData::SOA(SOA::new(
+                    name("ns1.example.com."),
+                    name("hostmaster.example.com."),
+                    2024072201,
+                    7200,
+                    3600,
+                    1209600,
+                    300,
+                )),
+            ),
+            Record::from_rdata(zone.clone(), 3600, RData::NS(NS(name("ns1.example.com.")))),
+            Record::from_rdata(
+                name("www.example.com."),
+                300,
+                RData::A(A::new(192, 0, 2, 1)),
+            ),
+        ])
+        .await;
+        let dir = temp_dir();
+        let path = dir.path().join("axfr.ndjson");
+        let output = Output::new(Writer::new(
+            OutputFormat::Ndjson,
+            ResultWriter::new(true, Some(&path), false).await.unwrap(),
+        ));
+
+        zone_transfer(
+            server.addr(),
+            &Fqdn::from_str("example.com").unwrap(),
+            None,
+            &output,
+        )
+        .await
+        .unwrap();
+        output.finish().await.unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let summary: Vec<_> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["fqdn"].as_str().unwrap(),
+                    record["record-type"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("example.com", "SOA"),
+                ("example.com", "NS"),
+                ("www.example.com", "A")
+            ]
+        );
+        assert_eq!(records[2]["values"], serde_json::json!(["192.0.2.1"]));
+    }
+}
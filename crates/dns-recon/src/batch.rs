@@ -0,0 +1,317 @@
+use std::{net::IpAddr, num::NonZeroUsize, time::Duration};
+
+use anyhow::{anyhow, Context};
+use grimoire::records::DnsReconRecord;
+use itertools::{EitherOrBoth, Itertools};
+use sqlx::{query, types::ipnetwork::IpNetwork, PgPool};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::debug;
+
+/// Partial batches are flushed at least this often, so that results are not held back when
+/// lookups complete slowly
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Accumulates DNS recon results in a background task and stores them in the recon database in
+/// batches
+#[derive(Debug)]
+pub struct DnsReconBatcher {
+    sender: mpsc::Sender<DnsReconRecord>,
+    task: JoinHandle<anyhow::Result<()>>,
+}
+
+impl DnsReconBatcher {
+    pub fn spawn(pg_pool: PgPool, batch_size: NonZeroUsize) -> Self {
+        let (sender, receiver) = mpsc::channel(batch_size.get());
+        let task = tokio::spawn(run(pg_pool, receiver, batch_size.get()));
+
+        DnsReconBatcher { sender, task }
+    }
+
+    /// Queues the record of a lookup. Waits if the background task is lagging behind
+    pub async fn submit(&self, record: DnsReconRecord) -> anyhow::Result<()> {
+        self.sender
+            .send(record)
+            .await
+            .map_err(|_| anyhow!("the recon database writer has stopped"))
+    }
+
+    /// Flushes all queued results and waits for the background task to complete
+    pub async fn finish(self) -> anyhow::Result<()> {
+        drop(self.sender);
+        self.task.await?
+    }
+}
+
+async fn run(
+    pg_pool: PgPool,
+    mut receiver: mpsc::Receiver<DnsReconRecord>,
+    batch_size: usize,
+) -> anyhow::Result<()> {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            result = receiver.recv() => match result {
+                Some(result) => {
+                    batch.push(result);
+                    if batch.len() >= batch_size {
+                        submit_dns_recon_results(&pg_pool, &batch).await?;
+                        batch.clear();
+                    }
+                }
+                None => break,
+            },
+            _ = flush_interval.tick() => {
+                if !batch.is_empty() {
+                    submit_dns_recon_results(&pg_pool, &batch).await?;
+                    batch.clear();
+                }
+            }
+        }
+    }
+
+    if !batch.is_empty() {
+        submit_dns_recon_results(&pg_pool, &batch).await?;
+    }
+
+    Ok(())
+}
+
+/// Stores a batch of results using a single statement. Names that occur multiple times within the
+/// batch or that are already known have their IP addresses merged. The DNSSEC validation status is
+/// only overwritten if the new result has one. The same applies to the name server and the query
+/// duration, which are not overwritten by results taken from the cache file either. The autonomous
+/// systems are merged by IP address. The addresses of the system resolver are stored in the rows
+/// alongside the addresses of the DNS server and replace known ones, if any
+#[tracing::instrument(skip_all, fields(batch_size = batch.len()))]
+async fn submit_dns_recon_results(
+    pg_pool: &PgPool,
+    batch: &[DnsReconRecord],
+) -> anyhow::Result<()> {
+    debug!("Flushing a batch of DNS recon results");
+
+    let mut fqdns = Vec::new();
+    let mut ip_networks = Vec::new();
+    let mut domains = Vec::new();
+    let mut dnssec_statuses = Vec::new();
+    let mut name_servers = Vec::new();
+    let mut query_durations = Vec::new();
+    let mut asns = Vec::new();
+    let mut as_orgs = Vec::new();
+    let mut system_ips = Vec::new();
+    let mut divergences = Vec::new();
+    for record in batch {
+        // Each row holds one address of the DNS server and one of the system resolver, if any are
+        // left. Names without any addresses still take up a row
+        let mut rows: Vec<(Option<&IpAddr>, Option<&IpAddr>)> = record
+            .ips
+            .iter()
+            .zip_longest(record.system_ips.iter().flatten())
+            .map(|pair| match pair {
+                EitherOrBoth::Both(ip, system_ip) => (Some(ip), Some(system_ip)),
+                EitherOrBoth::Left(ip) => (Some(ip), None),
+                EitherOrBoth::Right(system_ip) => (None, Some(system_ip)),
+            })
+            .collect();
+        if rows.is_empty() {
+            rows.push((None, None));
+        }
+        for (ip, system_ip) in rows {
+            fqdns.push(record.fqdn.clone());
+            // Host addresses carry the full prefix, i.e. /32 for IPv4 and /128 for IPv6
+            ip_networks.push(ip.map(|ip| IpNetwork::from(*ip)));
+            domains.push(record.domain.clone());
+            dnssec_statuses.push(record.dnssec.clone());
+            name_servers.push(record.name_server.clone());
+            query_durations.push(record.query_ms);
+            let asn_info = ip.and_then(|ip| record.asn_info.as_ref()?.get(ip));
+            asns.push(asn_info.map(|asn_info| i64::from(asn_info.asn)));
+            as_orgs.push(asn_info.and_then(|asn_info| asn_info.org.clone()));
+            system_ips.push(system_ip.map(|system_ip| IpNetwork::from(*system_ip)));
+            divergences.push(record.divergent);
+        }
+    }
+
+    query!(
+        r#"
+        INSERT INTO "dns-recon" (fqdn, ips, domain, dnssec, "name-server", "query-ms", "asn-info", "system-ips", divergent)
+        SELECT fqdn, ARRAY_REMOVE(ARRAY_AGG(DISTINCT ip), NULL), domain, MIN(dnssec), MIN(name_server), MAX(query_ms),
+            jsonb_object_agg(host(ip), jsonb_build_object('asn', asn, 'org', org)) FILTER (WHERE asn IS NOT NULL),
+            CASE WHEN BOOL_OR(divergent) IS NOT NULL THEN ARRAY_REMOVE(ARRAY_AGG(DISTINCT system_ip), NULL) END, BOOL_OR(divergent)
+        FROM UNNEST($1::varchar[], $2::inet[], $3::varchar[], $4::varchar[], $5::varchar[], $6::int4[], $7::int8[], $8::text[], $9::inet[], $10::bool[]) AS batch(fqdn, ip, domain, dnssec, name_server, query_ms, asn, org, system_ip, divergent)
+        GROUP BY fqdn, domain
+        ON CONFLICT ON CONSTRAINT "dns-recon_pkey" DO
+        UPDATE SET ips = (SELECT ARRAY(SELECT DISTINCT UNNEST("dns-recon".ips || EXCLUDED.ips))),
+            dnssec = COALESCE(EXCLUDED.dnssec, "dns-recon".dnssec),
+            "name-server" = COALESCE(EXCLUDED."name-server", "dns-recon"."name-server"),
+            "query-ms" = COALESCE(EXCLUDED."query-ms", "dns-recon"."query-ms"),
+            "asn-info" = COALESCE("dns-recon"."asn-info" || EXCLUDED."asn-info", EXCLUDED."asn-info", "dns-recon"."asn-info"),
+            "system-ips" = COALESCE(EXCLUDED."system-ips", "dns-recon"."system-ips"),
+            divergent = COALESCE(EXCLUDED.divergent, "dns-recon".divergent),
+            updated_at = now()
+        "#,
+        &fqdns,
+        &ip_networks as &[Option<IpNetwork>],
+        &domains,
+        &dnssec_statuses as &[Option<String>],
+        &name_servers as &[Option<String>],
+        &query_durations as &[Option<i32>],
+        &asns as &[Option<i64>],
+        &as_orgs as &[Option<String>],
+        &system_ips as &[Option<IpNetwork>],
+        &divergences as &[Option<bool>],
+    )
+    .execute(pg_pool)
+    .await
+    .context("Relating to a batch of DNS recon results")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire::Fqdn;
+    use sqlx::{query_as, query_scalar};
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn batched_results_have_their_addresses_merged(pg_pool: PgPool) {
+        let batcher = DnsReconBatcher::spawn(pg_pool.clone(), NonZeroUsize::new(7).unwrap());
+        // Each name is resolved twice, such that some of its addresses are merged within a batch
+        // and others with a row stored by an earlier batch
+        for round in 0..2u8 {
+            for i in 0..50u8 {
+                let fqdn = Fqdn::from_str(&format!("host{i}.example.com")).unwrap();
+                let ips = vec![
+                    IpAddr::from([192, 0, 2, i]),
+                    IpAddr::from([198, 51, 100, round]),
+                ];
+                batcher
+                    .submit(DnsReconRecord::new(&fqdn, ips))
+                    .await
+                    .unwrap();
+            }
+        }
+        batcher.finish().await.unwrap();
+
+        let rows: Vec<(String, String, Vec<IpNetwork>)> =
+            query_as(r#"SELECT fqdn, domain, ips FROM "dns-recon" ORDER BY id"#)
+                .fetch_all(&pg_pool)
+                .await
+                .unwrap();
+        assert_eq!(rows.len(), 50);
+        for (fqdn, domain, ips) in rows {
+            let i: u8 = fqdn
+                .strip_prefix("host")
+                .and_then(|fqdn| fqdn.strip_suffix(".example.com"))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut ips: Vec<_> = ips.into_iter().map(|ip| ip.ip()).collect();
+            ips.sort();
+            assert_eq!(domain, "example.com");
+            assert_eq!(
+                ips,
+                [
+                    IpAddr::from([192, 0, 2, i]),
+                    IpAddr::from([198, 51, 100, 0]),
+                    IpAddr::from([198, 51, 100, 1]),
+                ]
+            );
+        }
+    }
+
+    /// The addresses stored for `fqdn`, sorted
+    async fn stored_ips(pg_pool: &PgPool, fqdn: &str) -> Vec<IpNetwork> {
+        let (mut ips,): (Vec<IpNetwork>,) =
+            query_as(r#"SELECT ips FROM "dns-recon" WHERE fqdn = $1"#)
+                .bind(fqdn)
+                .fetch_one(pg_pool)
+                .await
+                .unwrap();
+        ips.sort();
+        ips
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn ipv4_and_ipv6_addresses_are_merged_as_hosts(pg_pool: PgPool) {
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let first: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        let second: Vec<IpAddr> =
+            vec!["192.0.2.2".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+
+        submit_dns_recon_results(&pg_pool, &[DnsReconRecord::new(&fqdn, first)])
+            .await
+            .unwrap();
+        submit_dns_recon_results(&pg_pool, &[DnsReconRecord::new(&fqdn, second)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored_ips(&pg_pool, "www.example.com").await,
+            [
+                IpNetwork::from_str("192.0.2.1/32").unwrap(),
+                IpNetwork::from_str("192.0.2.2/32").unwrap(),
+                IpNetwork::from_str("2001:db8::1/128").unwrap(),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn addresses_stored_with_a_short_prefix_are_converted(pg_pool: PgPool) {
+        sqlx::query(
+            r#"INSERT INTO "dns-recon" (fqdn, domain, ips)
+            VALUES ('www.example.com', 'example.com', '{192.0.2.1/32, 2001:db8::1/32, 2001:db8::1/128}')"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+
+        sqlx::raw_sql(include_str!(
+            "../../../migrations/20240801093025_store_host_addresses.up.sql"
+        ))
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            stored_ips(&pg_pool, "www.example.com").await,
+            [
+                IpNetwork::from_str("192.0.2.1/32").unwrap(),
+                IpNetwork::from_str("2001:db8::1/128").unwrap(),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn re_inserting_a_name_advances_updated_at(pg_pool: PgPool) {
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let is_updated = || async {
+            query_scalar::<_, bool>(r#"SELECT updated_at > created_at FROM "dns-recon""#)
+                .fetch_one(&pg_pool)
+                .await
+                .unwrap()
+        };
+
+        let batcher = DnsReconBatcher::spawn(pg_pool.clone(), NonZeroUsize::new(1).unwrap());
+        batcher
+            .submit(DnsReconRecord::new(&fqdn, Vec::new()))
+            .await
+            .unwrap();
+        batcher.finish().await.unwrap();
+        assert!(!is_updated().await);
+
+        // Each run stores its results in transactions of its own
+        let batcher = DnsReconBatcher::spawn(pg_pool.clone(), NonZeroUsize::new(1).unwrap());
+        batcher
+            .submit(DnsReconRecord::new(&fqdn, Vec::new()))
+            .await
+            .unwrap();
+        batcher.finish().await.unwrap();
+        assert!(is_updated().await);
+    }
+}
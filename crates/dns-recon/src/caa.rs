@@ -0,0 +1,225 @@
+use std::{fmt::Display, num::ParseIntError, str::FromStr};
+
+use anyhow::Context;
+use grimoire::Fqdn;
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    proto::rr::RecordType,
+    AsyncResolver,
+};
+use serde::Serialize;
+use sqlx::{query, PgPool};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::edns::EdnsConnectionProvider;
+
+#[derive(Debug, Error)]
+pub enum ParseCaaError {
+    #[error("Expected a CAA record of the form 'FLAGS TAG \"VALUE\"'")]
+    Split,
+    #[error(transparent)]
+    Flags(#[from] ParseIntError),
+}
+
+/// A single CAA record, which restricts the certificate authorities allowed to issue certificates
+/// for a name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaaRecord {
+    /// The flags of the record. If the most significant bit is set, a certificate authority that
+    /// does not understand the tag must not issue certificates
+    pub flags: u8,
+    /// The property, e.g. 'issue', 'issuewild' or 'iodef'
+    pub tag: String,
+    /// The value of the property, without the surrounding quotes
+    pub value: String,
+}
+
+impl FromStr for CaaRecord {
+    type Err = ParseCaaError;
+
+    /// Parses the presentation format of a CAA record, e.g. `0 issue "letsencrypt.org"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (flags, rest) = s.trim().split_once(' ').ok_or(ParseCaaError::Split)?;
+        let (tag, value) = rest
+            .trim_start()
+            .split_once(' ')
+            .ok_or(ParseCaaError::Split)?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+
+        Ok(CaaRecord {
+            flags: flags.parse()?,
+            tag: tag.to_ascii_lowercase(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl Display for CaaRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} \"{}\"", self.flags, self.tag, self.value)
+    }
+}
+
+/// Looks up the CAA records of `fqdn`. Names without CAA records yield an empty list, and records
+/// that cannot be parsed are skipped with a warning
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_caa(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+) -> Result<Vec<CaaRecord>, ResolveError> {
+    let lookup = match resolver.lookup(format!("{fqdn}."), RecordType::CAA).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(lookup
+        .record_iter()
+        .filter(|record| record.record_type() == RecordType::CAA)
+        .filter_map(|record| record.data())
+        .filter_map(|data| {
+            let data = data.to_string();
+            data.parse()
+                .map_err(|e| {
+                    warn!(
+                        "Cannot parse the CAA record '{}' of '{}': {}",
+                        data, fqdn, e
+                    )
+                })
+                .ok()
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip(pg_pool, records))]
+pub async fn submit_caa_recon_results(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    records: &[CaaRecord],
+) -> anyhow::Result<()> {
+    for record in records {
+        query!(
+            r#"
+            INSERT INTO "caa-recon" (domain, fqdn, flags, tag, value)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT ON CONSTRAINT "caa-recon_pkey" DO
+            UPDATE SET flags = EXCLUDED.flags
+            "#,
+            fqdn.domain(),
+            fqdn.to_string(),
+            record.flags as i16,
+            record.tag,
+            record.value,
+        )
+        .execute(pg_pool)
+        .await
+        .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{
+            rdata::{A, CAA},
+            Name, RData, Record,
+        },
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    fn caa(value: &str) -> CaaRecord {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn the_fields_of_a_record_are_split() {
+        assert_eq!(
+            caa(r#"0 issue "letsencrypt.org""#),
+            CaaRecord {
+                flags: 0,
+                tag: "issue".to_string(),
+                value: "letsencrypt.org".to_string(),
+            }
+        );
+        assert_eq!(
+            caa(r#"128 ISSUEWILD ";""#),
+            CaaRecord {
+                flags: 128,
+                tag: "issuewild".to_string(),
+                value: ";".to_string(),
+            }
+        );
+        assert_eq!(
+            caa(r#"0 iodef "mailto:security@example.com""#).to_string(),
+            r#"0 iodef "mailto:security@example.com""#
+        );
+        assert!(matches!(
+            CaaRecord::from_str("0 issue"),
+            Err(ParseCaaError::Split)
+        ));
+        assert!(matches!(
+            CaaRecord::from_str(r#"256 issue "letsencrypt.org""#),
+            Err(ParseCaaError::Flags(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn each_record_of_a_response_is_parsed() {
+        let name = Name::from_ascii("www.example.com.").unwrap();
+        let bare = Name::from_ascii("mail.example.com.").unwrap();
+        let issuer = |issuer: &str| Some(Name::from_ascii(issuer).unwrap());
+        let server = StubServer::spawn(vec![
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::CAA(CAA::new_issue(false, issuer("letsencrypt.org"), vec![])),
+            ),
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::CAA(CAA::new_issuewild(true, issuer("sectigo.com"), vec![])),
+            ),
+            Record::from_rdata(bare, 300, RData::A(A::new(192, 0, 2, 1))),
+        ])
+        .await;
+        let resolver = server.resolver(ResolverOpts::default());
+
+        let mut records = lookup_caa(&resolver, &"www.example.com".parse().unwrap())
+            .await
+            .unwrap();
+        records.sort_by(|a, b| a.tag.cmp(&b.tag));
+
+        assert_eq!(
+            records,
+            [
+                CaaRecord {
+                    flags: 0,
+                    tag: "issue".to_string(),
+                    value: "letsencrypt.org".to_string(),
+                },
+                CaaRecord {
+                    flags: 128,
+                    tag: "issuewild".to_string(),
+                    value: "sectigo.com".to_string(),
+                },
+            ]
+        );
+        assert!(lookup_caa(&resolver, &"mail.example.com".parse().unwrap())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use grimoire::{save_atomically, Fqdn};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// The IP addresses of a name along with the time their TTL expires, in seconds since the Unix
+/// epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    ips: Vec<IpAddr>,
+    expires: u64,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, now: u64) -> bool {
+        self.expires > now
+    }
+}
+
+/// Persists the IP addresses of resolved names between runs, so that names whose records are still
+/// within their TTL are not queried again. Only positive results are cached
+#[derive(Debug)]
+pub struct DiskCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl DiskCache {
+    /// Loads the cache file, discarding all expired entries. A missing file yields an empty cache
+    pub async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let mut entries: HashMap<String, CacheEntry> = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| {
+                anyhow::anyhow!("Cannot parse the cache file '{}': {}", path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let now = unix_time(SystemTime::now());
+        entries.retain(|_, entry| entry.is_fresh(now));
+        debug!(
+            "Loaded {} fresh entries from the cache file '{}'",
+            entries.len(),
+            path.display()
+        );
+
+        Ok(DiskCache {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Returns the cached IP addresses of `fqdn`, unless they have expired
+    pub fn get(&self, fqdn: &Fqdn) -> Option<Vec<IpAddr>> {
+        let now = unix_time(SystemTime::now());
+        self.entries
+            .lock()
+            .expect("the cache lock is never poisoned")
+            .get(&fqdn.to_string())
+            .filter(|entry| entry.is_fresh(now))
+            .map(|entry| entry.ips.clone())
+    }
+
+    /// Records the IP addresses of `fqdn`, which remain valid for `ttl`
+    pub fn insert(&self, fqdn: &Fqdn, ips: Vec<IpAddr>, ttl: Duration) {
+        let expires = unix_time(SystemTime::now() + ttl);
+        self.entries
+            .lock()
+            .expect("the cache lock is never poisoned")
+            .insert(fqdn.to_string(), CacheEntry { ips, expires });
+    }
+
+    /// Writes the fresh entries to the cache file, replacing it atomically
+    pub async fn save(&self) -> std::io::Result<()> {
+        let contents = {
+            let now = unix_time(SystemTime::now());
+            let mut entries = self
+                .entries
+                .lock()
+                .expect("the cache lock is never poisoned");
+            entries.retain(|_, entry| entry.is_fresh(now));
+            serde_json::to_vec(&*entries)?
+        };
+
+        debug!("Saving the cache file '{}'", self.path.display());
+        save_atomically(&self.path, contents).await
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn only_fresh_entries_are_kept_between_runs() {
+        let dir = temp_dir();
+        let path = dir.path().join("expiry.json");
+        let fresh: Fqdn = "www.example.com".parse().unwrap();
+        let expired: Fqdn = "mail.example.com".parse().unwrap();
+        let ips: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap()];
+
+        let cache = DiskCache::load(path.clone()).await.unwrap();
+        cache.insert(&fresh, ips.clone(), Duration::from_secs(60));
+        cache.insert(&expired, ips.clone(), Duration::ZERO);
+        assert_eq!(cache.get(&fresh), Some(ips.clone()));
+        assert_eq!(cache.get(&expired), None);
+        cache.save().await.unwrap();
+
+        let cache = DiskCache::load(path.clone()).await.unwrap();
+        assert_eq!(cache.get(&fresh), Some(ips));
+        assert_eq!(cache.get(&expired), None);
+    }
+}
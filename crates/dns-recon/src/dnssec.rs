@@ -0,0 +1,202 @@
+use std::fmt::Display;
+
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    proto::{error::ProtoErrorKind, rr::Record},
+};
+use serde::Serialize;
+
+/// The messages of the errors by which the validating resolver rejects a response whose signatures
+/// do not verify against the DNSKEYs of the zone
+const BOGUS_MESSAGES: &[&str] = &[
+    "validation failed",
+    "Could not validate all DNSKEYs",
+    "self-signed dnskey is invalid",
+];
+
+/// The outcome of validating the DNSSEC signatures of a response. The variants are ordered from
+/// best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnssecStatus {
+    /// The records are signed and the chain of trust validates
+    Secure,
+    /// The response carries no signatures, i.e. the zone is unsigned
+    Insecure,
+    /// Validation could not be completed, e.g. because the response is empty
+    Indeterminate,
+    /// The zone is signed, but the signatures do not validate
+    Bogus,
+}
+
+impl DnssecStatus {
+    /// The status of the records of a successful lookup. The validating resolver drops each record
+    /// set whose signatures do not verify, so any record it returns is secure
+    pub fn from_records<'a>(records: impl IntoIterator<Item = &'a Record>) -> Self {
+        if records.into_iter().next().is_some() {
+            DnssecStatus::Secure
+        } else {
+            DnssecStatus::Indeterminate
+        }
+    }
+
+    /// The status of a failed lookup, if it failed because the response did not validate, rather
+    /// than because the DNS server could not be reached or returned an error
+    pub fn from_error(error: &ResolveError) -> Option<Self> {
+        match error.kind() {
+            ResolveErrorKind::Proto(e) => match e.kind() {
+                ProtoErrorKind::RrsigsNotPresent { .. } => Some(DnssecStatus::Insecure),
+                ProtoErrorKind::Message(message) if BOGUS_MESSAGES.contains(message) => {
+                    Some(DnssecStatus::Bogus)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnssecStatus::Secure => "secure",
+            DnssecStatus::Insecure => "insecure",
+            DnssecStatus::Indeterminate => "indeterminate",
+            DnssecStatus::Bogus => "bogus",
+        }
+    }
+}
+
+impl Display for DnssecStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::{
+            error::ProtoError,
+            rr::{
+                dnssec::{
+                    rdata::{DNSSECRData, DNSKEY, RRSIG},
+                    Algorithm,
+                },
+                rdata::A,
+                Name, RData, RecordType,
+            },
+        },
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    fn name() -> Name {
+        Name::from_ascii("www.example.com.").unwrap()
+    }
+
+    #[test]
+    fn validated_records_are_secure() {
+        let record = Record::from_rdata(name(), 300, RData::A(A::new(192, 0, 2, 1)));
+        assert_eq!(DnssecStatus::from_records([&record]), DnssecStatus::Secure);
+    }
+
+    #[test]
+    fn empty_response_is_indeterminate() {
+        assert_eq!(DnssecStatus::from_records([]), DnssecStatus::Indeterminate);
+    }
+
+    #[test]
+    fn missing_signatures_are_insecure() {
+        let error = ResolveError::from(ProtoError::from(ProtoErrorKind::RrsigsNotPresent {
+            name: name(),
+            record_type: RecordType::A,
+        }));
+        assert_eq!(
+            DnssecStatus::from_error(&error),
+            Some(DnssecStatus::Insecure)
+        );
+    }
+
+    #[test]
+    fn signatures_that_do_not_verify_are_bogus() {
+        let error = ResolveError::from(ProtoError::from(ProtoErrorKind::Message(
+            "validation failed",
+        )));
+        assert_eq!(DnssecStatus::from_error(&error), Some(DnssecStatus::Bogus));
+    }
+
+    #[test]
+    fn other_errors_are_not_validation_failures() {
+        let error = ResolveError::from(ProtoError::from(ProtoErrorKind::Message(
+            "connection refused",
+        )));
+        assert_eq!(DnssecStatus::from_error(&error), None);
+        assert_eq!(
+            DnssecStatus::from_error(&ResolveError::from("timeout")),
+            None
+        );
+    }
+
+    /// An RRSIG record signing the records of `name` and `record_type` with the key of `zone`. The
+    /// signature itself is made up, so it never verifies
+    fn rrsig(name: &Name, record_type: RecordType, zone: &Name) -> Record {
+        let rrsig = RRSIG::new(
+            record_type,
+            Algorithm::ED25519,
+            name.num_labels(),
+            300,
+            u32::MAX,
+            0,
+            1,
+            zone.clone(),
+            vec![0; 64],
+        );
+        Record::from_rdata(name.clone(), 300, RData::DNSSEC(DNSSECRData::RRSIG(rrsig)))
+    }
+
+    /// The status of looking up the addresses of `name` via a validating resolver
+    async fn validate(server: &StubServer, name: Name) -> Option<DnssecStatus> {
+        let mut opts = ResolverOpts::default();
+        opts.validate = true;
+        opts.edns0 = true;
+        let resolver = server.resolver(opts);
+
+        match resolver.ipv4_lookup(name).await {
+            Ok(lookup) => Some(DnssecStatus::from_records(lookup.as_lookup().records())),
+            Err(e) => DnssecStatus::from_error(&e),
+        }
+    }
+
+    /// The stub zone is signed with a key that is not anchored at the root, so its records can at
+    /// best be bogus
+    #[tokio::test]
+    async fn signed_and_unsigned_zones_are_told_apart() {
+        let signed = Name::from_ascii("signed.example.").unwrap();
+        let www_signed = Name::from_ascii("www.signed.example.").unwrap();
+        let www_unsigned = Name::from_ascii("www.unsigned.example.").unwrap();
+        let dnskey = DNSKEY::new(true, true, false, Algorithm::ED25519, vec![0; 32]);
+        let server = StubServer::spawn(vec![
+            Record::from_rdata(
+                signed.clone(),
+                300,
+                RData::DNSSEC(DNSSECRData::DNSKEY(dnskey)),
+            ),
+            rrsig(&signed, RecordType::DNSKEY, &signed),
+            Record::from_rdata(www_signed.clone(), 300, RData::A(A::new(192, 0, 2, 1))),
+            rrsig(&www_signed, RecordType::A, &signed),
+            Record::from_rdata(www_unsigned.clone(), 300, RData::A(A::new(192, 0, 2, 2))),
+        ])
+        .await;
+
+        assert_eq!(
+            validate(&server, www_signed).await,
+            Some(DnssecStatus::Bogus)
+        );
+        assert_eq!(
+            validate(&server, www_unsigned).await,
+            Some(DnssecStatus::Insecure)
+        );
+    }
+}
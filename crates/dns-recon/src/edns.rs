@@ -0,0 +1,128 @@
+use std::{future::Future, pin::Pin};
+
+use hickory_resolver::{
+    config::{NameServerConfig, ResolverOpts},
+    error::ResolveError,
+    name_server::{ConnectionProvider, GenericConnection, GenericConnector},
+    proto::xfer::{DnsHandle, DnsRequest},
+};
+
+use crate::transport::{DnsProxy, ProxyRuntimeProvider};
+
+/// The maximum UDP payload size recommended by the DNS flag day 2020, which avoids IP fragmentation
+/// on virtually all paths
+pub const DEFAULT_EDNS_BUFFER_SIZE: u16 = 1232;
+
+/// Connects to the name servers just like the default Tokio connection provider, but advertises
+/// the given maximum UDP payload size in the EDNS OPT record of every query. The resolver only adds
+/// the OPT record if EDNS is enabled in its options. TCP connections are tunneled through `proxy`,
+/// if given
+#[derive(Clone)]
+pub struct EdnsConnectionProvider {
+    inner: GenericConnector<ProxyRuntimeProvider>,
+    max_payload: u16,
+}
+
+impl EdnsConnectionProvider {
+    pub fn new(max_payload: u16, proxy: Option<DnsProxy>) -> Self {
+        EdnsConnectionProvider {
+            inner: GenericConnector::new(ProxyRuntimeProvider::new(proxy)),
+            max_payload,
+        }
+    }
+}
+
+impl ConnectionProvider for EdnsConnectionProvider {
+    type Conn = EdnsConnection;
+    type FutureConn = Pin<Box<dyn Future<Output = Result<EdnsConnection, ResolveError>> + Send>>;
+    type RuntimeProvider = ProxyRuntimeProvider;
+
+    fn new_connection(
+        &self,
+        config: &NameServerConfig,
+        options: &ResolverOpts,
+    ) -> Self::FutureConn {
+        let connection = self.inner.new_connection(config, options);
+        let max_payload = self.max_payload;
+
+        Box::pin(async move {
+            Ok(EdnsConnection {
+                inner: connection.await?,
+                max_payload,
+            })
+        })
+    }
+}
+
+/// A connection to a name server that overrides the maximum payload size of outgoing queries
+#[derive(Clone)]
+pub struct EdnsConnection {
+    inner: GenericConnection,
+    max_payload: u16,
+}
+
+impl DnsHandle for EdnsConnection {
+    type Response = <GenericConnection as DnsHandle>::Response;
+    type Error = ResolveError;
+
+    fn send<R: Into<DnsRequest> + Unpin + Send + 'static>(&self, request: R) -> Self::Response {
+        let mut request = request.into();
+        if let Some(edns) = request.extensions_mut() {
+            edns.set_max_payload(self.max_payload);
+        }
+
+        self.inner.send(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::proto::rr::{rdata::A, Name, RData, Record};
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    #[tokio::test]
+    async fn the_buffer_size_is_advertised_in_each_query() {
+        let name = Name::from_ascii("www.example.com.").unwrap();
+        let server = StubServer::spawn(vec![Record::from_rdata(
+            name.clone(),
+            300,
+            RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+        )])
+        .await;
+        let mut opts = ResolverOpts::default();
+        opts.edns0 = true;
+        let resolver = server.resolver_with(opts, EdnsConnectionProvider::new(4096, None));
+
+        resolver.ipv4_lookup(name).await.unwrap();
+
+        let queries = server.queries();
+        assert!(!queries.is_empty());
+        for query in queries {
+            assert_eq!(query.extensions().as_ref().unwrap().max_payload(), 4096);
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_carry_no_opt_record_unless_edns_is_enabled() {
+        let name = Name::from_ascii("www.example.com.").unwrap();
+        let server = StubServer::spawn(vec![Record::from_rdata(
+            name.clone(),
+            300,
+            RData::A(A(Ipv4Addr::new(192, 0, 2, 1))),
+        )])
+        .await;
+        let resolver = server.resolver(ResolverOpts::default());
+
+        resolver.ipv4_lookup(name).await.unwrap();
+
+        assert!(server
+            .queries()
+            .iter()
+            .all(|query| query.extensions().is_none()));
+    }
+}
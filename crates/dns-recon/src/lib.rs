@@ -0,0 +1,179 @@
+pub mod authority;
+pub mod axfr;
+pub mod batch;
+pub mod caa;
+pub mod cache;
+pub mod dnssec;
+pub mod edns;
+pub mod mx;
+pub mod output;
+pub mod query_info;
+pub mod split_horizon;
+pub mod srv;
+pub mod stats;
+#[cfg(test)]
+mod stub;
+pub mod transport;
+pub mod txt;
+
+use std::{net::IpAddr, time::Instant};
+
+use grimoire::{asn::AsnLookup, Fqdn};
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    lookup_ip::LookupIp,
+};
+use itertools::Itertools;
+use tracing::{debug, info, warn};
+
+use crate::{
+    batch::DnsReconBatcher,
+    cache::DiskCache,
+    dnssec::DnssecStatus,
+    output::{lookup_record, Output},
+    query_info::QueryInfo,
+    split_horizon::SystemAnswer,
+    stats::Stats,
+};
+
+/// Compares the addresses the system resolver returned for `fqdn`, if it was queried, with the
+/// `ips` returned by the DNS server
+fn compare_system_answer(
+    fqdn: &Fqdn,
+    system_ips: Option<Vec<IpAddr>>,
+    ips: &[IpAddr],
+    stats: &Stats,
+) -> Option<SystemAnswer> {
+    let system = SystemAnswer::new(system_ips?, ips);
+    if system.divergent {
+        info!(
+            "The system resolver returns different addresses for '{}': {}",
+            fqdn,
+            system.ips.iter().join(" ")
+        );
+        stats.record_divergent();
+    }
+
+    Some(system)
+}
+
+/// Outputs and stores the IP addresses resolved for a name, along with their autonomous systems if
+/// an `asn_lookup` is given, and the addresses returned by the system resolver if it was queried.
+/// Returns the addresses
+#[allow(clippy::too_many_arguments)]
+pub async fn process_resolved(
+    batcher: Option<&DnsReconBatcher>,
+    asn_lookup: Option<&AsnLookup>,
+    fqdn: Fqdn,
+    ips: Vec<IpAddr>,
+    dnssec: Option<DnssecStatus>,
+    query_info: Option<QueryInfo>,
+    system_ips: Option<Vec<IpAddr>>,
+    output: &Output,
+    stats: &Stats,
+) -> anyhow::Result<Vec<IpAddr>> {
+    let asns = match asn_lookup {
+        Some(asn_lookup) => Some(asn_lookup.lookup_all(&ips).await),
+        None => None,
+    };
+    let system = compare_system_answer(&fqdn, system_ips, &ips, stats);
+    let record = lookup_record(&fqdn, ips, dnssec, query_info, asns, system);
+    output.write(&record).await?;
+    stats.record_resolved(&record);
+
+    let ips = record.ips.clone();
+    if let Some(batcher) = batcher {
+        batcher.submit(record).await?;
+        stats.record_stored();
+    }
+
+    Ok(ips)
+}
+
+/// Processes the result of looking up `fqdn`. If `dnssec` is set, the validation status of each
+/// response is recorded, and responses that fail validation are recorded as such rather than
+/// treated as errors. Resolved addresses are added to the `cache`, if any. The `query_info` is
+/// recorded along with the result, if given, and so are the `system_ips`. Returns the resolved
+/// addresses, which are empty if the name has none or its response failed validation
+#[tracing::instrument(skip(batcher, asn_lookup, cache, lookup_result, system_ips, output, stats))]
+#[allow(clippy::too_many_arguments)]
+pub async fn process_lookup_result(
+    batcher: Option<&DnsReconBatcher>,
+    asn_lookup: Option<&AsnLookup>,
+    cache: Option<&DiskCache>,
+    fqdn: &Fqdn,
+    lookup_result: Result<LookupIp, ResolveError>,
+    dnssec: bool,
+    query_info: Option<QueryInfo>,
+    system_ips: Option<Vec<IpAddr>>,
+    output: &Output,
+    stats: &Stats,
+) -> anyhow::Result<Vec<IpAddr>> {
+    match lookup_result {
+        Err(e) if dnssec && DnssecStatus::from_error(&e).is_some() => {
+            let status = DnssecStatus::from_error(&e);
+            if status == Some(DnssecStatus::Bogus) {
+                warn!(
+                    "The response for '{}' failed DNSSEC validation: {}",
+                    fqdn, e
+                );
+            } else {
+                debug!("The response for '{}' is not signed: {}", fqdn, e);
+            }
+            let system = compare_system_answer(fqdn, system_ips, &[], stats);
+            let record = lookup_record(fqdn, Vec::new(), status, query_info, None, system);
+            output.write(&record).await?;
+
+            if let Some(batcher) = batcher {
+                batcher.submit(record).await?;
+                stats.record_stored();
+            }
+
+            if status == Some(DnssecStatus::Bogus) {
+                stats.record_validation_failed();
+            }
+            Ok(Vec::new())
+        }
+        Err(e) => match e.kind() {
+            ResolveErrorKind::NoRecordsFound { query, .. } => {
+                let fqdn = Fqdn::from(query.name());
+                let dnssec = dnssec.then_some(DnssecStatus::Indeterminate);
+
+                debug!("Error resolving the FQDN '{}': {}", &fqdn, e);
+                let system = compare_system_answer(&fqdn, system_ips, &[], stats);
+                let record = lookup_record(&fqdn, Vec::new(), dnssec, query_info, None, system);
+                output.write(&record).await?;
+
+                if let Some(batcher) = batcher {
+                    batcher.submit(record).await?;
+                    stats.record_stored();
+                }
+
+                stats.record_no_records();
+                Ok(Vec::new())
+            }
+            _ => {
+                stats.record_failed();
+                Err(anyhow::Error::from(e))
+            }
+        },
+        Ok(lookup_ip) => {
+            let fqdn = Fqdn::from(lookup_ip.query().name());
+            let ips: Vec<_> = lookup_ip.iter().collect();
+            let dnssec =
+                dnssec.then(|| DnssecStatus::from_records(lookup_ip.as_lookup().record_iter()));
+
+            if let Some(cache) = cache {
+                let ttl = lookup_ip
+                    .valid_until()
+                    .saturating_duration_since(Instant::now());
+                cache.insert(&fqdn, ips.clone(), ttl);
+            }
+
+            process_resolved(
+                batcher, asn_lookup, fqdn, ips, dnssec, query_info, system_ips, output, stats,
+            )
+            .await
+        }
+    }
+}
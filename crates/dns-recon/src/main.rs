@@ -1,60 +1,198 @@
-use anyhow::{anyhow, Context};
-use itertools::Itertools;
-use sqlx::{query, query_scalar, types::ipnetwork::IpNetwork, PgPool};
+use sqlx::{query_scalar, PgPool};
 use std::{
-    net::{IpAddr, SocketAddr},
+    net::SocketAddr,
+    num::NonZeroUsize,
+    path::PathBuf,
     pin::pin,
-    str::FromStr,
+    process::ExitCode,
     sync::Arc,
+    time::{Duration, Instant},
 };
-use tokio::io::stdin;
 
 use clap::Parser;
-use futures::{FutureExt, StreamExt};
-use grimoire::{create_recon_db_pool, Fqdn, IpAddrOrFqdn};
-use hickory_resolver::{
-    config::{Protocol, ResolverConfig, ResolverOpts},
-    error::ResolveErrorKind,
-    AsyncResolver,
+use futures::{stream::BoxStream, StreamExt};
+use grimoire::{
+    build_info,
+    cidr::reverse_lookup,
+    cli::{
+        parse_with_config, AsnArgs, CidrArgs, ConfigArgs, LogArgs, OutputArgs, ReconDbArgs,
+        RejectArgs, RuntimeArgs, SeedArgs, ShuffleArgs,
+    },
+    exit::{report, RunError, EXIT_STATUS_HELP},
+    open_input,
+    resolver::dns_server_addr,
+    shutdown_on_ctrl_c, Fqdn, FqdnParseOptions, IpAddrOrFqdn, ParseReport,
+};
+use hickory_resolver::{config::ResolverOpts, AsyncResolver, TokioAsyncResolver};
+use metrics::{describe_counter, describe_gauge, gauge};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tokio::io::AsyncRead;
+use tokio_util::{
+    codec::{FramedRead, LinesCodec, LinesCodecError},
+    sync::CancellationToken,
 };
-use tokio_util::codec::{FramedRead, LinesCodec};
 use tracing::{debug, info, warn};
-use tracing_subscriber::EnvFilter;
+
+use dns_recon::{
+    authority, axfr,
+    batch::DnsReconBatcher,
+    caa,
+    cache::DiskCache,
+    edns::{EdnsConnectionProvider, DEFAULT_EDNS_BUFFER_SIZE},
+    mx,
+    output::Output,
+    process_lookup_result, process_resolved,
+    query_info::QueryInfo,
+    split_horizon::lookup_system,
+    srv,
+    stats::Stats,
+    transport::{DnsProxy, Transport},
+    txt,
+};
 
 /// Performs mass DNS resolution using the selected DNS server
 #[derive(Debug, Parser)]
-#[command(version, name = "dns-recon", about, long_about = None)]
+#[command(version, long_version = build_info(), name = "dns-recon", about, long_about = None, after_help = EXIT_STATUS_HELP)]
 struct Args {
-    /// The IPv4 or IPv6 address or the host name of the recon database service
-    #[arg(long, default_value = "localhost", env = "RECON_DB_HOST")]
-    recon_db_host: String,
-    /// The username used for authenticating with the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_USERNAME")]
-    recon_db_username: String,
-    /// The password used for authenticating with the recon database service
-    #[arg(long, env = "RECON_DB_PASSWORD")]
-    recon_db_password: Option<String>,
-    /// The database to connect to when using the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_DATABASE")]
-    recon_db_database: String,
-    /// If enabled, store the results in the recon database
-    #[arg(short, long)]
-    enable_db_storage: bool,
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
     /// If enabled, run queries again even if the result is known. Ignored when the recon database
     /// integration is disabled
     #[arg(long)]
     query_known_fqdns: bool,
-    /// The port used by the DNS resolver to connect to the DNS server
-    #[arg(short = 'p', long, env = "DNS_PORT", default_value_t = 53)]
-    dns_port: u16,
-    /// Disable output to stdout
-    #[arg(short, long)]
-    quiet: bool,
+    /// The port used by the DNS resolver to connect to the DNS server. Defaults to 53, or to 443
+    /// for DNS over HTTPS
+    #[arg(short = 'p', long, env = "DNS_PORT")]
+    dns_port: Option<u16>,
+    /// The protocol used to send queries to the DNS server. The certificate of a DNS over HTTPS
+    /// server must be valid for the DNS server argument
+    #[arg(long, value_enum, default_value_t = Transport::Udp)]
+    transport: Transport,
+    /// Tunnel the connections to the DNS server through this SOCKS5 or HTTP proxy, e.g.
+    /// 'socks5://127.0.0.1:1080'. Requires the TCP or DoH transport, since UDP cannot be tunneled.
+    /// The queries of '--also-system-resolver' and the ASN lookups are not tunneled
+    #[arg(long, env = "DNS_PROXY", value_name = "URL", conflicts_with = "axfr")]
+    proxy: Option<DnsProxy>,
+    /// Enable the EDNS(0) extension, which allows responses larger than 512 bytes over UDP
+    #[arg(long)]
+    enable_edns: bool,
+    /// The maximum UDP payload size in bytes advertised when EDNS(0) is enabled
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = DEFAULT_EDNS_BUFFER_SIZE,
+        value_parser = clap::value_parser!(u16).range(512..)
+    )]
+    edns_buffer_size: u16,
+    /// The time to wait for a response to each attempt of a query, overriding the default of 5
+    /// seconds. Truncated responses via UDP are not retried via TCP, so an unanswered query fails
+    /// after this timeout times one more than '--dns-attempts'. Since the IPv6 addresses are only
+    /// queried once the IPv4 query failed, a lookup may take twice as long
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    dns_timeout_secs: Option<u64>,
+    /// The number of times a query is sent again after timing out before it fails, overriding the
+    /// default of 2
+    #[arg(long, value_name = "N")]
+    dns_attempts: Option<NonZeroUsize>,
+    /// Validate the DNSSEC signatures of all responses and record whether each name is secure,
+    /// insecure or bogus. Implies EDNS(0)
+    #[arg(long)]
+    dnssec: bool,
+    /// The maximum number of DNS lookups that may be in flight at the same time
+    #[arg(short, long, env = "DNS_CONCURRENCY", default_value = "50")]
+    concurrency: NonZeroUsize,
+    /// The maximum number of results stored in the recon database with a single statement
+    #[arg(long, default_value = "100")]
+    batch_size: NonZeroUsize,
+    /// Read the FQDNs from this file instead of Stdin. Use '-' to read from Stdin explicitly
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+    #[command(flatten)]
+    cidr: CidrArgs,
+    #[command(flatten)]
+    rejects: RejectArgs,
+    /// Accept names whose leftmost labels start with an underscore, such as '_dmarc.example.com'
+    /// or '_sip._tcp.example.com'
+    #[arg(long)]
+    allow_service_labels: bool,
+    /// Stop reading the input after this many lines have been parsed as FQDNs
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    #[command(flatten)]
+    output: OutputArgs,
+    /// Only parse the input and report how many lines are valid FQDNs, without sending any
+    /// queries or accessing the recon database
+    #[arg(long)]
+    dry_run: bool,
+    /// Serve Prometheus metrics on this address, e.g. '127.0.0.1:9100'
+    #[arg(long, value_name = "HOST:PORT")]
+    metrics_addr: Option<SocketAddr>,
+    /// The maximum number of responses held in the in-memory cache of the resolver
+    #[arg(long, value_name = "N")]
+    cache_size: Option<usize>,
+    /// Persist the IP addresses of resolved names in this file, and skip looking up names whose
+    /// cached addresses are still within their TTL. Cached names carry no DNSSEC validation status
+    #[arg(long, value_name = "PATH")]
+    cache_file: Option<PathBuf>,
+    /// Record the name server that answered each lookup and how long the lookup took, in the
+    /// recon database and the structured output formats. Names taken from the cache file are
+    /// recorded without a name server and with a duration of zero
+    #[arg(long)]
+    record_query_info: bool,
+    #[command(flatten)]
+    asn: AsnArgs,
+    /// Additionally resolve each name via the system resolver and record its addresses along with
+    /// those returned by the DNS server, flagging names whose addresses differ. This detects
+    /// split-horizon DNS, where the DNS server of the target answers differently than public ones
+    #[arg(long)]
+    also_system_resolver: bool,
+    #[command(flatten)]
+    shuffle: ShuffleArgs,
+    #[command(flatten)]
+    seed: SeedArgs,
+    /// Additionally look up the CAA records of each name and store them in a dedicated table
+    #[arg(long)]
+    caa: bool,
+    /// Additionally look up the SRV records of each name and store them in a dedicated table. The
+    /// names usually start with service labels, see '--allow-service-labels'
+    #[arg(long)]
+    srv: bool,
+    /// Additionally look up the MX records of each name and store them in a dedicated table
+    #[arg(long)]
+    mx: bool,
+    /// Additionally look up the TXT records of each name and store them in a dedicated table
+    #[arg(long)]
+    txt: bool,
+    /// Additionally look up the NS and SOA records of each name and store them in dedicated tables
+    #[arg(long)]
+    authority: bool,
+    /// Resolve the IP addresses of the name servers found with '--authority'
+    #[arg(long, requires = "authority")]
+    resolve_ns: bool,
+    /// If set, attempt a zone transfer (AXFR) of this domain from the DNS server instead of
+    /// resolving names from Stdin
+    #[arg(long, value_name = "DOMAIN")]
+    axfr: Option<Fqdn>,
     /// The IP address or fully qualified domain name of the DNS server
     #[arg(env = "DNS_SERVER")]
     dns_server: IpAddrOrFqdn,
 }
 
+impl Args {
+    fn parse_opts(&self) -> FqdnParseOptions {
+        FqdnParseOptions {
+            service_labels: self.allow_service_labels,
+            ..FqdnParseOptions::default()
+        }
+    }
+}
+
 #[tracing::instrument(skip(pg_pool))]
 async fn is_fqdn_in_dns_recon_db(pg_pool: &PgPool, fqdn: &Fqdn) -> bool {
     query_scalar!(
@@ -67,153 +205,769 @@ async fn is_fqdn_in_dns_recon_db(pg_pool: &PgPool, fqdn: &Fqdn) -> bool {
     .unwrap_or(false)
 }
 
-#[tracing::instrument(skip(pg_pool, ips))]
-async fn submit_dns_recon_results(
-    pg_pool: &PgPool,
-    fqdn: &Fqdn,
-    ips: &[IpAddr],
-) -> anyhow::Result<()> {
-    let mut ip_networks = Vec::new();
-    for ip in ips {
-        ip_networks.push(IpNetwork::new(*ip, 32)?);
-    }
-
-    query!(
-        r#"
-        INSERT INTO "dns-recon" (id, fqdn, ips, domain) 
-        VALUES (DEFAULT, $1, $2, $3)
-        ON CONFLICT ON CONSTRAINT "dns-recon_pkey" DO 
-        UPDATE SET ips = (SELECT ARRAY(SELECT DISTINCT UNNEST("dns-recon".ips || EXCLUDED.ips)))
-        "#,
-        fqdn.to_string(),
-        &ip_networks,
-        fqdn.domain(),
-    )
-    .execute(pg_pool)
-    .await
-    .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
-
-    Ok(())
-}
-
-#[tracing::instrument(skip(pg_pool, query_known_results))]
+#[tracing::instrument(skip(pg_pool, query_known_results, stats))]
 async fn skip_known_fqdn(
     pg_pool: Option<Arc<PgPool>>,
     fqdn: Arc<Fqdn>,
     query_known_results: bool,
+    stats: &Stats,
 ) -> bool {
     if let Some(pg_pool) = pg_pool {
-        return query_known_results || !is_fqdn_in_dns_recon_db(&pg_pool, &fqdn).await;
+        let keep = query_known_results || !is_fqdn_in_dns_recon_db(&pg_pool, &fqdn).await;
+        if !keep {
+            stats.record_skipped_known();
+        }
+        return keep;
     }
     true
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(std::io::stderr)
-        .init();
+/// Serves the Prometheus metrics on the given address
+fn install_metrics_exporter(addr: SocketAddr) -> anyhow::Result<()> {
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .install()?;
+
+    describe_counter!(
+        "dns_recon_processed_total",
+        "The number of names that were looked up"
+    );
+    describe_counter!(
+        "dns_recon_succeeded_total",
+        "The number of lookups that resolved, or found that the name has no records"
+    );
+    describe_counter!(
+        "dns_recon_failed_total",
+        "The number of lookups that failed"
+    );
+    describe_counter!(
+        "dns_recon_validation_failed_total",
+        "The number of lookups whose DNSSEC signatures did not validate"
+    );
+    describe_counter!(
+        "dns_recon_cached_total",
+        "The number of names resolved from the cache file instead of the DNS server"
+    );
+    describe_counter!(
+        "dns_recon_skipped_known_total",
+        "The number of names skipped because they are already in the recon database"
+    );
+    describe_gauge!(
+        "dns_recon_in_flight",
+        "The number of lookups currently in flight"
+    );
+
+    Ok(())
+}
+
+/// Parses the input just like a regular run, counting the valid and rejected lines
+async fn dry_run(input: impl AsyncRead + Unpin, parse_opts: FqdnParseOptions) -> ParseReport {
+    let mut report = ParseReport::default();
+    let mut lines = FramedRead::new(input, LinesCodec::new());
+    while let Some(line_result) = lines.next().await {
+        match line_result {
+            Ok(line) => match Fqdn::parse_with(&line, parse_opts) {
+                Ok(_) => report.record_valid(),
+                Err(e) => report.record_rejected(e),
+            },
+            Err(e) => report.record_rejected(e),
+        }
+    }
+
+    report
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+    )
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    run_until(args, shutdown_on_ctrl_c()).await
+}
+
+/// Resolves the names of the input until it is exhausted or `shutdown` is cancelled, in which case
+/// the lookups in flight are completed
+async fn run_until(args: Args, shutdown: CancellationToken) -> anyhow::Result<()> {
+    args.log.init();
+
+    if args.dry_run {
+        let input = open_input(args.input.as_deref()).await?;
+        println!("{}", dry_run(input, args.parse_opts()).await);
+
+        return Ok(());
+    }
 
-    debug!("Parsing command line arguments");
-    let args = Args::parse();
+    let hosts = args.cidr.expand()?;
 
-    let recon_pg_pool = if args.enable_db_storage {
+    if let Some(metrics_addr) = args.metrics_addr {
+        debug!("Serving Prometheus metrics on {metrics_addr}");
+        install_metrics_exporter(metrics_addr)?;
+    }
+
+    let recon_pg_pool = if args.recon_db.enable_db_storage {
         debug!("Establishing a connection to the recon database");
-        Some(Arc::new(
-            create_recon_db_pool(
-                &args.recon_db_host,
-                &args.recon_db_username,
-                args.recon_db_password.as_deref(),
-                &args.recon_db_database,
-            )
-            .await?,
-        ))
+        Some(Arc::new(args.recon_db.connect().await?))
     } else {
         None
     };
 
-    let dns_server = match &args.dns_server {
-        IpAddrOrFqdn::IpAddr(dns_addr) => *dns_addr,
-        IpAddrOrFqdn::Fqdn(dns_fqdn) => {
-            debug!("Resolving the DNS server IP address");
-            let resolver = AsyncResolver::tokio_from_system_conf()?;
-            resolver
-                .lookup_ip(format!("{}.", &dns_fqdn))
-                .await?
-                .iter()
-                .next()
-                .ok_or_else(|| anyhow!("no IP address found for {}", &dns_fqdn))?
-        }
-    };
+    args.transport
+        .check_proxy(args.proxy.as_ref())
+        .map_err(|e| RunError::Input(e.into()))?;
+
+    let dns_server = dns_server_addr(&args.dns_server).await?;
+    let dns_port = args.dns_port.unwrap_or(args.transport.default_port());
+
+    let output = &Output::new(args.output.writer().await?);
+
+    if let Some(zone) = &args.axfr {
+        axfr::zone_transfer(
+            SocketAddr::new(dns_server, dns_port),
+            zone,
+            recon_pg_pool.as_deref(),
+            output,
+        )
+        .await?;
+        output.finish().await?;
+
+        return Ok(());
+    }
 
     debug!("Creating the resolver configuration");
-    let mut resolver_config = ResolverConfig::new();
-    resolver_config.add_name_server(hickory_resolver::config::NameServerConfig {
-        socket_addr: SocketAddr::new(dns_server, args.dns_port),
-        protocol: Protocol::Udp,
-        tls_dns_name: None,
-        trust_negative_responses: false,
-        bind_addr: None,
-    });
+    let resolver_config = args.transport.resolver_config(
+        SocketAddr::new(dns_server, dns_port),
+        &args.dns_server.to_string(),
+    );
 
     debug!("Creating the resolver");
-    let resolver = AsyncResolver::tokio(resolver_config, ResolverOpts::default());
+    let mut resolver_opts = ResolverOpts::default();
+    resolver_opts.edns0 = args.enable_edns || args.dnssec;
+    resolver_opts.validate = args.dnssec;
+    if let Some(timeout_secs) = args.dns_timeout_secs {
+        resolver_opts.timeout = Duration::from_secs(timeout_secs);
+    }
+    if let Some(attempts) = args.dns_attempts {
+        resolver_opts.attempts = attempts.get();
+    }
+    if let Some(cache_size) = args.cache_size {
+        resolver_opts.cache_size = cache_size;
+    }
+    let resolver = Arc::new(AsyncResolver::new(
+        resolver_config,
+        resolver_opts,
+        EdnsConnectionProvider::new(args.edns_buffer_size, args.proxy.clone()),
+    ));
 
-    debug!("Creating a stream from Stdin, decoded as lines, and parsed as FQDNs");
-    info!("Lines that don't parse as FQDNs are silently ignored");
+    let lines: BoxStream<'static, Result<String, LinesCodecError>> = match hosts {
+        Some(hosts) => {
+            info!("Looking up the names of the network hosts instead of reading the input");
+            reverse_lookup(resolver.clone(), hosts, args.concurrency.get())
+                .map(|(fqdn, _)| Ok(fqdn.to_string()))
+                .boxed()
+        }
+        None => {
+            let input = open_input(args.input.as_deref()).await?;
+            FramedRead::new(input, LinesCodec::new()).boxed()
+        }
+    };
+
+    debug!("Creating a stream from the input, decoded as lines, and parsed as FQDNs");
+    if !args.rejects.strict_input {
+        info!("Lines that don't parse as FQDNs are ignored");
+    }
+    let rejects = &args.rejects.rejects().await?;
     let query_known_fqdns = args.query_known_fqdns;
-    let mut data_stream = pin!(FramedRead::new(stdin(), LinesCodec::new())
-        .filter_map(|line_result| async move { line_result.map_err(|e| warn!("{e}")).ok() })
-        .filter_map(|line| async move {
-            Fqdn::from_str(&line)
-                .map(Arc::new)
-                .map_err(|e| warn!("{e}"))
-                .ok()
-        })
-        .filter(|fqdn| skip_known_fqdn(recon_pg_pool.clone(), fqdn.clone(), query_known_fqdns))
-        .flat_map_unordered(None, |fqdn| Box::pin(
-            resolver.lookup_ip(format!("{}.", fqdn)).into_stream()
-        ))
-        .flat_map_unordered(None, |lookup_result| Box::pin(
-            async {
-                match lookup_result {
-                    Err(e) => match e.kind() {
-                        ResolveErrorKind::NoRecordsFound { query, .. } => {
-                            let fqdn = Fqdn::from(query.name());
-
-                            debug!("Error resolving the FQDN '{}': {}", &fqdn, e);
-                            if let Some(recon_pg_pool) = recon_pg_pool.clone() {
-                                submit_dns_recon_results(&recon_pg_pool, &fqdn, &[]).await?;
+    let parse_opts = args.parse_opts();
+    let dnssec = args.dnssec;
+    let name_server = SocketAddr::new(dns_server, dns_port);
+    let record_query_info = args.record_query_info;
+    let asn_lookup = args.asn.lookup()?;
+    let asn_lookup = asn_lookup.as_ref();
+    let system_resolver = if args.also_system_resolver {
+        debug!("Creating the system resolver");
+        Some(TokioAsyncResolver::tokio_from_system_conf()?)
+    } else {
+        None
+    };
+    let system_resolver = system_resolver.as_ref();
+    let lookup_caa = args.caa;
+    let lookup_srv = args.srv;
+    let lookup_mx = args.mx;
+    let lookup_txt = args.txt;
+    let lookup_authority = args.authority;
+    let resolve_ns = args.resolve_ns;
+    let cache = match args.cache_file {
+        Some(path) => Some(DiskCache::load(path).await?),
+        None => None,
+    };
+    let cache = cache.as_ref();
+    let resolver = &resolver;
+    let recon_pg_pool = &recon_pg_pool;
+    let batcher = recon_pg_pool
+        .as_deref()
+        .map(|pg_pool| DnsReconBatcher::spawn(pg_pool.clone(), args.batch_size));
+    let stats = &Stats::default();
+    {
+        let batcher = batcher.as_ref();
+        let fqdns = lines
+            .take_until(shutdown.cancelled())
+            .take_until(rejects.aborted())
+            .enumerate()
+            .filter_map(|(index, line_result)| async move {
+                let (line, reason) = match line_result {
+                    Ok(line) => match Fqdn::parse_with(&line, parse_opts) {
+                        Ok(fqdn) => return Some(Arc::new(fqdn)),
+                        Err(e) => (Some(line), e.to_string()),
+                    },
+                    Err(e) => (None, e.to_string()),
+                };
+                rejects.reject(index + 1, line.as_deref(), reason).await;
+                None
+            })
+            .take(args.limit.unwrap_or(usize::MAX));
+        // Randomizing reads ahead, so names that are held back are dropped on shutdown as well
+        let mut data_stream = pin!(args
+            .shuffle
+            .apply(fqdns, args.seed.rng())
+            .take_until(shutdown.cancelled())
+            .filter(|fqdn| skip_known_fqdn(
+                recon_pg_pool.clone(),
+                fqdn.clone(),
+                query_known_fqdns,
+                stats
+            ))
+            .map(|fqdn| async move {
+                let system_ips = match system_resolver {
+                    Some(system_resolver) => Some(lookup_system(system_resolver, &fqdn).await),
+                    None => None,
+                };
+                if let Some(ips) = cache.and_then(|cache| cache.get(&fqdn)) {
+                    debug!("Using the cached IP addresses of '{}'", &fqdn);
+                    stats.record_cached();
+                    let query_info = record_query_info.then(QueryInfo::cached);
+                    process_resolved(
+                        batcher,
+                        asn_lookup,
+                        (*fqdn).clone(),
+                        ips,
+                        None,
+                        query_info,
+                        system_ips,
+                        output,
+                        stats,
+                    )
+                    .await?;
+                } else {
+                    gauge!("dns_recon_in_flight").increment(1.0);
+                    let started = Instant::now();
+                    let lookup_result = resolver.lookup_ip(format!("{}.", fqdn)).await;
+                    let query_info = record_query_info
+                        .then(|| QueryInfo::answered(name_server, started.elapsed()));
+                    gauge!("dns_recon_in_flight").decrement(1.0);
+                    process_lookup_result(
+                        batcher,
+                        asn_lookup,
+                        cache,
+                        &fqdn,
+                        lookup_result,
+                        dnssec,
+                        query_info,
+                        system_ips,
+                        output,
+                        stats,
+                    )
+                    .await?;
+                }
+
+                if lookup_caa {
+                    match caa::lookup_caa(resolver, &fqdn).await {
+                        Ok(records) => {
+                            output.write_caa(&fqdn, &records).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                caa::submit_caa_recon_results(pg_pool, &fqdn, &records).await?;
                             }
+                        }
+                        Err(e) => warn!("Error looking up the CAA records of '{}': {}", &fqdn, e),
+                    }
+                }
 
-                            Ok(())
+                if lookup_srv {
+                    match srv::lookup_srv(resolver, &fqdn).await {
+                        Ok(records) => {
+                            output.write_srv(&fqdn, &records).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                srv::submit_srv_recon_results(pg_pool, &fqdn, &records).await?;
+                            }
                         }
-                        _ => Err(anyhow::Error::from(e)),
-                    },
-                    Ok(lookup_ip) => {
-                        let fqdn = Fqdn::from(lookup_ip.query().name());
-                        let ips: Vec<_> = lookup_ip.iter().collect();
+                        Err(e) => warn!("Error looking up the SRV records of '{}': {}", &fqdn, e),
+                    }
+                }
 
-                        if !args.quiet {
-                            println!("{} {}", &fqdn, ips.iter().join(" "));
+                if lookup_mx {
+                    match mx::lookup_mx(resolver, &fqdn).await {
+                        Ok(records) => {
+                            output.write_mx(&fqdn, &records).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                mx::submit_mx_recon_results(pg_pool, &fqdn, &records).await?;
+                            }
                         }
+                        Err(e) => warn!("Error looking up the MX records of '{}': {}", &fqdn, e),
+                    }
+                }
 
-                        if let Some(recon_pg_pool) = recon_pg_pool.clone() {
-                            submit_dns_recon_results(&recon_pg_pool, &fqdn, &ips).await?;
+                if lookup_txt {
+                    match txt::lookup_txt(resolver, &fqdn).await {
+                        Ok(records) => {
+                            output.write_txt(&fqdn, &records).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                txt::submit_txt_recon_results(pg_pool, &fqdn, &records).await?;
+                            }
                         }
+                        Err(e) => warn!("Error looking up the TXT records of '{}': {}", &fqdn, e),
+                    }
+                }
 
-                        Ok(())
+                if lookup_authority {
+                    match authority::lookup_ns(resolver, &fqdn, resolve_ns).await {
+                        Ok(records) => {
+                            output.write_ns(&fqdn, &records).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                authority::submit_ns_recon_results(pg_pool, &fqdn, &records)
+                                    .await?;
+                            }
+                        }
+                        Err(e) => warn!("Error looking up the NS records of '{}': {}", &fqdn, e),
+                    }
+                    match authority::lookup_soa(resolver, &fqdn).await {
+                        Ok(Some(record)) => {
+                            output.write_soa(&fqdn, &record).await?;
+                            if let Some(pg_pool) = recon_pg_pool {
+                                authority::submit_soa_recon_result(pg_pool, &fqdn, &record).await?;
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(e) => warn!("Error looking up the SOA record of '{}': {}", &fqdn, e),
                     }
                 }
-            }
-            .into_stream()
-        )));
 
-    info!("Starting DNS recon");
-    while let Some(dns_recon_result) = data_stream.next().await {
-        dns_recon_result?;
+                anyhow::Ok(())
+            })
+            .buffer_unordered(args.concurrency.get()));
+
+        info!("Starting DNS recon");
+        while let Some(dns_recon_result) = data_stream.next().await {
+            dns_recon_result?;
+            stats.record_processed();
+        }
+    }
+
+    if let Some(batcher) = batcher {
+        batcher.finish().await?;
+    }
+    output.finish().await?;
+    if let Some(cache) = cache {
+        cache.save().await?;
+    }
+
+    if shutdown.is_cancelled() {
+        eprintln!("Interrupted after resolving {} names", stats.processed());
     }
+    eprintln!("{stats}");
+    rejects.finish().await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use grimoire_test::{
+        dns::{StubServer, UnknownNames},
+        temp_dir,
+    };
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    /// Spawns a DNS server that answers every A query with 127.0.0.1 after `delay`
+    async fn spawn_dns(delay: Duration) -> StubServer {
+        StubServer::builder(Vec::new())
+            .unknown_names(UnknownNames::Resolve(Ipv4Addr::LOCALHOST))
+            .delay(delay)
+            .spawn()
+            .await
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        use clap::CommandFactory;
+
+        Args::command().debug_assert();
+    }
+
+    #[tokio::test]
+    async fn input_files_are_read_like_stdin() {
+        let names = "www.example.com\nnot a name\nmail.example.org\n";
+        let dir = temp_dir();
+        let input = dir.path().join("input.txt");
+        std::fs::write(&input, names).unwrap();
+
+        let from_file = dry_run(
+            open_input(Some(&input)).await.unwrap(),
+            FqdnParseOptions::default(),
+        )
+        .await;
+        // Stdin is read through the same codec, so any reader of the same bytes stands in for it
+        let from_stdin = dry_run(names.as_bytes(), FqdnParseOptions::default()).await;
+
+        assert_eq!(from_file.valid(), 2);
+        assert_eq!(from_file.rejected(), 1);
+        assert_eq!(from_file.to_string(), from_stdin.to_string());
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_abort_the_run_in_strict_mode() {
+        let server = spawn_dns(Duration::ZERO).await;
+        let port = server.addr().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("strict.txt");
+        std::fs::write(&input, "www.example.com\nnot a name\nmail.example.com\n").unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--strict-input",
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        let error = run(args).await.unwrap_err();
+
+        assert!(
+            error.to_string().starts_with("Line 2 of the input"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn lookups_fail_promptly_after_a_short_timeout() {
+        // Receives the queries, but never answers them
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("timeout.txt");
+        let output = dir.path().join("timeout.ndjson");
+        std::fs::write(&input, "www.example.com\n").unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--dns-timeout-secs",
+            "1",
+            "--dns-attempts",
+            "1",
+            "--output",
+            "ndjson",
+            "--output-file",
+            output.to_str().unwrap(),
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        let start = std::time::Instant::now();
+        let error = run(args).await.unwrap_err();
+        let elapsed = start.elapsed();
+
+        drop(socket);
+        // The A and AAAA queries are each sent twice, where the defaults would stall for 30 seconds
+        assert!(error.to_string().contains("timed out"), "{error:?}");
+        assert!(elapsed < Duration::from_secs(6), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn lookups_in_flight_are_limited_by_the_concurrency() {
+        let server = spawn_dns(Duration::from_millis(50)).await;
+        let port = server.addr().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("concurrency.txt");
+        let names: String = (0..12).map(|i| format!("host{i}.example.com\n")).collect();
+        std::fs::write(&input, names).unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--concurrency",
+            "3",
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        run(args).await.unwrap();
+
+        assert_eq!(server.max_pending(), 3);
+    }
+
+    #[tokio::test]
+    async fn lookups_in_flight_are_completed_on_shutdown() {
+        let server = spawn_dns(Duration::from_millis(200)).await;
+        let port = server.addr().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("shutdown.txt");
+        let output = dir.path().join("shutdown.ndjson");
+        let names: String = (0..12).map(|i| format!("host{i}.example.com\n")).collect();
+        std::fs::write(&input, names).unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--concurrency",
+            "3",
+            "--output",
+            "ndjson",
+            "--output-file",
+            output.to_str().unwrap(),
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        // Shuts down as soon as the first lookups are in flight
+        let shutdown = CancellationToken::new();
+        let trigger = shutdown.clone();
+        tokio::spawn(async move {
+            while server.pending() < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            trigger.cancel();
+        });
+        run_until(args, shutdown).await.unwrap();
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        let results: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(results.len(), 3);
+        for result in results {
+            assert_eq!(result["ips"], serde_json::json!(["127.0.0.1"]));
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_first_names_are_looked_up_up_to_the_limit() {
+        let server = spawn_dns(Duration::ZERO).await;
+        let port = server.addr().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("limit.txt");
+        let output = dir.path().join("limit.ndjson");
+        // Rejected lines do not count towards the limit
+        let names: String = ["not a name\n".to_string()]
+            .into_iter()
+            .chain((0..10).map(|i| format!("host{i}.example.com\n")))
+            .collect();
+        std::fs::write(&input, names).unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--limit",
+            "4",
+            "--output",
+            "ndjson",
+            "--output-file",
+            output.to_str().unwrap(),
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        run(args).await.unwrap();
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        let mut fqdns: Vec<String> = written
+            .lines()
+            .map(|line| {
+                let result: serde_json::Value = serde_json::from_str(line).unwrap();
+                result["fqdn"].as_str().unwrap().to_string()
+            })
+            .collect();
+        fqdns.sort();
+        assert_eq!(
+            fqdns,
+            [
+                "host0.example.com",
+                "host1.example.com",
+                "host2.example.com",
+                "host3.example.com"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_names_are_not_queried_again() {
+        let server = spawn_dns(Duration::ZERO).await;
+        let port = server.addr().port();
+
+        let dir = temp_dir();
+        let input = dir.path().join("cache.txt");
+        let cache_file = dir.path().join("cache.json");
+        let output = dir.path().join("cache.ndjson");
+        std::fs::write(&input, "www.example.com\nmail.example.com\n").unwrap();
+        let args = || {
+            Args::try_parse_from([
+                "dns-recon",
+                "--quiet",
+                "--cache-file",
+                cache_file.to_str().unwrap(),
+                "--output",
+                "ndjson",
+                "--output-file",
+                output.to_str().unwrap(),
+                "--input",
+                input.to_str().unwrap(),
+                "--dns-port",
+                &port.to_string(),
+                "127.0.0.1",
+            ])
+            .unwrap()
+        };
+
+        run(args()).await.unwrap();
+        let queried = server.queries().len();
+        // The records are valid for 60 seconds, so the second run finds both names in the cache
+        run(args()).await.unwrap();
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        assert!(queried > 0);
+        assert_eq!(server.queries().len(), queried);
+        assert_eq!(written.lines().count(), 2);
+        for line in written.lines() {
+            let result: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(result["ips"], serde_json::json!(["127.0.0.1"]));
+        }
+    }
+
+    #[tokio::test]
+    async fn the_answering_server_and_the_duration_are_recorded() {
+        let server = spawn_dns(Duration::from_millis(50)).await;
+        let addr = server.addr();
+
+        let dir = temp_dir();
+        let input = dir.path().join("query-info.txt");
+        let cache_file = dir.path().join("query-info.json");
+        let output = dir.path().join("query-info.ndjson");
+        std::fs::write(&input, "www.example.com\n").unwrap();
+        let args = || {
+            Args::try_parse_from([
+                "dns-recon",
+                "--quiet",
+                "--record-query-info",
+                "--cache-file",
+                cache_file.to_str().unwrap(),
+                "--output",
+                "ndjson",
+                "--output-file",
+                output.to_str().unwrap(),
+                "--input",
+                input.to_str().unwrap(),
+                "--dns-port",
+                &addr.port().to_string(),
+                "127.0.0.1",
+            ])
+            .unwrap()
+        };
+
+        run(args()).await.unwrap();
+        let queried: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        // The second run takes the addresses from the cache file
+        run(args()).await.unwrap();
+        let cached: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+
+        assert_eq!(queried["name-server"], addr.to_string());
+        assert!(queried["query-ms"].as_u64().unwrap() >= 50, "{queried}");
+        assert_eq!(cached["ips"], serde_json::json!(["127.0.0.1"]));
+        assert_eq!(cached["name-server"], serde_json::Value::Null);
+        assert_eq!(cached["query-ms"], 0);
+    }
+
+    /// The value of `metric` in the Prometheus metrics served on `addr`
+    async fn scrape(addr: SocketAddr, metric: &str) -> Option<f64> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|(name, _)| *name == metric)
+            .map(|(_, value)| value.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn metrics_count_the_lookups() {
+        let server = spawn_dns(Duration::ZERO).await;
+        let port = server.addr().port();
+        let metrics_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let dir = temp_dir();
+        let input = dir.path().join("metrics.txt");
+        let names: String = (0..4).map(|i| format!("host{i}.example.com\n")).collect();
+        std::fs::write(&input, names).unwrap();
+        let args = Args::try_parse_from([
+            "dns-recon",
+            "--quiet",
+            "--metrics-addr",
+            &metrics_addr.to_string(),
+            "--input",
+            input.to_str().unwrap(),
+            "--dns-port",
+            &port.to_string(),
+            "127.0.0.1",
+        ])
+        .unwrap();
+
+        run(args).await.unwrap();
+
+        // Other tests of this process may count their lookups as well, once the exporter is installed
+        let processed = scrape(metrics_addr, "dns_recon_processed_total").await;
+        let succeeded = scrape(metrics_addr, "dns_recon_succeeded_total").await;
+        assert!(processed.unwrap() >= 4.0, "{processed:?}");
+        assert!(succeeded.unwrap() >= 4.0, "{succeeded:?}");
+    }
+}
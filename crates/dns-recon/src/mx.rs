@@ -0,0 +1,160 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use grimoire::Fqdn;
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    AsyncResolver,
+};
+use serde::Serialize;
+use sqlx::{query, PgPool};
+
+use crate::edns::EdnsConnectionProvider;
+
+/// A single MX record, which names a host accepting mail for a name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MxRecord {
+    /// Senders try the exchanges with the lowest preference first
+    pub preference: u16,
+    /// The host accepting mail, or '.' for a null MX
+    pub exchange: String,
+}
+
+impl MxRecord {
+    /// Determines whether this is a null MX as per RFC 7505, which declares that the name does not
+    /// accept any mail
+    pub fn is_null(&self) -> bool {
+        self.exchange == "."
+    }
+}
+
+impl Display for MxRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange)
+    }
+}
+
+/// Looks up the MX records of `fqdn`, ordered by their preference. Names without MX records yield
+/// an empty list
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_mx(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+) -> Result<Vec<MxRecord>, ResolveError> {
+    let lookup = match resolver.mx_lookup(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut records: Vec<_> = lookup
+        .iter()
+        .map(|mx| MxRecord {
+            preference: mx.preference(),
+            exchange: if mx.exchange().is_root() {
+                ".".to_string()
+            } else {
+                Fqdn::from(mx.exchange()).to_string()
+            },
+        })
+        .collect();
+    records.sort_by(|a, b| (a.preference, &a.exchange).cmp(&(b.preference, &b.exchange)));
+
+    Ok(records)
+}
+
+#[tracing::instrument(skip(pg_pool, records))]
+pub async fn submit_mx_recon_results(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    records: &[MxRecord],
+) -> anyhow::Result<()> {
+    for record in records {
+        query!(
+            r#"
+            INSERT INTO "mx-recon" (domain, fqdn, preference, exchange)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT ON CONSTRAINT "mx-recon_pkey" DO
+            UPDATE SET preference = EXCLUDED.preference
+            "#,
+            fqdn.domain(),
+            fqdn.to_string(),
+            i32::from(record.preference),
+            record.exchange,
+        )
+        .execute(pg_pool)
+        .await
+        .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{rdata::MX, Name, RData, Record},
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    fn mx(name: &str, preference: u16, exchange: &str) -> Record {
+        Record::from_rdata(
+            Name::from_ascii(name).unwrap(),
+            300,
+            RData::MX(MX::new(preference, Name::from_ascii(exchange).unwrap())),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_exchanges_are_ordered_by_preference() {
+        let server = StubServer::spawn(vec![
+            mx("example.com.", 20, "mx2.example.com."),
+            mx("example.com.", 10, "mx1.example.com."),
+            mx("example.com.", 20, "mx0.example.com."),
+        ])
+        .await;
+        let resolver = server.resolver(ResolverOpts::default());
+
+        let records = lookup_mx(&resolver, &"example.com".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "10 mx1.example.com",
+                "20 mx0.example.com",
+                "20 mx2.example.com"
+            ]
+        );
+        assert!(records.iter().all(|record| !record.is_null()));
+    }
+
+    #[tokio::test]
+    async fn a_null_mx_is_recognized() {
+        let server = StubServer::spawn(vec![mx("example.org.", 0, ".")]).await;
+        let resolver = server.resolver(ResolverOpts::default());
+
+        let records = lookup_mx(&resolver, &"example.org".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            records,
+            [MxRecord {
+                preference: 0,
+                exchange: ".".to_string(),
+            }]
+        );
+        assert!(records[0].is_null());
+    }
+}
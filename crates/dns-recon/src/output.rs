@@ -0,0 +1,452 @@
+use std::{fmt::Display, net::IpAddr};
+
+use base64ct::Encoding;
+use grimoire::{
+    asn::AsnMap,
+    output::{OutputFormat, Writer},
+    records::DnsReconRecord,
+    Fqdn,
+};
+use hickory_resolver::proto::rr::Record;
+use itertools::Itertools;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    authority::{NsRecord, SoaRecord},
+    caa::CaaRecord,
+    dnssec::DnssecStatus,
+    mx::MxRecord,
+    query_info::QueryInfo,
+    split_horizon::SystemAnswer,
+    srv::SrvRecord,
+    txt::TxtRecord,
+};
+
+/// Builds the record of a lookup, which is written to the output and stored alike
+pub fn lookup_record(
+    fqdn: &Fqdn,
+    ips: Vec<IpAddr>,
+    dnssec: Option<DnssecStatus>,
+    query: Option<QueryInfo>,
+    asns: Option<AsnMap>,
+    system: Option<SystemAnswer>,
+) -> DnsReconRecord {
+    DnsReconRecord {
+        dnssec: dnssec.map(|dnssec| dnssec.as_str().to_string()),
+        name_server: query
+            .and_then(|query| query.server)
+            .map(|server| server.to_string()),
+        query_ms: query.map(|query| i32::try_from(query.duration_ms).unwrap_or(i32::MAX)),
+        asn_info: asns,
+        system_ips: system.as_ref().map(|system| system.ips.clone()),
+        divergent: system.map(|system| system.divergent),
+        ..DnsReconRecord::new(fqdn, ips)
+    }
+}
+
+/// A record obtained from a zone transfer, as written in the structured output formats. Just like
+/// the records shared with the recon database, the keys are written in kebab-case
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ZoneReconRecord {
+    fqdn: String,
+    record_type: String,
+    values: Vec<String>,
+    /// When the zone was transferred
+    #[serde(rename = "updated_at", with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+impl ZoneReconRecord {
+    fn new(record: &Record) -> Self {
+        ZoneReconRecord {
+            fqdn: Fqdn::from(record.name()).to_string(),
+            record_type: record.record_type().to_string(),
+            values: record.data().map(|d| d.to_string()).into_iter().collect(),
+            updated_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+/// A CAA record in the structured output formats
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct CaaReconRecord<'a> {
+    fqdn: String,
+    record_type: &'static str,
+    #[serde(flatten)]
+    record: &'a CaaRecord,
+}
+
+/// An SRV record in the structured output formats
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SrvReconRecord<'a> {
+    fqdn: String,
+    record_type: &'static str,
+    #[serde(flatten)]
+    record: &'a SrvRecord,
+}
+
+/// An MX record in the structured output formats
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct MxReconRecord<'a> {
+    fqdn: String,
+    record_type: &'static str,
+    #[serde(flatten)]
+    record: &'a MxRecord,
+    /// Whether the record is a null MX, i.e. the name does not accept any mail
+    null: bool,
+}
+
+/// A TXT record in the structured output formats. Values that are valid UTF-8 are given as a
+/// string, all others are encoded as base64
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TxtReconRecord<'a> {
+    fqdn: String,
+    record_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_base64: Option<String>,
+}
+
+impl<'a> TxtReconRecord<'a> {
+    fn new(fqdn: &Fqdn, record: &'a TxtRecord) -> Self {
+        let value = record.as_str();
+        TxtReconRecord {
+            fqdn: fqdn.to_string(),
+            record_type: "TXT",
+            value,
+            value_base64: value
+                .is_none()
+                .then(|| base64ct::Base64::encode_string(&record.value)),
+        }
+    }
+}
+
+/// A record of another type in the structured output formats
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TypedReconRecord<'a, T> {
+    fqdn: String,
+    record_type: &'static str,
+    #[serde(flatten)]
+    record: &'a T,
+}
+
+/// Writes DNS recon results to stdout and the output file in the selected format. In the text
+/// format, each line contains a name followed by all resolved values and, if DNSSEC validation is
+/// enabled, the validation status. Names for which the system resolver returned other values are
+/// marked as 'divergent'. The other formats contain one entry per name, just like it is stored
+#[derive(Debug)]
+pub struct Output {
+    writer: Writer,
+}
+
+impl Output {
+    pub fn new(writer: Writer) -> Self {
+        Output { writer }
+    }
+
+    /// Writes the record of a single name. Names without addresses are omitted from the text
+    /// format
+    pub async fn write(&self, record: &DnsReconRecord) -> anyhow::Result<()> {
+        if self.writer.format() == OutputFormat::Text {
+            if !record.ips.is_empty() {
+                let mut line = format!("{} {}", record.fqdn, record.ips.iter().join(" "));
+                if let Some(dnssec) = &record.dnssec {
+                    line.push_str(&format!(" {dnssec}"));
+                }
+                if record.divergent == Some(true) {
+                    line.push_str(" divergent");
+                }
+                self.writer.write_line(&line).await?;
+            }
+        } else {
+            self.writer.write_record(record).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the CAA records of a single name. The text format uses one line per record, holding
+    /// the name followed by the presentation format of the record
+    pub async fn write_caa(&self, fqdn: &Fqdn, records: &[CaaRecord]) -> anyhow::Result<()> {
+        for record in records {
+            if self.writer.format() == OutputFormat::Text {
+                self.writer
+                    .write_line(&format!("{fqdn} CAA {record}"))
+                    .await?;
+            } else {
+                self.writer
+                    .write_record(&CaaReconRecord {
+                        fqdn: fqdn.to_string(),
+                        record_type: "CAA",
+                        record,
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the SRV records of a single name. The text format uses one line per record, holding
+    /// the name followed by the priority, weight, port and target
+    pub async fn write_srv(&self, fqdn: &Fqdn, records: &[SrvRecord]) -> anyhow::Result<()> {
+        for record in records {
+            if self.writer.format() == OutputFormat::Text {
+                self.writer
+                    .write_line(&format!("{fqdn} SRV {record}"))
+                    .await?;
+            } else {
+                self.writer
+                    .write_record(&SrvReconRecord {
+                        fqdn: fqdn.to_string(),
+                        record_type: "SRV",
+                        record,
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the MX records of a single name in the order given. The text format uses one line per
+    /// record, holding the name followed by the preference and exchange, whereas a null MX is
+    /// written as 'MX null'
+    pub async fn write_mx(&self, fqdn: &Fqdn, records: &[MxRecord]) -> anyhow::Result<()> {
+        for record in records {
+            if self.writer.format() == OutputFormat::Text {
+                let line = if record.is_null() {
+                    format!("{fqdn} MX null")
+                } else {
+                    format!("{fqdn} MX {record}")
+                };
+                self.writer.write_line(&line).await?;
+            } else {
+                self.writer
+                    .write_record(&MxReconRecord {
+                        fqdn: fqdn.to_string(),
+                        record_type: "MX",
+                        record,
+                        null: record.is_null(),
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the TXT records of a single name. The text format uses one line per record, holding
+    /// the name followed by the value as a quoted and escaped JSON string, or prefixed with
+    /// 'base64:' if it is not valid UTF-8
+    pub async fn write_txt(&self, fqdn: &Fqdn, records: &[TxtRecord]) -> anyhow::Result<()> {
+        for record in records {
+            if self.writer.format() == OutputFormat::Text {
+                let value = match record.as_str() {
+                    Some(value) => serde_json::to_string(value)?,
+                    None => format!("base64:{}", base64ct::Base64::encode_string(&record.value)),
+                };
+                self.writer
+                    .write_line(&format!("{fqdn} TXT {value}"))
+                    .await?;
+            } else {
+                self.writer
+                    .write_record(&TxtReconRecord::new(fqdn, record))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the NS records of a single name. The text format uses one line per record, holding the
+    /// name followed by the name server and its IP addresses, if they were resolved
+    pub async fn write_ns(&self, fqdn: &Fqdn, records: &[NsRecord]) -> anyhow::Result<()> {
+        for record in records {
+            self.write_typed(fqdn, "NS", record).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the SOA record of a single name. The text format holds the name followed by all
+    /// fields of the record in presentation order
+    pub async fn write_soa(&self, fqdn: &Fqdn, record: &SoaRecord) -> anyhow::Result<()> {
+        self.write_typed(fqdn, "SOA", record).await
+    }
+
+    async fn write_typed<T: Display + Serialize>(
+        &self,
+        fqdn: &Fqdn,
+        record_type: &'static str,
+        record: &T,
+    ) -> anyhow::Result<()> {
+        if self.writer.format() == OutputFormat::Text {
+            self.writer
+                .write_line(&format!("{fqdn} {record_type} {record}"))
+                .await?;
+        } else {
+            self.writer
+                .write_record(&TypedReconRecord {
+                    fqdn: fqdn.to_string(),
+                    record_type,
+                    record,
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a single record obtained from a zone transfer. The text format uses the zone file
+    /// representation of the record
+    pub async fn write_zone_record(&self, record: &Record) -> anyhow::Result<()> {
+        if self.writer.format() == OutputFormat::Text {
+            self.writer.write_line(&record.to_string()).await?;
+        } else {
+            self.writer
+                .write_record(&ZoneReconRecord::new(record))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Completes the output and flushes the output file
+    pub async fn finish(&self) -> anyhow::Result<()> {
+        self.writer.finish().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire::output::ResultWriter;
+    use grimoire_test::temp_dir;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    /// The fields of a record written in a structured output format
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct ParsedRecord {
+        fqdn: String,
+        domain: String,
+        ips: Vec<IpAddr>,
+        dnssec: Option<String>,
+        name_server: Option<String>,
+        #[serde(rename = "updated_at", with = "time::serde::rfc3339")]
+        updated_at: OffsetDateTime,
+    }
+
+    #[test]
+    fn structured_records_use_kebab_case_keys() {
+        let fqdn = Fqdn::from_str("example.com").unwrap();
+        let txt = TxtRecord {
+            value: vec![0xff, 0x00],
+        };
+        let mx = MxRecord {
+            preference: 0,
+            exchange: ".".to_string(),
+        };
+
+        assert_eq!(
+            serde_json::to_value(TxtReconRecord::new(&fqdn, &txt)).unwrap(),
+            json!({"fqdn": "example.com", "record-type": "TXT", "value-base64": "/wA="})
+        );
+        assert_eq!(
+            serde_json::to_value(MxReconRecord {
+                fqdn: fqdn.to_string(),
+                record_type: "MX",
+                record: &mx,
+                null: true,
+            })
+            .unwrap(),
+            json!({
+                "fqdn": "example.com",
+                "record-type": "MX",
+                "preference": 0,
+                "exchange": ".",
+                "null": true,
+            })
+        );
+    }
+
+    #[test]
+    fn lookup_records_hold_the_addresses_of_both_families() {
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ips = vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        let record = lookup_record(&fqdn, ips, Some(DnssecStatus::Secure), None, None, None);
+        let line = serde_json::to_string(&record).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(value["fqdn"], "www.example.com");
+        assert_eq!(value["domain"], "example.com");
+        assert_eq!(value["ips"], json!(["192.0.2.1", "2001:db8::1"]));
+        assert_eq!(value["dnssec"], "secure");
+        assert_eq!(value["name-server"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn ndjson_output_parses_back_into_records() {
+        let dir = temp_dir();
+        let path = dir.path().join("output.ndjson");
+        let output = Output::new(Writer::new(
+            OutputFormat::Ndjson,
+            ResultWriter::new(true, Some(&path), false).await.unwrap(),
+        ));
+        let resolved = lookup_record(
+            &Fqdn::from_str("www.example.com").unwrap(),
+            vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+            None,
+            None,
+            None,
+            None,
+        );
+        let without_records = lookup_record(
+            &Fqdn::from_str("mail.example.org").unwrap(),
+            Vec::new(),
+            Some(DnssecStatus::Indeterminate),
+            None,
+            None,
+            None,
+        );
+        output.write(&resolved).await.unwrap();
+        output.write(&without_records).await.unwrap();
+        output.finish().await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<ParsedRecord> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fqdn, "www.example.com");
+        assert_eq!(records[0].domain, "example.com");
+        assert_eq!(records[0].ips, resolved.ips);
+        assert_eq!(records[0].dnssec, None);
+        assert_eq!(records[0].name_server, None);
+        assert_eq!(
+            records[0].updated_at.unix_timestamp(),
+            resolved.updated_at.unix_timestamp()
+        );
+        assert_eq!(records[1].fqdn, "mail.example.org");
+        assert_eq!(records[1].domain, "example.org");
+        assert!(records[1].ips.is_empty());
+        assert_eq!(records[1].dnssec.as_deref(), Some("indeterminate"));
+    }
+}
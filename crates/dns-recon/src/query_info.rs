@@ -0,0 +1,55 @@
+use std::{net::SocketAddr, time::Duration};
+
+use serde::Serialize;
+
+/// Which name server answered a lookup and how long the lookup took
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueryInfo {
+    /// `None` if the addresses were taken from the cache file
+    pub server: Option<SocketAddr>,
+    pub duration_ms: u64,
+}
+
+impl QueryInfo {
+    /// A lookup answered by `server`. Answers from the in-memory cache of the resolver are
+    /// attributed to the server as well, but take next to no time
+    pub fn answered(server: SocketAddr, duration: Duration) -> Self {
+        QueryInfo {
+            server: Some(server),
+            duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        }
+    }
+
+    /// A lookup answered from the cache file, without querying any name server
+    pub fn cached() -> Self {
+        QueryInfo {
+            server: None,
+            duration_ms: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answered_lookups_record_the_server_and_duration() {
+        let server = "192.0.2.53:53".parse().unwrap();
+        let info = QueryInfo::answered(server, Duration::from_micros(12_900));
+
+        assert_eq!(info.server, Some(server));
+        assert_eq!(info.duration_ms, 12);
+    }
+
+    #[test]
+    fn cached_lookups_have_no_server_and_take_no_time() {
+        assert_eq!(
+            QueryInfo::cached(),
+            QueryInfo {
+                server: None,
+                duration_ms: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::BTreeSet, net::IpAddr};
+
+use grimoire::Fqdn;
+use hickory_resolver::TokioAsyncResolver;
+use serde::Serialize;
+use tracing::debug;
+
+/// The addresses the system resolver returned for a name, recorded along with the answer of the
+/// DNS server to detect split-horizon DNS
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SystemAnswer {
+    pub ips: Vec<IpAddr>,
+    /// Whether the addresses differ from those returned by the DNS server, ignoring their order
+    pub divergent: bool,
+}
+
+impl SystemAnswer {
+    pub fn new(system_ips: Vec<IpAddr>, ips: &[IpAddr]) -> Self {
+        let system_ips: BTreeSet<_> = system_ips.into_iter().collect();
+        let divergent = system_ips != ips.iter().copied().collect();
+
+        SystemAnswer {
+            ips: system_ips.into_iter().collect(),
+            divergent,
+        }
+    }
+}
+
+/// Looks up the addresses of `fqdn` via the system resolver. Failed lookups, including names
+/// without records, yield no addresses
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_system(resolver: &TokioAsyncResolver, fqdn: &Fqdn) -> Vec<IpAddr> {
+    match resolver.lookup_ip(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup.iter().collect(),
+        Err(e) => {
+            debug!("The system resolver cannot resolve '{}': {}", fqdn, e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{rdata::A, Name, RData, Record},
+    };
+
+    use super::*;
+    use crate::{stub::StubResolver as _, transport::Transport};
+
+    async fn spawn_server(ip: [u8; 4]) -> StubServer {
+        let [a, b, c, d] = ip;
+        StubServer::spawn(vec![Record::from_rdata(
+            Name::from_ascii("www.example.com.").unwrap(),
+            300,
+            RData::A(A::new(a, b, c, d)),
+        )])
+        .await
+    }
+
+    /// A resolver of the kind created from the system configuration, which sends all queries to
+    /// `server`
+    fn system_resolver(server: &StubServer) -> TokioAsyncResolver {
+        TokioAsyncResolver::tokio(
+            Transport::Udp.resolver_config(server.addr(), ""),
+            ResolverOpts::default(),
+        )
+    }
+
+    fn ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn the_order_of_the_addresses_is_ignored() {
+        let system = SystemAnswer::new(
+            ips(&["192.0.2.2", "192.0.2.1"]),
+            &ips(&["192.0.2.1", "192.0.2.2"]),
+        );
+
+        assert_eq!(system.ips, ips(&["192.0.2.1", "192.0.2.2"]));
+        assert!(!system.divergent);
+        assert!(SystemAnswer::new(Vec::new(), &ips(&["192.0.2.1"])).divergent);
+    }
+
+    #[tokio::test]
+    async fn different_answers_of_the_two_resolvers_diverge() {
+        let server = spawn_server([192, 0, 2, 1]).await;
+        let internal = spawn_server([10, 0, 0, 1]).await;
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let server_ips: Vec<_> = server
+            .resolver(ResolverOpts::default())
+            .lookup_ip("www.example.com.")
+            .await
+            .unwrap()
+            .iter()
+            .collect();
+
+        let divergent = SystemAnswer::new(
+            lookup_system(&system_resolver(&internal), &fqdn).await,
+            &server_ips,
+        );
+        let agreeing = SystemAnswer::new(
+            lookup_system(&system_resolver(&server), &fqdn).await,
+            &server_ips,
+        );
+
+        assert_eq!(
+            divergent,
+            SystemAnswer {
+                ips: ips(&["10.0.0.1"]),
+                divergent: true,
+            }
+        );
+        assert_eq!(
+            agreeing,
+            SystemAnswer {
+                ips: ips(&["192.0.2.1"]),
+                divergent: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_names_yield_no_addresses() {
+        let server = spawn_server([192, 0, 2, 1]).await;
+
+        let system_ips = lookup_system(
+            &system_resolver(&server),
+            &Fqdn::from_str("mail.example.com").unwrap(),
+        )
+        .await;
+
+        assert!(system_ips.is_empty());
+    }
+}
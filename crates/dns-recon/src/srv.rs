@@ -0,0 +1,159 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use grimoire::Fqdn;
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    AsyncResolver,
+};
+use serde::Serialize;
+use sqlx::{query, PgPool};
+
+use crate::edns::EdnsConnectionProvider;
+
+/// A single SRV record, which locates a service offered for a name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SrvRecord {
+    /// Clients contact the targets with the lowest priority first
+    pub priority: u16,
+    /// The relative share of targets with the same priority
+    pub weight: u16,
+    pub port: u16,
+    /// The host offering the service, or '.' if the service is decidedly not available
+    pub target: String,
+}
+
+impl Display for SrvRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.priority, self.weight, self.port, self.target
+        )
+    }
+}
+
+/// Looks up the SRV records of `fqdn`, which usually starts with service labels like
+/// '_sip._tcp'. Names without SRV records yield an empty list
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_srv(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+) -> Result<Vec<SrvRecord>, ResolveError> {
+    let lookup = match resolver.srv_lookup(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(lookup
+        .iter()
+        .map(|srv| SrvRecord {
+            priority: srv.priority(),
+            weight: srv.weight(),
+            port: srv.port(),
+            target: if srv.target().is_root() {
+                ".".to_string()
+            } else {
+                Fqdn::from(srv.target()).to_string()
+            },
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip(pg_pool, records))]
+pub async fn submit_srv_recon_results(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    records: &[SrvRecord],
+) -> anyhow::Result<()> {
+    for record in records {
+        query!(
+            r#"
+            INSERT INTO "srv-recon" (domain, fqdn, priority, weight, port, target)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT ON CONSTRAINT "srv-recon_pkey" DO
+            UPDATE SET priority = EXCLUDED.priority, weight = EXCLUDED.weight
+            "#,
+            fqdn.domain(),
+            fqdn.to_string(),
+            i32::from(record.priority),
+            i32::from(record.weight),
+            i32::from(record.port),
+            record.target,
+        )
+        .execute(pg_pool)
+        .await
+        .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire::FqdnParseOptions;
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{rdata::SRV, Name, RData, Record},
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    #[tokio::test]
+    async fn all_fields_of_the_records_are_captured() {
+        let name = Name::from_ascii("_sip._tcp.example.com.").unwrap();
+        let srv = |priority, weight, port, target| {
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::SRV(SRV::new(
+                    priority,
+                    weight,
+                    port,
+                    Name::from_ascii(target).unwrap(),
+                )),
+            )
+        };
+        let server = StubServer::spawn(vec![
+            srv(10, 60, 5060, "sip.example.com."),
+            srv(20, 0, 5061, "."),
+        ])
+        .await;
+        let resolver = server.resolver(ResolverOpts::default());
+        let fqdn = Fqdn::parse_with(
+            "_sip._tcp.example.com",
+            FqdnParseOptions {
+                service_labels: true,
+                ..FqdnParseOptions::default()
+            },
+        )
+        .unwrap();
+
+        let mut records = lookup_srv(&resolver, &fqdn).await.unwrap();
+        records.sort_by_key(|record| record.priority);
+
+        assert_eq!(
+            records,
+            [
+                SrvRecord {
+                    priority: 10,
+                    weight: 60,
+                    port: 5060,
+                    target: "sip.example.com".to_string(),
+                },
+                SrvRecord {
+                    priority: 20,
+                    weight: 0,
+                    port: 5061,
+                    target: ".".to_string(),
+                },
+            ]
+        );
+        assert_eq!(records[0].to_string(), "10 60 5060 sip.example.com");
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use grimoire::records::DnsReconRecord;
+use metrics::counter;
+
+/// Counts the outcomes of a run, for the summary printed on completion. Every outcome is also
+/// recorded as a Prometheus metric
+#[derive(Debug, Default)]
+pub struct Stats {
+    processed: AtomicUsize,
+    resolved: AtomicUsize,
+    no_records: AtomicUsize,
+    failed: AtomicUsize,
+    validation_failed: AtomicUsize,
+    skipped_known: AtomicUsize,
+    cached: AtomicUsize,
+    divergent: AtomicUsize,
+    stored: AtomicUsize,
+    /// The number of resolved values per record type
+    record_types: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Stats {
+    pub fn processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn record_processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_processed_total").increment(1);
+    }
+
+    pub fn record_resolved(&self, record: &DnsReconRecord) {
+        self.resolved.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_succeeded_total").increment(1);
+
+        let mut record_types = self.record_types.lock().expect("locking the record types");
+        for ip in &record.ips {
+            let record_type = match ip {
+                IpAddr::V4(_) => "A",
+                IpAddr::V6(_) => "AAAA",
+            };
+            *record_types.entry(record_type.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn record_no_records(&self) {
+        self.no_records.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_succeeded_total").increment(1);
+    }
+
+    pub fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_failed_total").increment(1);
+    }
+
+    pub fn record_validation_failed(&self) {
+        self.validation_failed.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_validation_failed_total").increment(1);
+    }
+
+    pub fn record_skipped_known(&self) {
+        self.skipped_known.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_skipped_known_total").increment(1);
+    }
+
+    pub fn record_cached(&self) {
+        self.cached.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_cached_total").increment(1);
+    }
+
+    pub fn record_divergent(&self) {
+        self.divergent.fetch_add(1, Ordering::Relaxed);
+        counter!("dns_recon_divergent_total").increment(1);
+    }
+
+    pub fn record_stored(&self) {
+        self.stored.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Processed:     {}", self.processed())?;
+        writeln!(
+            f,
+            "Resolved:      {}",
+            self.resolved.load(Ordering::Relaxed)
+        )?;
+        let record_types = self.record_types.lock().map_err(|_| std::fmt::Error)?;
+        for (record_type, count) in record_types.iter() {
+            writeln!(f, "  {record_type:<12}{count}")?;
+        }
+        writeln!(
+            f,
+            "No records:    {}",
+            self.no_records.load(Ordering::Relaxed)
+        )?;
+        writeln!(f, "Failed:        {}", self.failed.load(Ordering::Relaxed))?;
+        writeln!(
+            f,
+            "Bogus:         {}",
+            self.validation_failed.load(Ordering::Relaxed)
+        )?;
+        writeln!(
+            f,
+            "Skipped known: {}",
+            self.skipped_known.load(Ordering::Relaxed)
+        )?;
+        writeln!(f, "Cached:        {}", self.cached.load(Ordering::Relaxed))?;
+        writeln!(
+            f,
+            "Divergent:     {}",
+            self.divergent.load(Ordering::Relaxed)
+        )?;
+        write!(f, "Stored:        {}", self.stored.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire::Fqdn;
+
+    use super::*;
+
+    #[test]
+    fn the_summary_counts_each_outcome() {
+        let stats = Stats::default();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        for ips in [
+            vec!["192.0.2.1".parse().unwrap()],
+            vec!["192.0.2.2".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+        ] {
+            stats.record_resolved(&DnsReconRecord::new(&fqdn, ips));
+            stats.record_processed();
+        }
+        stats.record_no_records();
+        stats.record_processed();
+        stats.record_failed();
+        stats.record_processed();
+        stats.record_skipped_known();
+
+        assert_eq!(stats.processed(), 4);
+        assert_eq!(
+            stats.to_string(),
+            "Processed:     4\n\
+             Resolved:      2\n  \
+               A           2\n  \
+               AAAA        1\n\
+             No records:    1\n\
+             Failed:        1\n\
+             Bogus:         0\n\
+             Skipped known: 1\n\
+             Cached:        0\n\
+             Divergent:     0\n\
+             Stored:        0"
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Resolvers that send their queries to the DNS server for tests
+
+use grimoire_test::dns::StubServer;
+use hickory_resolver::{config::ResolverOpts, AsyncResolver};
+
+use crate::{edns::EdnsConnectionProvider, transport::Transport};
+
+/// Creates resolvers that send all queries to a [`StubServer`] via UDP
+pub trait StubResolver {
+    fn resolver(&self, opts: ResolverOpts) -> AsyncResolver<EdnsConnectionProvider> {
+        self.resolver_with(opts, EdnsConnectionProvider::new(1232, None))
+    }
+
+    fn resolver_with(
+        &self,
+        opts: ResolverOpts,
+        provider: EdnsConnectionProvider,
+    ) -> AsyncResolver<EdnsConnectionProvider>;
+}
+
+impl StubResolver for StubServer {
+    fn resolver_with(
+        &self,
+        opts: ResolverOpts,
+        provider: EdnsConnectionProvider,
+    ) -> AsyncResolver<EdnsConnectionProvider> {
+        AsyncResolver::new(
+            Transport::Udp.resolver_config(self.addr(), ""),
+            opts,
+            provider,
+        )
+    }
+}
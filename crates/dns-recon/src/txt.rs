@@ -0,0 +1,139 @@
+use anyhow::Context;
+use grimoire::Fqdn;
+use hickory_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    AsyncResolver,
+};
+use sqlx::{query, PgPool};
+
+use crate::edns::EdnsConnectionProvider;
+
+/// A single TXT record, reassembled from the character strings it is split into on the wire. Its
+/// value may contain arbitrary bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxtRecord {
+    pub value: Vec<u8>,
+}
+
+impl TxtRecord {
+    /// Concatenates the character strings of a record, which hold at most 255 bytes each, without
+    /// any separator, as is required for SPF, DKIM and DMARC values
+    pub fn from_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        TxtRecord {
+            value: chunks.into_iter().flatten().copied().collect(),
+        }
+    }
+
+    /// Returns the value if it is valid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.value).ok()
+    }
+}
+
+/// Looks up the TXT records of `fqdn`. Names without TXT records yield an empty list
+#[tracing::instrument(skip(resolver))]
+pub async fn lookup_txt(
+    resolver: &AsyncResolver<EdnsConnectionProvider>,
+    fqdn: &Fqdn,
+) -> Result<Vec<TxtRecord>, ResolveError> {
+    let lookup = match resolver.txt_lookup(format!("{fqdn}.")).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(lookup
+        .iter()
+        .map(|txt| TxtRecord::from_chunks(txt.txt_data().iter().map(|chunk| &chunk[..])))
+        .collect())
+}
+
+#[tracing::instrument(skip(pg_pool, records))]
+pub async fn submit_txt_recon_results(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    records: &[TxtRecord],
+) -> anyhow::Result<()> {
+    for record in records {
+        query!(
+            r#"
+            INSERT INTO "txt-recon" (domain, fqdn, value)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (fqdn, md5(value)) DO NOTHING
+            "#,
+            fqdn.domain(),
+            fqdn.to_string(),
+            &record.value,
+        )
+        .execute(pg_pool)
+        .await
+        .with_context(|| format!("Relating to FQDN '{fqdn}'"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::ResolverOpts,
+        proto::rr::{rdata::TXT, Name, RData, Record},
+    };
+
+    use super::*;
+    use crate::stub::StubResolver as _;
+
+    #[test]
+    fn chunks_are_concatenated_without_a_separator() {
+        let record =
+            TxtRecord::from_chunks([&b"v=spf1 "[..], b"include:_spf.example.com", b" -all"]);
+
+        assert_eq!(
+            record.as_str(),
+            Some("v=spf1 include:_spf.example.com -all")
+        );
+        assert_eq!(TxtRecord::from_chunks([&b"\xff\""[..]]).as_str(), None);
+    }
+
+    #[tokio::test]
+    async fn multi_chunk_records_are_reassembled_exactly() {
+        // A DKIM key is longer than a single character string of 255 bytes
+        let key = format!("v=DKIM1; k=rsa; p={}", "MIIBIjANBgkqhkiG9w0B".repeat(20));
+        let (first, rest) = key.as_bytes().split_at(255);
+        let (second, third) = rest.split_at(100);
+        let name = Name::from_ascii("selector._domainkey.example.com.").unwrap();
+        let server = StubServer::spawn(vec![
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::TXT(TXT::from_bytes(vec![first, second, third])),
+            ),
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::TXT(TXT::from_bytes(vec![b"quoted \"value\"\n\xff"])),
+            ),
+        ])
+        .await;
+        let resolver = server.resolver(ResolverOpts::default());
+        let fqdn = Fqdn::from(&name);
+
+        let mut records = lookup_txt(&resolver, &fqdn).await.unwrap();
+        records.sort_by_key(|record| record.value.len());
+
+        assert_eq!(
+            records,
+            [
+                TxtRecord {
+                    value: b"quoted \"value\"\n\xff".to_vec(),
+                },
+                TxtRecord {
+                    value: key.into_bytes(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use grimoire::{
+    build_info,
+    cli::{parse_with_config, ConfigArgs, LogArgs, ReconDbArgs, RuntimeArgs},
+    exit::{ExitStatus, EXIT_STATUS_HELP},
+    health::check_recon_db,
+    tables::ReconTable,
+};
+use tracing::debug;
+
+/// Checks that the recon database is reachable, that all migrations of this build have been
+/// applied and that each table holding results exists, without changing the database. Exits with
+/// status 3 if any check fails, such that deployments can wait for the database to become usable
+#[derive(Debug, Parser)]
+#[command(version, long_version = build_info(), about, long_about = None, after_help = EXIT_STATUS_HELP)]
+struct Args {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    let result = args
+        .runtime
+        .build()
+        .map_err(anyhow::Error::from)
+        .and_then(|runtime| runtime.block_on(run(args)));
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitStatus::ReconDb.into(),
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitStatus::Failure.into()
+        }
+    }
+}
+
+/// Prints the outcome of each check. Returns whether all of them passed
+async fn run(args: Args) -> anyhow::Result<bool> {
+    args.log.init();
+
+    debug!("Establishing a connection to the recon database");
+    let pg_pool = match args.recon_db.connect_unmigrated().await {
+        Ok(pg_pool) => pg_pool,
+        Err(e) => {
+            println!("Connection:    failed");
+            println!("  {e}");
+            println!("Verdict:       unhealthy");
+            return Ok(false);
+        }
+    };
+    println!("Connection:    ok");
+
+    let report = match check_recon_db(&pg_pool).await {
+        Ok(report) => report,
+        Err(e) => {
+            println!("Checks:        failed");
+            println!("  {e}");
+            println!("Verdict:       unhealthy");
+            return Ok(false);
+        }
+    };
+    println!(
+        "Migrations:    {} of {} applied",
+        report.applied, report.expected
+    );
+    println!(
+        "Tables:        {} of {} present",
+        report.tables,
+        ReconTable::ALL.len()
+    );
+    for problem in &report.problems {
+        println!("  {problem}");
+    }
+
+    let healthy = report.is_healthy();
+    println!(
+        "Verdict:       {}",
+        if healthy { "healthy" } else { "unhealthy" }
+    );
+
+    Ok(healthy)
+}
@@ -0,0 +1,189 @@
+use std::{path::PathBuf, pin::pin, process::ExitCode};
+
+use clap::Parser;
+use futures::StreamExt;
+use grimoire::{
+    build_info,
+    cli::{parse_with_config, ConfigArgs, LogArgs, ReconDbArgs, RuntimeArgs},
+    exit::{report, EXIT_STATUS_HELP},
+    shutdown_on_ctrl_c,
+    tables::ReconTable,
+    Fqdn, ResultWriter,
+};
+use sqlx::{query_scalar, PgPool};
+use tracing::{debug, info};
+
+/// Exports the tables of the recon database as JSON Lines. Each line holds an object with the name
+/// of the table under 'table' and the row under 'row'
+#[derive(Debug, Parser)]
+#[command(version, long_version = build_info(), about, long_about = None, after_help = EXIT_STATUS_HELP)]
+struct Args {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+    /// Only export this table. May be specified multiple times. If omitted, all tables are exported
+    #[arg(short, long = "table", value_enum)]
+    tables: Vec<ReconTable>,
+    /// Only export rows of this domain. May be specified multiple times
+    #[arg(short, long = "domain")]
+    domains: Vec<Fqdn>,
+    /// Write the rows to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output_file: Option<PathBuf>,
+    /// Append to the output file instead of truncating it
+    #[arg(long, requires = "output_file")]
+    append: bool,
+}
+
+/// Streams the rows of `table` that belong to one of `domains`, or all rows if `domains` is empty,
+/// to the writer. Returns the number of exported rows
+#[tracing::instrument(skip(pg_pool, writer, shutdown))]
+async fn export_table(
+    pg_pool: &PgPool,
+    writer: &ResultWriter,
+    table: ReconTable,
+    domains: &[String],
+    shutdown: impl std::future::Future<Output = ()>,
+) -> anyhow::Result<usize> {
+    // The table name is taken from a fixed set and is thus safe to interpolate
+    let sql = format!(
+        r#"SELECT json_build_object('table', $1::text, 'row', row_to_json(t))::text FROM "{table}" AS t WHERE cardinality($2::varchar[]) = 0 OR t.domain = ANY($2) ORDER BY t.id"#
+    );
+    let mut rows = pin!(query_scalar::<_, String>(&sql)
+        .bind(table.name())
+        .bind(domains)
+        .fetch(pg_pool)
+        .take_until(shutdown));
+
+    let mut exported = 0_usize;
+    while let Some(line) = rows.next().await {
+        writer.write_line(&line?).await?;
+        exported += 1;
+    }
+
+    Ok(exported)
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+    )
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    args.log.init();
+
+    let shutdown = shutdown_on_ctrl_c();
+
+    debug!("Establishing a connection to the recon database");
+    let pg_pool = args.recon_db.connect().await?;
+
+    let writer = ResultWriter::new(
+        args.output_file.is_some(),
+        args.output_file.as_deref(),
+        args.append,
+    )
+    .await?;
+
+    let tables = if args.tables.is_empty() {
+        ReconTable::ALL.to_vec()
+    } else {
+        args.tables
+    };
+    let domains: Vec<_> = args.domains.iter().map(|d| d.to_string()).collect();
+    for table in tables {
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        let exported =
+            export_table(&pg_pool, &writer, table, &domains, shutdown.cancelled()).await?;
+        info!("Exported {exported} rows from '{table}'");
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire::exit::{ExitStatus, RunError};
+    use grimoire_test::temp_dir;
+    use serde_json::{json, Value};
+    use sqlx::query;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn the_rows_of_the_domain_are_exported_as_json_lines(pg_pool: PgPool) {
+        query(
+            r#"INSERT INTO "dns-recon" (fqdn, domain, ips) VALUES ('www.example.com', 'example.com', '{192.0.2.1}'), ('www.example.org', 'example.org', '{192.0.2.2}'), ('mail.example.com', 'example.com', '{}')"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let dir = temp_dir();
+        let path = dir.path().join("export.jsonl");
+        let writer = ResultWriter::new(true, Some(&path), false).await.unwrap();
+
+        let exported = export_table(
+            &pg_pool,
+            &writer,
+            ReconTable::Dns,
+            &["example.com".to_string()],
+            std::future::pending(),
+        )
+        .await
+        .unwrap();
+        writer.flush().await.unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(exported, 2);
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let rows: Vec<_> = lines
+            .iter()
+            .map(|line| {
+                assert_eq!(line["table"], "dns-recon");
+                json!([
+                    line["row"]["fqdn"],
+                    line["row"]["domain"],
+                    line["row"]["ips"]
+                ])
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                json!(["www.example.com", "example.com", ["192.0.2.1"]]),
+                json!(["mail.example.com", "example.com", []]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_recon_db_exits_with_3() {
+        // A host that starts with a slash is the directory of the Unix socket of the server
+        let args = Args::try_parse_from([
+            "grimoire-export",
+            "--recon-db-host",
+            "/nonexistent/grimoire",
+        ])
+        .unwrap();
+
+        let error = run(args).await.unwrap_err();
+
+        assert_eq!(RunError::from(error).status(), ExitStatus::ReconDb);
+    }
+}
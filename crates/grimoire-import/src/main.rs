@@ -0,0 +1,295 @@
+use std::{collections::HashMap, path::PathBuf, pin::pin, process::ExitCode};
+
+use clap::Parser;
+use futures::StreamExt;
+use grimoire::{
+    build_info,
+    cli::{parse_with_config, ConfigArgs, LogArgs, ReconDbArgs, RejectArgs, RuntimeArgs},
+    exit::{report, EXIT_STATUS_HELP},
+    open_input, shutdown_on_ctrl_c,
+    tables::ReconTable,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sqlx::{query, query_scalar, PgPool};
+use thiserror::Error;
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tracing::{debug, info};
+
+/// Imports the JSON Lines written by grimoire-export into the recon database. Rows that conflict
+/// with known results are merged like the recon binaries do for 'dns-recon' and are otherwise
+/// ignored, such that known results take precedence
+#[derive(Debug, Parser)]
+#[command(version, long_version = build_info(), about, long_about = None, after_help = EXIT_STATUS_HELP)]
+struct Args {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+    #[command(flatten)]
+    rejects: RejectArgs,
+    /// Read the rows from this file instead of Stdin. Use '-' to read from Stdin explicitly
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+}
+
+/// A line written by grimoire-export
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportedRow {
+    table: String,
+    row: Map<String, Value>,
+}
+
+/// Why a line cannot be imported
+#[derive(Debug, Error)]
+enum Error {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Unknown table '{0}'")]
+    UnknownTable(String),
+    #[error("The row of '{0}' has no domain")]
+    MissingDomain(ReconTable),
+    #[error("The table '{0}' has no column '{1}'")]
+    UnknownColumn(ReconTable, String),
+    #[error("The row of '{0}' was refused by the recon database: {1}")]
+    Refused(ReconTable, String),
+}
+
+/// The statement that resolves conflicts with known rows of `table`
+fn conflict_clause(table: ReconTable) -> &'static str {
+    match table {
+        ReconTable::Dns => {
+            r#"ON CONFLICT ON CONSTRAINT "dns-recon_pkey" DO UPDATE SET ips = (SELECT ARRAY(SELECT DISTINCT UNNEST("dns-recon".ips || EXCLUDED.ips))), dnssec = COALESCE(EXCLUDED.dnssec, "dns-recon".dnssec), "name-server" = COALESCE(EXCLUDED."name-server", "dns-recon"."name-server"), "query-ms" = COALESCE(EXCLUDED."query-ms", "dns-recon"."query-ms"), "asn-info" = COALESCE("dns-recon"."asn-info" || EXCLUDED."asn-info", EXCLUDED."asn-info", "dns-recon"."asn-info"), "system-ips" = COALESCE(EXCLUDED."system-ips", "dns-recon"."system-ips"), divergent = COALESCE(EXCLUDED.divergent, "dns-recon".divergent), updated_at = GREATEST("dns-recon".updated_at, EXCLUDED.updated_at)"#
+        }
+        _ => "ON CONFLICT DO NOTHING",
+    }
+}
+
+/// Inserts the rows of the export into the recon database, looking up the columns of each table on
+/// first use
+struct Importer {
+    pg_pool: PgPool,
+    columns: HashMap<ReconTable, Vec<String>>,
+}
+
+impl Importer {
+    fn new(pg_pool: PgPool) -> Self {
+        Importer {
+            pg_pool,
+            columns: HashMap::new(),
+        }
+    }
+
+    /// Returns the columns of `table`, except for `id`, which is assigned anew on import
+    async fn columns(&mut self, table: ReconTable) -> Result<&[String], sqlx::Error> {
+        if !self.columns.contains_key(&table) {
+            let columns = query_scalar::<_, String>(
+                "SELECT column_name::text FROM information_schema.columns WHERE table_schema = current_schema() AND table_name = $1 AND column_name <> 'id' ORDER BY ordinal_position",
+            )
+            .bind(table.name())
+            .fetch_all(&self.pg_pool)
+            .await?;
+            self.columns.insert(table, columns);
+        }
+
+        Ok(&self.columns[&table])
+    }
+
+    /// Validates and inserts a single line. Returns whether the row was stored, as opposed to
+    /// being ignored in favour of a known row
+    async fn import(&mut self, line: &str) -> anyhow::Result<Result<bool, Error>> {
+        let exported: ExportedRow = match serde_json::from_str(line) {
+            Ok(exported) => exported,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        let Some(table) = ReconTable::from_name(&exported.table) else {
+            return Ok(Err(Error::UnknownTable(exported.table)));
+        };
+        if !exported.row.get("domain").is_some_and(Value::is_string) {
+            return Ok(Err(Error::MissingDomain(table)));
+        }
+
+        let columns = self.columns(table).await?;
+        if let Some(column) = exported
+            .row
+            .keys()
+            .find(|key| *key != "id" && !columns.contains(*key))
+        {
+            return Ok(Err(Error::UnknownColumn(table, column.clone())));
+        }
+
+        // Columns missing from the row keep their defaults. The table and column names stem from
+        // the schema and are thus safe to interpolate
+        let column_list = columns
+            .iter()
+            .filter(|column| exported.row.contains_key(*column))
+            .map(|column| format!(r#""{}""#, column.replace('"', r#""""#)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            r#"INSERT INTO "{table}" ({column_list}) SELECT {column_list} FROM jsonb_populate_record(NULL::"{table}", $1) {}"#,
+            conflict_clause(table)
+        );
+
+        match query(&sql)
+            .bind(Value::Object(exported.row))
+            .execute(&self.pg_pool)
+            .await
+        {
+            Ok(result) => Ok(Ok(result.rows_affected() > 0)),
+            Err(sqlx::Error::Database(e)) => Ok(Err(Error::Refused(table, e.to_string()))),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+    )
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    args.log.init();
+
+    let shutdown = shutdown_on_ctrl_c();
+
+    debug!("Establishing a connection to the recon database");
+    let pg_pool = args.recon_db.connect().await?;
+    let mut importer = Importer::new(pg_pool);
+
+    if !args.rejects.strict_input {
+        info!("Lines that cannot be imported are ignored");
+    }
+    let rejects = &args.rejects.rejects().await?;
+
+    let input = open_input(args.input.as_deref()).await?;
+    let mut lines = pin!(FramedRead::new(input, LinesCodec::new())
+        .take_until(shutdown.cancelled())
+        .take_until(rejects.aborted())
+        .enumerate());
+
+    let mut imported = 0_usize;
+    let mut skipped_known = 0_usize;
+    let mut rejected = 0_usize;
+    while let Some((index, line)) = lines.next().await {
+        let (line, reason) = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => match importer.import(&line).await? {
+                Ok(true) => {
+                    imported += 1;
+                    continue;
+                }
+                Ok(false) => {
+                    skipped_known += 1;
+                    continue;
+                }
+                Err(e) => (Some(line), e.to_string()),
+            },
+            Err(e) => (None, e.to_string()),
+        };
+        rejected += 1;
+        rejects.reject(index + 1, line.as_deref(), reason).await;
+    }
+
+    eprintln!("Imported:      {imported}");
+    eprintln!("Skipped known: {skipped_known}");
+    eprintln!("Rejected:      {rejected}");
+    rejects.finish().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rows of `table` without their IDs, which are assigned anew on import
+    async fn rows(pg_pool: &PgPool, table: ReconTable) -> Vec<String> {
+        query_scalar(&format!(
+            r#"SELECT (to_jsonb(t) - 'id')::text FROM "{table}" AS t ORDER BY t.id"#
+        ))
+        .fetch_all(pg_pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn exported_rows_round_trip(pg_pool: PgPool) {
+        query(
+            r#"INSERT INTO "dns-recon" (fqdn, domain, ips, dnssec, "asn-info") VALUES ('www.example.com', 'example.com', '{192.0.2.1,2001:db8::1}', 'secure', '{"192.0.2.1": {"asn": 64496, "org": "Example"}}'), ('mail.example.com', 'example.com', '{}', NULL, NULL)"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        query(
+            r#"INSERT INTO "cert-recon" (domain, "cert-name", issuer, "not-before") VALUES ('example.com', 'www.example.com', 'C=US, O=Example CA', '2024-07-01T00:00:00Z')"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let tables = [ReconTable::Dns, ReconTable::Cert];
+        let mut before = Vec::new();
+        let mut exported = Vec::new();
+        for table in tables {
+            before.push(rows(&pg_pool, table).await);
+            // The lines written by grimoire-export
+            exported.extend(
+                query_scalar::<_, String>(&format!(
+                    r#"SELECT json_build_object('table', $1::text, 'row', row_to_json(t))::text FROM "{table}" AS t ORDER BY t.id"#
+                ))
+                .bind(table.name())
+                .fetch_all(&pg_pool)
+                .await
+                .unwrap(),
+            );
+            query(&format!(r#"TRUNCATE "{table}""#))
+                .execute(&pg_pool)
+                .await
+                .unwrap();
+        }
+
+        let mut importer = Importer::new(pg_pool.clone());
+        for line in &exported {
+            assert!(importer.import(line).await.unwrap().unwrap());
+        }
+
+        for (table, before) in tables.into_iter().zip(before) {
+            assert_eq!(rows(&pg_pool, table).await, before);
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn malformed_lines_are_rejected(pg_pool: PgPool) {
+        let mut importer = Importer::new(pg_pool.clone());
+
+        for (line, reason) in [
+            ("not json", "expected ident at line 1 column 2"),
+            (
+                r#"{"table": "dns-recon", "row": {"fqdn": "www.example.com"}}"#,
+                "The row of 'dns-recon' has no domain",
+            ),
+            (
+                r#"{"table": "smtp-recon", "row": {"domain": "example.com"}}"#,
+                "Unknown table 'smtp-recon'",
+            ),
+            (
+                r#"{"table": "dns-recon", "row": {"domain": "example.com", "port": 25}}"#,
+                "The table 'dns-recon' has no column 'port'",
+            ),
+        ] {
+            let error = importer.import(line).await.unwrap().unwrap_err();
+            assert_eq!(error.to_string(), reason);
+        }
+        assert!(rows(&pg_pool, ReconTable::Dns).await.is_empty());
+    }
+}
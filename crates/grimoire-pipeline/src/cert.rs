@@ -0,0 +1,68 @@
+use std::{collections::HashSet, str::FromStr, sync::Arc};
+
+use cert_recon::{json_api::fetch_cert_names, submit_cert_recon_results, unseen_names};
+use grimoire::{records::CertReconRecord, Fqdn};
+use leaky_bucket::RateLimiter;
+use reqwest::{Client, Url};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// The first stage, which looks up the subdomains of each domain via the JSON API of the CT
+/// service, stores them just like cert-recon and passes them on. Domains whose query fails are
+/// skipped with a warning. Returns the number of subdomains found
+pub async fn run(
+    client: &Client,
+    ct_url: &Url,
+    limiter: &RateLimiter,
+    pg_pool: Option<&PgPool>,
+    domains: Vec<Fqdn>,
+    sender: mpsc::Sender<Arc<Fqdn>>,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<usize> {
+    let mut seen = HashSet::new();
+    let mut found = 0_usize;
+    for domain in domains {
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        info!("Querying certificates for '{domain}'");
+        let domain = domain.to_string();
+        limiter.acquire_one().await;
+        let cert_names = match fetch_cert_names(client, ct_url, &domain).await {
+            Ok(cert_names) => cert_names,
+            Err(e) => {
+                warn!("Cannot query the certificates for '{}': {}", domain, e);
+                continue;
+            }
+        };
+
+        for cert in cert_names {
+            for name in unseen_names(&cert.name_value, false, &mut seen) {
+                let record = CertReconRecord {
+                    domain: domain.clone(),
+                    cert_name: name,
+                    issuer: cert.issuer.clone(),
+                    not_before: cert.not_before,
+                    not_after: cert.not_after,
+                    updated_at: OffsetDateTime::now_utc(),
+                };
+                if let Some(pg_pool) = pg_pool {
+                    submit_cert_recon_results(pg_pool, &record).await?;
+                }
+                found += 1;
+
+                let fqdn = Fqdn::from_str(&record.cert_name)?;
+                // Waits while the next stage is busy, such that the stages advance at the same pace
+                if sender.send(Arc::new(fqdn)).await.is_err() {
+                    return Ok(found);
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
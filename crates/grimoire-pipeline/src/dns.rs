@@ -0,0 +1,71 @@
+use std::{net::IpAddr, sync::Arc};
+
+use dns_recon::{batch::DnsReconBatcher, output::Output, process_lookup_result, stats::Stats};
+use futures::{stream, StreamExt};
+use grimoire::{
+    output::{OutputFormat, Writer},
+    Fqdn, ResultWriter,
+};
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+use leaky_bucket::RateLimiter;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// The second stage, which resolves the FQDNs passed on by the first stage, stores the addresses
+/// just like dns-recon and passes on each pair of FQDN and IP address. Names whose lookup fails
+/// are skipped. Returns the number of FQDNs that resolved to at least one address
+pub async fn run(
+    resolver: &TokioAsyncResolver,
+    concurrency: usize,
+    limiter: &RateLimiter,
+    batcher: Option<&DnsReconBatcher>,
+    mut receiver: mpsc::Receiver<Arc<Fqdn>>,
+    sender: mpsc::Sender<(Arc<Fqdn>, IpAddr)>,
+) -> anyhow::Result<usize> {
+    // Only the results of the last stage are written
+    let output = &Output::new(Writer::new(OutputFormat::Text, ResultWriter::discard()));
+    let stats = &Stats::default();
+
+    let mut lookups = stream::poll_fn(|cx| receiver.poll_recv(cx))
+        .map(|fqdn| async move {
+            limiter.acquire_one().await;
+            let lookup_result = resolver.lookup_ip(format!("{fqdn}.")).await;
+            (fqdn, lookup_result)
+        })
+        .buffer_unordered(concurrency);
+
+    let mut resolved = 0_usize;
+    while let Some((fqdn, lookup_result)) = lookups.next().await {
+        if let Err(e) = &lookup_result {
+            if !matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
+                debug!("Cannot resolve '{}': {}", fqdn, e);
+                continue;
+            }
+        }
+        let ips = process_lookup_result(
+            batcher,
+            None,
+            None,
+            &fqdn,
+            lookup_result,
+            false,
+            None,
+            None,
+            output,
+            stats,
+        )
+        .await?;
+        if ips.is_empty() {
+            continue;
+        }
+        resolved += 1;
+
+        for ip in ips {
+            if sender.send((fqdn.clone(), ip)).await.is_err() {
+                return Ok(resolved);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
@@ -0,0 +1,61 @@
+use std::{net::IpAddr, sync::Arc};
+
+use futures::{stream, StreamExt};
+use grimoire::Fqdn;
+use http_recon::{
+    client_pool::ClientPool, host_limit::HostLimiter, output::Output, recon_http, stats::Stats,
+    writer::ReconDbWriter, ReconOptions,
+};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+/// The last stage, which probes the ports of each pair of FQDN and IP address passed on by the
+/// second stage just like http-recon, and writes and stores the results. Returns the number of
+/// probed URLs that responded
+pub async fn run(
+    clients: Arc<ClientPool>,
+    opts: Arc<ReconOptions>,
+    concurrency: usize,
+    pg_pool: Option<Arc<PgPool>>,
+    db_writer: Option<&ReconDbWriter>,
+    output: Arc<Output>,
+    mut receiver: mpsc::Receiver<(Arc<Fqdn>, IpAddr)>,
+) -> anyhow::Result<usize> {
+    let host_limiter = Arc::new(HostLimiter::new(None, None));
+    let stats = &Stats::new(false);
+
+    let mut probes = stream::poll_fn(|cx| receiver.poll_recv(cx))
+        .map(|(fqdn, ip)| {
+            let pg_pool = pg_pool.clone();
+            let clients = clients.clone();
+            let host_limiter = host_limiter.clone();
+            let output = output.clone();
+            let opts = opts.clone();
+
+            async move {
+                recon_http(
+                    pg_pool,
+                    db_writer,
+                    None,
+                    stats,
+                    clients,
+                    host_limiter,
+                    output,
+                    fqdn,
+                    Arc::new(ip),
+                    opts,
+                )
+                .await?;
+                stats.record_processed();
+
+                anyhow::Ok(())
+            }
+        })
+        .buffer_unordered(concurrency);
+
+    while let Some(result) = probes.next().await {
+        result?;
+    }
+
+    Ok(stats.connected())
+}
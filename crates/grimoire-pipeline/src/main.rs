@@ -0,0 +1,417 @@
+mod cert;
+mod dns;
+mod http;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU32, NonZeroUsize},
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
+
+use cert_recon::json_api::ct_url;
+use clap::Parser;
+use dns_recon::batch::DnsReconBatcher;
+use grimoire::{
+    build_info,
+    cli::{parse_with_config, ConfigArgs, LogArgs, OutputArgs, ReconDbArgs, RuntimeArgs, SeedArgs},
+    exit::{report, EXIT_STATUS_HELP},
+    resolver::udp_resolver_config,
+    shutdown_on_ctrl_c, Fqdn, IpAddrOrFqdn,
+};
+use hickory_resolver::{config::ResolverOpts, TokioAsyncResolver};
+use http_recon::{
+    classify::StatusClassifier,
+    client_pool::{ClientPool, ClientSettings},
+    jitter::JitteredRateLimiter,
+    output::Output,
+    waf::WafSignatures,
+    writer::{OnDbError, ReconDbWriter},
+    ConnectMode, Method, PortSpec, ReconOptions, Scheme,
+};
+use leaky_bucket::RateLimiter;
+use reqwest::{header::HeaderMap, Url};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// Finds the subdomains of each domain in the certificate transparency logs, resolves them and
+/// probes their HTTP(s) ports, just like piping cert-recon into dns-recon into http-recon. The
+/// stages run concurrently within a single process and share the rate limit and the connection to
+/// the recon database
+#[derive(Debug, Parser)]
+#[command(version, long_version = build_info(), about, long_about = None, after_help = EXIT_STATUS_HELP)]
+struct Args {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
+    /// The host name of the certificate transparency log (CT) service, queried via its JSON API
+    #[arg(long, default_value = "crt.sh", env = "CT_HOST")]
+    ct_host: IpAddrOrFqdn,
+    /// The IP address of the DNS server. If omitted, the system configuration is used
+    #[arg(long, env = "DNS_SERVER")]
+    dns_server: Option<IpAddr>,
+    /// The maximum number of requests per minute, shared by all stages
+    #[arg(short, long, default_value = "600")]
+    requests_per_minute: NonZeroU32,
+    /// The maximum number of concurrent DNS lookups
+    #[arg(long, default_value = "50")]
+    dns_concurrency: NonZeroUsize,
+    /// The maximum number of concurrent HTTP(s) requests
+    #[arg(long, default_value = "50")]
+    http_concurrency: NonZeroUsize,
+    /// The number of results each stage may queue for the next. Once the queue is full, a stage
+    /// waits for the next one to catch up
+    #[arg(long, default_value = "100")]
+    queue_size: NonZeroUsize,
+    /// The maximum number of results stored in the recon database with a single statement
+    #[arg(long, default_value = "100")]
+    batch_size: NonZeroUsize,
+    /// Probe HTTP on this port. May be specified multiple times
+    #[arg(long = "http-port", value_name = "PORT", default_values_t = [80_u16])]
+    http_ports: Vec<u16>,
+    /// Probe HTTPS on this port. May be specified multiple times
+    #[arg(long = "https-port", value_name = "PORT", default_values_t = [443_u16])]
+    https_ports: Vec<u16>,
+    /// The timeout of each HTTP(s) request in seconds
+    #[arg(short, long, default_value_t = 10_u64)]
+    timeout_secs: u64,
+    /// The connect timeout of each HTTP(s) request in seconds, so that unresponsive hosts are
+    /// skipped quickly
+    #[arg(long, default_value_t = 5_u64)]
+    connect_timeout_secs: u64,
+    /// When connecting to HTTPS services, accept invalid certificates. Certificates are validated
+    /// first regardless, and responses received over an invalid certificate are marked as such
+    #[arg(short, long)]
+    accept_invalid_certs: bool,
+    /// The user agent sent with each HTTP(s) request
+    #[arg(
+        long,
+        env = "USER_AGENT",
+        default_value = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36"
+    )]
+    user_agent: String,
+    #[command(flatten)]
+    seed: SeedArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+    /// The domains whose subdomains are probed
+    #[arg(required = true)]
+    domains: Vec<Fqdn>,
+}
+
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+    )
+}
+
+/// Everything shared by the stages
+struct Stages {
+    /// The client used to query the CT service
+    ct_client: reqwest::Client,
+    /// The base URL of the JSON API of the CT service
+    ct_url: Url,
+    limiter: Arc<RateLimiter>,
+    resolver: TokioAsyncResolver,
+    dns_concurrency: usize,
+    /// The HTTP clients, which share `limiter`
+    clients: Arc<ClientPool>,
+    opts: Arc<ReconOptions>,
+    http_concurrency: usize,
+    output: Arc<Output>,
+    recon_pg_pool: Option<Arc<PgPool>>,
+    queue_size: usize,
+    batch_size: NonZeroUsize,
+}
+
+/// The number of results of each stage
+struct Counts {
+    found: usize,
+    resolved: usize,
+    responded: usize,
+}
+
+impl Stages {
+    /// Runs the stages concurrently on the subdomains of `domains` until all of them have been
+    /// probed, then flushes the results
+    async fn run(self, domains: Vec<Fqdn>, shutdown: &CancellationToken) -> anyhow::Result<Counts> {
+        let batcher = self
+            .recon_pg_pool
+            .as_deref()
+            .map(|pg_pool| DnsReconBatcher::spawn(pg_pool.clone(), self.batch_size));
+        let db_writer = self.recon_pg_pool.as_deref().map(|pg_pool| {
+            ReconDbWriter::spawn(pg_pool.clone(), self.batch_size, OnDbError::Abort)
+        });
+
+        let (fqdn_sender, fqdn_receiver) = mpsc::channel(self.queue_size);
+        let (target_sender, target_receiver) = mpsc::channel(self.queue_size);
+        let (found, resolved, responded) = tokio::try_join!(
+            cert::run(
+                &self.ct_client,
+                &self.ct_url,
+                &self.limiter,
+                self.recon_pg_pool.as_deref(),
+                domains,
+                fqdn_sender,
+                shutdown,
+            ),
+            dns::run(
+                &self.resolver,
+                self.dns_concurrency,
+                &self.limiter,
+                batcher.as_ref(),
+                fqdn_receiver,
+                target_sender,
+            ),
+            http::run(
+                self.clients,
+                self.opts,
+                self.http_concurrency,
+                self.recon_pg_pool.clone(),
+                db_writer.as_ref(),
+                self.output.clone(),
+                target_receiver,
+            ),
+        )?;
+
+        if let Some(batcher) = batcher {
+            batcher.finish().await?;
+        }
+        if let Some(db_writer) = db_writer {
+            db_writer.finish().await?;
+        }
+        self.output.finish().await?;
+
+        Ok(Counts {
+            found,
+            resolved,
+            responded,
+        })
+    }
+}
+
+/// The settings with which the last stage probes each target, which are the defaults of
+/// http-recon, except that known FQDNs are probed again
+fn recon_options(ports: Vec<PortSpec>) -> ReconOptions {
+    ReconOptions {
+        query_known_fqdns: true,
+        only_changed: false,
+        connect_mode: ConnectMode::Ip,
+        compare: false,
+        sni: true,
+        anonymize_headers: true,
+        method: Method::Get,
+        max_body_bytes: 64 * 1024,
+        follow_redirects: 0,
+        favicon: false,
+        waf_signatures: WafSignatures::builtin(),
+        status_classifier: StatusClassifier::new(Vec::new()),
+        asn_lookup: None,
+        credentials: Vec::new(),
+        ports,
+        retries: 0,
+        retry_backoff: Duration::from_millis(500),
+    }
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    args.log.init();
+
+    let shutdown = shutdown_on_ctrl_c();
+
+    let recon_pg_pool = if args.recon_db.enable_db_storage {
+        debug!("Establishing a connection to the recon database");
+        Some(Arc::new(args.recon_db.connect().await?))
+    } else {
+        None
+    };
+
+    debug!("Creating the resolver");
+    let resolver = match args.dns_server {
+        Some(dns_server) => TokioAsyncResolver::tokio(
+            udp_resolver_config(SocketAddr::new(dns_server, 53)),
+            ResolverOpts::default(),
+        ),
+        None => TokioAsyncResolver::tokio_from_system_conf()?,
+    };
+
+    let limiter = Arc::new(
+        RateLimiter::builder()
+            .initial(0)
+            .refill(1)
+            .interval(Duration::from_secs(60) / args.requests_per_minute.get())
+            .max(args.requests_per_minute.get() as usize)
+            .build(),
+    );
+
+    debug!("Creating the HTTP client, which shares the rate limit with the other stages");
+    let settings = ClientSettings {
+        user_agent: args.user_agent.clone(),
+        default_headers: HeaderMap::new(),
+        timeout: Duration::from_secs(args.timeout_secs),
+        connect_timeout: Duration::from_secs(args.connect_timeout_secs),
+        http1_only: false,
+        accept_invalid_certs: args.accept_invalid_certs,
+        rate_limiter: Arc::new(reqwest_ratelimit::all(JitteredRateLimiter::new(
+            limiter.clone(),
+            Duration::ZERO,
+            args.seed.rng(),
+        ))),
+    };
+    let ports = args
+        .http_ports
+        .iter()
+        .map(|port| (*port, Scheme::Http))
+        .chain(args.https_ports.iter().map(|port| (*port, Scheme::Https)))
+        .map(|(port, scheme)| PortSpec { port, scheme })
+        .collect();
+
+    let stages = Stages {
+        ct_client: reqwest::Client::new(),
+        ct_url: ct_url(&args.ct_host)?,
+        limiter,
+        resolver,
+        dns_concurrency: args.dns_concurrency.get(),
+        clients: Arc::new(ClientPool::new(settings, vec![None])?),
+        opts: Arc::new(recon_options(ports)),
+        http_concurrency: args.http_concurrency.get(),
+        output: Arc::new(Output::new(args.output.writer().await?, None)),
+        recon_pg_pool,
+        queue_size: args.queue_size.get(),
+        batch_size: args.batch_size,
+    };
+    let counts = stages.run(args.domains.clone(), &shutdown).await?;
+
+    eprintln!("Domains:       {}", args.domains.len());
+    eprintln!("Subdomains:    {}", counts.found);
+    eprintln!("Resolved:      {}", counts.resolved);
+    eprintln!("Responded:     {}", counts.responded);
+    if shutdown.is_cancelled() {
+        eprintln!("Interrupted after finding {} subdomains", counts.found);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, str::FromStr};
+
+    use grimoire::output::{OutputFormat, ResultWriter, Writer};
+    use grimoire_test::{
+        dns::{StubServer, UnknownNames},
+        http::serve,
+        temp_dir,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// The raw response with status 200 and `body` of `content_type`
+    fn respond_ok(content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn subdomains_from_the_ct_logs_are_probed() {
+        let ct_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ct_addr = ct_listener.local_addr().unwrap();
+        tokio::spawn(serve(ct_listener, |_| {
+            respond_ok(
+                "application/json",
+                r#"[{"name_value": "www.example.com\nexample.com", "issuer_name": "C=US, O=Test CA"}]"#,
+            )
+        }));
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_port = http_listener.local_addr().unwrap().port();
+        tokio::spawn(serve(http_listener, |_| {
+            respond_ok(
+                "text/html",
+                "<html><head><title>Example</title></head></html>",
+            )
+        }));
+        let dns_server = StubServer::builder(Vec::new())
+            .unknown_names(UnknownNames::Resolve(Ipv4Addr::LOCALHOST))
+            .spawn()
+            .await;
+        let dns_addr = dns_server.addr();
+
+        let dir = temp_dir();
+        let path = dir.path().join("results.ndjson");
+        let writer = Writer::new(
+            OutputFormat::Ndjson,
+            ResultWriter::new(true, Some(&path), false).await.unwrap(),
+        );
+        let limiter = Arc::new(RateLimiter::builder().initial(100).max(100).build());
+        let settings = ClientSettings {
+            user_agent: "grimoire-pipeline-test".to_string(),
+            default_headers: HeaderMap::new(),
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(5),
+            http1_only: false,
+            accept_invalid_certs: false,
+            rate_limiter: Arc::new(reqwest_ratelimit::all(JitteredRateLimiter::new(
+                limiter.clone(),
+                Duration::ZERO,
+                StdRng::seed_from_u64(0),
+            ))),
+        };
+        let stages = Stages {
+            ct_client: reqwest::Client::new(),
+            ct_url: Url::parse(&format!("http://{ct_addr}/")).unwrap(),
+            limiter,
+            resolver: TokioAsyncResolver::tokio(
+                udp_resolver_config(dns_addr),
+                ResolverOpts::default(),
+            ),
+            dns_concurrency: 2,
+            clients: Arc::new(ClientPool::new(settings, vec![None]).unwrap()),
+            opts: Arc::new(recon_options(vec![PortSpec {
+                port: http_port,
+                scheme: Scheme::Http,
+            }])),
+            http_concurrency: 2,
+            output: Arc::new(Output::new(writer, None)),
+            recon_pg_pool: None,
+            queue_size: 4,
+            batch_size: NonZeroUsize::new(10).unwrap(),
+        };
+
+        let counts = stages
+            .run(
+                vec![Fqdn::from_str("example.com").unwrap()],
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let output = std::fs::read_to_string(&path).unwrap();
+
+        assert_eq!(counts.found, 1);
+        assert_eq!(counts.resolved, 1);
+        assert_eq!(counts.responded, 1);
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["fqdn"], "www.example.com");
+        assert_eq!(records[0]["response-status"], 200);
+        assert_eq!(records[0]["title"], "Example");
+    }
+}
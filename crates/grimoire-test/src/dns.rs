@@ -0,0 +1,267 @@
+//! A DNS server for tests, which answers queries from a fixed set of records via UDP and TCP
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use hickory_resolver::proto::{
+    op::{Message, MessageType, ResponseCode},
+    rr::{dnssec::rdata::DNSSECRData, rdata::A, RData, Record, RecordType},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+};
+
+/// How the server answers the queries for names without any records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownNames {
+    /// The names do not exist, i.e. the queries yield NXDOMAIN
+    NxDomain,
+    /// The names exist, but have no records
+    NoRecords,
+    /// The A queries for the names yield this address, all others no records
+    Resolve(Ipv4Addr),
+}
+
+/// The records a server answers with, and how it answers the queries for other names
+#[derive(Debug)]
+struct Zone {
+    records: Vec<Record>,
+    unknown_names: UnknownNames,
+    delay: Duration,
+}
+
+/// Configures a [`StubServer`] before spawning it
+#[derive(Debug)]
+pub struct StubServerBuilder {
+    zone: Zone,
+}
+
+impl StubServerBuilder {
+    /// Answers the queries for names without any records according to `unknown_names`, instead of
+    /// with NXDOMAIN
+    pub fn unknown_names(mut self, unknown_names: UnknownNames) -> Self {
+        self.zone.unknown_names = unknown_names;
+        self
+    }
+
+    /// Delays each response by `delay`, such that queries are pending for a while
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.zone.delay = delay;
+        self
+    }
+
+    pub async fn spawn(self) -> StubServer {
+        let (socket, listener) = bind().await;
+        let addr = socket.local_addr().unwrap();
+        let server = StubServer {
+            addr,
+            queries: Arc::default(),
+            pending: Arc::default(),
+            max_pending: Arc::default(),
+        };
+        let zone = Arc::new(self.zone);
+
+        let udp_server = server.clone();
+        let udp_zone = zone.clone();
+        tokio::spawn(async move {
+            let mut buf = [0; 4096];
+            loop {
+                let Ok((n, peer)) = socket.recv_from(&mut buf).await else {
+                    return;
+                };
+                let query = buf[..n].to_vec();
+                let (server, zone, socket) = (udp_server.clone(), udp_zone.clone(), socket.clone());
+                tokio::spawn(async move {
+                    if let Some(response) = server.respond(&query, &zone).await {
+                        let _ = socket.send_to(&response, peer).await;
+                    }
+                });
+            }
+        });
+
+        let tcp_server = server.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(tcp_server.clone().serve_tcp(stream, zone.clone()));
+            }
+        });
+
+        server
+    }
+}
+
+/// A DNS server on localhost that answers each query with the records of the queried name and
+/// type. A name without any records yields NXDOMAIN, unless configured otherwise. Zone transfers
+/// return all records, framed by the SOA record. The RRSIG records covering the queried type are
+/// answered along with the records. The OPT record of each query is echoed in its response
+#[derive(Debug, Clone)]
+pub struct StubServer {
+    addr: SocketAddr,
+    queries: Arc<Mutex<Vec<Message>>>,
+    pending: Arc<AtomicUsize>,
+    max_pending: Arc<AtomicUsize>,
+}
+
+impl StubServer {
+    pub async fn spawn(records: Vec<Record>) -> Self {
+        StubServer::builder(records).spawn().await
+    }
+
+    pub fn builder(records: Vec<Record>) -> StubServerBuilder {
+        StubServerBuilder {
+            zone: Zone {
+                records,
+                unknown_names: UnknownNames::NxDomain,
+                delay: Duration::ZERO,
+            },
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The queries received so far
+    pub fn queries(&self) -> Vec<Message> {
+        self.queries.lock().unwrap().clone()
+    }
+
+    /// The number of queries that have been received, but not answered yet
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// The largest number of queries that were pending at the same time
+    pub fn max_pending(&self) -> usize {
+        self.max_pending.load(Ordering::SeqCst)
+    }
+
+    async fn serve_tcp(self, mut stream: TcpStream, zone: Arc<Zone>) {
+        loop {
+            let Ok(len) = stream.read_u16().await else {
+                return;
+            };
+            let mut buf = vec![0; usize::from(len)];
+            if stream.read_exact(&mut buf).await.is_err() {
+                return;
+            }
+            let Some(response) = self.respond(&buf, &zone).await else {
+                return;
+            };
+            let len = u16::try_from(response.len()).unwrap();
+            if stream.write_u16(len).await.is_err() || stream.write_all(&response).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn respond(&self, query: &[u8], zone: &Zone) -> Option<Vec<u8>> {
+        let query = Message::from_vec(query).ok()?;
+        self.queries.lock().unwrap().push(query.clone());
+        let pending = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_pending.fetch_max(pending, Ordering::SeqCst);
+
+        tokio::time::sleep(zone.delay).await;
+        let response = answer(&query, zone);
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+
+        response?.to_vec().ok()
+    }
+}
+
+/// Binds a UDP socket and a TCP listener to the same port on localhost, trying other ports until
+/// one is free for both
+async fn bind() -> (Arc<UdpSocket>, TcpListener) {
+    loop {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        if let Ok(listener) = TcpListener::bind(socket.local_addr().unwrap()).await {
+            return (Arc::new(socket), listener);
+        }
+    }
+}
+
+/// The response to `query` from the records of `zone`, or `None` if a zone transfer is requested
+/// of a zone without an SOA record
+fn answer(query: &Message, zone: &Zone) -> Option<Message> {
+    let mut response = Message::new();
+    response
+        .set_id(query.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(query.op_code())
+        .set_recursion_desired(query.recursion_desired())
+        .set_recursion_available(true)
+        .set_authoritative(true)
+        .add_queries(query.queries().to_vec());
+    if let Some(edns) = query.extensions() {
+        response.set_edns(edns.clone());
+    }
+
+    let records = &zone.records;
+    let mut response_code = ResponseCode::NoError;
+    for question in query.queries() {
+        if question.query_type() == RecordType::AXFR {
+            let soa = records
+                .iter()
+                .find(|record| record.record_type() == RecordType::SOA)?;
+            response.add_answer(soa.clone());
+            response.add_answers(
+                records
+                    .iter()
+                    .filter(|record| record.record_type() != RecordType::SOA)
+                    .cloned(),
+            );
+            response.add_answer(soa.clone());
+            continue;
+        }
+
+        let mut known = false;
+        for record in records {
+            if record.name() != question.name() {
+                continue;
+            }
+            known = true;
+            if record.record_type() == question.query_type()
+                || covered_type(record) == Some(question.query_type())
+            {
+                response.add_answer(record.clone());
+            }
+        }
+        if known {
+            continue;
+        }
+        match zone.unknown_names {
+            UnknownNames::NxDomain => response_code = ResponseCode::NXDomain,
+            UnknownNames::NoRecords => {}
+            UnknownNames::Resolve(ip) => {
+                if question.query_type() == RecordType::A {
+                    response.add_answer(Record::from_rdata(
+                        question.name().clone(),
+                        60,
+                        RData::A(A::from(ip)),
+                    ));
+                }
+            }
+        }
+    }
+    response.set_response_code(response_code);
+
+    Some(response)
+}
+
+/// The type of the records signed by `record`, if it is an RRSIG record
+fn covered_type(record: &Record) -> Option<RecordType> {
+    match record.data()? {
+        RData::DNSSEC(DNSSECRData::RRSIG(rrsig)) => Some(rrsig.type_covered()),
+        _ => None,
+    }
+}
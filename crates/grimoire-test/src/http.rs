@@ -0,0 +1,47 @@
+//! An HTTP server for tests, which answers each request with a raw response
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Reads the head of a request from `stream`, i.e. its request line and headers. Returns `None` if
+/// the connection is closed before the head is complete
+pub async fn read_request_head(stream: &mut (impl AsyncRead + Unpin)) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    Some(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Answers each request on `listener` with the raw response that `respond` returns for the head of
+/// the request, and closes the connection afterwards
+pub async fn serve<F>(listener: TcpListener, respond: F)
+where
+    F: Fn(&str) -> String + Clone + Send + 'static,
+{
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let respond = respond.clone();
+        tokio::spawn(async move {
+            let Some(request) = read_request_head(&mut stream).await else {
+                return;
+            };
+            let response = respond(&request);
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// The raw response with status 200 and `body`
+pub fn ok_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,15 @@
+//! Stub servers and temporary files shared by the tests of the workspace
+
+pub mod dns;
+pub mod http;
+
+pub use tempfile::TempDir;
+
+/// Creates a directory for the files of a test, which is removed along with its contents once the
+/// returned handle is dropped
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("grimoire-test-")
+        .tempdir()
+        .unwrap()
+}
@@ -0,0 +1,17 @@
+use std::process::Command;
+
+fn main() {
+    // The commit is optional, since builds from a source archive have no git repository
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=GRIMOIRE_GIT_COMMIT={}", commit.trim());
+    }
+
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs/heads");
+}
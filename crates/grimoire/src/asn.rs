@@ -0,0 +1,291 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use hickory_resolver::{error::ResolveError, TokioAsyncResolver};
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::debug;
+
+/// The zones of Team Cymru's IP to ASN mapping service
+const CYMRU_ORIGIN_ZONE: &str = "origin.asn.cymru.com.";
+const CYMRU_ORIGIN6_ZONE: &str = "origin6.asn.cymru.com.";
+const CYMRU_ASN_ZONE: &str = "asn.cymru.com.";
+
+#[derive(Debug, Error)]
+pub enum AsnError {
+    #[error("Cannot open the ASN database '{}': {}", .0.display(), .1)]
+    Database(PathBuf, MaxMindDBError),
+    #[error("Cannot create the resolver for the ASN lookups: {0}")]
+    Resolver(#[from] ResolveError),
+}
+
+/// The autonomous system an IP address belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AsnInfo {
+    pub asn: u32,
+    /// The name of the organization operating the autonomous system, if known
+    pub org: Option<String>,
+}
+
+impl Display for AsnInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AS{}", self.asn)
+    }
+}
+
+/// The autonomous systems of the IP addresses resolved for a name
+pub type AsnMap = BTreeMap<IpAddr, AsnInfo>;
+
+/// Where the autonomous systems are looked up
+enum AsnSource {
+    /// A MaxMind ASN database, e.g. GeoLite2-ASN
+    Database(Reader<Vec<u8>>),
+    /// The DNS service of Team Cymru
+    Cymru(Box<TokioAsyncResolver>),
+}
+
+/// Looks up the autonomous system of IP addresses, caching the result for each address such that
+/// it is looked up at most once per run
+pub struct AsnLookup {
+    source: AsnSource,
+    cache: Mutex<HashMap<IpAddr, Option<AsnInfo>>>,
+}
+
+impl std::fmt::Debug for AsnLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self.source {
+            AsnSource::Database(_) => "database",
+            AsnSource::Cymru(_) => "cymru",
+        };
+        f.debug_struct("AsnLookup")
+            .field("source", &source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsnLookup {
+    /// Looks up the autonomous systems in the MaxMind ASN database at `path`
+    pub fn open(path: &Path) -> Result<Self, AsnError> {
+        let reader =
+            Reader::open_readfile(path).map_err(|e| AsnError::Database(path.to_path_buf(), e))?;
+
+        Ok(Self::new(AsnSource::Database(reader)))
+    }
+
+    /// Looks up the autonomous systems via Team Cymru's DNS service, using the system resolver
+    pub fn cymru() -> Result<Self, AsnError> {
+        Ok(Self::new(AsnSource::Cymru(Box::new(
+            TokioAsyncResolver::tokio_from_system_conf()?,
+        ))))
+    }
+
+    fn new(source: AsnSource) -> Self {
+        AsnLookup {
+            source,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the autonomous system of `ip`, or `None` if it is unknown or the lookup failed
+    pub async fn lookup(&self, ip: IpAddr) -> Option<AsnInfo> {
+        if let Some(cached) = self.cache.lock().expect("poisoned lock").get(&ip) {
+            return cached.clone();
+        }
+
+        let info = match &self.source {
+            AsnSource::Database(reader) => lookup_database(reader, ip),
+            AsnSource::Cymru(resolver) => lookup_cymru(resolver, ip).await,
+        };
+        self.cache
+            .lock()
+            .expect("poisoned lock")
+            .insert(ip, info.clone());
+
+        info
+    }
+
+    /// Returns the autonomous systems of all `ips` that are known
+    pub async fn lookup_all(&self, ips: &[IpAddr]) -> AsnMap {
+        let mut asns = AsnMap::new();
+        for ip in ips {
+            if let Some(info) = self.lookup(*ip).await {
+                asns.insert(*ip, info);
+            }
+        }
+
+        asns
+    }
+}
+
+fn lookup_database(reader: &Reader<Vec<u8>>, ip: IpAddr) -> Option<AsnInfo> {
+    let asn: geoip2::Asn = reader
+        .lookup(ip)
+        .map_err(|e| debug!("Cannot look up the ASN of {}: {}", ip, e))
+        .ok()?;
+
+    Some(AsnInfo {
+        asn: asn.autonomous_system_number?,
+        org: asn.autonomous_system_organization.map(str::to_string),
+    })
+}
+
+/// The name queried for the origin of `ip`, i.e. its reversed octets or nibbles within the origin
+/// zone
+fn cymru_origin_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => format!(
+            "{}.{CYMRU_ORIGIN_ZONE}",
+            ip.octets()
+                .iter()
+                .rev()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(".")
+        ),
+        IpAddr::V6(ip) => format!(
+            "{}.{CYMRU_ORIGIN6_ZONE}",
+            ip.octets()
+                .iter()
+                .rev()
+                .map(|octet| format!("{:x}.{:x}", octet & 0xf, octet >> 4))
+                .collect::<Vec<_>>()
+                .join(".")
+        ),
+    }
+}
+
+/// Queries the TXT record at `name` and returns its fields, which are separated by '|'
+async fn cymru_fields(resolver: &TokioAsyncResolver, name: &str) -> Option<Vec<String>> {
+    let lookup = resolver
+        .txt_lookup(name)
+        .await
+        .map_err(|e| debug!("Cannot look up '{}': {}", name, e))
+        .ok()?;
+    let txt = lookup.iter().next()?;
+
+    Some(
+        txt.to_string()
+            .split('|')
+            .map(|field| field.trim().to_string())
+            .collect(),
+    )
+}
+
+/// Looks up the origin AS of `ip`, e.g. '13335 | 1.1.1.0/24 | AU | apnic | 2011-08-11', and then
+/// the name of the AS, e.g. '13335 | US | arin | 2010-07-14 | CLOUDFLARENET - Cloudflare, Inc., US'.
+/// Addresses announced by several ASes are attributed to the first one
+async fn lookup_cymru(resolver: &TokioAsyncResolver, ip: IpAddr) -> Option<AsnInfo> {
+    let origin = cymru_fields(resolver, &cymru_origin_name(ip)).await?;
+    let asn = origin.first()?.split_whitespace().next()?.parse().ok()?;
+    let org = cymru_fields(resolver, &format!("AS{asn}.{CYMRU_ASN_ZONE}"))
+        .await
+        .and_then(|fields| fields.into_iter().nth(4))
+        .filter(|org| !org.is_empty());
+
+    Some(AsnInfo { asn, org })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use grimoire_test::dns::StubServer;
+    use hickory_resolver::{
+        config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+        proto::rr::{rdata::TXT, Name, RData, Record},
+    };
+
+    use super::*;
+
+    /// Answers the TXT queries for the origin of 192.0.2.1 and the name of AS64496 like Team
+    /// Cymru's service does, regardless of case. Any other name yields NXDOMAIN
+    async fn spawn_cymru_stub() -> StubServer {
+        let txt = |name: &str, txt: &str| {
+            Record::from_rdata(
+                Name::from_ascii(name).unwrap(),
+                60,
+                RData::TXT(TXT::new(vec![txt.to_string()])),
+            )
+        };
+
+        StubServer::spawn(vec![
+            txt(
+                "1.2.0.192.origin.asn.cymru.com.",
+                "64496 | 192.0.2.0/24 | ZZ | test | 2024-01-01",
+            ),
+            txt(
+                "as64496.asn.cymru.com.",
+                "64496 | ZZ | test | 2024-01-01 | EXAMPLE-NET",
+            ),
+        ])
+        .await
+    }
+
+    fn cymru_lookup(addr: SocketAddr) -> AsnLookup {
+        let config = ResolverConfig::from_parts(
+            None,
+            Vec::new(),
+            NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true),
+        );
+        let mut opts = ResolverOpts::default();
+        opts.cache_size = 0;
+
+        AsnLookup::new(AsnSource::Cymru(Box::new(TokioAsyncResolver::tokio(
+            config, opts,
+        ))))
+    }
+
+    #[test]
+    fn origin_names_reverse_the_address() {
+        assert_eq!(
+            cymru_origin_name("192.0.2.1".parse().unwrap()),
+            "1.2.0.192.origin.asn.cymru.com."
+        );
+        assert!(
+            cymru_origin_name("2001:db8::1".parse().unwrap()).starts_with(
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.origin6."
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_asn_and_the_organization_are_attached() {
+        let lookup = cymru_lookup(spawn_cymru_stub().await.addr());
+        let known = "192.0.2.1".parse().unwrap();
+        let unknown = "198.51.100.1".parse().unwrap();
+
+        let asns = lookup.lookup_all(&[known, unknown]).await;
+
+        assert_eq!(
+            asns,
+            AsnMap::from([(
+                known,
+                AsnInfo {
+                    asn: 64496,
+                    org: Some("EXAMPLE-NET".to_string())
+                }
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn each_address_is_looked_up_once() {
+        let server = spawn_cymru_stub().await;
+        let lookup = cymru_lookup(server.addr());
+        let ip = "192.0.2.1".parse().unwrap();
+
+        let first = lookup.lookup(ip).await;
+        let sent = server.queries().len();
+        let second = lookup.lookup(ip).await;
+
+        assert_eq!(first.as_ref().map(|info| info.asn), Some(64496));
+        assert_eq!(second, first);
+        assert_eq!(server.queries().len(), sent);
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::OnceLock;
+
+/// The optional features of this library that were enabled at compile time
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "clap")]
+    "clap",
+    #[cfg(feature = "strict-fqdn-validation")]
+    "strict-fqdn-validation",
+];
+
+/// Describes the build, consisting of the version, the git commit if it was known at compile time,
+/// and the enabled features. Suitable as the long version of the binaries
+pub fn build_info() -> &'static str {
+    static BUILD_INFO: OnceLock<String> = OnceLock::new();
+
+    BUILD_INFO.get_or_init(|| {
+        let mut info = env!("CARGO_PKG_VERSION").to_string();
+        if let Some(commit) = option_env!("GRIMOIRE_GIT_COMMIT") {
+            info.push_str(&format!(" ({commit})"));
+        }
+        if FEATURES.is_empty() {
+            info.push_str("\nfeatures: none");
+        } else {
+            info.push_str(&format!("\nfeatures: {}", FEATURES.join(", ")));
+        }
+
+        info
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_feature_list_reflects_the_strict_fqdn_validation_cfg() {
+        assert_eq!(
+            FEATURES.contains(&"strict-fqdn-validation"),
+            cfg!(feature = "strict-fqdn-validation")
+        );
+        assert_eq!(
+            build_info().contains("strict-fqdn-validation"),
+            cfg!(feature = "strict-fqdn-validation")
+        );
+    }
+
+    #[test]
+    fn the_build_info_starts_with_the_version() {
+        let info = build_info();
+
+        assert!(info.starts_with(env!("CARGO_PKG_VERSION")), "{info}");
+        assert!(
+            info.lines().last().unwrap().starts_with("features: "),
+            "{info}"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::{net::IpAddr, sync::Arc};
+
+use futures::{stream, Stream, StreamExt};
+use hickory_resolver::{error::ResolveErrorKind, name_server::ConnectionProvider, AsyncResolver};
+use ipnetwork::IpNetwork;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::Fqdn;
+
+/// Networks with more host addresses than this are only expanded if forced
+pub const MAX_NETWORK_HOSTS: u128 = 1 << 16;
+
+#[derive(Debug, Error)]
+#[error("The network {0} holds {1} host addresses, more than the limit of {MAX_NETWORK_HOSTS}")]
+pub struct NetworkTooLarge(IpNetwork, u128);
+
+/// Counts the host addresses of a network. The network and broadcast addresses of IPv4 networks
+/// larger than /31 are not host addresses
+pub fn host_count(network: &IpNetwork) -> u128 {
+    match network {
+        IpNetwork::V4(network) if network.prefix() < 31 => (1 << (32 - network.prefix())) - 2,
+        IpNetwork::V4(network) => 1 << (32 - network.prefix()),
+        IpNetwork::V6(network) => 1_u128
+            .checked_shl(u32::from(128 - network.prefix()))
+            .unwrap_or(u128::MAX),
+    }
+}
+
+/// Returns the host addresses of a network in ascending order
+pub fn hosts(network: IpNetwork) -> impl Iterator<Item = IpAddr> {
+    let skip_ends = matches!(network, IpNetwork::V4(network) if network.prefix() < 31);
+    let broadcast = network.broadcast();
+
+    network
+        .iter()
+        .skip(usize::from(skip_ends))
+        .filter(move |ip| !skip_ends || *ip != broadcast)
+}
+
+/// Expands the networks into their host addresses. Unless `force` is set, networks with more than
+/// `MAX_NETWORK_HOSTS` host addresses are rejected before any address is produced, so that huge
+/// IPv6 ranges are not scanned by accident
+pub fn expand_networks(
+    networks: Vec<IpNetwork>,
+    force: bool,
+) -> Result<impl Iterator<Item = IpAddr>, NetworkTooLarge> {
+    if !force {
+        if let Some(network) = networks
+            .iter()
+            .find(|network| host_count(network) > MAX_NETWORK_HOSTS)
+        {
+            return Err(NetworkTooLarge(*network, host_count(network)));
+        }
+    }
+
+    Ok(networks.into_iter().flat_map(hosts))
+}
+
+/// Looks up the PTR records of the host addresses, `concurrency` at a time, and yields each name
+/// along with its address, in the order of the addresses. Addresses without PTR records are skipped
+pub fn reverse_lookup<P: ConnectionProvider>(
+    resolver: Arc<AsyncResolver<P>>,
+    hosts: impl Iterator<Item = IpAddr>,
+    concurrency: usize,
+) -> impl Stream<Item = (Fqdn, IpAddr)> {
+    stream::iter(hosts)
+        .map(move |ip| {
+            let resolver = resolver.clone();
+            async move { (ip, resolver.reverse_lookup(ip).await) }
+        })
+        .buffered(concurrency)
+        .flat_map(|(ip, lookup_result)| {
+            let names: Vec<_> = match lookup_result {
+                Ok(lookup) => lookup.iter().map(|ptr| (Fqdn::from(&ptr.0), ip)).collect(),
+                Err(e) => {
+                    match e.kind() {
+                        ResolveErrorKind::NoRecordsFound { .. } => {
+                            debug!("No PTR records found for {}", ip)
+                        }
+                        _ => warn!("Error looking up the PTR records of {}: {}", ip, e),
+                    }
+                    Vec::new()
+                }
+            };
+
+            stream::iter(names)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(networks: &[&str], force: bool) -> Result<Vec<String>, NetworkTooLarge> {
+        let networks = networks
+            .iter()
+            .map(|network| network.parse().unwrap())
+            .collect();
+
+        Ok(expand_networks(networks, force)?
+            .map(|ip| ip.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn a_slash_30_expands_into_its_two_hosts() {
+        assert_eq!(
+            expanded(&["192.0.2.4/30"], false).unwrap(),
+            ["192.0.2.5", "192.0.2.6"]
+        );
+    }
+
+    #[test]
+    fn point_to_point_and_host_networks_have_no_reserved_addresses() {
+        assert_eq!(
+            expanded(&["192.0.2.4/31", "192.0.2.9/32"], false).unwrap(),
+            ["192.0.2.4", "192.0.2.5", "192.0.2.9"]
+        );
+        assert_eq!(
+            expanded(&["2001:db8::/127"], false).unwrap(),
+            ["2001:db8::", "2001:db8::1"]
+        );
+    }
+
+    #[test]
+    fn huge_networks_are_only_expanded_if_forced() {
+        assert!(expanded(&["192.0.2.0/24", "2001:db8::/64"], false).is_err());
+        assert_eq!(host_count(&"2001:db8::/64".parse().unwrap()), 1 << 64);
+        assert_eq!(host_count(&"::/0".parse().unwrap()), u128::MAX);
+
+        let networks = vec!["2001:db8::/64".parse().unwrap()];
+        assert_eq!(expand_networks(networks, true).unwrap().take(3).count(), 3);
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    ffi::OsString,
+    net::IpAddr,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use clap::{error::ErrorKind, parser::ValueSource, ArgAction, ArgMatches, Command, Parser};
+use futures::{Stream, StreamExt};
+use ipnetwork::IpNetwork;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio::runtime::Runtime;
+use tracing::info;
+
+use crate::{
+    asn::{AsnError, AsnLookup},
+    cidr::{expand_networks, NetworkTooLarge},
+    connect_recon_db, create_recon_db_pool, init_logging,
+    output::{OutputFormat, ResultWriter, Writer},
+    rejects::Rejects,
+    shuffle::shuffle_window,
+    LogFormat, ReconDbError,
+};
+
+/// Command line arguments for the connection to the recon database, shared by all binaries
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReconDbArgs {
+    /// The IPv4 or IPv6 address or the host name of the recon database service
+    #[arg(long, default_value = "localhost", env = "RECON_DB_HOST")]
+    pub recon_db_host: String,
+    /// The username used for authenticating with the recon database service
+    #[arg(long, default_value = "recon", env = "RECON_DB_USERNAME")]
+    pub recon_db_username: String,
+    /// The password used for authenticating with the recon database service
+    #[arg(long, env = "RECON_DB_PASSWORD")]
+    pub recon_db_password: Option<String>,
+    /// The database to connect to when using the recon database service
+    #[arg(long, default_value = "recon", env = "RECON_DB_DATABASE")]
+    pub recon_db_database: String,
+    /// Keep the tables in this schema of the recon database instead of the default one, such that
+    /// independent datasets can share a database. The schema is created if required
+    #[arg(long, env = "RECON_DB_SCHEMA")]
+    pub recon_db_schema: Option<String>,
+    /// If enabled, store the results in the recon database
+    #[arg(short, long)]
+    pub enable_db_storage: bool,
+}
+
+impl ReconDbArgs {
+    /// Creates the connection pool for the recon database, checks that it is reachable and applies
+    /// any pending migrations
+    pub async fn connect(&self) -> Result<PgPool, ReconDbError> {
+        create_recon_db_pool(
+            &self.recon_db_host,
+            &self.recon_db_username,
+            self.recon_db_password.as_deref(),
+            &self.recon_db_database,
+            self.recon_db_schema.as_deref(),
+        )
+        .await
+    }
+
+    /// Creates the connection pool for the recon database and checks that it is reachable, without
+    /// creating the schema or applying any migrations
+    pub async fn connect_unmigrated(&self) -> Result<PgPool, ReconDbError> {
+        connect_recon_db(
+            &self.recon_db_host,
+            &self.recon_db_username,
+            self.recon_db_password.as_deref(),
+            &self.recon_db_database,
+            self.recon_db_schema.as_deref(),
+        )
+        .await
+    }
+}
+
+/// Command line arguments controlling where and in which format results are written, shared by all
+/// binaries
+#[derive(Debug, Clone, clap::Args)]
+pub struct OutputArgs {
+    /// Disable output to stdout
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// Additionally write the results to this file
+    #[arg(long, value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+    /// Append to the output file instead of truncating it
+    #[arg(long, requires = "output_file")]
+    pub append: bool,
+    /// The format of the results written to stdout and the output file
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+impl OutputArgs {
+    /// Opens the output file, if any, and creates a writer for the selected format
+    pub async fn writer(&self) -> std::io::Result<Writer> {
+        let writer =
+            ResultWriter::new(self.quiet, self.output_file.as_deref(), self.append).await?;
+
+        Ok(Writer::new(self.output, writer))
+    }
+}
+
+/// Command line arguments controlling the log messages, shared by all binaries
+#[derive(Debug, Clone, clap::Args)]
+pub struct LogArgs {
+    /// The format of the log messages written to stderr. The verbosity is controlled by RUST_LOG
+    #[arg(long, value_enum, env = "RECON_LOG_FORMAT", default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// Installs the global tracing subscriber in the selected format
+    pub fn init(&self) {
+        init_logging(self.log_format);
+    }
+}
+
+/// Command line argument sizing the async runtime, shared by all binaries
+#[derive(Debug, Clone, clap::Args)]
+pub struct RuntimeArgs {
+    /// The number of worker threads of the async runtime. Defaults to one per CPU core. Lower it
+    /// when running several binaries on the same machine
+    #[arg(long, value_name = "N", env = "TOKIO_WORKER_THREADS")]
+    pub worker_threads: Option<NonZeroUsize>,
+}
+
+impl RuntimeArgs {
+    /// Builds the multi-threaded runtime the binary runs on
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads.get());
+        }
+
+        builder.build()
+    }
+}
+
+/// Command line arguments controlling how lines of the input that cannot be parsed are handled,
+/// shared by the binaries that read their input line by line
+#[derive(Debug, Clone, clap::Args)]
+pub struct RejectArgs {
+    /// Instead of ignoring lines of the input that cannot be parsed, abort with the number of the
+    /// first such line, or write them to the rejects file if one is given
+    #[arg(long)]
+    pub strict_input: bool,
+    /// In strict mode, write the lines that cannot be parsed to this file instead of aborting
+    #[arg(long, value_name = "PATH", requires = "strict_input")]
+    pub rejects_file: Option<PathBuf>,
+}
+
+impl RejectArgs {
+    /// Creates the handler for the lines that cannot be parsed
+    pub async fn rejects(&self) -> std::io::Result<Rejects> {
+        Rejects::new(self.strict_input, self.rejects_file.as_deref()).await
+    }
+}
+
+/// Command line arguments to process the input in random order, shared by the binaries that probe
+/// the targets of their input
+#[derive(Debug, Clone, clap::Args)]
+pub struct ShuffleArgs {
+    /// Process the input in random order, such that hosts of the same network are not probed in
+    /// sequence. Lines are read ahead into a window, from which they are drawn at random
+    #[arg(long)]
+    pub randomize: bool,
+    /// The number of lines read ahead when randomizing the order. Larger windows mix the input
+    /// better, at the cost of memory and of a delay before the first lookup
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "10000",
+        requires = "randomize"
+    )]
+    pub randomize_window: NonZeroUsize,
+}
+
+impl ShuffleArgs {
+    /// Shuffles the items of `stream` with `rng` if randomizing is enabled, and passes them on as
+    /// they are otherwise
+    pub fn apply<S: Stream>(&self, stream: S, rng: StdRng) -> impl Stream<Item = S::Item> {
+        if !self.randomize {
+            return stream.left_stream();
+        }
+
+        shuffle_window(stream, self.randomize_window.get(), rng).right_stream()
+    }
+}
+
+/// Command line arguments to seed the randomized behaviour, shared by the binaries that randomize
+/// the order or timing of their probes
+#[derive(Debug, Clone, clap::Args)]
+pub struct SeedArgs {
+    /// Seed all randomized behaviour, such as the order of '--randomize' and the delays of
+    /// '--jitter', such that a run can be reproduced. If omitted, the seed is drawn from entropy
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+}
+
+impl SeedArgs {
+    /// Creates the generator all randomized components derive theirs from via [`SeedArgs::fork`],
+    /// such that a seed determines the behaviour of each of them. A seed drawn from entropy is
+    /// logged, such that the run can be reproduced nonetheless
+    pub fn rng(&self) -> StdRng {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        info!(seed, "Seeding the random generator");
+        StdRng::seed_from_u64(seed)
+    }
+
+    /// Derives the generator of a randomized component from `rng`
+    pub fn fork(rng: &mut StdRng) -> StdRng {
+        StdRng::seed_from_u64(rng.gen())
+    }
+}
+
+/// Command line arguments to scan the host addresses of networks instead of reading the input,
+/// shared by the binaries that probe IP addresses
+#[derive(Debug, Clone, clap::Args)]
+pub struct CidrArgs {
+    /// Scan the host addresses of this network, e.g. '192.0.2.0/24', instead of reading the input.
+    /// The names of the hosts are looked up via their PTR records. May be specified multiple times
+    #[arg(long = "cidr", value_name = "NETWORK")]
+    pub networks: Vec<IpNetwork>,
+    /// Expand networks with more than 65536 host addresses
+    #[arg(long, requires = "networks")]
+    pub force: bool,
+}
+
+impl CidrArgs {
+    /// Expands the networks into their host addresses, or returns `None` if no network was given
+    pub fn expand(&self) -> Result<Option<impl Iterator<Item = IpAddr>>, NetworkTooLarge> {
+        if self.networks.is_empty() {
+            return Ok(None);
+        }
+
+        expand_networks(self.networks.clone(), self.force).map(Some)
+    }
+}
+
+/// Command line arguments enriching the IP addresses of the results with their autonomous system,
+/// shared by the binaries that record IP addresses
+#[derive(Debug, Clone, clap::Args)]
+pub struct AsnArgs {
+    /// Look up the autonomous system number (ASN) and organization of each IP address and record
+    /// them along with the results. By default, Team Cymru's DNS service 'origin.asn.cymru.com' is
+    /// queried via the system resolver. Each address is looked up at most once per run
+    #[arg(long)]
+    pub enrich_asn: bool,
+    /// Look up the ASNs in this MaxMind ASN database, e.g. 'GeoLite2-ASN.mmdb', instead of querying
+    /// DNS
+    #[arg(long, value_name = "PATH", requires = "enrich_asn")]
+    pub asn_db: Option<PathBuf>,
+}
+
+impl AsnArgs {
+    /// Creates the lookup of the autonomous systems, or returns `None` if enrichment is disabled
+    pub fn lookup(&self) -> Result<Option<AsnLookup>, AsnError> {
+        if !self.enrich_asn {
+            return Ok(None);
+        }
+
+        match &self.asn_db {
+            Some(path) => AsnLookup::open(path).map(Some),
+            None => AsnLookup::cymru().map(Some),
+        }
+    }
+}
+
+/// Command line argument naming a config file, shared by all binaries
+#[derive(Debug, Clone, clap::Args)]
+pub struct ConfigArgs {
+    /// Read the values of arguments that are not given on the command line from this TOML file.
+    /// Its keys are the argument names with underscores instead of dashes, e.g. 'recon_db_host'.
+    /// Values from the file take precedence over environment variables
+    #[arg(long, value_name = "PATH", env = "RECON_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+enum ConfigError {
+    #[error("Cannot read the config file '{}': {}", .0.display(), .1)]
+    Io(PathBuf, std::io::Error),
+    #[error("Cannot parse the config file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Unknown key '{0}' in the config file")]
+    UnknownKey(String),
+    #[error("The positional argument '{0}' cannot be set in the config file")]
+    Positional(String),
+    #[error("Invalid value for '{0}' in the config file")]
+    InvalidValue(String),
+}
+
+/// Parses the command line arguments just like [`Parser::parse`], except that arguments which are
+/// not given on the command line are read from the config file named by [`ConfigArgs`], if any.
+/// Exits with an error message if the config file cannot be read or holds unknown keys
+pub fn parse_with_config<T: Parser>() -> T {
+    try_parse_with_config(std::env::args_os().collect()).unwrap_or_else(|e| e.exit())
+}
+
+fn try_parse_with_config<T: Parser>(args: Vec<OsString>) -> Result<T, clap::Error> {
+    // The first pass only finds the config file and the arguments given on the command line. The
+    // arguments are validated once the values from the config file are merged in, such that these
+    // count towards required arguments and the requirements of other arguments
+    let mut command = T::command().ignore_errors(true);
+    let matches = command.try_get_matches_from_mut(&args)?;
+    let Some(path) = matches.try_get_one::<PathBuf>("config").ok().flatten() else {
+        return T::try_parse_from(args);
+    };
+
+    let config_args = config_to_args(&command, &matches, path)
+        .map_err(|e| command.error(ErrorKind::InvalidValue, e))?;
+
+    // The values from the config file are passed as if they were given on the command line, ahead
+    // of the actual command line arguments, so that clap parses and validates them as usual
+    let mut args = args.into_iter();
+    T::try_parse_from(
+        args.next()
+            .into_iter()
+            .chain(config_args.into_iter().map(OsString::from))
+            .chain(args),
+    )
+}
+
+/// Converts the entries of the config file to command line arguments, skipping those that were
+/// given on the command line. A table is passed as one 'KEY=VALUE' argument per entry
+fn config_to_args(
+    command: &Command,
+    matches: &ArgMatches,
+    path: &Path,
+) -> Result<Vec<String>, ConfigError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let table: toml::Table = contents.parse()?;
+
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str())
+            .filter(|_| !matches!(key.as_str(), "config" | "help" | "version"))
+            .ok_or_else(|| ConfigError::UnknownKey(key.clone()))?;
+        let long = arg
+            .get_long()
+            .ok_or_else(|| ConfigError::Positional(key.clone()))?;
+        if matches.value_source(&key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values,
+            toml::Value::Table(entries) => entries
+                .into_iter()
+                .map(|(name, value)| match value {
+                    toml::Value::String(value) => {
+                        Ok(toml::Value::String(format!("{name}={value}")))
+                    }
+                    toml::Value::Array(_) | toml::Value::Table(_) => {
+                        Err(ConfigError::InvalidValue(key.clone()))
+                    }
+                    value => Ok(toml::Value::String(format!("{name}={value}"))),
+                })
+                .collect::<Result<_, _>>()?,
+            value => vec![value],
+        };
+        for value in values {
+            match (arg.get_action(), value) {
+                (ArgAction::SetTrue, toml::Value::Boolean(true)) => args.push(format!("--{long}")),
+                (ArgAction::SetTrue, toml::Value::Boolean(false)) => {}
+                (ArgAction::SetTrue, _) | (_, toml::Value::Array(_) | toml::Value::Table(_)) => {
+                    return Err(ConfigError::InvalidValue(key))
+                }
+                (_, toml::Value::String(value)) => args.push(format!("--{long}={value}")),
+                (_, value) => args.push(format!("--{long}={value}")),
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use grimoire_test::temp_dir;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        recon_db: ReconDbArgs,
+    }
+
+    #[derive(Debug, Parser)]
+    struct PrecedenceArgs {
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[arg(long, env = "GRIMOIRE_TEST_FROM_CLI", default_value = "default")]
+        from_cli: String,
+        #[arg(long, env = "GRIMOIRE_TEST_FROM_FILE", default_value = "default")]
+        from_file: String,
+        #[arg(long, env = "GRIMOIRE_TEST_FROM_ENV", default_value = "default")]
+        from_env: String,
+        #[arg(long, default_value = "default")]
+        from_default: String,
+    }
+
+    #[derive(Debug, Parser)]
+    struct RequiresArgs {
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[arg(long)]
+        enable_db_storage: bool,
+        #[arg(long, requires = "enable_db_storage")]
+        only_changed: bool,
+    }
+
+    #[derive(Debug, Parser)]
+    struct RuntimeTestArgs {
+        #[command(flatten)]
+        runtime: RuntimeArgs,
+    }
+
+    /// Appends everything written to it to the shared buffer
+    struct CapturedWriter(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Parser)]
+    struct RandomTestArgs {
+        #[command(flatten)]
+        shuffle: ShuffleArgs,
+        #[command(flatten)]
+        seed: SeedArgs,
+    }
+
+    /// Sets the environment variables, which are only read by this test, to 'env' and restores
+    /// their previous values when dropped
+    struct EnvGuard(Vec<(&'static str, Option<OsString>)>);
+
+    impl EnvGuard {
+        fn set(names: &[&'static str]) -> Self {
+            let previous = names
+                .iter()
+                .map(|name| (*name, std::env::var_os(name)))
+                .collect();
+            for name in names {
+                std::env::set_var(name, "env");
+            }
+
+            EnvGuard(previous)
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (name, value) in &self.0 {
+                match value {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_command_line_overrides_the_config_file_which_overrides_the_environment() {
+        let dir = temp_dir();
+        let path = dir.path().join("precedence.toml");
+        std::fs::write(&path, "from_cli = \"file\"\nfrom_file = \"file\"\n").unwrap();
+        let _env = EnvGuard::set(&[
+            "GRIMOIRE_TEST_FROM_CLI",
+            "GRIMOIRE_TEST_FROM_FILE",
+            "GRIMOIRE_TEST_FROM_ENV",
+        ]);
+
+        let args: PrecedenceArgs = try_parse_with_config(vec![
+            OsString::from("test"),
+            OsString::from("--config"),
+            path.clone().into_os_string(),
+            OsString::from("--from-cli=cli"),
+        ])
+        .unwrap();
+
+        assert_eq!(args.from_cli, "cli");
+        assert_eq!(args.from_file, "file");
+        assert_eq!(args.from_env, "env");
+        assert_eq!(args.from_default, "default");
+    }
+
+    #[test]
+    fn config_values_satisfy_the_requirements_of_arguments() {
+        let dir = temp_dir();
+        let path = dir.path().join("requires.toml");
+        std::fs::write(&path, "enable_db_storage = true\n").unwrap();
+        let parse = |args: &[&str]| {
+            try_parse_with_config::<RequiresArgs>(
+                ["test"].iter().chain(args).map(OsString::from).collect(),
+            )
+        };
+
+        let args = parse(&["--config", path.to_str().unwrap(), "--only-changed"]).unwrap();
+
+        assert!(args.enable_db_storage && args.only_changed);
+        assert_eq!(
+            parse(&["--only-changed"]).unwrap_err().kind(),
+            ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn unknown_keys_in_the_config_file_are_rejected() {
+        let dir = temp_dir();
+        let path = dir.path().join("unknown.toml");
+        std::fs::write(&path, "from_elsewhere = \"file\"\n").unwrap();
+
+        let error = try_parse_with_config::<PrecedenceArgs>(vec![
+            OsString::from("test"),
+            OsString::from("--config"),
+            path.clone().into_os_string(),
+        ])
+        .unwrap_err();
+
+        assert!(error.to_string().contains("Unknown key 'from_elsewhere'"));
+    }
+
+    /// The password of the database server the tests run against, as given in DATABASE_URL
+    fn database_password() -> Option<String> {
+        let url = std::env::var("DATABASE_URL").ok()?;
+        let (_, location) = url.split_once("://")?;
+        let (credentials, _) = location.rsplit_once('@')?;
+
+        credentials
+            .split_once(':')
+            .map(|(_, password)| password.to_string())
+    }
+
+    /// The arguments connecting to the test database of `connect_opts`, along with `args`
+    fn recon_db_args(connect_opts: &PgConnectOptions, args: &[&str]) -> ReconDbArgs {
+        let password = database_password();
+        let mut argv = vec![
+            "test",
+            "--recon-db-host",
+            connect_opts.get_host(),
+            "--recon-db-username",
+            connect_opts.get_username(),
+            "--recon-db-database",
+            connect_opts.get_database().unwrap(),
+        ];
+        if let Some(password) = &password {
+            argv.extend(["--recon-db-password", password]);
+        }
+        argv.extend(args);
+
+        TestArgs::try_parse_from(argv).unwrap().recon_db
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn the_recon_db_args_connect_and_migrate(
+        _: PgPoolOptions,
+        connect_opts: PgConnectOptions,
+    ) {
+        let args = recon_db_args(&connect_opts, &[]);
+
+        let pg_pool = args.connect().await.unwrap();
+
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations")
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap();
+        assert!(applied > 0);
+    }
+
+    #[test]
+    fn the_worker_threads_are_applied() {
+        let args = RuntimeTestArgs::try_parse_from(["test", "--worker-threads", "1"]).unwrap();
+        let runtime = args.runtime.build().unwrap();
+
+        // Each task blocks its worker until both are running, which only happens if there are at
+        // least two workers, as there are by default on any machine with two cores. Otherwise, the
+        // first task gives up after a while, and the second one runs on the same worker
+        let workers = runtime.block_on(async {
+            let running = Arc::new(AtomicUsize::new(0));
+            let tasks: Vec<_> = (0..2)
+                .map(|_| {
+                    let running = running.clone();
+                    tokio::spawn(async move {
+                        running.fetch_add(1, Ordering::SeqCst);
+                        let deadline = Instant::now() + Duration::from_millis(500);
+                        while running.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+                            std::thread::sleep(Duration::from_millis(1));
+                        }
+                        std::thread::current().id()
+                    })
+                })
+                .collect();
+            let mut workers = HashSet::new();
+            for task in tasks {
+                workers.insert(task.await.unwrap());
+            }
+            workers
+        });
+
+        assert_eq!(workers.len(), 1);
+    }
+
+    /// The order of the items and the jitter that a run with `args` yields
+    async fn random_run(args: &[&str]) -> (Vec<usize>, Vec<u64>) {
+        let args =
+            RandomTestArgs::try_parse_from(["test", "--randomize"].iter().chain(args)).unwrap();
+        let mut rng = args.seed.rng();
+        let mut jitter_rng = SeedArgs::fork(&mut rng);
+
+        let order = args
+            .shuffle
+            .apply(futures::stream::iter(0..100), rng)
+            .collect()
+            .await;
+        let jitter = (0..10).map(|_| jitter_rng.gen_range(0..1000)).collect();
+
+        (order, jitter)
+    }
+
+    #[tokio::test]
+    async fn runs_with_the_same_seed_are_identical() {
+        let first = random_run(&["--seed", "7"]).await;
+
+        assert_eq!(random_run(&["--seed", "7"]).await, first);
+        assert_ne!(random_run(&["--seed", "8"]).await, first);
+        assert_ne!(first.0, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn the_seed_drawn_from_entropy_is_logged() {
+        let captured = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || CapturedWriter(writer.clone()))
+            .with_ansi(false)
+            .finish();
+
+        let mut rng =
+            tracing::subscriber::with_default(subscriber, || SeedArgs { seed: None }.rng());
+
+        let output = String::from_utf8(captured.lock().unwrap().clone()).unwrap();
+        let seed: u64 = output
+            .split_once("seed=")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut reproduced = SeedArgs { seed: Some(seed) }.rng();
+        let drawn: Vec<u64> = (0..10).map(|_| rng.gen()).collect();
+        assert_eq!(
+            drawn,
+            (0..10).map(|_| reproduced.gen()).collect::<Vec<u64>>()
+        );
+    }
+}
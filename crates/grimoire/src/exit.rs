@@ -0,0 +1,272 @@
+use std::{error::Error as StdError, process::ExitCode};
+
+use hickory_resolver::error::ResolveError;
+use thiserror::Error;
+
+use crate::{
+    asn::AsnError, cidr::NetworkTooLarge, resolver::DnsServerError, ParseFqdnError,
+    ParseIpAddrOrFqdnError, ReconDbError, RejectError,
+};
+
+/// The exit codes of the binaries, as listed at the end of their help text
+pub const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  The run completed
+  1  The run failed for any other reason
+  2  The arguments, the config file or the input are invalid
+  3  The recon database is unreachable or refused a statement
+  4  A remote service, such as the DNS server, cannot be reached";
+
+/// Why a run ended unsuccessfully, which determines the exit code of the binaries. Invalid
+/// arguments and config files exit with 2 via clap already
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Failure = 1,
+    Input = 2,
+    ReconDb = 3,
+    Network = 4,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+/// Classifies an error by the first error of this library, or of the libraries it builds on, that
+/// is found among its sources. Returns `None` if there is no such error
+pub fn classify(error: &(dyn StdError + 'static)) -> Option<ExitStatus> {
+    let mut next = Some(error);
+    while let Some(error) = next {
+        if error.is::<ReconDbError>() || error.is::<sqlx::Error>() {
+            return Some(ExitStatus::ReconDb);
+        }
+        if error.is::<RejectError>()
+            || error.is::<ParseFqdnError>()
+            || error.is::<ParseIpAddrOrFqdnError>()
+            || error.is::<NetworkTooLarge>()
+            || error.is::<AsnError>()
+        {
+            return Some(ExitStatus::Input);
+        }
+        if error.is::<DnsServerError>() || error.is::<ResolveError>() {
+            return Some(ExitStatus::Network);
+        }
+        next = error.source();
+    }
+
+    None
+}
+
+/// The errors that end a run of a binary, each with its own exit code. Errors are classified
+/// where they occur by wrapping them in the corresponding variant, which is kept when context is
+/// added to them
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error(transparent)]
+    Input(anyhow::Error),
+    #[error(transparent)]
+    ReconDb(anyhow::Error),
+    #[error(transparent)]
+    Network(anyhow::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl RunError {
+    fn new(status: Option<ExitStatus>, error: anyhow::Error) -> Self {
+        match status {
+            Some(ExitStatus::Input) => RunError::Input(error),
+            Some(ExitStatus::ReconDb) => RunError::ReconDb(error),
+            Some(ExitStatus::Network) => RunError::Network(error),
+            Some(ExitStatus::Failure) | None => RunError::Other(error),
+        }
+    }
+
+    /// Classifies `error` like [`RunError::from`] does, and by `classify_more` if neither it nor
+    /// any of its sources is known to this library. This allows the binaries to classify the
+    /// errors of the libraries only they build on
+    pub fn classify_with(
+        error: anyhow::Error,
+        classify_more: fn(&anyhow::Error) -> Option<ExitStatus>,
+    ) -> Self {
+        let status = match error.downcast_ref::<RunError>() {
+            Some(run_error) => Some(run_error.status()),
+            None => classify(&*error).or_else(|| classify_more(&error)),
+        };
+        RunError::new(status, error)
+    }
+
+    pub fn status(&self) -> ExitStatus {
+        match self {
+            RunError::Input(_) => ExitStatus::Input,
+            RunError::ReconDb(_) => ExitStatus::ReconDb,
+            RunError::Network(_) => ExitStatus::Network,
+            RunError::Other(_) => ExitStatus::Failure,
+        }
+    }
+
+    pub fn into_inner(self) -> anyhow::Error {
+        match self {
+            RunError::Input(error)
+            | RunError::ReconDb(error)
+            | RunError::Network(error)
+            | RunError::Other(error) => error,
+        }
+    }
+}
+
+/// Errors that were classified where they occurred keep their status, all others are classified
+/// by their sources
+impl From<anyhow::Error> for RunError {
+    fn from(error: anyhow::Error) -> Self {
+        RunError::classify_with(error, |_| None)
+    }
+}
+
+/// Reports the error that ended the run, if any, and returns the corresponding exit code
+pub fn report(result: anyhow::Result<()>) -> ExitCode {
+    report_with(result, |_| None)
+}
+
+/// Reports the error that ended the run, if any, and returns the corresponding exit code. Errors
+/// unknown to this library are classified by `classify_more`, see [`RunError::classify_with`]
+pub fn report_with(
+    result: anyhow::Result<()>,
+    classify_more: fn(&anyhow::Error) -> Option<ExitStatus>,
+) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let error = RunError::classify_with(error, classify_more);
+            let status = error.status();
+            eprintln!("Error: {:?}", error.into_inner());
+            status.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Context as _};
+
+    use super::*;
+
+    /// An error that adds context to its source, like the errors of the binaries do
+    #[derive(Debug, Error)]
+    #[error("{0}")]
+    struct Context(&'static str, #[source] Box<dyn StdError + Send + Sync>);
+
+    #[test]
+    fn recon_db_failures_exit_with_3() {
+        let timeout = ReconDbError::Timeout("db".to_string(), 5432, Duration::from_secs(10));
+
+        assert_eq!(classify(&timeout), Some(ExitStatus::ReconDb));
+        assert_eq!(
+            classify(&sqlx::Error::PoolClosed),
+            Some(ExitStatus::ReconDb)
+        );
+        assert_eq!(ExitStatus::ReconDb.code(), 3);
+    }
+
+    #[test]
+    fn invalid_input_exits_with_2() {
+        let strict = RejectError::Strict(2, "not a name".to_string());
+
+        assert_eq!(classify(&strict), Some(ExitStatus::Input));
+        assert_eq!(classify(&ParseFqdnError::Empty), Some(ExitStatus::Input));
+        assert_eq!(ExitStatus::Input.code(), 2);
+    }
+
+    #[test]
+    fn unreachable_remote_services_exit_with_4() {
+        let dns_server = DnsServerError::NoAddress("ns.example.com".parse().unwrap());
+
+        assert_eq!(classify(&dns_server), Some(ExitStatus::Network));
+        assert_eq!(
+            classify(&ResolveError::from("request timed out")),
+            Some(ExitStatus::Network)
+        );
+        assert_eq!(ExitStatus::Network.code(), 4);
+    }
+
+    #[test]
+    fn errors_are_classified_by_their_sources() {
+        let error = Context(
+            "Cannot parse the DNS server",
+            Box::new(ParseFqdnError::EmptyLabel),
+        );
+        assert_eq!(classify(&error), Some(ExitStatus::Input));
+
+        let error = Context(
+            "Relating to a batch of DNS recon results",
+            Box::new(Context("Storing", Box::new(sqlx::Error::PoolTimedOut))),
+        );
+        assert_eq!(classify(&error), Some(ExitStatus::ReconDb));
+    }
+
+    #[test]
+    fn other_errors_are_not_classified() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+
+        assert_eq!(classify(&io), None);
+        assert_eq!(classify(&Context("Writing the output", Box::new(io))), None);
+        assert_eq!(ExitStatus::Failure.code(), 1);
+    }
+
+    #[test]
+    fn errors_classified_where_they_occur_keep_their_status() {
+        let error = anyhow::Error::from(RunError::ReconDb(anyhow!("pool timed out")))
+            .context("Cannot store the addresses of 'www.example.com'");
+
+        assert_eq!(RunError::from(error).status(), ExitStatus::ReconDb);
+    }
+
+    #[test]
+    fn run_errors_are_classified_by_their_sources() {
+        let input = Err::<(), _>(ParseFqdnError::EmptyLabel)
+            .context("Cannot parse the name")
+            .unwrap_err();
+
+        assert_eq!(RunError::from(input).status(), ExitStatus::Input);
+        assert_eq!(
+            RunError::from(anyhow!("unexpected")).status(),
+            ExitStatus::Failure
+        );
+    }
+
+    #[test]
+    fn binaries_classify_the_errors_unknown_to_the_library() {
+        let classify_io = |error: &anyhow::Error| {
+            error
+                .chain()
+                .any(|e| e.is::<std::io::Error>())
+                .then_some(ExitStatus::Network)
+        };
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let error = anyhow::Error::from(io).context("Cannot fetch the robots.txt");
+
+        assert_eq!(
+            RunError::classify_with(error, classify_io).status(),
+            ExitStatus::Network
+        );
+        // The library classifies the errors it knows first
+        let error = anyhow::Error::from(ParseFqdnError::Empty);
+        assert_eq!(
+            RunError::classify_with(error, classify_io).status(),
+            ExitStatus::Input
+        );
+        assert_eq!(
+            RunError::classify_with(anyhow!("unexpected"), classify_io).status(),
+            ExitStatus::Failure
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use sqlx::{query_as, query_scalar, PgPool};
+use thiserror::Error;
+
+use crate::{quote_identifier, tables::ReconTable, MIGRATOR};
+
+/// A reason why the binaries of this build cannot use the recon database as it is
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HealthProblem {
+    #[error("The migration {0} '{1}' has not been applied")]
+    Pending(i64, String),
+    #[error("The migration {0} '{1}' failed and left the recon database in an unknown state")]
+    Dirty(i64, String),
+    #[error("The migration {0} '{1}' was modified after it had been applied")]
+    Modified(i64, String),
+    #[error("The migration {0} was applied, but is unknown to this build")]
+    Unknown(i64),
+    #[error("The table '{0}' does not exist")]
+    MissingTable(ReconTable),
+}
+
+/// The outcome of checking the recon database
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    /// The number of migrations applied to the recon database
+    pub applied: usize,
+    /// The number of migrations of this build
+    pub expected: usize,
+    /// The number of tables holding results that exist
+    pub tables: usize,
+    pub problems: Vec<HealthProblem>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Compares the migrations applied to the recon database with those of this build and checks that
+/// each table holding results exists. Unlike connecting via [`crate::create_recon_db_pool`], this
+/// does not change the database, such that a fresh database is reported with all migrations pending
+#[tracing::instrument(skip_all)]
+pub async fn check_recon_db(pg_pool: &PgPool) -> Result<HealthReport, sqlx::Error> {
+    let mut report = HealthReport::default();
+
+    // The record of applied migrations is only created along with the first migration
+    let recorded: bool = query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pg_pool)
+        .await?;
+    let applied: Vec<(i64, bool, Vec<u8>)> = if recorded {
+        query_as("SELECT version, success, checksum FROM _sqlx_migrations ORDER BY version")
+            .fetch_all(pg_pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+    report.applied = applied.len();
+
+    let expected: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .collect();
+    report.expected = expected.len();
+    for migration in &expected {
+        let (version, description) = (migration.version, migration.description.to_string());
+        match applied.iter().find(|(applied, ..)| *applied == version) {
+            None => report
+                .problems
+                .push(HealthProblem::Pending(version, description)),
+            Some((_, false, _)) => report
+                .problems
+                .push(HealthProblem::Dirty(version, description)),
+            Some((_, true, checksum)) if checksum[..] != migration.checksum[..] => report
+                .problems
+                .push(HealthProblem::Modified(version, description)),
+            Some(_) => (),
+        }
+    }
+    for (version, ..) in &applied {
+        if !expected
+            .iter()
+            .any(|migration| migration.version == *version)
+        {
+            report.problems.push(HealthProblem::Unknown(*version));
+        }
+    }
+
+    for table in ReconTable::ALL {
+        let exists: bool = query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(quote_identifier(table.name()))
+            .fetch_one(pg_pool)
+            .await?;
+        if exists {
+            report.tables += 1;
+        } else {
+            report.problems.push(HealthProblem::MissingTable(*table));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = false)]
+    async fn a_fresh_database_is_unhealthy(pg_pool: PgPool) {
+        let report = check_recon_db(&pg_pool).await.unwrap();
+
+        assert!(!report.is_healthy());
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.tables, 0);
+        let pending = report
+            .problems
+            .iter()
+            .filter(|problem| matches!(problem, HealthProblem::Pending(..)))
+            .count();
+        assert_eq!(pending, report.expected);
+        assert!(report
+            .problems
+            .contains(&HealthProblem::MissingTable(ReconTable::ALL[0])));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn a_migrated_database_is_healthy(pg_pool: PgPool) {
+        let report = check_recon_db(&pg_pool).await.unwrap();
+
+        assert!(report.is_healthy(), "{:?}", report.problems);
+        assert_eq!(report.applied, report.expected);
+        assert_eq!(report.tables, ReconTable::ALL.len());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn dirty_migrations_and_dropped_tables_are_reported(pg_pool: PgPool) {
+        let table = ReconTable::ALL[0];
+        sqlx::query(&format!("DROP TABLE {}", quote_identifier(table.name())))
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+        let version: i64 =
+            query_scalar("UPDATE _sqlx_migrations SET success = false WHERE version = (SELECT max(version) FROM _sqlx_migrations) RETURNING version")
+                .fetch_one(&pg_pool)
+                .await
+                .unwrap();
+
+        let report = check_recon_db(&pg_pool).await.unwrap();
+
+        assert!(!report.is_healthy());
+        assert_eq!(report.tables, ReconTable::ALL.len() - 1);
+        assert!(report
+            .problems
+            .contains(&HealthProblem::MissingTable(table)));
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| matches!(problem, HealthProblem::Dirty(dirty, _) if *dirty == version)));
+    }
+}
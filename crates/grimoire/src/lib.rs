@@ -1,8 +1,26 @@
+pub mod asn;
+mod build_info;
+pub mod cidr;
+#[cfg(feature = "clap")]
+pub mod cli;
+pub mod exit;
+pub mod health;
+mod logging;
+pub mod output;
+pub mod records;
+mod rejects;
+mod report;
+pub mod resolver;
+pub mod shuffle;
+pub mod tables;
+
 use std::{
     fmt::Display,
     net::{AddrParseError, IpAddr},
+    path::Path,
     str::FromStr,
     sync::OnceLock,
+    time::Duration,
 };
 
 use regex::Regex;
@@ -11,42 +29,239 @@ use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
 };
 use thiserror::Error;
-use tracing::{debug, error, trace};
+use tokio::io::AsyncRead;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, trace, warn};
+
+pub use crate::{
+    build_info::{build_info, FEATURES},
+    logging::{init_logging, LogFormat},
+    output::ResultWriter,
+    rejects::{RejectError, Rejects},
+    report::ParseReport,
+};
 
 const FQDN_RE_SRC: &str = r"^(?P<fqdn>(?:[a-zA-Z0-9-]{1,63}\.){1,}(?:[a-zA-Z0-9-]{1,63}))$";
 static FQDN_RE: OnceLock<Regex> = OnceLock::new();
+/// Additionally permits leading service labels, which start with an underscore
+const SERVICE_FQDN_RE_SRC: &str =
+    r"^(?P<fqdn>(?:_[a-zA-Z0-9-]{1,62}\.)*(?:[a-zA-Z0-9-]{1,63}\.){1,}(?:[a-zA-Z0-9-]{1,63}))$";
+static SERVICE_FQDN_RE: OnceLock<Regex> = OnceLock::new();
 static MIGRATOR: Migrator = sqlx::migrate!("../../migrations");
 
+/// How long the connection check of the recon database may take
+const RECON_DB_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum ReconDbError {
+    #[error("Cannot connect to the recon database at {0}:{1}: {2}")]
+    Unreachable(String, u16, sqlx::Error),
+    #[error("Cannot connect to the recon database at {0}:{1}: timed out after {2:?}")]
+    Timeout(String, u16, Duration),
+    #[error("Cannot create the schema of the recon database: {0}")]
+    Schema(sqlx::Error),
+    #[error("Cannot migrate the recon database: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+}
+
+/// Quotes an SQL identifier, such that it may contain any character
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Creates the connection pool for the recon database, checks that the database is reachable and
+/// applies any pending migrations. The pool itself connects lazily. If a schema is given, it is
+/// created if required and used for all tables, including the record of applied migrations
 #[tracing::instrument]
 pub async fn create_recon_db_pool(
     host: &str,
     username: &str,
     password: Option<&str>,
     database: &str,
-) -> Result<sqlx::postgres::PgPool, sqlx::migrate::MigrateError> {
-    let recon_pg_connect_ops = if let Some(recon_db_password) = password {
+    schema: Option<&str>,
+) -> Result<sqlx::postgres::PgPool, ReconDbError> {
+    create_recon_db_pool_with(connect_options(host, username, password, database), schema).await
+}
+
+async fn create_recon_db_pool_with(
+    connect_options: PgConnectOptions,
+    schema: Option<&str>,
+) -> Result<sqlx::postgres::PgPool, ReconDbError> {
+    let recon_pg_pool = connect_recon_db_with(connect_options, schema).await?;
+
+    if let Some(schema) = schema {
+        debug!("Creating the schema '{schema}' unless it exists");
+        sqlx::raw_sql(&format!(
+            "CREATE SCHEMA IF NOT EXISTS {}",
+            quote_identifier(schema)
+        ))
+        .execute(&recon_pg_pool)
+        .await
+        .map_err(ReconDbError::Schema)?;
+    }
+
+    MIGRATOR.run(&recon_pg_pool).await?;
+
+    Ok(recon_pg_pool)
+}
+
+/// Creates the connection pool for the recon database and checks that the database is reachable,
+/// without changing it. If a schema is given, it is used for all tables
+#[tracing::instrument]
+pub async fn connect_recon_db(
+    host: &str,
+    username: &str,
+    password: Option<&str>,
+    database: &str,
+    schema: Option<&str>,
+) -> Result<sqlx::postgres::PgPool, ReconDbError> {
+    connect_recon_db_with(connect_options(host, username, password, database), schema).await
+}
+
+fn connect_options(
+    host: &str,
+    username: &str,
+    password: Option<&str>,
+    database: &str,
+) -> PgConnectOptions {
+    if let Some(recon_db_password) = password {
         PgConnectOptions::new().password(recon_db_password)
     } else {
         PgConnectOptions::new()
     }
     .host(host)
     .username(username)
-    .database(database);
+    .database(database)
+}
 
+async fn connect_recon_db_with(
+    mut recon_pg_connect_ops: PgConnectOptions,
+    schema: Option<&str>,
+) -> Result<sqlx::postgres::PgPool, ReconDbError> {
+    if let Some(schema) = schema {
+        // The server splits the options at whitespace, unless it is escaped with a backslash
+        let search_path = quote_identifier(schema)
+            .replace('\\', r"\\")
+            .replace(' ', r"\ ");
+        recon_pg_connect_ops = recon_pg_connect_ops.options([("search_path", search_path)]);
+    }
+
+    let host = recon_pg_connect_ops.get_host().to_string();
+    let port = recon_pg_connect_ops.get_port();
     let recon_pg_pool = PgPoolOptions::new().connect_lazy_with(recon_pg_connect_ops);
 
-    MIGRATOR.run(&recon_pg_pool).await?;
+    debug!("Checking the connection to the recon database");
+    match tokio::time::timeout(
+        RECON_DB_CHECK_TIMEOUT,
+        sqlx::query("SELECT 1").execute(&recon_pg_pool),
+    )
+    .await
+    {
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) => return Err(ReconDbError::Unreachable(host, port, e)),
+        Err(_) => return Err(ReconDbError::Timeout(host, port, RECON_DB_CHECK_TIMEOUT)),
+    }
 
     Ok(recon_pg_pool)
 }
 
+/// Installs a Ctrl-C handler and returns a token that is cancelled on the first Ctrl-C. Binaries are
+/// expected to stop consuming input once the token is cancelled and to finish any in-flight work.
+/// A second Ctrl-C terminates the process immediately
+pub fn shutdown_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let shutdown = token.clone();
+    tokio::spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Unable to listen for Ctrl-C: {}", e);
+            return;
+        }
+        warn!("Received Ctrl-C, finishing in-flight work. Press Ctrl-C again to exit immediately");
+        shutdown.cancel();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("Received Ctrl-C again, exiting immediately");
+            std::process::exit(130);
+        }
+    });
+
+    token
+}
+
+/// Opens the input of a binary, which is either a file or Stdin if no path or `-` is given
+#[tracing::instrument]
+pub async fn open_input(path: Option<&Path>) -> std::io::Result<Box<dyn AsyncRead + Unpin + Send>> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            debug!("Reading input from '{}'", path.display());
+            Ok(Box::new(tokio::fs::File::open(path).await?))
+        }
+        _ => Ok(Box::new(tokio::io::stdin())),
+    }
+}
+
+/// Writes `contents` to the file at `path` via a file next to it with '.tmp' appended to its name,
+/// which then replaces the file atomically, such that an interruption never leaves a partially
+/// written file behind
+#[tracing::instrument(skip(contents))]
+pub async fn save_atomically(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(&tmp_path, path).await
+}
+
+/// A fully qualified domain name, stored as its labels. The labels are only validated when parsing
+/// a string or assembling them with [`Fqdn::try_from_labels`]
 #[derive(Debug, Clone)]
-pub struct Fqdn(pub Vec<String>);
+pub struct Fqdn(Vec<String>);
 
 impl Fqdn {
+    /// Assembles an FQDN from its labels, validating them just like [`Fqdn::from_str`] does
+    pub fn try_from_labels(
+        labels: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self, ParseFqdnError> {
+        let labels: Vec<String> = labels.into_iter().map(Into::into).collect();
+        if labels.iter().any(|label| label.is_empty()) {
+            return Err(ParseFqdnError::EmptyLabel);
+        }
+        if labels.iter().any(|label| label.contains('.')) {
+            return Err(ParseFqdnError::InvalidCharacters);
+        }
+
+        Fqdn::from_str(&labels.join("."))
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.0
+    }
+
     pub fn domain(&self) -> String {
         self.0[self.0.len() - 2..].join(".")
     }
+
+    /// The name directly below the public suffix of this FQDN as per the Public Suffix List, e.g.
+    /// 'example.co.uk' for 'www.example.co.uk'. Returns `None` if the FQDN is a public suffix
+    /// itself. Unlike [`Fqdn::domain`], this does not assume that the suffix is a single label
+    pub fn registrable_domain(&self) -> Option<Fqdn> {
+        let name = self.to_string().to_ascii_lowercase();
+        let labels = psl::domain(name.as_bytes())?
+            .as_bytes()
+            .split(|b| *b == b'.')
+            .count();
+
+        Some(Fqdn(self.0[self.0.len() - labels..].to_vec()))
+    }
+
+    /// Iterates over this FQDN and its parents, up to and including the domain returned by
+    /// [`Fqdn::registrable_domain`], e.g. 'a.b.example.co.uk', 'b.example.co.uk' and
+    /// 'example.co.uk'. Public suffixes have no ancestors
+    pub fn ancestors(&self) -> impl Iterator<Item = Fqdn> + '_ {
+        let len = self
+            .registrable_domain()
+            .map_or(0, |domain| self.0.len() + 1 - domain.0.len());
+        (0..len).map(|start| Fqdn(self.0[start..].to_vec()))
+    }
 }
 
 impl<'a> From<&'a hickory_resolver::Name> for Fqdn {
@@ -60,20 +275,68 @@ impl<'a> From<&'a hickory_resolver::Name> for Fqdn {
     }
 }
 
-impl FromStr for Fqdn {
-    type Err = ParseFqdnError;
+/// Controls how strictly FQDNs are validated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FqdnParseOptions {
+    /// Additionally reject labels that end in a hyphen, contain two consecutive hyphens or start
+    /// with a hyphen or a digit. Service labels are exempt
+    pub strict: bool,
+    /// Permit leading labels that start with an underscore, as used by service names like
+    /// '_dmarc.example.com' or '_sip._tcp.example.com'
+    pub service_labels: bool,
+}
 
+impl Default for FqdnParseOptions {
+    /// Strict validation is the default if the `strict-fqdn-validation` feature is enabled
+    fn default() -> Self {
+        FqdnParseOptions {
+            strict: cfg!(feature = "strict-fqdn-validation"),
+            service_labels: false,
+        }
+    }
+}
+
+impl Fqdn {
+    /// Parses an FQDN, validating it as configured by `opts`
     #[tracing::instrument]
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let fqdn_re = FQDN_RE.get_or_init(|| {
-            debug!("Compiling the FQDN regular expression");
-            Regex::new(FQDN_RE_SRC).expect("compiling the FQDN_RE_SRC regular expression")
-        });
+    pub fn parse_with(s: &str, opts: FqdnParseOptions) -> Result<Self, ParseFqdnError> {
+        let fqdn_re = if opts.service_labels {
+            SERVICE_FQDN_RE.get_or_init(|| {
+                debug!("Compiling the service FQDN regular expression");
+                Regex::new(SERVICE_FQDN_RE_SRC)
+                    .expect("compiling the SERVICE_FQDN_RE_SRC regular expression")
+            })
+        } else {
+            FQDN_RE.get_or_init(|| {
+                debug!("Compiling the FQDN regular expression");
+                Regex::new(FQDN_RE_SRC).expect("compiling the FQDN_RE_SRC regular expression")
+            })
+        };
+
+        // A single trailing dot denotes the root of an absolute name, which is implied
+        let s = s.strip_suffix('.').unwrap_or(s);
 
         trace!("Validating string length");
-        if s.is_empty() || s.len() > 253 {
-            error!("String is empty or longer than 253 characters: '{s}'");
-            return Err(ParseFqdnError);
+        if s.is_empty() {
+            return Err(ParseFqdnError::Empty);
+        }
+        if s.len() > 253 {
+            error!("String is longer than 253 characters: '{s}'");
+            return Err(ParseFqdnError::TooLong(s.len()));
+        }
+
+        trace!("Validating the labels");
+        if s.starts_with('.') {
+            return Err(ParseFqdnError::LeadingDot);
+        }
+        if s.split('.').any(str::is_empty) {
+            return Err(ParseFqdnError::EmptyLabel);
+        }
+        if let Some(label) = s.split('.').find(|label| label.len() > 63) {
+            return Err(ParseFqdnError::LabelTooLong(label.to_string()));
+        }
+        if !s.contains('.') {
+            return Err(ParseFqdnError::SingleLabel);
         }
 
         trace!("Validating string for FQDN format");
@@ -82,18 +345,22 @@ impl FromStr for Fqdn {
             .and_then(|cap| cap.name("fqdn"))
             .map(|mat| mat.as_str().split('.'))
             .map(|splt| splt.map(|elmt| elmt.to_string()).collect())
-            .ok_or(ParseFqdnError)?;
+            .ok_or(ParseFqdnError::InvalidCharacters)?;
 
-        #[cfg(feature = "strict-fqdn-validation")]
-        {
+        if opts.strict {
             trace!("Validating string against illegal characters");
-            if fqdn.iter().any(|label| {
-                label.ends_with('-')
-                    || label.contains("--")
-                    || label.starts_with(['-', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9'])
-            }) {
+            if let Some(label) = fqdn
+                .iter()
+                .filter(|label| !label.starts_with('_'))
+                .find(|label| {
+                    label.ends_with('-')
+                        || label.contains("--")
+                        || label
+                            .starts_with(['-', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9'])
+                })
+            {
                 error!("String contains illegal characters: '{}'", fqdn.join("."));
-                return Err(ParseFqdnError);
+                return Err(ParseFqdnError::IllegalLabel(label.clone()));
             }
         }
 
@@ -101,6 +368,14 @@ impl FromStr for Fqdn {
     }
 }
 
+impl FromStr for Fqdn {
+    type Err = ParseFqdnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Fqdn::parse_with(s, FqdnParseOptions::default())
+    }
+}
+
 impl Display for Fqdn {
     #[tracing::instrument(skip_all)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -108,9 +383,26 @@ impl Display for Fqdn {
     }
 }
 
-#[derive(Debug, Error)]
-#[error("expected a fully qualified domain name")]
-pub struct ParseFqdnError;
+/// Why a string is not a fully qualified domain name
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseFqdnError {
+    #[error("expected a fully qualified domain name, but the string is empty")]
+    Empty,
+    #[error("expected a fully qualified domain name of at most 253 characters, but it has {0}")]
+    TooLong(usize),
+    #[error("expected a fully qualified domain name, but it starts with a dot")]
+    LeadingDot,
+    #[error("expected a fully qualified domain name, but it contains an empty label")]
+    EmptyLabel,
+    #[error("expected labels of at most 63 characters, but '{0}' is longer")]
+    LabelTooLong(String),
+    #[error("expected a fully qualified domain name of at least two labels")]
+    SingleLabel,
+    #[error("expected a fully qualified domain name of letters, digits and hyphens")]
+    InvalidCharacters,
+    #[error("expected labels that neither start nor end with a hyphen, start with a digit or contain two consecutive hyphens, but got '{0}'")]
+    IllegalLabel(String),
+}
 
 #[derive(Debug, Clone)]
 pub enum IpAddrOrFqdn {
@@ -148,3 +440,312 @@ impl Display for IpAddrOrFqdn {
 #[derive(Debug, Error)]
 #[error("expected either an IP address or a fully qualified domain name: {}; {}", .0, .1)]
 pub struct ParseIpAddrOrFqdnError(pub AddrParseError, pub ParseFqdnError);
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    fn names(fqdns: impl IntoIterator<Item = Fqdn>) -> Vec<String> {
+        fqdns.into_iter().map(|fqdn| fqdn.to_string()).collect()
+    }
+
+    fn registrable_domain(s: &str) -> Option<String> {
+        Fqdn::from_str(s)
+            .unwrap()
+            .registrable_domain()
+            .map(|domain| domain.to_string())
+    }
+
+    fn ancestors(s: &str) -> Vec<String> {
+        names(Fqdn::from_str(s).unwrap().ancestors())
+    }
+
+    const STRICT: FqdnParseOptions = FqdnParseOptions {
+        strict: true,
+        service_labels: false,
+    };
+    const LENIENT: FqdnParseOptions = FqdnParseOptions {
+        strict: false,
+        service_labels: false,
+    };
+
+    fn parse(s: &str, opts: FqdnParseOptions) -> Result<String, ParseFqdnError> {
+        Fqdn::parse_with(s, opts).map(|fqdn| fqdn.to_string())
+    }
+
+    #[test]
+    fn strictness_is_chosen_per_call() {
+        assert_eq!(
+            parse("1foo.example.com", LENIENT).unwrap(),
+            "1foo.example.com"
+        );
+        assert!(matches!(
+            parse("1foo.example.com", STRICT),
+            Err(ParseFqdnError::IllegalLabel(label)) if label == "1foo"
+        ));
+        for s in ["foo-.example.com", "f--oo.example.com", "-foo.example.com"] {
+            assert!(parse(s, LENIENT).is_ok(), "{s}");
+            assert!(
+                matches!(parse(s, STRICT), Err(ParseFqdnError::IllegalLabel(_))),
+                "{s}"
+            );
+        }
+        assert_eq!(parse("www.example.com", STRICT).unwrap(), "www.example.com");
+    }
+
+    #[test]
+    fn the_default_strictness_follows_the_feature() {
+        assert_eq!(
+            FqdnParseOptions::default().strict,
+            cfg!(feature = "strict-fqdn-validation")
+        );
+        assert_eq!(
+            Fqdn::from_str("1foo.example.com").is_ok(),
+            !cfg!(feature = "strict-fqdn-validation")
+        );
+    }
+
+    #[test]
+    fn service_labels_are_only_permitted_on_request() {
+        let service = FqdnParseOptions {
+            service_labels: true,
+            ..STRICT
+        };
+
+        for s in [
+            "_dmarc.example.com",
+            "_sip._tcp.example.com",
+            "_acme-challenge.example.com",
+        ] {
+            assert_eq!(parse(s, service).unwrap(), s);
+            assert!(
+                matches!(parse(s, LENIENT), Err(ParseFqdnError::InvalidCharacters)),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn service_labels_do_not_weaken_the_hostname_rules() {
+        let service = FqdnParseOptions {
+            service_labels: true,
+            ..STRICT
+        };
+
+        // Underscores are only permitted at the start of the leading labels
+        assert!(parse("www._tcp.example.com", service).is_err());
+        assert!(parse("w_w.example.com", service).is_err());
+        assert!(parse("_sip.www_.example.com", service).is_err());
+        assert!(matches!(
+            parse("_sip.1foo.example.com", service),
+            Err(ParseFqdnError::IllegalLabel(label)) if label == "1foo"
+        ));
+    }
+
+    #[test]
+    fn each_malformed_shape_has_its_own_error() {
+        let long_label = "a".repeat(64);
+        let long_name = format!("{}.example.com", ["a"; 125].join("."));
+
+        assert_eq!(parse("", LENIENT), Err(ParseFqdnError::Empty));
+        assert_eq!(parse(".", LENIENT), Err(ParseFqdnError::Empty));
+        assert_eq!(
+            parse(&long_name, LENIENT),
+            Err(ParseFqdnError::TooLong(261))
+        );
+        assert_eq!(
+            parse(".example.com", LENIENT),
+            Err(ParseFqdnError::LeadingDot)
+        );
+        for s in ["a..b.com", "www..example.com", "example.com.."] {
+            assert_eq!(parse(s, LENIENT), Err(ParseFqdnError::EmptyLabel), "{s}");
+        }
+        assert_eq!(
+            parse(&format!("{long_label}.example.com"), LENIENT),
+            Err(ParseFqdnError::LabelTooLong(long_label))
+        );
+        assert_eq!(
+            parse("localhost", LENIENT),
+            Err(ParseFqdnError::SingleLabel)
+        );
+        assert_eq!(
+            parse("www.exam ple.com", LENIENT),
+            Err(ParseFqdnError::InvalidCharacters)
+        );
+    }
+
+    #[test]
+    fn a_single_trailing_dot_is_accepted() {
+        assert_eq!(parse("example.com.", LENIENT).unwrap(), "example.com");
+        assert_eq!(
+            parse("www.example.com.", STRICT).unwrap(),
+            "www.example.com"
+        );
+    }
+
+    #[test]
+    fn valid_labels_are_assembled() {
+        let fqdn = Fqdn::try_from_labels(["www", "example", "com"]).unwrap();
+        assert_eq!(fqdn.to_string(), "www.example.com");
+        assert_eq!(fqdn.labels(), ["www", "example", "com"]);
+
+        let labels = vec!["mail".to_string(), "example".to_string(), "org".to_string()];
+        assert_eq!(
+            Fqdn::try_from_labels(labels).unwrap().to_string(),
+            "mail.example.org"
+        );
+    }
+
+    #[test]
+    fn invalid_labels_are_rejected() {
+        let no_labels: [&str; 0] = [];
+        let long_label = "a".repeat(64);
+
+        assert_eq!(
+            Fqdn::try_from_labels(no_labels).unwrap_err(),
+            ParseFqdnError::Empty
+        );
+        assert_eq!(
+            Fqdn::try_from_labels(["www", "", "com"]).unwrap_err(),
+            ParseFqdnError::EmptyLabel
+        );
+        // A label must not smuggle in further labels
+        assert_eq!(
+            Fqdn::try_from_labels(["www.example", "com"]).unwrap_err(),
+            ParseFqdnError::InvalidCharacters
+        );
+        assert_eq!(
+            Fqdn::try_from_labels(["www", "exam ple", "com"]).unwrap_err(),
+            ParseFqdnError::InvalidCharacters
+        );
+        assert_eq!(
+            Fqdn::try_from_labels([long_label.as_str(), "com"]).unwrap_err(),
+            ParseFqdnError::LabelTooLong(long_label)
+        );
+        assert_eq!(
+            Fqdn::try_from_labels(["localhost"]).unwrap_err(),
+            ParseFqdnError::SingleLabel
+        );
+        assert_eq!(
+            Fqdn::try_from_labels(["foo-", "example", "com"]).is_ok(),
+            !cfg!(feature = "strict-fqdn-validation")
+        );
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_recon_db_fails_fast() {
+        // A host that starts with a slash is the directory of the Unix socket of the server
+        let host = "/nonexistent/grimoire";
+        let start = std::time::Instant::now();
+        let error = create_recon_db_pool(host, "recon", None, "recon", None)
+            .await
+            .unwrap_err();
+
+        assert!(start.elapsed() < RECON_DB_CHECK_TIMEOUT);
+        assert!(
+            matches!(&error, ReconDbError::Unreachable(h, _, _) if h == host),
+            "{error:?}"
+        );
+        assert!(error
+            .to_string()
+            .starts_with("Cannot connect to the recon database at /nonexistent/grimoire:"));
+    }
+
+    /// The names stored in the 'dns-recon' table of the schema that `pg_pool` uses
+    async fn stored_names(pg_pool: &sqlx::PgPool) -> Vec<String> {
+        sqlx::query_scalar(r#"SELECT fqdn FROM "dns-recon" ORDER BY fqdn"#)
+            .fetch_all(pg_pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn schemas_keep_datasets_apart(_: PgPoolOptions, connect_options: PgConnectOptions) {
+        let tenant_a = create_recon_db_pool_with(connect_options.clone(), Some("tenant a"))
+            .await
+            .unwrap();
+        let tenant_b = create_recon_db_pool_with(connect_options.clone(), Some("tenant_b"))
+            .await
+            .unwrap();
+
+        for (pg_pool, fqdns) in [
+            (&tenant_a, &["www.example.com"][..]),
+            (&tenant_b, &["www.example.com", "mail.example.com"][..]),
+        ] {
+            for fqdn in fqdns {
+                sqlx::query(
+                    r#"INSERT INTO "dns-recon" (fqdn, domain, ips) VALUES ($1, 'example.com', '{}')"#,
+                )
+                .bind(fqdn)
+                .execute(pg_pool)
+                .await
+                .unwrap();
+            }
+        }
+
+        assert_eq!(stored_names(&tenant_a).await, ["www.example.com"]);
+        assert_eq!(
+            stored_names(&tenant_b).await,
+            ["mail.example.com", "www.example.com"]
+        );
+        let public = connect_recon_db_with(connect_options, None).await.unwrap();
+        let in_public: bool =
+            sqlx::query_scalar(r#"SELECT to_regclass('public."dns-recon"') IS NOT NULL"#)
+                .fetch_one(&public)
+                .await
+                .unwrap();
+        assert!(!in_public);
+    }
+
+    #[tokio::test]
+    async fn save_atomically_replaces_the_file() {
+        let dir = temp_dir();
+        let path = dir.path().join("save.json");
+        save_atomically(&path, "first").await.unwrap();
+        save_atomically(&path, "second").await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert!(!dir.path().join("save.json.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn save_atomically_keeps_files_differing_only_in_their_extension() {
+        let dir = temp_dir();
+        let other = dir.path().join("save.tmp");
+        std::fs::write(&other, "other").unwrap();
+
+        save_atomically(&dir.path().join("save.json"), "saved")
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "other");
+    }
+
+    #[test]
+    fn registrable_domain_respects_the_public_suffix() {
+        assert_eq!(
+            registrable_domain("a.b.example.com").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            registrable_domain("www.example.co.uk").as_deref(),
+            Some("example.co.uk")
+        );
+        assert_eq!(registrable_domain("co.uk"), None);
+    }
+
+    #[test]
+    fn ancestors_stop_at_the_registrable_domain() {
+        assert_eq!(
+            ancestors("a.b.example.com"),
+            ["a.b.example.com", "b.example.com", "example.com"]
+        );
+        assert_eq!(
+            ancestors("a.example.co.uk"),
+            ["a.example.co.uk", "example.co.uk"]
+        );
+        assert!(ancestors("co.uk").is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use tracing::Subscriber;
+use tracing_subscriber::{fmt::MakeWriter, util::SubscriberInitExt, EnvFilter};
+
+/// The format of the log messages written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of all enclosing spans
+    Json,
+}
+
+/// Installs the global tracing subscriber, which writes to stderr and is filtered by `RUST_LOG`.
+/// A subscriber that is already installed, e.g. by an earlier run in the same test binary, is kept
+pub fn init_logging(format: LogFormat) {
+    let env_filter = EnvFilter::from_default_env();
+
+    let _ = match format {
+        LogFormat::Text => tracing_subscriber::fmt::fmt()
+            .with_env_filter(env_filter)
+            .with_writer(std::io::stderr)
+            .finish()
+            .try_init(),
+        LogFormat::Json => json_subscriber(env_filter, std::io::stderr).try_init(),
+    };
+}
+
+/// A subscriber writing one JSON object per event to `writer`
+fn json_subscriber<W>(env_filter: EnvFilter, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::fmt()
+        .with_env_filter(env_filter)
+        .with_writer(writer)
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+    use tracing::info;
+
+    use super::*;
+
+    /// Collects everything written to it
+    #[derive(Debug, Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tracing::instrument]
+    fn lookup(fqdn: &str) {
+        info!(ips = 2, "Resolved the name");
+    }
+
+    #[test]
+    fn json_events_include_the_span_fields() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = json_subscriber(EnvFilter::new("info"), move || writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || lookup("www.example.com"));
+
+        let output = captured.0.lock().unwrap().clone();
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        let event = &lines[0];
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["fields"]["message"], "Resolved the name");
+        assert_eq!(event["fields"]["ips"], 2);
+        assert_eq!(event["span"]["name"], "lookup");
+        assert_eq!(event["span"]["fqdn"], "www.example.com");
+        assert_eq!(event["spans"][0]["fqdn"], "www.example.com");
+    }
+}
@@ -0,0 +1,372 @@
+use std::{io::Write as _, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    sync::Mutex,
+};
+
+/// Writes the results of a binary to stdout and, optionally, to a file
+#[derive(Debug)]
+pub struct ResultWriter {
+    stdout: bool,
+    file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl ResultWriter {
+    /// Creates a writer that writes to stdout unless `quiet` is set, and to the file at `path` if
+    /// one is given. The file is truncated unless `append` is set
+    #[tracing::instrument]
+    pub async fn new(quiet: bool, path: Option<&Path>, append: bool) -> std::io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(path)
+                    .await?,
+            ))),
+            None => None,
+        };
+
+        Ok(ResultWriter {
+            stdout: !quiet,
+            file,
+        })
+    }
+
+    /// Creates a writer that discards all results, for results that are only stored
+    pub fn discard() -> Self {
+        ResultWriter {
+            stdout: false,
+            file: None,
+        }
+    }
+
+    /// Writes the text as is, without appending a newline. Fails if stdout is closed, e.g. because
+    /// the output is piped into a program that exited
+    pub async fn write_str(&self, text: &str) -> std::io::Result<()> {
+        if self.stdout {
+            std::io::stdout().lock().write_all(text.as_bytes())?;
+        }
+
+        if let Some(file) = &self.file {
+            file.lock().await.write_all(text.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the line followed by a newline
+    pub async fn write_line(&self, line: &str) -> std::io::Result<()> {
+        self.write_str(&format!("{line}\n")).await
+    }
+
+    /// Flushes any results buffered for stdout or the output file
+    pub async fn flush(&self) -> std::io::Result<()> {
+        if self.stdout {
+            std::io::stdout().lock().flush()?;
+        }
+        if let Some(file) = &self.file {
+            file.lock().await.flush().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The format in which the binaries write their results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    /// One line per result, in a format specific to each binary
+    #[default]
+    Text,
+    /// A single JSON array containing one object per result
+    Json,
+    /// One JSON object per result, each on its own line
+    Ndjson,
+    /// One row per result, preceded by a header row. Nested values are encoded as JSON
+    Csv,
+}
+
+#[derive(Debug, Default)]
+struct WriterState {
+    /// Whether the opening bracket of the JSON array has been written
+    array_started: bool,
+    /// The columns of the CSV output, taken from the first record
+    csv_header: Option<Vec<String>>,
+}
+
+/// Writes the results of a binary in the selected format, such that all binaries follow the same
+/// conventions
+#[derive(Debug)]
+pub struct Writer {
+    format: OutputFormat,
+    writer: ResultWriter,
+    state: Mutex<WriterState>,
+}
+
+impl Writer {
+    pub fn new(format: OutputFormat, writer: ResultWriter) -> Self {
+        Writer {
+            format,
+            writer,
+            state: Mutex::new(WriterState::default()),
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Writes a line of text as is. Binaries use this for the text format, where they are free to
+    /// choose their own representation of a result
+    pub async fn write_line(&self, line: &str) -> std::io::Result<()> {
+        self.writer.write_line(line).await
+    }
+
+    /// Writes a single result. In the text format, the fields are separated by spaces
+    pub async fn write_record<T: Serialize>(&self, record: &T) -> Result<(), WriteError> {
+        match self.format {
+            OutputFormat::Text => {
+                let line = record_fields(record)?
+                    .into_iter()
+                    .map(|(_, value)| value_to_string(value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.writer.write_line(&line).await?;
+            }
+            OutputFormat::Json => {
+                let record = serde_json::to_string(record)?;
+                let mut state = self.state.lock().await;
+                if state.array_started {
+                    self.writer.write_str(&format!(",\n{record}")).await?;
+                } else {
+                    self.writer.write_str(&format!("[\n{record}")).await?;
+                    state.array_started = true;
+                }
+            }
+            OutputFormat::Ndjson => {
+                self.writer
+                    .write_line(&serde_json::to_string(record)?)
+                    .await?;
+            }
+            OutputFormat::Csv => {
+                let mut fields = record_fields(record)?;
+                let mut state = self.state.lock().await;
+                if state.csv_header.is_none() {
+                    let header: Vec<_> = fields.iter().map(|(name, _)| name.clone()).collect();
+                    self.writer.write_line(&csv_row(&header)).await?;
+                    state.csv_header = Some(header);
+                }
+                let header = state.csv_header.as_deref().unwrap_or_default();
+                let row = header.iter().map(|name| {
+                    fields
+                        .iter_mut()
+                        .find(|(field, _)| field == name)
+                        .map(|(_, value)| value_to_string(value.take()))
+                        .unwrap_or_default()
+                });
+                self.writer.write_line(&csv_row(row)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Completes the output, closing the JSON array if required, and flushes the output file
+    pub async fn finish(&self) -> std::io::Result<()> {
+        if self.format == OutputFormat::Json {
+            if self.state.lock().await.array_started {
+                self.writer.write_str("\n]\n").await?;
+            } else {
+                self.writer.write_str("[]\n").await?;
+            }
+        }
+
+        self.writer.flush().await
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Splits a record into its named fields. Records that do not serialize to a map have a single
+/// field named `value`
+fn record_fields<T: Serialize>(record: &T) -> Result<Vec<(String, Value)>, serde_json::Error> {
+    match serde_json::to_value(record)? {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        value => Ok(vec![("value".to_string(), value)]),
+    }
+}
+
+/// Represents a field as plain text. Strings are written without quotes, missing values as empty
+/// strings and nested values as JSON
+fn value_to_string(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        value => value.to_string(),
+    }
+}
+
+/// Joins the fields of a CSV row, quoting fields that contain separators, quotes or line breaks
+fn csv_row<I, S>(fields: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    fields
+        .into_iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', ' ', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Record {
+        fqdn: &'static str,
+        ips: Vec<&'static str>,
+        note: Option<&'static str>,
+    }
+
+    fn records() -> [Record; 2] {
+        [
+            Record {
+                fqdn: "www.example.com",
+                ips: vec!["192.0.2.1", "192.0.2.2"],
+                note: Some("say \"hi\", twice"),
+            },
+            Record {
+                fqdn: "mail.example.com",
+                ips: Vec::new(),
+                note: None,
+            },
+        ]
+    }
+
+    /// Writes `records` in `format` to a file, and returns its contents
+    async fn written<T: Serialize>(name: &str, format: OutputFormat, records: &[T]) -> String {
+        let dir = temp_dir();
+        let path = dir.path().join(name);
+        let writer = Writer::new(
+            format,
+            ResultWriter::new(true, Some(&path), false).await.unwrap(),
+        );
+        for record in records {
+            writer.write_record(record).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        std::fs::read_to_string(&path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_output_file_contains_exactly_the_result_lines() {
+        let dir = temp_dir();
+        let path = dir.path().join("results.txt");
+        std::fs::write(&path, "stale\n").unwrap();
+
+        let writer = ResultWriter::new(true, Some(&path), false).await.unwrap();
+        writer
+            .write_line("www.example.com 192.0.2.1")
+            .await
+            .unwrap();
+        writer
+            .write_line("mail.example.com 192.0.2.2")
+            .await
+            .unwrap();
+        writer.flush().await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents,
+            "www.example.com 192.0.2.1\nmail.example.com 192.0.2.2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn results_are_appended_to_the_output_file_on_request() {
+        let dir = temp_dir();
+        let path = dir.path().join("appended.txt");
+        std::fs::write(&path, "earlier\n").unwrap();
+
+        let writer = ResultWriter::new(true, Some(&path), true).await.unwrap();
+        writer.write_line("later").await.unwrap();
+        writer.flush().await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "earlier\nlater\n");
+    }
+
+    #[tokio::test]
+    async fn text_output_separates_the_fields_by_spaces() {
+        assert_eq!(
+            written("records.txt", OutputFormat::Text, &records()).await,
+            "www.example.com [\"192.0.2.1\",\"192.0.2.2\"] say \"hi\", twice\nmail.example.com [] \n"
+        );
+    }
+
+    #[tokio::test]
+    async fn json_output_is_a_single_array() {
+        let contents = written("records.json", OutputFormat::Json, &records()).await;
+
+        let parsed: Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(parsed, serde_json::to_value(records()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn json_output_without_records_is_an_empty_array() {
+        let contents = written::<Record>("empty.json", OutputFormat::Json, &[]).await;
+
+        assert_eq!(contents, "[]\n");
+    }
+
+    #[tokio::test]
+    async fn ndjson_output_holds_one_object_per_line() {
+        let contents = written("records.ndjson", OutputFormat::Ndjson, &records()).await;
+
+        let parsed: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            parsed,
+            serde_json::to_value(records()).unwrap().as_array().unwrap()[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn csv_output_quotes_fields_with_separators() {
+        assert_eq!(
+            written("records.csv", OutputFormat::Csv, &records()).await,
+            "fqdn,ips,note\n\
+             www.example.com,\"[\"\"192.0.2.1\"\",\"\"192.0.2.2\"\"]\",\"say \"\"hi\"\", twice\"\n\
+             mail.example.com,[],\n"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::net::IpAddr;
+
+use base64ct::Encoding;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::{asn::AsnMap, Fqdn};
+
+/// A row of the 'cert-recon' table, i.e. a name found in a certificate. Just like the other
+/// records, the same record is written to the structured output formats and bound to the insert
+/// statement, and its fields are serialized under the names of the columns, such that the output
+/// matches the rows written by grimoire-export. The name is therefore written as `cert-name`, where
+/// cert-recon used to write `name`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CertReconRecord {
+    pub domain: String,
+    pub cert_name: String,
+    pub issuer: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub not_before: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub not_after: Option<OffsetDateTime>,
+    /// When the name was retrieved from the CT logs
+    #[serde(rename = "updated_at", with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+/// A row of the 'dns-recon' table, i.e. the addresses resolved for a name. Names without
+/// addresses are recorded as well. It replaces the objects with the `fqdn`, `record_type`, `values`
+/// and `status` of each address family that dns-recon used to write: `ips` holds the addresses of
+/// both families and is empty if the name has no records
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DnsReconRecord {
+    pub fqdn: String,
+    pub domain: String,
+    pub ips: Vec<IpAddr>,
+    /// The DNSSEC validation status, only present if validation is enabled
+    pub dnssec: Option<String>,
+    /// The name server that answered, absent if the addresses were taken from the cache file
+    pub name_server: Option<String>,
+    pub query_ms: Option<i32>,
+    /// The autonomous systems of the addresses, only present if enrichment is enabled
+    pub asn_info: Option<AsnMap>,
+    /// The addresses returned by the system resolver, only present if it is queried as well
+    pub system_ips: Option<Vec<IpAddr>>,
+    pub divergent: Option<bool>,
+    /// When the name was looked up
+    #[serde(rename = "updated_at", with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl DnsReconRecord {
+    /// A record of the addresses of `fqdn`, without any of the optional details
+    pub fn new(fqdn: &Fqdn, ips: Vec<IpAddr>) -> Self {
+        DnsReconRecord {
+            fqdn: fqdn.to_string(),
+            domain: fqdn.domain(),
+            ips,
+            dnssec: None,
+            name_server: None,
+            query_ms: None,
+            asn_info: None,
+            system_ips: None,
+            divergent: None,
+            updated_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+/// A row of the 'http-recon' or 'https-recon' table, i.e. the response of a single port
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HttpReconRecord {
+    pub fqdn: String,
+    pub domain: String,
+    pub url: String,
+    pub port: u16,
+    /// 0 if the request failed
+    pub response_status: u16,
+    pub class: String,
+    /// Why the request failed, if it did
+    pub error_kind: Option<String>,
+    pub http_version: Option<String>,
+    pub latency_ms: Option<i32>,
+    pub final_url: Option<String>,
+    pub redirect_chain: Vec<String>,
+    pub headers: Value,
+    pub security_flags: Option<Value>,
+    pub content_length: Option<i64>,
+    pub content_type: Option<String>,
+    /// The captured prefix of the response body, base64-encoded in the output
+    #[serde(serialize_with = "serialize_base64")]
+    pub body: Option<Vec<u8>>,
+    /// Whether the response body exceeded the maximum size and was cut short
+    pub body_truncated: Option<bool>,
+    pub title: Option<String>,
+    pub waf: Option<String>,
+    /// Whether the certificate is valid, only stored for HTTPS
+    pub tls_valid: Option<bool>,
+    /// The version of the result, which is only incremented in '--only-changed' mode
+    pub version: i32,
+    /// Whether the response received via the FQDN differs, only present in '--compare' mode
+    pub mismatch: Option<bool>,
+    /// The record of connecting to the FQDN, only present in '--compare' mode
+    pub direct_result: Option<Value>,
+    /// The autonomous system of the IP address, only present if enrichment is enabled
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+    /// The status of each retry with a credential, only present if the response status is 401
+    pub auth_attempts: Option<Value>,
+    /// When the URL was probed
+    #[serde(rename = "updated_at", with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+fn serialize_base64<S: Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    bytes
+        .as_deref()
+        .map(base64ct::Base64::encode_string)
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// The keys of the JSON object that `record` is serialized to, sorted
+    fn sorted_keys<T: Serialize>(record: &T) -> Vec<String> {
+        let mut keys: Vec<_> = match serde_json::to_value(record).unwrap() {
+            Value::Object(object) => object.keys().cloned().collect(),
+            value => panic!("{value} is not an object"),
+        };
+        keys.sort();
+        keys
+    }
+
+    fn sorted(columns: &[&str]) -> Vec<String> {
+        let mut columns: Vec<_> = columns.iter().map(|column| column.to_string()).collect();
+        columns.sort();
+        columns
+    }
+
+    #[test]
+    fn cert_recon_record_matches_the_inserted_columns() {
+        let record = CertReconRecord {
+            domain: "example.com".to_string(),
+            cert_name: "www.example.com".to_string(),
+            issuer: Some("C=US, O=Example CA".to_string()),
+            not_before: None,
+            not_after: None,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        };
+
+        assert_eq!(
+            sorted_keys(&record),
+            sorted(&[
+                "domain",
+                "cert-name",
+                "issuer",
+                "not-before",
+                "not-after",
+                "updated_at"
+            ])
+        );
+        assert_eq!(
+            serde_json::to_value(&record).unwrap()["cert-name"],
+            "www.example.com"
+        );
+    }
+
+    #[test]
+    fn dns_recon_record_matches_the_inserted_columns() {
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let record = DnsReconRecord::new(&fqdn, vec!["192.0.2.1".parse().unwrap()]);
+
+        assert_eq!(
+            sorted_keys(&record),
+            sorted(&[
+                "fqdn",
+                "ips",
+                "domain",
+                "dnssec",
+                "name-server",
+                "query-ms",
+                "asn-info",
+                "system-ips",
+                "divergent",
+                "updated_at",
+            ])
+        );
+        let value = serde_json::to_value(&record).unwrap();
+        assert_eq!(value["domain"], "example.com");
+        assert_eq!(value["ips"], json!(["192.0.2.1"]));
+    }
+
+    #[test]
+    fn http_recon_record_matches_the_inserted_columns() {
+        let record = HttpReconRecord {
+            fqdn: "www.example.com".to_string(),
+            domain: "example.com".to_string(),
+            url: "https://192.0.2.1:443/".to_string(),
+            port: 443,
+            response_status: 200,
+            class: "ok".to_string(),
+            error_kind: None,
+            http_version: Some("HTTP/1.1".to_string()),
+            latency_ms: Some(12),
+            final_url: None,
+            redirect_chain: Vec::new(),
+            headers: json!({}),
+            security_flags: None,
+            content_length: None,
+            content_type: None,
+            body: Some(b"<html>".to_vec()),
+            body_truncated: Some(false),
+            title: None,
+            waf: None,
+            tls_valid: Some(true),
+            version: 1,
+            mismatch: None,
+            direct_result: None,
+            asn: None,
+            as_org: None,
+            auth_attempts: None,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        };
+
+        // The columns of 'https-recon', which are those of 'http-recon' along with 'tls-valid'
+        assert_eq!(
+            sorted_keys(&record),
+            sorted(&[
+                "fqdn",
+                "url",
+                "response-status",
+                "headers",
+                "domain",
+                "body",
+                "body-truncated",
+                "final-url",
+                "redirect-chain",
+                "latency-ms",
+                "port",
+                "security-flags",
+                "content-length",
+                "content-type",
+                "http-version",
+                "tls-valid",
+                "title",
+                "waf",
+                "version",
+                "mismatch",
+                "direct-result",
+                "class",
+                "asn",
+                "as-org",
+                "error-kind",
+                "auth-attempts",
+                "updated_at",
+            ])
+        );
+        assert_eq!(serde_json::to_value(&record).unwrap()["body"], "PGh0bWw+");
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fmt::Display, path::Path, sync::Mutex};
+
+use thiserror::Error;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+use tracing::{error, warn};
+
+use crate::ResultWriter;
+
+#[derive(Debug, Error)]
+pub enum RejectError {
+    #[error("Line {0} of the input cannot be parsed: {1}")]
+    Strict(usize, String),
+    #[error("Cannot write to the rejects file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Handles the lines of the input that cannot be parsed. By default, they are ignored with a
+/// warning. In strict mode, they are either written to the rejects file, or the first one aborts
+/// the run. Binaries are expected to stop consuming input once `aborted` resolves, to finish any
+/// in-flight work and to report the error returned by `finish`
+#[derive(Debug)]
+pub struct Rejects {
+    strict: bool,
+    file: Option<ResultWriter>,
+    aborted: CancellationToken,
+    abort_reason: Mutex<Option<RejectError>>,
+}
+
+impl Rejects {
+    /// Creates the handler, truncating the rejects file at `path`, if one is given
+    pub async fn new(strict: bool, path: Option<&Path>) -> std::io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(ResultWriter::new(true, Some(path), false).await?),
+            None => None,
+        };
+
+        Ok(Rejects {
+            strict,
+            file,
+            aborted: CancellationToken::new(),
+            abort_reason: Mutex::new(None),
+        })
+    }
+
+    /// Handles the line with the 1-based `line_number`, which could not be parsed for `reason`. The
+    /// line is `None` if it could not even be decoded
+    pub async fn reject(&self, line_number: usize, line: Option<&str>, reason: impl Display) {
+        if !self.strict {
+            warn!("Ignoring line {}: {}", line_number, reason);
+            return;
+        }
+
+        match (&self.file, line) {
+            (Some(file), Some(line)) => {
+                if let Err(e) = file.write_line(line).await {
+                    self.abort(RejectError::Io(e));
+                }
+            }
+            (Some(_), None) => warn!(
+                "Cannot write line {} to the rejects file: {}",
+                line_number, reason
+            ),
+            (None, _) => self.abort(RejectError::Strict(line_number, reason.to_string())),
+        }
+    }
+
+    fn abort(&self, reason: RejectError) {
+        error!("{}", reason);
+        self.abort_reason
+            .lock()
+            .expect("the abort reason lock is never poisoned")
+            .get_or_insert(reason);
+        self.aborted.cancel();
+    }
+
+    /// Resolves once the run has been aborted due to a rejected line
+    pub fn aborted(&self) -> WaitForCancellationFuture<'_> {
+        self.aborted.cancelled()
+    }
+
+    /// Flushes the rejects file and returns the reason the run was aborted, if it was
+    pub async fn finish(&self) -> Result<(), RejectError> {
+        if let Some(file) = &self.file {
+            file.flush().await?;
+        }
+
+        match self
+            .abort_reason
+            .lock()
+            .expect("the abort reason lock is never poisoned")
+            .take()
+        {
+            Some(reason) => Err(reason),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn malformed_lines_are_ignored_unless_strict() {
+        let rejects = Rejects::new(false, None).await.unwrap();
+
+        rejects.reject(3, Some("not a name"), "invalid").await;
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), rejects.aborted())
+                .await
+                .is_err()
+        );
+        assert!(rejects.finish().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn the_first_malformed_line_aborts_in_strict_mode() {
+        let rejects = Rejects::new(true, None).await.unwrap();
+
+        rejects.reject(3, Some("not a name"), "invalid").await;
+        rejects.reject(5, Some("no name either"), "invalid").await;
+
+        rejects.aborted().await;
+        let error = rejects.finish().await.unwrap_err();
+        assert!(matches!(error, RejectError::Strict(3, _)));
+        assert_eq!(
+            error.to_string(),
+            "Line 3 of the input cannot be parsed: invalid"
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_are_written_to_the_rejects_file() {
+        let dir = temp_dir();
+        let path = dir.path().join("rejects.txt");
+        let rejects = Rejects::new(true, Some(&path)).await.unwrap();
+
+        rejects.reject(3, Some("not a name"), "invalid").await;
+        rejects.reject(4, None, "invalid UTF-8").await;
+        rejects.reject(5, Some("no name either"), "invalid").await;
+        rejects.finish().await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written, "not a name\nno name either\n");
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), rejects.aborted())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+/// Counts the input lines that parse and those that are rejected, grouped by the reason, as
+/// reported by a dry run
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    valid: usize,
+    rejected: BTreeMap<String, usize>,
+}
+
+impl ParseReport {
+    pub fn record_valid(&mut self) {
+        self.valid += 1;
+    }
+
+    pub fn record_rejected(&mut self, reason: impl Display) {
+        *self.rejected.entry(reason.to_string()).or_default() += 1;
+    }
+
+    pub fn valid(&self) -> usize {
+        self.valid
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.rejected.values().sum()
+    }
+}
+
+impl Display for ParseReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Valid:    {}", self.valid())?;
+        write!(f, "Rejected: {}", self.rejected())?;
+        for (reason, count) in &self.rejected {
+            write!(f, "\n  {count:>6} {reason}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejected_lines_are_grouped_by_reason() {
+        let mut report = ParseReport::default();
+        report.record_valid();
+        report.record_rejected("invalid IP address syntax");
+        report.record_valid();
+        report.record_rejected("empty label");
+        report.record_rejected("invalid IP address syntax");
+
+        assert_eq!(report.valid(), 2);
+        assert_eq!(report.rejected(), 3);
+        assert_eq!(
+            report.to_string(),
+            "Valid:    2\n\
+             Rejected: 3\n       \
+             1 empty label\n       \
+             2 invalid IP address syntax"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::net::{IpAddr, SocketAddr};
+
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig},
+    error::ResolveError,
+    TokioAsyncResolver,
+};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{Fqdn, IpAddrOrFqdn};
+
+#[derive(Debug, Error)]
+pub enum DnsServerError {
+    #[error("Cannot resolve the DNS server '{0}': {1}")]
+    Lookup(Fqdn, ResolveError),
+    #[error("No IP address found for the DNS server '{0}'")]
+    NoAddress(Fqdn),
+}
+
+/// Returns the IP address of the DNS server, looking it up via the system configuration if it is
+/// given by name
+pub async fn dns_server_addr(dns_server: &IpAddrOrFqdn) -> Result<IpAddr, DnsServerError> {
+    match dns_server {
+        IpAddrOrFqdn::IpAddr(dns_addr) => Ok(*dns_addr),
+        IpAddrOrFqdn::Fqdn(dns_fqdn) => {
+            debug!("Resolving the DNS server IP address");
+            let lookup = async {
+                TokioAsyncResolver::tokio_from_system_conf()?
+                    .lookup_ip(format!("{dns_fqdn}."))
+                    .await
+            }
+            .await
+            .map_err(|e| DnsServerError::Lookup(dns_fqdn.clone(), e))?;
+
+            lookup
+                .iter()
+                .next()
+                .ok_or_else(|| DnsServerError::NoAddress(dns_fqdn.clone()))
+        }
+    }
+}
+
+/// The configuration of a resolver that sends all queries to a single name server via UDP, as
+/// used by the recon binaries
+pub fn udp_resolver_config(name_server: SocketAddr) -> ResolverConfig {
+    let mut resolver_config = ResolverConfig::new();
+    resolver_config.add_name_server(NameServerConfig::new(name_server, Protocol::Udp));
+
+    resolver_config
+}
@@ -0,0 +1,72 @@
+use futures::{stream, Stream, StreamExt};
+use rand::{rngs::StdRng, Rng};
+
+/// Emits the items of `stream` in random order. Up to `window` items are read ahead, and each
+/// item emitted is drawn at random from those, so that memory stays bounded for large inputs.
+/// Inputs that fit into the window are shuffled completely
+pub fn shuffle_window<S: Stream>(
+    stream: S,
+    window: usize,
+    rng: StdRng,
+) -> impl Stream<Item = S::Item> {
+    let buffer = Vec::with_capacity(window);
+    stream::unfold(
+        (Box::pin(stream), buffer, rng, false),
+        move |(mut stream, mut buffer, mut rng, mut exhausted)| async move {
+            while !exhausted && buffer.len() < window {
+                match stream.next().await {
+                    Some(item) => buffer.push(item),
+                    None => exhausted = true,
+                }
+            }
+            if buffer.is_empty() {
+                return None;
+            }
+
+            let item = buffer.swap_remove(rng.gen_range(0..buffer.len()));
+            Some((item, (stream, buffer, rng, exhausted)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    async fn shuffled(items: usize, window: usize, seed: u64) -> Vec<usize> {
+        shuffle_window(stream::iter(0..items), window, StdRng::seed_from_u64(seed))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn all_items_are_emitted_in_a_different_order() {
+        let items: Vec<_> = (0..100).collect();
+
+        let mut emitted = shuffled(100, 10_000, 42).await;
+
+        assert_ne!(emitted, items);
+        emitted.sort_unstable();
+        assert_eq!(emitted, items);
+    }
+
+    #[tokio::test]
+    async fn items_are_only_drawn_from_the_window() {
+        let emitted = shuffled(1000, 10, 42).await;
+
+        // The n-th item emitted has been drawn from the first n + 10 items read
+        for (position, item) in emitted.iter().enumerate() {
+            assert!(*item < position + 10, "{item} emitted at {position}");
+        }
+        let mut sorted = emitted.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn a_window_of_one_keeps_the_order() {
+        assert_eq!(shuffled(10, 1, 42).await, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Display;
+
+/// The tables of the recon database that hold results. Each of them has a `domain` column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ReconTable {
+    #[cfg_attr(feature = "clap", value(name = "cert-recon"))]
+    Cert,
+    #[cfg_attr(feature = "clap", value(name = "dns-recon"))]
+    Dns,
+    #[cfg_attr(feature = "clap", value(name = "caa-recon"))]
+    Caa,
+    #[cfg_attr(feature = "clap", value(name = "srv-recon"))]
+    Srv,
+    #[cfg_attr(feature = "clap", value(name = "mx-recon"))]
+    Mx,
+    #[cfg_attr(feature = "clap", value(name = "txt-recon"))]
+    Txt,
+    #[cfg_attr(feature = "clap", value(name = "ns-recon"))]
+    Ns,
+    #[cfg_attr(feature = "clap", value(name = "soa-recon"))]
+    Soa,
+    #[cfg_attr(feature = "clap", value(name = "axfr-recon"))]
+    Axfr,
+    #[cfg_attr(feature = "clap", value(name = "http-recon"))]
+    Http,
+    #[cfg_attr(feature = "clap", value(name = "https-recon"))]
+    Https,
+    #[cfg_attr(feature = "clap", value(name = "tls-recon"))]
+    Tls,
+    #[cfg_attr(feature = "clap", value(name = "favicon-recon"))]
+    Favicon,
+}
+
+impl ReconTable {
+    pub const ALL: &'static [ReconTable] = &[
+        ReconTable::Cert,
+        ReconTable::Dns,
+        ReconTable::Caa,
+        ReconTable::Srv,
+        ReconTable::Mx,
+        ReconTable::Txt,
+        ReconTable::Ns,
+        ReconTable::Soa,
+        ReconTable::Axfr,
+        ReconTable::Http,
+        ReconTable::Https,
+        ReconTable::Tls,
+        ReconTable::Favicon,
+    ];
+
+    /// The name of the table in the recon database
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReconTable::Cert => "cert-recon",
+            ReconTable::Dns => "dns-recon",
+            ReconTable::Caa => "caa-recon",
+            ReconTable::Srv => "srv-recon",
+            ReconTable::Mx => "mx-recon",
+            ReconTable::Txt => "txt-recon",
+            ReconTable::Ns => "ns-recon",
+            ReconTable::Soa => "soa-recon",
+            ReconTable::Axfr => "axfr-recon",
+            ReconTable::Http => "http-recon",
+            ReconTable::Https => "https-recon",
+            ReconTable::Tls => "tls-recon",
+            ReconTable::Favicon => "favicon-recon",
+        }
+    }
+
+    /// Looks up a table by its name in the recon database
+    pub fn from_name(name: &str) -> Option<Self> {
+        ReconTable::ALL
+            .iter()
+            .copied()
+            .find(|table| table.name() == name)
+    }
+}
+
+impl Display for ReconTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use anyhow::anyhow;
+use base64ct::Encoding;
+use reqwest::header::HeaderValue;
+use serde::Serialize;
+
+use crate::Error;
+
+/// A user name and password for basic authentication, given as 'USER:PASS'
+#[derive(Debug, Clone)]
+pub struct BasicAuth {
+    user: String,
+    password: String,
+}
+
+impl FromStr for BasicAuth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, password) = s.split_once(':').ok_or(Error::AuthSplit)?;
+
+        Ok(BasicAuth {
+            user: user.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+/// A credential tried against targets that require authentication
+#[derive(Clone)]
+pub enum Credential {
+    Basic(BasicAuth),
+    /// A bearer token along with its position among the tokens, which labels it in the results
+    /// instead of the token itself
+    Bearer(usize, String),
+}
+
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.label())
+    }
+}
+
+impl Credential {
+    /// Identifies the credential in the results without revealing the secret
+    pub fn label(&self) -> String {
+        match self {
+            Credential::Basic(basic) => format!("basic:{}", basic.user),
+            Credential::Bearer(index, _) => format!("bearer:#{index}"),
+        }
+    }
+
+    /// The value of the Authorization header, which is marked as sensitive
+    pub fn header_value(&self) -> Result<HeaderValue, Error> {
+        let value = match self {
+            Credential::Basic(basic) => format!(
+                "Basic {}",
+                base64ct::Base64::encode_string(
+                    format!("{}:{}", basic.user, basic.password).as_bytes()
+                )
+            ),
+            Credential::Bearer(_, token) => format!("Bearer {token}"),
+        };
+        let mut value = HeaderValue::from_str(&value)?;
+        value.set_sensitive(true);
+
+        Ok(value)
+    }
+}
+
+/// Collects the credentials given on the command line and in the credentials file, whose lines
+/// hold either 'basic USER:PASS' or 'bearer TOKEN'. Empty lines and lines starting with '#' are
+/// ignored. Bearer tokens are numbered from 1 in the order they are given
+pub async fn load_credentials(
+    mut basic: Vec<BasicAuth>,
+    mut bearer: Vec<String>,
+    path: Option<&Path>,
+) -> anyhow::Result<Vec<Credential>> {
+    if let Some(path) = path {
+        let contents = tokio::fs::read_to_string(path).await?;
+        for (index, line) in contents.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                anyhow!(
+                    "Expected 'basic USER:PASS' or 'bearer TOKEN' on line {} of '{}'",
+                    index + 1,
+                    path.display()
+                )
+            };
+            match line.split_once(char::is_whitespace).ok_or_else(invalid)? {
+                ("basic", credential) => {
+                    basic.push(credential.trim().parse().map_err(|_| invalid())?)
+                }
+                ("bearer", token) => bearer.push(token.trim().to_string()),
+                _ => return Err(invalid()),
+            }
+        }
+    }
+
+    let credentials: Vec<_> = basic
+        .into_iter()
+        .map(Credential::Basic)
+        .chain(
+            bearer
+                .into_iter()
+                .enumerate()
+                .map(|(index, token)| Credential::Bearer(index + 1, token)),
+        )
+        .collect();
+    for credential in &credentials {
+        credential.header_value().map_err(|e| {
+            anyhow!(
+                "The credential {} cannot be sent as a header: {}",
+                credential.label(),
+                e
+            )
+        })?;
+    }
+
+    Ok(credentials)
+}
+
+/// The response status received when retrying a request that required authentication with a
+/// credential
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthAttempt {
+    pub credential: String,
+    pub status: u16,
+}
+
+impl AuthAttempt {
+    /// Whether the credential was accepted, i.e. the response is no longer 401 or 403
+    pub fn succeeded(&self) -> bool {
+        !matches!(self.status, 0 | 401 | 403)
+    }
+}
+
+impl Display for AuthAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.credential, self.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn credentials_are_sent_as_sensitive_authorization_headers() {
+        let basic = Credential::Basic("user:secret".parse().unwrap());
+        let bearer = Credential::Bearer(1, "token".to_string());
+
+        let value = basic.header_value().unwrap();
+        assert_eq!(value, "Basic dXNlcjpzZWNyZXQ=");
+        assert!(value.is_sensitive());
+        assert_eq!(bearer.header_value().unwrap(), "Bearer token");
+    }
+
+    #[test]
+    fn labels_do_not_reveal_the_secret() {
+        assert_eq!(
+            Credential::Basic("user:secret".parse().unwrap()).label(),
+            "basic:user"
+        );
+        assert_eq!(
+            Credential::Bearer(2, "token".to_string()).label(),
+            "bearer:#2"
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_are_read_from_the_file_after_the_arguments() {
+        let dir = temp_dir();
+        let path = dir.path().join("credentials.auth");
+        std::fs::write(&path, "# comment\n\nbasic admin:admin\nbearer file-token\n").unwrap();
+
+        let credentials = load_credentials(
+            vec!["user:secret".parse().unwrap()],
+            vec!["arg-token".to_string()],
+            Some(&path),
+        )
+        .await;
+
+        let labels: Vec<_> = credentials.unwrap().iter().map(Credential::label).collect();
+        assert_eq!(
+            labels,
+            ["basic:user", "basic:admin", "bearer:#1", "bearer:#2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_of_the_file_are_rejected() {
+        let dir = temp_dir();
+        let path = dir.path().join("bad.auth");
+        std::fs::write(&path, "basic admin:admin\ndigest admin:admin\n").unwrap();
+
+        let error = load_credentials(Vec::new(), Vec::new(), Some(&path))
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("on line 2"), "{error}");
+    }
+
+    #[test]
+    fn only_accepted_credentials_succeed() {
+        let attempt = |status| AuthAttempt {
+            credential: "basic:user".to_string(),
+            status,
+        };
+
+        assert!(attempt(200).succeeded());
+        assert!(!attempt(401).succeeded());
+        assert!(!attempt(403).succeeded());
+        assert!(!attempt(0).succeeded());
+    }
+}
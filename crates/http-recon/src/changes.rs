@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, HashMap};
+
+use grimoire::Fqdn;
+use sqlx::{query, PgPool};
+
+use crate::{PortSpec, ProbeResult, Scheme};
+
+/// The response headers whose values are compared to detect changes. All other headers tend to
+/// change on every request, e.g. 'date' or 'etag'
+const KEY_HEADERS: &[&str] = &[
+    "server",
+    "location",
+    "content-type",
+    "x-powered-by",
+    "strict-transport-security",
+    "content-security-policy",
+];
+
+/// The parts of a result that are compared to decide whether it changed since the last run
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    status: u16,
+    title: Option<String>,
+    key_headers: BTreeMap<&'static str, Vec<String>>,
+}
+
+impl Snapshot {
+    pub fn from_result(result: &ProbeResult) -> Self {
+        Snapshot::new(
+            result.response_status,
+            result.title.clone(),
+            result.headers.as_ref().map(|h| &h.0),
+        )
+    }
+
+    fn new(
+        status: u16,
+        title: Option<String>,
+        headers: Option<&HashMap<String, Vec<String>>>,
+    ) -> Self {
+        let key_headers = KEY_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let values = headers?.get(*name)?;
+                Some((*name, values.clone()))
+            })
+            .collect();
+
+        Snapshot {
+            status,
+            title,
+            key_headers,
+        }
+    }
+
+    /// Describes how `new` differs from this snapshot, one entry per changed field. Returns an
+    /// empty list if nothing changed
+    pub fn diff(&self, new: &Snapshot) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.status != new.status {
+            changes.push(format!("status {} -> {}", self.status, new.status));
+        }
+        if self.title != new.title {
+            changes.push(format!("title {:?} -> {:?}", self.title, new.title));
+        }
+        for name in KEY_HEADERS {
+            let old_values = self.key_headers.get(name);
+            let new_values = new.key_headers.get(name);
+            if old_values != new_values {
+                changes.push(format!("{name} {old_values:?} -> {new_values:?}"));
+            }
+        }
+
+        changes
+    }
+}
+
+/// The latest version of a result stored in the recon database
+#[derive(Debug)]
+pub struct StoredVersion {
+    pub version: i32,
+    pub snapshot: Snapshot,
+}
+
+/// Retrieves the latest stored result of `fqdn` on the port, if there is one
+#[tracing::instrument(skip(pg_pool))]
+pub async fn latest_version(
+    pg_pool: &PgPool,
+    fqdn: &Fqdn,
+    port_spec: &PortSpec,
+) -> anyhow::Result<Option<StoredVersion>> {
+    let row = match port_spec.scheme {
+        Scheme::Http => query!(
+            r#"SELECT version, "response-status" AS response_status, headers, title FROM "http-recon" WHERE fqdn = $1 AND port = $2 ORDER BY version DESC LIMIT 1"#,
+            fqdn.to_string(),
+            port_spec.port as i32,
+        )
+        .fetch_optional(pg_pool)
+        .await?
+        .map(|r| (r.version, r.response_status, r.headers, r.title)),
+        Scheme::Https => query!(
+            r#"SELECT version, "response-status" AS response_status, headers, title FROM "https-recon" WHERE fqdn = $1 AND port = $2 ORDER BY version DESC LIMIT 1"#,
+            fqdn.to_string(),
+            port_spec.port as i32,
+        )
+        .fetch_optional(pg_pool)
+        .await?
+        .map(|r| (r.version, r.response_status, r.headers, r.title)),
+    };
+
+    Ok(row.map(|(version, status, headers, title)| {
+        let headers: Option<HashMap<String, Vec<String>>> = serde_json::from_value(headers).ok();
+        StoredVersion {
+            version,
+            snapshot: Snapshot::new(status as u16, title, headers.as_ref()),
+        }
+    }))
+}
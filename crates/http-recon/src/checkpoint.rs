@@ -0,0 +1,241 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+};
+
+use futures::{Stream, StreamExt};
+use grimoire::save_atomically;
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::LinesCodecError;
+use tracing::{debug, info};
+
+/// The number of additional lines that must be processed before the checkpoint is saved again
+const SAVE_INTERVAL: usize = 1000;
+
+/// The contents of the checkpoint file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// The number of leading lines of the input that were processed completely
+    offset: usize,
+    /// The last of those lines, used to detect whether the input changed between runs
+    last_line: Option<String>,
+}
+
+/// Records how many leading lines of the input were processed completely, so that an interrupted
+/// run can be resumed without probing those lines again. Lines may complete out of order, so the
+/// checkpoint only advances past lines whose predecessors have all completed. A line only counts
+/// as completed once its results are stored, i.e. once the results submitted to the recon
+/// database up to its completion are committed
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    state: State,
+    /// The offset found in the checkpoint file when the run started
+    resumed_offset: usize,
+    saved_offset: usize,
+    /// Lines that completed before one of their predecessors, by index
+    completed: BTreeMap<usize, Option<String>>,
+    /// Lines that were processed, but whose results may not be stored yet, along with the number
+    /// of results that must be committed before they are, in the order they were processed
+    uncommitted: VecDeque<(u64, usize, Option<String>)>,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint file, or starts from the beginning of the input if it does not exist
+    pub async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let state = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| {
+                anyhow::anyhow!("Cannot parse the checkpoint '{}': {}", path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Checkpoint {
+            path,
+            resumed_offset: state.offset,
+            saved_offset: state.offset,
+            state,
+            completed: BTreeMap::new(),
+            uncommitted: VecDeque::new(),
+        })
+    }
+
+    /// The index of the first line that was not skipped
+    pub fn resumed_offset(&self) -> usize {
+        self.resumed_offset
+    }
+
+    /// Consumes the lines that were processed by a previous run. Fails if the input ends early or
+    /// if the last skipped line differs from the one recorded, because the input must have changed
+    pub async fn skip_processed<S>(&self, lines: &mut S) -> anyhow::Result<()>
+    where
+        S: Stream<Item = Result<String, LinesCodecError>> + Unpin,
+    {
+        if self.resumed_offset == 0 {
+            return Ok(());
+        }
+
+        info!("Resuming after line {}", self.resumed_offset);
+        let mut last_line = None;
+        for index in 0..self.resumed_offset {
+            let Some(line_result) = lines.next().await else {
+                anyhow::bail!(
+                    "The input ends after {} lines, but the checkpoint '{}' records {} processed lines. Delete it to start over",
+                    index,
+                    self.path.display(),
+                    self.resumed_offset
+                );
+            };
+            last_line = line_result.ok();
+        }
+
+        if self.state.last_line.is_some() && last_line != self.state.last_line {
+            anyhow::bail!(
+                "Line {} of the input differs from the one recorded in the checkpoint '{}'. Delete it to start over",
+                self.resumed_offset,
+                self.path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Marks the line with the given index as processed, once the first `watermark` results
+    /// submitted to the recon database are committed. `line` is `None` if the line could not be
+    /// decoded
+    pub fn complete(&mut self, index: usize, line: Option<String>, watermark: u64) {
+        self.uncommitted.push_back((watermark, index, line));
+    }
+
+    /// Counts the processed lines whose results are among the first `committed` results as
+    /// completed and saves the checkpoint every `SAVE_INTERVAL` lines
+    pub async fn commit(&mut self, committed: u64) -> std::io::Result<()> {
+        while self
+            .uncommitted
+            .front()
+            .is_some_and(|(watermark, ..)| *watermark <= committed)
+        {
+            let (_, index, line) = self.uncommitted.pop_front().expect("a front line");
+            self.completed.insert(index, line);
+        }
+        while let Some(line) = self.completed.remove(&self.state.offset) {
+            self.state.offset += 1;
+            self.state.last_line = line;
+        }
+
+        if self.state.offset - self.saved_offset >= SAVE_INTERVAL {
+            self.save().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the checkpoint file. The file is replaced atomically, so that an interruption never
+    /// leaves a partially written checkpoint behind
+    pub async fn save(&mut self) -> std::io::Result<()> {
+        debug!(
+            "Saving the checkpoint at line {} to '{}'",
+            self.state.offset,
+            self.path.display()
+        );
+        save_atomically(&self.path, serde_json::to_vec(&self.state)?).await?;
+        self.saved_offset = self.state.offset;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use grimoire_test::temp_dir;
+
+    use super::*;
+
+    fn lines(count: usize) -> impl Stream<Item = Result<String, LinesCodecError>> + Unpin {
+        stream::iter((0..count).map(|index| Ok(format!("www{index}.example.com"))))
+    }
+
+    #[tokio::test]
+    async fn resuming_skips_the_completed_lines() {
+        let dir = temp_dir();
+        let path = dir.path().join("resume.json");
+        let _ = tokio::fs::remove_file(&path).await;
+
+        // Lines 0 to 2 complete out of order, line 4 completes before line 3
+        let mut checkpoint = Checkpoint::load(path.clone()).await.unwrap();
+        for index in [1, 0, 2, 4] {
+            let line = format!("www{index}.example.com");
+            checkpoint.complete(index, Some(line), 0);
+        }
+        checkpoint.commit(0).await.unwrap();
+        checkpoint.save().await.unwrap();
+
+        let checkpoint = Checkpoint::load(path.clone()).await.unwrap();
+        assert_eq!(checkpoint.resumed_offset(), 3);
+        let mut input = lines(6);
+        checkpoint.skip_processed(&mut input).await.unwrap();
+        let remaining: Vec<_> = input.map(Result::unwrap).collect().await;
+        assert_eq!(
+            remaining,
+            ["www3.example.com", "www4.example.com", "www5.example.com"]
+        );
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lines_are_only_completed_once_their_results_are_committed() {
+        let dir = temp_dir();
+        let path = dir.path().join("commit.json");
+        let _ = tokio::fs::remove_file(&path).await;
+
+        // Line 0 submitted one result and line 1 three more, but only two are committed
+        let mut checkpoint = Checkpoint::load(path.clone()).await.unwrap();
+        checkpoint.complete(0, Some("www0.example.com".to_string()), 1);
+        checkpoint.complete(1, Some("www1.example.com".to_string()), 4);
+        checkpoint.commit(2).await.unwrap();
+        checkpoint.save().await.unwrap();
+        assert_eq!(
+            Checkpoint::load(path.clone())
+                .await
+                .unwrap()
+                .resumed_offset(),
+            1
+        );
+
+        checkpoint.commit(4).await.unwrap();
+        checkpoint.save().await.unwrap();
+        assert_eq!(
+            Checkpoint::load(path.clone())
+                .await
+                .unwrap()
+                .resumed_offset(),
+            2
+        );
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resuming_with_a_changed_input_fails() {
+        let dir = temp_dir();
+        let path = dir.path().join("changed.json");
+        let _ = tokio::fs::remove_file(&path).await;
+
+        let mut checkpoint = Checkpoint::load(path.clone()).await.unwrap();
+        for index in 0..2 {
+            let line = format!("mail{index}.example.com");
+            checkpoint.complete(index, Some(line), 0);
+        }
+        checkpoint.commit(0).await.unwrap();
+        checkpoint.save().await.unwrap();
+
+        let checkpoint = Checkpoint::load(path.clone()).await.unwrap();
+        assert!(checkpoint.skip_processed(&mut lines(6)).await.is_err());
+        assert!(checkpoint.skip_processed(&mut lines(1)).await.is_err());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
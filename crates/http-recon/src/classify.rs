@@ -0,0 +1,150 @@
+use std::{fmt::Display, str::FromStr};
+
+use clap::ValueEnum;
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
+use serde::Serialize;
+
+use crate::{output::StatusFilter, Error};
+
+/// The class of a result, which allows filtering the interesting ones in the recon database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatusClass {
+    Live,
+    Redirect,
+    AuthRequired,
+    Forbidden,
+    Error,
+    /// The connection failed, i.e. the status is 0
+    Dead,
+}
+
+impl StatusClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatusClass::Live => "live",
+            StatusClass::Redirect => "redirect",
+            StatusClass::AuthRequired => "auth-required",
+            StatusClass::Forbidden => "forbidden",
+            StatusClass::Error => "error",
+            StatusClass::Dead => "dead",
+        }
+    }
+}
+
+impl Display for StatusClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Assigns the statuses to a class, given as 'CLASS=STATUSES', e.g. 'live=200-299,404'
+#[derive(Debug, Clone)]
+pub struct ClassRule {
+    class: StatusClass,
+    statuses: StatusFilter,
+}
+
+impl FromStr for ClassRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, statuses) = s.split_once('=').ok_or(Error::ClassSplit)?;
+        let class = StatusClass::from_str(class.trim(), true)
+            .map_err(|_| Error::UnknownClass(class.trim().to_string()))?;
+
+        Ok(ClassRule {
+            class,
+            statuses: statuses.parse()?,
+        })
+    }
+}
+
+/// Classifies results by their status. The rules are checked in order and take precedence over
+/// the default classes, except that a failed connection is always dead
+#[derive(Debug, Clone, Default)]
+pub struct StatusClassifier(Vec<ClassRule>);
+
+impl StatusClassifier {
+    pub fn new(rules: Vec<ClassRule>) -> Self {
+        StatusClassifier(rules)
+    }
+
+    pub fn classify(&self, status: u16, headers: &HeaderMap) -> StatusClass {
+        if status == 0 {
+            return StatusClass::Dead;
+        }
+        if let Some(rule) = self.0.iter().find(|rule| rule.statuses.matches(status)) {
+            return rule.class;
+        }
+
+        match status {
+            401 | 407 => StatusClass::AuthRequired,
+            _ if headers.contains_key(WWW_AUTHENTICATE) => StatusClass::AuthRequired,
+            403 => StatusClass::Forbidden,
+            100..=299 => StatusClass::Live,
+            300..=399 => StatusClass::Redirect,
+            _ => StatusClass::Error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn statuses_are_assigned_their_default_class() {
+        let classifier = StatusClassifier::default();
+        let headers = HeaderMap::new();
+
+        assert_eq!(classifier.classify(200, &headers), StatusClass::Live);
+        assert_eq!(classifier.classify(301, &headers), StatusClass::Redirect);
+        assert_eq!(
+            classifier.classify(401, &headers),
+            StatusClass::AuthRequired
+        );
+        assert_eq!(classifier.classify(403, &headers), StatusClass::Forbidden);
+        assert_eq!(classifier.classify(500, &headers), StatusClass::Error);
+        assert_eq!(classifier.classify(0, &headers), StatusClass::Dead);
+    }
+
+    #[test]
+    fn a_challenge_requires_authentication_regardless_of_the_status() {
+        let mut headers = HeaderMap::new();
+        headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+
+        assert_eq!(
+            StatusClassifier::default().classify(200, &headers),
+            StatusClass::AuthRequired
+        );
+    }
+
+    #[test]
+    fn rules_take_precedence_over_the_default_classes() {
+        let classifier = StatusClassifier::new(vec![
+            "live=200-299,404".parse().unwrap(),
+            "dead=500".parse().unwrap(),
+        ]);
+        let headers = HeaderMap::new();
+
+        assert_eq!(classifier.classify(404, &headers), StatusClass::Live);
+        assert_eq!(classifier.classify(500, &headers), StatusClass::Dead);
+        assert_eq!(classifier.classify(0, &headers), StatusClass::Dead);
+        assert_eq!(classifier.classify(403, &headers), StatusClass::Forbidden);
+    }
+
+    #[test]
+    fn malformed_rules_are_rejected() {
+        assert!(matches!(
+            "live".parse::<ClassRule>(),
+            Err(Error::ClassSplit)
+        ));
+        assert!(matches!(
+            "fine=200".parse::<ClassRule>(),
+            Err(Error::UnknownClass(class)) if class == "fine"
+        ));
+    }
+}
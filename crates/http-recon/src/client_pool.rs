@@ -0,0 +1,227 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use grimoire::Fqdn;
+use reqwest::{header::HeaderMap, redirect::Policy, Proxy};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
+
+/// The settings shared by all HTTP clients
+pub struct ClientSettings {
+    pub user_agent: String,
+    pub default_headers: HeaderMap,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub http1_only: bool,
+    /// Whether each client is accompanied by a fallback client that does not validate certificates
+    pub accept_invalid_certs: bool,
+    /// The rate limiting middleware shared by all clients
+    pub rate_limiter: Arc<dyn Middleware>,
+}
+
+impl ClientSettings {
+    /// Builds a client that uses `proxy`. If `pinned` is given, the FQDN is resolved to the IP
+    /// address instead of querying DNS, for any port
+    fn build(
+        &self,
+        proxy: Option<&Proxy>,
+        accept_invalid_certs: bool,
+        pinned: Option<(&Fqdn, IpAddr)>,
+    ) -> anyhow::Result<ClientWithMiddleware> {
+        let mut client = reqwest::ClientBuilder::default();
+        if let Some(proxy) = proxy {
+            client = client.proxy(proxy.clone());
+        }
+        if let Some((fqdn, ip)) = pinned {
+            client = client.resolve(&fqdn.to_string(), SocketAddr::new(ip, 0));
+        }
+        client = client
+            .danger_accept_invalid_certs(accept_invalid_certs)
+            .user_agent(&self.user_agent)
+            .default_headers(self.default_headers.clone())
+            // Redirects are followed by `probe`, so that the Host header can be kept consistent
+            // across hops
+            .redirect(Policy::none())
+            .tls_info(true)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+        if self.http1_only {
+            client = client.http1_only();
+        }
+
+        Ok(ClientBuilder::new(client.build()?)
+            .with_arc(self.rate_limiter.clone())
+            .build())
+    }
+
+    /// Builds a client that uses `proxy`, along with its fallback
+    fn build_pair(
+        &self,
+        proxy: Option<&Proxy>,
+        pinned: Option<(&Fqdn, IpAddr)>,
+    ) -> anyhow::Result<(ClientWithMiddleware, Option<ClientWithMiddleware>)> {
+        let fallback = if self.accept_invalid_certs {
+            Some(self.build(proxy, true, pinned)?)
+        } else {
+            None
+        };
+
+        Ok((self.build(proxy, false, pinned)?, fallback))
+    }
+}
+
+/// A set of HTTP clients, usually one per proxy, that are handed out in round-robin order. Each
+/// client may be accompanied by a fallback client that uses the same proxy, but does not validate
+/// certificates
+pub struct ClientPool {
+    settings: ClientSettings,
+    proxies: Vec<Option<Proxy>>,
+    clients: Vec<(ClientWithMiddleware, Option<ClientWithMiddleware>)>,
+    next: AtomicUsize,
+}
+
+impl ClientPool {
+    /// Builds one client per proxy, where `None` stands for a direct connection
+    pub fn new(settings: ClientSettings, proxies: Vec<Option<Proxy>>) -> anyhow::Result<Self> {
+        assert!(
+            !proxies.is_empty(),
+            "the client pool requires at least one client"
+        );
+
+        let clients = proxies
+            .iter()
+            .map(|proxy| settings.build_pair(proxy.as_ref(), None))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(ClientPool {
+            settings,
+            proxies,
+            clients,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    fn next_index(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len()
+    }
+
+    /// Returns the client that should be used for the next request, along with its fallback
+    pub fn next(&self) -> (&ClientWithMiddleware, Option<&ClientWithMiddleware>) {
+        let (client, fallback) = &self.clients[self.next_index()];
+        (client, fallback.as_ref())
+    }
+
+    /// Builds a dedicated client, along with its fallback, that resolves `fqdn` to `ip`. Requesting
+    /// URLs of the FQDN with it connects to the IP address while sending the FQDN via SNI. The
+    /// proxy is chosen in the same order as by `next`, but HTTP proxies resolve the FQDN themselves
+    pub fn pinned(
+        &self,
+        fqdn: &Fqdn,
+        ip: IpAddr,
+    ) -> anyhow::Result<(ClientWithMiddleware, Option<ClientWithMiddleware>)> {
+        let proxy = self.proxies[self.next_index()].as_ref();
+        self.settings.build_pair(proxy, Some((fqdn, ip)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::http::{ok_response, read_request_head};
+    use rand::{rngs::StdRng, SeedableRng};
+    use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::jitter::JitteredRateLimiter;
+
+    fn settings() -> ClientSettings {
+        let limiter = RateLimiter::builder().initial(10).max(10).build();
+        ClientSettings {
+            user_agent: "http-recon-test".to_string(),
+            default_headers: HeaderMap::new(),
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(5),
+            http1_only: false,
+            accept_invalid_certs: false,
+            rate_limiter: Arc::new(reqwest_ratelimit::all(JitteredRateLimiter::new(
+                Arc::new(limiter),
+                Duration::ZERO,
+                StdRng::seed_from_u64(0),
+            ))),
+        }
+    }
+
+    /// Reads the head of a request from `stream` and answers it with status 200 and `name` as the
+    /// body
+    async fn respond(stream: &mut TcpStream, name: &str) -> std::io::Result<()> {
+        if read_request_head(stream).await.is_none() {
+            return Ok(());
+        }
+        stream.write_all(ok_response(name).as_bytes()).await
+    }
+
+    /// Completes the handshake of a SOCKS5 client without authentication, accepting any target
+    async fn socks5_accept(stream: &mut TcpStream) -> std::io::Result<()> {
+        let mut greeting = [0; 2];
+        stream.read_exact(&mut greeting).await?;
+        let mut methods = vec![0; usize::from(greeting[1])];
+        stream.read_exact(&mut methods).await?;
+        stream.write_all(&[5, 0]).await?;
+
+        let mut request = [0; 4];
+        stream.read_exact(&mut request).await?;
+        let address_len = match request[3] {
+            1 => 4,
+            4 => 16,
+            _ => usize::from(stream.read_u8().await?),
+        };
+        let mut address_and_port = vec![0; address_len + 2];
+        stream.read_exact(&mut address_and_port).await?;
+        stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await
+    }
+
+    /// Acts as an HTTP or SOCKS5 proxy that answers each request itself with status 200 and `name`
+    /// as the body
+    async fn spawn_proxy(scheme: &'static str, name: &'static str) -> Proxy {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    if scheme == "socks5h" && socks5_accept(&mut stream).await.is_err() {
+                        return;
+                    }
+                    let _ = respond(&mut stream, name).await;
+                });
+            }
+        });
+
+        Proxy::all(format!("{scheme}://{addr}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn consecutive_requests_rotate_through_the_proxies() {
+        let proxies = vec![
+            Some(spawn_proxy("http", "http").await),
+            Some(spawn_proxy("socks5h", "socks5").await),
+        ];
+        let pool = ClientPool::new(settings(), proxies).unwrap();
+
+        let mut bodies = Vec::new();
+        for _ in 0..4 {
+            let (client, _) = pool.next();
+            let response = client.get("http://www.example.com/").send().await.unwrap();
+            bodies.push(response.text().await.unwrap());
+        }
+
+        assert_eq!(bodies, ["http", "socks5", "http", "socks5"]);
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{changes::Snapshot, ProbeResult};
+
+/// The result of probing a target via its FQDN in addition to via its IP address. Differences
+/// reveal virtual hosts and CDNs, whose edge servers respond unlike the origin server
+#[derive(Debug)]
+pub struct Comparison {
+    /// The result of connecting to the FQDN, as resolved via DNS
+    pub direct: Box<ProbeResult>,
+    /// How the status, title and key headers of the direct response differ, one entry per field
+    pub differences: Vec<String>,
+}
+
+impl Comparison {
+    pub fn new(result: &ProbeResult, direct: ProbeResult) -> Self {
+        let differences = Snapshot::from_result(result).diff(&Snapshot::from_result(&direct));
+
+        Comparison {
+            direct: Box::new(direct),
+            differences,
+        }
+    }
+
+    pub fn is_mismatch(&self) -> bool {
+        !self.differences.is_empty()
+    }
+}
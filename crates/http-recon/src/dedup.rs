@@ -0,0 +1,93 @@
+use std::{collections::HashSet, net::IpAddr, num::NonZeroUsize, sync::Mutex};
+
+use bloomfilter::Bloom;
+use grimoire::Fqdn;
+use rand::{rngs::StdRng, Rng};
+
+/// The false positive rate of the approximate deduplicator
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// Tracks the pairs of FQDN and IP address seen during a run
+pub enum Deduplicator {
+    /// Remembers every pair exactly, using memory proportional to the input
+    Exact(Mutex<HashSet<(String, IpAddr)>>),
+    /// Uses a Bloom filter with bounded memory, at the cost of occasionally skipping a pair that
+    /// was not seen before
+    Approximate(Mutex<Bloom<(String, IpAddr)>>),
+}
+
+impl Deduplicator {
+    /// Creates an exact deduplicator, or an approximate one sized for `capacity` pairs. The hash
+    /// functions of the latter are seeded from `rng`, such that '--seed' determines which pairs
+    /// collide
+    pub fn new(capacity: Option<NonZeroUsize>, mut rng: StdRng) -> Self {
+        match capacity {
+            Some(capacity) => {
+                let mut seed = [0; 32];
+                rng.fill(&mut seed);
+                Deduplicator::Approximate(Mutex::new(Bloom::new_for_fp_rate_with_seed(
+                    capacity.get(),
+                    BLOOM_FALSE_POSITIVE_RATE,
+                    &seed,
+                )))
+            }
+            None => Deduplicator::Exact(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Records the pair and returns true if it has not been seen before
+    pub fn insert(&self, fqdn: &Fqdn, ip: &IpAddr) -> bool {
+        let key = (fqdn.to_string(), *ip);
+        match self {
+            Deduplicator::Exact(seen) => seen
+                .lock()
+                .expect("the deduplicator lock is never poisoned")
+                .insert(key),
+            Deduplicator::Approximate(seen) => !seen
+                .lock()
+                .expect("the deduplicator lock is never poisoned")
+                .check_and_set(&key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn sip_keys(deduplicator: &Deduplicator) -> [(u64, u64); 2] {
+        match deduplicator {
+            Deduplicator::Approximate(seen) => seen.lock().unwrap().sip_keys(),
+            Deduplicator::Exact(_) => panic!("the deduplicator is exact"),
+        }
+    }
+
+    #[test]
+    fn pairs_are_only_inserted_once() {
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "192.0.2.1".parse().unwrap();
+        let other_ip = "192.0.2.2".parse().unwrap();
+
+        for capacity in [None, NonZeroUsize::new(100)] {
+            let deduplicator = Deduplicator::new(capacity, StdRng::seed_from_u64(1));
+            assert!(deduplicator.insert(&fqdn, &ip));
+            assert!(!deduplicator.insert(&fqdn, &ip));
+            assert!(deduplicator.insert(&fqdn, &other_ip));
+        }
+    }
+
+    #[test]
+    fn the_bloom_filter_is_seeded_from_the_generator() {
+        let capacity = NonZeroUsize::new(1);
+        let first = Deduplicator::new(capacity, StdRng::seed_from_u64(7));
+        let second = Deduplicator::new(capacity, StdRng::seed_from_u64(7));
+        let other = Deduplicator::new(capacity, StdRng::seed_from_u64(8));
+
+        assert_eq!(sip_keys(&first), sip_keys(&second));
+        assert_ne!(sip_keys(&first), sip_keys(&other));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{error::Error as StdError, fmt::Display, io};
+
+use serde::Serialize;
+
+/// Fragments of the error messages of the TLS backend, which does not expose its error type via
+/// reqwest
+const TLS_MARKERS: [&str; 4] = ["tls", "ssl", "certificate", "handshake"];
+
+/// Why a request failed, which tells a filtered port from a slow host or a misconfigured TLS
+/// service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The request timed out
+    Timeout,
+    /// The connection was refused or could not be established, e.g. because the FQDN does not
+    /// resolve
+    Connect,
+    /// The TLS handshake failed
+    Tls,
+    /// The connection was reset or closed prematurely
+    Reset,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Reset => "reset",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Classifies the error of a request by the first matching error among its sources. TLS
+    /// failures are checked before connection failures, since reqwest reports both as the latter
+    pub fn classify(error: &reqwest_middleware::Error) -> Self {
+        let reqwest_middleware::Error::Reqwest(error) = error else {
+            return ErrorKind::Other;
+        };
+        if error.is_timeout() {
+            return ErrorKind::Timeout;
+        }
+
+        let mut next: Option<&(dyn StdError + 'static)> = error.source();
+        let mut tls = false;
+        while let Some(source) = next {
+            if let Some(io_error) = source.downcast_ref::<io::Error>() {
+                match io_error.kind() {
+                    io::ErrorKind::TimedOut => return ErrorKind::Timeout,
+                    io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof => return ErrorKind::Reset,
+                    _ => {}
+                }
+            }
+            let message = source.to_string().to_ascii_lowercase();
+            tls |= TLS_MARKERS.iter().any(|marker| message.contains(marker));
+            next = source.source();
+        }
+
+        if tls {
+            ErrorKind::Tls
+        } else if error.is_connect() {
+            ErrorKind::Connect
+        } else {
+            ErrorKind::Other
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Requests `url` and classifies the error, which the request must fail with
+    async fn classify(url: String) -> ErrorKind {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .build()
+            .unwrap();
+        let client = reqwest_middleware::ClientBuilder::new(client).build();
+
+        let error = client.get(url).send().await.unwrap_err();
+
+        ErrorKind::classify(&error)
+    }
+
+    #[tokio::test]
+    async fn stalled_responses_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        assert_eq!(
+            classify(format!("http://127.0.0.1:{port}/")).await,
+            ErrorKind::Timeout
+        );
+    }
+
+    #[tokio::test]
+    async fn closed_ports_refuse_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        assert_eq!(
+            classify(format!("http://127.0.0.1:{port}/")).await,
+            ErrorKind::Connect
+        );
+    }
+
+    #[tokio::test]
+    async fn plain_http_services_fail_the_tls_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+
+        assert_eq!(
+            classify(format!("https://127.0.0.1:{port}/")).await,
+            ErrorKind::Tls
+        );
+    }
+
+    #[tokio::test]
+    async fn reset_connections_are_recognized() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // Closing the socket with a zero linger time sends a RST instead of a FIN
+                let _ = stream.read(&mut [0; 1024]).await;
+                let _ = stream.set_linger(Some(Duration::ZERO));
+                drop(stream);
+            }
+        });
+
+        assert_eq!(
+            classify(format!("http://127.0.0.1:{port}/")).await,
+            ErrorKind::Reset
+        );
+    }
+
+    #[test]
+    fn middleware_errors_are_other() {
+        let error = reqwest_middleware::Error::Middleware(anyhow::anyhow!("rate limited"));
+
+        assert_eq!(ErrorKind::classify(&error), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{fmt::Display, io::Cursor, net::IpAddr};
+
+use base64ct::Encoding;
+use grimoire::Fqdn;
+use itertools::Itertools;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Serialize;
+use sqlx::{query, types::ipnetwork::IpNetwork, PgConnection};
+use tracing::debug;
+
+use crate::{host_header, next_hop, read_body_prefix, ConnectMode};
+
+/// Favicons larger than this are truncated before hashing
+const MAX_FAVICON_BYTES: usize = 1 << 20;
+
+/// The maximum number of redirects followed to find the favicon
+const MAX_FAVICON_REDIRECTS: usize = 3;
+
+/// The favicon served by a host, identified by the hash used by Shodan
+#[derive(Debug, Clone, Serialize)]
+pub struct Favicon {
+    /// The URL the favicon was served from, after following redirects
+    pub url: String,
+    pub hash: i32,
+}
+
+impl Favicon {
+    /// Computes the MurmurHash3 (x86, 32 bit, seed 0) of the favicon encoded as base64 with a line
+    /// break after every 76 characters, just like Python's `base64.encodebytes` used by Shodan
+    pub fn hash(data: &[u8]) -> i32 {
+        let encoded = base64ct::Base64::encode_string(data);
+        let mut wrapped = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| String::from_utf8_lossy(line))
+            .join("\n");
+        wrapped.push('\n');
+
+        murmur3::murmur3_32(&mut Cursor::new(wrapped.as_bytes()), 0)
+            .expect("reading from memory never fails") as i32
+    }
+}
+
+impl Display for Favicon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "favicon {} {}", self.hash, self.url)
+    }
+}
+
+/// Fetches `/favicon.ico` from the origin of `url` on behalf of `fqdn`, following redirects. In
+/// 'fqdn' mode, the FQDN is requested even if `url` names the IP address. Returns `None` if there is
+/// no favicon
+#[tracing::instrument(skip(client))]
+pub async fn fetch_favicon(
+    client: &ClientWithMiddleware,
+    fqdn: &Fqdn,
+    ip: &IpAddr,
+    connect_mode: ConnectMode,
+    url: &Url,
+) -> Option<Favicon> {
+    let mut logical = url.join("/favicon.ico").ok()?;
+    let mut target = logical.clone();
+    if connect_mode == ConnectMode::Fqdn {
+        target.set_host(Some(&fqdn.to_string())).ok()?;
+    }
+    let mut host = (connect_mode == ConnectMode::Ip).then(|| host_header(fqdn, url));
+
+    for _ in 0..=MAX_FAVICON_REDIRECTS {
+        let mut request = client.get(target.clone());
+        if let Some(host) = &host {
+            request = request.header(reqwest::header::HOST, host);
+        }
+        let mut response = request
+            .send()
+            .await
+            .map_err(|e| debug!("Error when fetching the favicon from '{}': {}", &target, e))
+            .ok()?;
+
+        if response.status().is_redirection() {
+            (logical, target, host) = next_hop(fqdn, ip, connect_mode, &target, &response)?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return None;
+        }
+
+        let data = read_body_prefix(&mut response, MAX_FAVICON_BYTES)
+            .await
+            .map_err(|e| debug!("Error when reading the favicon from '{}': {}", &target, e))
+            .ok()?
+            .data;
+        if data.is_empty() {
+            return None;
+        }
+
+        return Some(Favicon {
+            url: logical.to_string(),
+            hash: Favicon::hash(&data),
+        });
+    }
+
+    None
+}
+
+#[tracing::instrument(skip(conn, favicon))]
+pub async fn submit_favicon_recon_results(
+    conn: &mut PgConnection,
+    fqdn: &Fqdn,
+    ip: &IpAddr,
+    favicon: &Favicon,
+) -> anyhow::Result<()> {
+    query!(
+        r#"
+        INSERT INTO "favicon-recon" (id, domain, fqdn, ip, url, hash)
+        VALUES (DEFAULT, $1, $2, $3, $4, $5)
+        ON CONFLICT ON CONSTRAINT "favicon-recon_pkey" DO
+        UPDATE SET url = EXCLUDED.url, hash = EXCLUDED.hash
+        "#,
+        fqdn.domain(),
+        fqdn.to_string(),
+        IpNetwork::from(*ip),
+        &favicon.url,
+        favicon.hash,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use grimoire_test::http::{ok_response, serve};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Redirects '/favicon.ico' to '/static/icon.ico' on the FQDN, which serves `icon` if given.
+    /// Any other path is not found
+    async fn serve_favicon(listener: TcpListener, icon: Option<&'static str>) {
+        let port = listener.local_addr().unwrap().port();
+        serve(listener, move |request| {
+            match (request.split_whitespace().nth(1), icon) {
+                (Some("/favicon.ico"), _) => format!(
+                    "HTTP/1.1 301 Moved Permanently\r\nlocation: http://www.example.com:{port}/static/icon.ico\r\ncontent-length: 0\r\n\r\n"
+                ),
+                (Some("/static/icon.ico"), Some(icon)) => ok_response(icon),
+                _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_string(),
+            }
+        })
+        .await;
+    }
+
+    /// Fetches the favicon of 'www.example.com' from the stub on `port` of localhost
+    async fn fetch_from_stub(port: u16) -> Option<Favicon> {
+        let client = reqwest_middleware::ClientBuilder::new(
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap(),
+        )
+        .build();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{port}/")).unwrap();
+
+        fetch_favicon(
+            &client,
+            &fqdn,
+            &"127.0.0.1".parse().unwrap(),
+            ConnectMode::Ip,
+            &url,
+        )
+        .await
+    }
+
+    #[test]
+    fn the_hash_matches_shodan() {
+        // The values of `mmh3.hash(base64.encodebytes(data))` in Python, the second spanning several
+        // lines of base64
+        assert_eq!(Favicon::hash(b"hello"), 1_155_597_304);
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(Favicon::hash(&data), -757_223_386);
+    }
+
+    #[tokio::test]
+    async fn redirects_to_the_favicon_are_followed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_favicon(listener, Some("icon")));
+
+        let favicon = fetch_from_stub(port).await.unwrap();
+
+        assert_eq!(
+            favicon.url,
+            format!("http://www.example.com:{port}/static/icon.ico")
+        );
+        assert_eq!(favicon.hash, Favicon::hash(b"icon"));
+    }
+
+    #[tokio::test]
+    async fn missing_favicons_yield_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_favicon(listener, None));
+
+        assert!(fetch_from_stub(port).await.is_none());
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    num::{NonZeroU32, NonZeroUsize},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Rate limiters of IP addresses that have not been probed for this long are evicted. By then,
+/// their budget has been refilled, so a new rate limiter behaves the same
+const RATE_LIMITER_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The rate limiters of all IP addresses, along with the time each was last used
+#[derive(Debug)]
+struct RateLimiters {
+    limiters: HashMap<IpAddr, (Arc<RateLimiter>, Instant)>,
+    last_eviction: Instant,
+}
+
+/// The semaphores of the IP addresses, which are swept for unused ones whenever their number has
+/// doubled since the last sweep, so that sweeping takes amortized constant time per probe
+#[derive(Debug)]
+struct Semaphores {
+    semaphores: HashMap<IpAddr, Arc<Semaphore>>,
+    /// The number of semaphores that triggers the next sweep
+    next_sweep: usize,
+}
+
+/// The number of semaphores below which no sweep takes place
+const MIN_SWEEP: usize = 1024;
+
+/// Limits the number of targets that are probed simultaneously on the same IP address, and the rate
+/// at which the same IP address is probed
+#[derive(Debug)]
+pub struct HostLimiter {
+    limit: Option<NonZeroUsize>,
+    requests_per_minute: Option<NonZeroU32>,
+    semaphores: Mutex<Semaphores>,
+    rate_limiters: Mutex<RateLimiters>,
+}
+
+impl HostLimiter {
+    pub fn new(limit: Option<NonZeroUsize>, requests_per_minute: Option<NonZeroU32>) -> Self {
+        HostLimiter {
+            limit,
+            requests_per_minute,
+            semaphores: Mutex::new(Semaphores {
+                semaphores: HashMap::new(),
+                next_sweep: MIN_SWEEP,
+            }),
+            rate_limiters: Mutex::new(RateLimiters {
+                limiters: HashMap::new(),
+                last_eviction: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until the IP address may be probed. The returned permit must be held for the duration
+    /// of the probe. If no limit is configured, this returns immediately
+    pub async fn acquire(&self, ip: IpAddr) -> Option<OwnedSemaphorePermit> {
+        let limit = self.limit?;
+        let semaphore = {
+            let mut semaphores = self
+                .semaphores
+                .lock()
+                .expect("the host limiter lock is never poisoned");
+            if semaphores.semaphores.len() >= semaphores.next_sweep {
+                // Permits and pending acquisitions hold a reference to their semaphore, so that
+                // the semaphores without any have all their permits available
+                semaphores
+                    .semaphores
+                    .retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+                semaphores.next_sweep = MIN_SWEEP.max(semaphores.semaphores.len() * 2);
+            }
+
+            semaphores
+                .semaphores
+                .entry(ip)
+                .or_insert_with(|| Arc::new(Semaphore::new(limit.get())))
+                .clone()
+        };
+
+        Some(
+            semaphore
+                .acquire_owned()
+                .await
+                .expect("the host limiter semaphores are never closed"),
+        )
+    }
+
+    /// Waits until the next request may be sent to the IP address, in addition to the global rate
+    /// limit. If no per-host rate is configured, this returns immediately
+    pub async fn throttle(&self, ip: IpAddr) {
+        let Some(requests_per_minute) = self.requests_per_minute else {
+            return;
+        };
+
+        let limiter = {
+            let mut rate_limiters = self
+                .rate_limiters
+                .lock()
+                .expect("the host limiter lock is never poisoned");
+            let now = Instant::now();
+            if now.duration_since(rate_limiters.last_eviction) >= RATE_LIMITER_IDLE_TIMEOUT {
+                // Rate limiters that are still awaited must be kept, lest the rate is exceeded
+                rate_limiters.limiters.retain(|_, (limiter, last_used)| {
+                    Arc::strong_count(limiter) > 1
+                        || now.duration_since(*last_used) < RATE_LIMITER_IDLE_TIMEOUT
+                });
+                rate_limiters.last_eviction = now;
+            }
+
+            let (limiter, last_used) = rate_limiters.limiters.entry(ip).or_insert_with(|| {
+                let limiter = RateLimiter::builder()
+                    .initial(1)
+                    .refill(1)
+                    .interval(Duration::from_secs(60) / requests_per_minute.get())
+                    .max(1)
+                    .build();
+                (Arc::new(limiter), now)
+            });
+            *last_used = now;
+            limiter.clone()
+        };
+
+        limiter.acquire_one().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::future::join_all;
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn probes_of_the_same_ip_never_exceed_the_limit() {
+        let limiter = HostLimiter::new(NonZeroUsize::new(2), None);
+        let current = AtomicUsize::new(0);
+        let max = AtomicUsize::new(0);
+
+        join_all((0..10).map(|_| async {
+            let _permit = limiter.acquire(ip("192.0.2.1")).await;
+            let probes = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(probes, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            current.fetch_sub(1, Ordering::SeqCst);
+        }))
+        .await;
+
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn other_ips_are_not_held_back() {
+        let limiter = HostLimiter::new(NonZeroUsize::new(1), None);
+        let _permit = limiter.acquire(ip("192.0.2.1")).await;
+
+        let other = tokio::time::timeout(Duration::from_secs(1), limiter.acquire(ip("192.0.2.2")))
+            .await
+            .expect("a permit for another IP address is available immediately");
+        assert!(other.is_some());
+    }
+
+    #[tokio::test]
+    async fn the_semaphores_of_finished_probes_are_evicted() {
+        let limiter = HostLimiter::new(NonZeroUsize::new(1), None);
+        let held = limiter.acquire(ip("192.0.2.1")).await;
+
+        for host in 0..(MIN_SWEEP as u32 * 4) {
+            let ip = IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + host));
+            drop(limiter.acquire(ip).await);
+        }
+
+        let semaphores = limiter.semaphores.lock().unwrap();
+        assert!(
+            semaphores.semaphores.len() <= MIN_SWEEP,
+            "{}",
+            semaphores.semaphores.len()
+        );
+        // The semaphore whose permit is still held is kept, lest the limit is exceeded
+        assert!(semaphores.semaphores.contains_key(&ip("192.0.2.1")));
+        drop(semaphores);
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn no_permits_are_required_without_a_limit() {
+        let limiter = HostLimiter::new(None, None);
+        assert!(limiter.acquire(ip("192.0.2.1")).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn requests_to_the_same_ip_are_spaced_by_the_per_host_rate() {
+        // One request every 50ms, as if the FQDNs of six targets resolved to the same IP address
+        let limiter = HostLimiter::new(None, NonZeroU32::new(1200));
+
+        let start = Instant::now();
+        let mut sent = join_all((0..6).map(|_| async {
+            limiter.throttle(ip("192.0.2.1")).await;
+            start.elapsed()
+        }))
+        .await;
+        sent.sort();
+
+        for pair in sent.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(40), "{sent:?}");
+        }
+        assert!(sent[5] >= Duration::from_millis(250), "{sent:?}");
+    }
+
+    #[tokio::test]
+    async fn the_per_host_rate_does_not_hold_back_other_ips() {
+        let limiter = HostLimiter::new(None, NonZeroU32::new(1));
+        limiter.throttle(ip("192.0.2.1")).await;
+
+        tokio::time::timeout(Duration::from_secs(1), limiter.throttle(ip("192.0.2.2")))
+            .await
+            .expect("another IP address may be probed immediately");
+        assert!(tokio::time::timeout(
+            Duration::from_millis(100),
+            limiter.throttle(ip("192.0.2.1"))
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng};
+use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
+
+/// Wraps the leaky bucket rate limiter and delays each request by a random duration of at most
+/// `max_delay` after acquiring its permit. Since the delay does not hold back the permits of other
+/// requests, the average rate is still governed by the leaky bucket. The delays are drawn from
+/// `rng`, such that a seeded generator yields the same sequence of delays. The leaky bucket may be
+/// shared with other stages that send requests
+pub struct JitteredRateLimiter {
+    limiter: Arc<RateLimiter>,
+    max_delay: Duration,
+    rng: Mutex<StdRng>,
+}
+
+impl JitteredRateLimiter {
+    pub fn new(limiter: Arc<RateLimiter>, max_delay: Duration, rng: StdRng) -> Self {
+        JitteredRateLimiter {
+            limiter,
+            max_delay,
+            rng: Mutex::new(rng),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl reqwest_ratelimit::RateLimiter for JitteredRateLimiter {
+    async fn acquire_permit(&self) {
+        self.limiter.acquire_one().await;
+        if !self.max_delay.is_zero() {
+            let fraction = self.rng.lock().expect("poisoned lock").gen::<f64>();
+            tokio::time::sleep(self.max_delay.mul_f64(fraction)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::future::join_all;
+    use rand::SeedableRng;
+    use reqwest_ratelimit::RateLimiter as _;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn average_rate_stays_within_budget() {
+        // One permit every 10ms, and delays of up to 20ms on top
+        let interval = Duration::from_millis(10);
+        let limiter = RateLimiter::builder()
+            .initial(1)
+            .refill(1)
+            .interval(interval)
+            .max(1)
+            .build();
+        let limiter = Arc::new(limiter);
+        let limiter =
+            JitteredRateLimiter::new(limiter, Duration::from_millis(20), StdRng::seed_from_u64(1));
+
+        let permits = 30;
+        let start = Instant::now();
+        join_all((0..permits).map(|_| limiter.acquire_permit())).await;
+        let elapsed = start.elapsed();
+
+        // The first permit is available immediately, each further one takes an interval
+        assert!(elapsed >= interval * (permits - 1), "{elapsed:?}");
+        // The delays overlap with waiting for the permits rather than adding up
+        assert!(
+            elapsed < interval * permits + Duration::from_millis(200),
+            "{elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn intervals_vary_around_the_average() {
+        // One permit every 20ms, and delays of up to 20ms on top
+        let interval = Duration::from_millis(20);
+        let limiter = RateLimiter::builder()
+            .initial(1)
+            .refill(1)
+            .interval(interval)
+            .max(1)
+            .build();
+        let limiter =
+            JitteredRateLimiter::new(Arc::new(limiter), interval, StdRng::seed_from_u64(7));
+
+        let permits = 25;
+        let start = Instant::now();
+        let mut granted = join_all((0..permits).map(|_| async {
+            limiter.acquire_permit().await;
+            start.elapsed()
+        }))
+        .await;
+        granted.sort();
+        let intervals: Vec<_> = granted.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        let average = (granted[permits - 1] - granted[0]) / (permits as u32 - 1);
+        assert!(average > interval * 3 / 4, "{average:?}");
+        assert!(average < interval * 3 / 2, "{average:?}");
+        let shortest = intervals.iter().min().unwrap();
+        let longest = intervals.iter().max().unwrap();
+        assert!(*longest - *shortest > interval / 2, "{intervals:?}");
+    }
+}
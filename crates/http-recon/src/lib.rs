@@ -0,0 +1,1808 @@
+pub mod auth;
+pub mod changes;
+pub mod checkpoint;
+pub mod classify;
+pub mod client_pool;
+pub mod compare;
+pub mod dedup;
+pub mod error_kind;
+pub mod favicon;
+pub mod host_limit;
+pub mod jitter;
+pub mod output;
+pub mod robots;
+pub mod security;
+pub mod stats;
+pub mod title;
+pub mod tls;
+pub mod waf;
+pub mod writer;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::{AddrParseError, IpAddr, SocketAddr},
+    num::{ParseFloatError, ParseIntError},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64ct::Encoding;
+use clap::ValueEnum;
+use cookie::Cookie;
+use grimoire::{
+    asn::{AsnInfo, AsnLookup},
+    Fqdn, ParseFqdnError,
+};
+use itertools::Itertools;
+use metrics::gauge;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, InvalidHeaderName, InvalidHeaderValue},
+    tls::TlsInfo,
+    Request, Response, Url, Version,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use sqlx::{query_scalar, PgPool};
+use thiserror::Error;
+use tokio_util::codec::LinesCodecError;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    auth::{AuthAttempt, Credential},
+    changes::{latest_version, Snapshot},
+    classify::{StatusClass, StatusClassifier},
+    client_pool::ClientPool,
+    compare::Comparison,
+    error_kind::ErrorKind,
+    favicon::{fetch_favicon, Favicon},
+    host_limit::HostLimiter,
+    output::{recon_record, Output},
+    robots::RobotsCache,
+    security::SecurityFlags,
+    stats::Stats,
+    title::extract_title,
+    tls::TlsCertificate,
+    waf::WafSignatures,
+    writer::{ReconDbWriter, ReconRecord},
+};
+
+#[tracing::instrument(skip(pg_pool))]
+async fn is_fqdn_in_http_recon_db(pg_pool: &PgPool, fqdn: &Fqdn, port_spec: &PortSpec) -> bool {
+    let exists = match port_spec.scheme {
+        Scheme::Http => {
+            query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM "http-recon" WHERE "fqdn" = $1 AND port = $2) AS "exists!""#,
+                fqdn.to_string(),
+                port_spec.port as i32,
+            )
+            .fetch_one(pg_pool)
+            .await
+        }
+        Scheme::Https => {
+            query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM "https-recon" WHERE "fqdn" = $1 AND port = $2) AS "exists!""#,
+                fqdn.to_string(),
+                port_spec.port as i32,
+            )
+            .fetch_one(pg_pool)
+            .await
+        }
+    };
+
+    exists.unwrap_or_default()
+}
+
+/// How the connection to each target is established
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConnectMode {
+    /// Connect to the IP address and set the Host header to the FQDN
+    Ip,
+    /// Connect to the FQDN, letting the client resolve it and set SNI accordingly
+    Fqdn,
+}
+
+/// The HTTP method used to probe each target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    Head,
+    Get,
+}
+
+impl From<Method> for reqwest::Method {
+    fn from(value: Method) -> Self {
+        match value {
+            Method::Head => reqwest::Method::HEAD,
+            Method::Get => reqwest::Method::GET,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scheme::Http => write!(f, "http"),
+            Scheme::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// A port to probe along with the scheme spoken on it
+#[derive(Debug, Clone, Copy)]
+pub struct PortSpec {
+    pub port: u16,
+    pub scheme: Scheme,
+}
+
+impl FromStr for PortSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (port, scheme) = s.split_once(':').ok_or(Error::PortSplit)?;
+        let scheme = match scheme {
+            "http" => Scheme::Http,
+            "https" => Scheme::Https,
+            _ => return Err(Error::PortScheme),
+        };
+
+        Ok(PortSpec {
+            port: port.parse()?,
+            scheme,
+        })
+    }
+}
+
+/// Settings that control how each target is probed
+#[derive(Debug, Clone)]
+pub struct ReconOptions {
+    pub query_known_fqdns: bool,
+    pub only_changed: bool,
+    pub connect_mode: ConnectMode,
+    pub compare: bool,
+    pub sni: bool,
+    pub anonymize_headers: bool,
+    pub method: Method,
+    pub max_body_bytes: usize,
+    pub follow_redirects: usize,
+    pub favicon: bool,
+    pub waf_signatures: WafSignatures,
+    pub status_classifier: StatusClassifier,
+    pub asn_lookup: Option<Arc<AsnLookup>>,
+    pub credentials: Vec<Credential>,
+    pub ports: Vec<PortSpec>,
+    pub retries: u32,
+    pub retry_backoff: Duration,
+}
+
+/// The outcome of a single HTTP(s) request. Failed requests are recorded with a response status of
+/// zero and neither headers nor body. If redirects are followed, the status, headers and body
+/// pertain to the last response, and the redirect chain holds the URLs visited after `url` in
+/// order. The certificate is taken from the first response, if it was received via TLS, and
+/// `tls_valid` records whether it passed validation. The favicon is only fetched for the first port
+/// of a target that responds. The latency covers connecting and receiving the response headers of
+/// the last request, but not the body
+#[derive(Debug)]
+pub struct ProbeResult {
+    url: Url,
+    response_status: u16,
+    class: StatusClass,
+    /// Why the request failed, only present if the response status is zero
+    error_kind: Option<ErrorKind>,
+    http_version: Option<Version>,
+    latency: Option<Duration>,
+    headers: Option<HttpHeaders>,
+    security_flags: Option<SecurityFlags>,
+    content_length: Option<u64>,
+    content_type: Option<String>,
+    body: Option<Vec<u8>>,
+    /// Whether the body exceeded the maximum size and was cut short
+    body_truncated: Option<bool>,
+    title: Option<String>,
+    /// The CDN or WAF the response was served by, as detected from its headers
+    waf: Option<String>,
+    redirect_chain: Vec<Url>,
+    certificate: Option<TlsCertificate>,
+    tls_valid: Option<bool>,
+    favicon: Option<Favicon>,
+    /// Only present in '--compare' mode
+    comparison: Option<Comparison>,
+    /// The autonomous system of the IP address, only present if enrichment is enabled
+    asn: Option<AsnInfo>,
+    /// The outcome of retrying with each credential, if the response status is 401
+    auth_attempts: Vec<AuthAttempt>,
+}
+
+impl Display for ProbeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            std::iter::once(&self.url)
+                .chain(&self.redirect_chain)
+                .join("->"),
+            self.response_status
+        )?;
+        write!(f, " {}", self.class)?;
+        if let Some(error_kind) = &self.error_kind {
+            write!(f, " {error_kind}")?;
+        }
+        if let Some(http_version) = &self.http_version {
+            write!(f, " {http_version:?}")?;
+        }
+        if let Some(latency) = &self.latency {
+            write!(f, " {}ms", latency.as_millis())?;
+        }
+        if let Some(headers) = &self.headers {
+            write!(f, " {headers}")?;
+        }
+        if let Some(body) = &self.body {
+            write!(f, " {}", base64ct::Base64::encode_string(body))?;
+        }
+        if self.body_truncated == Some(true) {
+            write!(f, " truncated")?;
+        }
+        if let Some(title) = &self.title {
+            write!(f, " {title:?}")?;
+        }
+        if let Some(waf) = &self.waf {
+            write!(f, " waf={waf:?}")?;
+        }
+        if let Some(asn) = &self.asn {
+            write!(f, " {asn}")?;
+        }
+        if !self.auth_attempts.is_empty() {
+            write!(f, " auth=[{}]", self.auth_attempts.iter().join(","))?;
+        }
+        if self
+            .comparison
+            .as_ref()
+            .is_some_and(Comparison::is_mismatch)
+        {
+            write!(f, " mismatch")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The first bytes of a response body
+#[derive(Debug)]
+struct BodyPrefix {
+    data: Vec<u8>,
+    /// Whether the body holds more than the bytes read
+    truncated: bool,
+}
+
+/// Reads at most `max_bytes` of the response body chunk by chunk, so that the remainder of a large
+/// body is never buffered in memory. Reading stops at the first chunk that exceeds the limit
+#[tracing::instrument(skip(response))]
+async fn read_body_prefix(
+    response: &mut Response,
+    max_bytes: usize,
+) -> reqwest::Result<BodyPrefix> {
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = max_bytes - data.len();
+        if chunk.len() > remaining {
+            data.extend_from_slice(&chunk[..remaining]);
+            return Ok(BodyPrefix {
+                data,
+                truncated: true,
+            });
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(BodyPrefix {
+        data,
+        truncated: false,
+    })
+}
+
+/// Sends the request, retrying connection errors and timeouts with exponential backoff. Responses
+/// with an HTTP error status are never retried. Returns the response along with the time it took
+/// to receive it on the last attempt
+#[tracing::instrument(skip(client, request, opts))]
+async fn execute_with_retries(
+    client: &ClientWithMiddleware,
+    request: Request,
+    opts: &ReconOptions,
+) -> reqwest_middleware::Result<(Response, Duration)> {
+    let mut attempt = 0_u32;
+    loop {
+        let start = Instant::now();
+        let Some(attempt_request) = request.try_clone() else {
+            let response = client.execute(request).await?;
+            return Ok((response, start.elapsed()));
+        };
+
+        match client.execute(attempt_request).await {
+            Err(reqwest_middleware::Error::Reqwest(e))
+                if attempt < opts.retries && (e.is_connect() || e.is_timeout()) =>
+            {
+                let backoff = opts
+                    .retry_backoff
+                    .saturating_mul(2_u32.saturating_pow(attempt));
+                debug!(
+                    "Retrying the request to '{}' in {:?}: {}",
+                    request.url(),
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            result => return result.map(|response| (response, start.elapsed())),
+        }
+    }
+}
+
+/// Parses the Content-Length header, ignoring it if it is not a valid length
+fn parse_content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(reqwest::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The value of the Host header for a request to `url` on behalf of `fqdn`. The port is only
+/// included if it differs from the default port of the scheme
+fn host_header(fqdn: &Fqdn, url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{fqdn}:{port}"),
+        None => fqdn.to_string(),
+    }
+}
+
+/// Determines the next hop of a redirect, returning the URL to record in the redirect chain, the
+/// URL to request and the `Host` header to send with it. When connecting to IP addresses, redirects
+/// to the same host or to the FQDN itself continue to be sent to the probed IP address, whereas
+/// redirects to any other host are followed directly
+fn next_hop(
+    fqdn: &Fqdn,
+    ip: &IpAddr,
+    connect_mode: ConnectMode,
+    target: &Url,
+    response: &Response,
+) -> Option<(Url, Url, Option<String>)> {
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)?
+        .to_str()
+        .ok()?;
+    let next = target.join(location).ok()?;
+    if connect_mode == ConnectMode::Fqdn {
+        return Some((next.clone(), next, None));
+    }
+
+    let next_host = next.host_str()?;
+    if next_host == fqdn.to_string() || next_host == target.host_str()? {
+        let mut physical = next.clone();
+        physical.set_ip_host(*ip).ok()?;
+        let host = host_header(fqdn, &next);
+        Some((next, physical, Some(host)))
+    } else {
+        Some((next.clone(), next, None))
+    }
+}
+
+/// Probes a single URL. If the TLS handshake fails and a `fallback` client is given, the request is
+/// repeated without validating the certificate. In 'fqdn' mode, the FQDN is requested even if `url`
+/// names the IP address, which is how pinned clients are used. The `authorization` header is only
+/// sent with the request to `url`, such that redirects cannot leak it to other hosts
+#[tracing::instrument(skip(client, fallback, opts, authorization))]
+#[allow(clippy::too_many_arguments)]
+async fn probe(
+    mut client: &ClientWithMiddleware,
+    mut fallback: Option<&ClientWithMiddleware>,
+    fqdn: &Fqdn,
+    ip: &IpAddr,
+    url: Url,
+    connect_mode: ConnectMode,
+    opts: &ReconOptions,
+    authorization: Option<&HeaderValue>,
+) -> anyhow::Result<ProbeResult> {
+    let mut target = url.clone();
+    if connect_mode == ConnectMode::Fqdn {
+        target.set_host(Some(&fqdn.to_string()))?;
+    }
+    let mut host = (connect_mode == ConnectMode::Ip).then(|| host_header(fqdn, &url));
+    let mut redirect_chain = Vec::new();
+    let mut certificate = None;
+    let mut tls_valid = None;
+    let mut validated = true;
+
+    loop {
+        let mut request = client.request(opts.method.into(), target.clone());
+        if let Some(host) = &host {
+            request = request.header(reqwest::header::HOST, host);
+        }
+        if let Some(authorization) = authorization.filter(|_| redirect_chain.is_empty()) {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+        let request = request.build()?;
+
+        let (mut response, latency) = match execute_with_retries(client, request, opts).await {
+            Ok(result) => result,
+            Err(e) => {
+                if target.scheme() == "https" && redirect_chain.is_empty() {
+                    if let Some(lenient) = fallback.take() {
+                        debug!(
+                            "Retrying '{}' without validating the certificate: {}",
+                            &target, e
+                        );
+                        client = lenient;
+                        validated = false;
+                        continue;
+                    }
+                }
+
+                let error_kind = ErrorKind::classify(&e);
+                debug!(
+                    "Error when sending a request to '{}' ({}): {}",
+                    &target, error_kind, e
+                );
+                return Ok(ProbeResult {
+                    url,
+                    response_status: 0,
+                    class: StatusClass::Dead,
+                    error_kind: Some(error_kind),
+                    http_version: None,
+                    latency: None,
+                    headers: None,
+                    security_flags: None,
+                    content_length: None,
+                    content_type: None,
+                    body: None,
+                    body_truncated: None,
+                    title: None,
+                    waf: None,
+                    redirect_chain,
+                    certificate,
+                    tls_valid,
+                    favicon: None,
+                    comparison: None,
+                    asn: None,
+                    auth_attempts: Vec::new(),
+                });
+            }
+        };
+
+        if redirect_chain.is_empty() {
+            tls_valid = response.extensions().get::<TlsInfo>().map(|_| validated);
+            certificate = response
+                .extensions()
+                .get::<TlsInfo>()
+                .and_then(|tls_info| tls_info.peer_certificate())
+                .and_then(|der| {
+                    TlsCertificate::from_der(der)
+                        .map_err(|e| {
+                            warn!("Error when parsing the certificate of '{}': {}", &target, e)
+                        })
+                        .ok()
+                });
+        }
+
+        if response.status().is_redirection() && redirect_chain.len() < opts.follow_redirects {
+            if let Some((next, physical, next_host)) =
+                next_hop(fqdn, ip, connect_mode, &target, &response)
+            {
+                debug!("Following the redirect from '{}' to '{}'", &target, &next);
+                redirect_chain.push(next);
+                target = physical;
+                host = next_host;
+                continue;
+            }
+        }
+
+        let response_status = response.status().as_u16();
+        let class = opts
+            .status_classifier
+            .classify(response_status, response.headers());
+        let headers = HttpHeaders::new(response.headers(), opts.anonymize_headers);
+        let security_flags = SecurityFlags::from(response.headers());
+        let waf = opts.waf_signatures.detect(response.headers());
+        let content_length = parse_content_length(response.headers());
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).to_string());
+        let body = match opts.method {
+            Method::Get => read_body_prefix(&mut response, opts.max_body_bytes)
+                .await
+                .map_err(|e| debug!("Error when reading the body from '{}': {}", &target, e))
+                .ok(),
+            Method::Head => None,
+        };
+        if body.as_ref().is_some_and(|body| body.truncated) {
+            debug!(
+                "The body of '{}' exceeds {} bytes and was truncated",
+                &target, opts.max_body_bytes
+            );
+        }
+        let (body, body_truncated) = match body {
+            Some(body) => (Some(body.data), Some(body.truncated)),
+            None => (None, None),
+        };
+        let title = body
+            .as_deref()
+            .and_then(|body| extract_title(body, content_type.as_deref()));
+
+        return Ok(ProbeResult {
+            url,
+            response_status,
+            class,
+            error_kind: None,
+            http_version: Some(response.version()),
+            latency: Some(latency),
+            headers: Some(headers),
+            security_flags: Some(security_flags),
+            content_length,
+            content_type,
+            body,
+            body_truncated,
+            title,
+            waf,
+            redirect_chain,
+            certificate,
+            tls_valid,
+            favicon: None,
+            comparison: None,
+            asn: None,
+            auth_attempts: Vec::new(),
+        });
+    }
+}
+
+#[tracing::instrument(skip(
+    pg_pool,
+    db_writer,
+    robots,
+    stats,
+    clients,
+    host_limiter,
+    output,
+    opts
+))]
+#[allow(clippy::too_many_arguments)]
+pub async fn recon_http(
+    pg_pool: Option<Arc<PgPool>>,
+    db_writer: Option<&ReconDbWriter>,
+    robots: Option<&RobotsCache>,
+    stats: &Stats,
+    clients: Arc<ClientPool>,
+    host_limiter: Arc<HostLimiter>,
+    output: Arc<Output>,
+    fqdn: Arc<Fqdn>,
+    ip: Arc<IpAddr>,
+    opts: Arc<ReconOptions>,
+) -> anyhow::Result<()> {
+    let _permit = host_limiter.acquire(*ip).await;
+    gauge!("http_recon_in_flight").increment(1.0);
+    let result = recon_ports(
+        pg_pool,
+        db_writer,
+        robots,
+        stats,
+        &clients,
+        &host_limiter,
+        &output,
+        &fqdn,
+        &ip,
+        &opts,
+    )
+    .await;
+    gauge!("http_recon_in_flight").decrement(1.0);
+
+    result
+}
+
+/// Probes all configured ports of a single target
+#[allow(clippy::too_many_arguments)]
+async fn recon_ports(
+    pg_pool: Option<Arc<PgPool>>,
+    db_writer: Option<&ReconDbWriter>,
+    robots: Option<&RobotsCache>,
+    stats: &Stats,
+    clients: &ClientPool,
+    host_limiter: &HostLimiter,
+    output: &Output,
+    fqdn: &Arc<Fqdn>,
+    ip: &IpAddr,
+    opts: &ReconOptions,
+) -> anyhow::Result<()> {
+    let mut favicon_pending = opts.favicon;
+    let asn = match &opts.asn_lookup {
+        Some(asn_lookup) => asn_lookup.lookup(*ip).await,
+        None => None,
+    };
+    for port_spec in &opts.ports {
+        if let (Some(recon_pg_pool), false) = (&pg_pool, opts.query_known_fqdns) {
+            if is_fqdn_in_http_recon_db(recon_pg_pool, fqdn, port_spec).await {
+                stats.record_skipped_known();
+                continue;
+            }
+        }
+
+        let url = match opts.connect_mode {
+            ConnectMode::Ip => Url::parse(&format!(
+                "{}://{}",
+                port_spec.scheme,
+                SocketAddr::new(*ip, port_spec.port)
+            ))?,
+            ConnectMode::Fqdn => Url::parse(&format!(
+                "{}://{}:{}",
+                port_spec.scheme, fqdn, port_spec.port
+            ))?,
+        };
+        // Over HTTPS, the client is pinned to the IP address and requests the FQDN instead, so that
+        // the FQDN is sent via SNI
+        let pinned = if opts.sni
+            && opts.connect_mode == ConnectMode::Ip
+            && port_spec.scheme == Scheme::Https
+        {
+            Some(clients.pinned(fqdn, *ip)?)
+        } else {
+            None
+        };
+        let (client, fallback, connect_mode) = match &pinned {
+            Some((client, fallback)) => (client, fallback.as_ref(), ConnectMode::Fqdn),
+            None => {
+                let (client, fallback) = clients.next();
+                (client, fallback, opts.connect_mode)
+            }
+        };
+        if let Some(robots) = robots {
+            if !robots
+                .is_allowed(fallback.unwrap_or(client), fqdn, *ip, &url)
+                .await
+            {
+                debug!("Skipping '{}', which is disallowed by robots.txt", &url);
+                stats.record_skipped_robots();
+                continue;
+            }
+        }
+
+        host_limiter.throttle(*ip).await;
+        let mut result = probe(client, fallback, fqdn, ip, url, connect_mode, opts, None).await?;
+        if favicon_pending && result.response_status != 0 {
+            favicon_pending = false;
+            host_limiter.throttle(*ip).await;
+            result.favicon = fetch_favicon(
+                fallback.unwrap_or(client),
+                fqdn,
+                ip,
+                connect_mode,
+                &result.url,
+            )
+            .await;
+        }
+        result.asn = asn.clone();
+        stats.record_probe(result.response_status);
+        if let Some(latency) = result.latency {
+            stats.record_latency(latency);
+        }
+
+        if result.response_status == 401 {
+            for credential in &opts.credentials {
+                host_limiter.throttle(*ip).await;
+                let authenticated = probe(
+                    client,
+                    fallback,
+                    fqdn,
+                    ip,
+                    result.url.clone(),
+                    connect_mode,
+                    opts,
+                    Some(&credential.header_value()?),
+                )
+                .await?;
+                let attempt = AuthAttempt {
+                    credential: credential.label(),
+                    status: authenticated.response_status,
+                };
+                if attempt.succeeded() {
+                    info!(
+                        "'{}' accepts the credential {}",
+                        &result.url, attempt.credential
+                    );
+                    stats.record_authenticated();
+                }
+                result.auth_attempts.push(attempt);
+            }
+        }
+
+        if opts.compare {
+            let direct_url = Url::parse(&format!(
+                "{}://{}:{}",
+                port_spec.scheme, fqdn, port_spec.port
+            ))?;
+            let (client, fallback) = clients.next();
+            host_limiter.throttle(*ip).await;
+            let direct = probe(
+                client,
+                fallback,
+                fqdn,
+                ip,
+                direct_url,
+                ConnectMode::Fqdn,
+                opts,
+                None,
+            )
+            .await?;
+            let comparison = Comparison::new(&result, direct);
+            if comparison.is_mismatch() {
+                info!(
+                    "'{}' on port {} responds differently via {} and via DNS: {}",
+                    fqdn,
+                    port_spec.port,
+                    ip,
+                    comparison.differences.join(", ")
+                );
+                stats.record_mismatch();
+            }
+            result.comparison = Some(comparison);
+        }
+
+        let mut version = 1;
+        if let (Some(recon_pg_pool), true) = (&pg_pool, opts.only_changed) {
+            if let Some(latest) = latest_version(recon_pg_pool, fqdn, port_spec).await? {
+                let changes = latest.snapshot.diff(&Snapshot::from_result(&result));
+                if changes.is_empty() {
+                    debug!("'{}' on port {} is unchanged", fqdn, port_spec.port);
+                    stats.record_unchanged();
+                    continue;
+                }
+                info!(
+                    "'{}' on port {} changed: {}",
+                    fqdn,
+                    port_spec.port,
+                    changes.join(", ")
+                );
+                version = latest.version + 1;
+            }
+        }
+        let record = recon_record(fqdn, &result, version);
+        output.write(ip, &result, &record).await?;
+
+        if let Some(db_writer) = db_writer.filter(|db_writer| !db_writer.is_degraded()) {
+            db_writer
+                .submit(ReconRecord {
+                    fqdn: fqdn.clone(),
+                    ip: *ip,
+                    scheme: port_spec.scheme,
+                    row: record,
+                    result,
+                })
+                .await?;
+            stats.record_stored();
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(transparent)]
+struct HttpHeaders(HashMap<String, Vec<String>>);
+
+/// Replaces a cookie that cannot be parsed when anonymizing, since its value cannot be told apart
+const REDACTED_COOKIE: &str = "<redacted>";
+
+impl HttpHeaders {
+    /// Groups the header values by name. If `anonymize` is set, the values of all cookies are
+    /// removed, and cookies that cannot be parsed are redacted as a whole
+    #[tracing::instrument(skip(value))]
+    fn new(value: &HeaderMap, anonymize: bool) -> Self {
+        let mut map = HashMap::default();
+        let groups = value.iter().chunk_by(|(header, _)| *header);
+        for (header, group) in groups.into_iter() {
+            map.insert(
+                header.to_string(),
+                group
+                    .map(|(_, value)| {
+                        let utf8_value = String::from_utf8_lossy(value.as_bytes());
+                        if anonymize && header == reqwest::header::SET_COOKIE {
+                            match Cookie::parse(utf8_value) {
+                                Ok(mut cookie) => {
+                                    cookie.set_value("");
+                                    cookie.to_string()
+                                }
+                                Err(e) => {
+                                    debug!("Redacting a malformed cookie: {}", e);
+                                    REDACTED_COOKIE.to_string()
+                                }
+                            }
+                        } else {
+                            utf8_value.to_string()
+                        }
+                    })
+                    .collect(),
+            );
+        }
+
+        Self(map)
+    }
+}
+
+impl Display for HttpHeaders {
+    #[tracing::instrument(skip_all)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let map_str = serde_json::to_string(&self).map_err(|e| {
+            error!("serializing the header map to JSON: {}", e);
+            std::fmt::Error
+        })?;
+        write!(f, "{}", base64ct::Base64::encode_string(map_str.as_bytes()))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot split the line into an FQDN and an IP address")]
+    InputSplit,
+    #[error("Expected an FQDN and an IP address, but the line holds {0} fields")]
+    InputFields(usize),
+    #[error(transparent)]
+    Codec(#[from] LinesCodecError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Expected the object to hold an 'ip' field or a non-empty 'ips' list")]
+    JsonAddress,
+    #[error(transparent)]
+    Fqdn(#[from] ParseFqdnError),
+    #[error(transparent)]
+    IpAddr(#[from] AddrParseError),
+    #[error("Expected a header of the form 'Name: Value'")]
+    HeaderSplit,
+    #[error(transparent)]
+    HeaderName(#[from] InvalidHeaderName),
+    #[error(transparent)]
+    HeaderValue(#[from] InvalidHeaderValue),
+    #[error("Expected a port of the form 'PORT:SCHEME'")]
+    PortSplit,
+    #[error("Expected the scheme to be either 'http' or 'https'")]
+    PortScheme,
+    #[error("Expected a status code or a range of status codes of the form 'LOW-HIGH'")]
+    StatusRange,
+    #[error("Expected a user name and password of the form 'USER:PASS'")]
+    AuthSplit,
+    #[error("Expected a class and statuses of the form 'CLASS=STATUSES'")]
+    ClassSplit,
+    #[error("Unknown class '{0}'")]
+    UnknownClass(String),
+    #[error("Expected the jitter to be a fraction between 0 and 1")]
+    JitterRange,
+    #[error(transparent)]
+    ParseInt(#[from] ParseIntError),
+    #[error(transparent)]
+    ParseFloat(#[from] ParseFloatError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use grimoire::output::{OutputFormat, ResultWriter, Writer};
+    use grimoire_test::{
+        http::{ok_response, read_request_head, serve},
+        temp_dir,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use rcgen::CertifiedKey;
+    use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
+    use sqlx::query_as;
+    use tokio::{
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+    use tokio_rustls::{
+        rustls::{crypto::ring, pki_types::PrivateKeyDer, ServerConfig},
+        TlsAcceptor,
+    };
+
+    use super::*;
+    use crate::{client_pool::ClientSettings, jitter::JitteredRateLimiter, writer::OnDbError};
+
+    /// The fields of a result written in a structured output format
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct ParsedOutput {
+        fqdn: String,
+        ip: IpAddr,
+        response_status: u16,
+        headers: HashMap<String, Vec<String>>,
+    }
+
+    fn client_settings() -> ClientSettings {
+        let limiter = RateLimiter::builder().initial(10).max(10).build();
+        ClientSettings {
+            user_agent: "http-recon-test".to_string(),
+            default_headers: HeaderMap::new(),
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(5),
+            http1_only: false,
+            accept_invalid_certs: false,
+            rate_limiter: Arc::new(reqwest_ratelimit::all(JitteredRateLimiter::new(
+                Arc::new(limiter),
+                Duration::ZERO,
+                StdRng::seed_from_u64(0),
+            ))),
+        }
+    }
+
+    fn client_pool() -> ClientPool {
+        ClientPool::new(client_settings(), vec![None]).unwrap()
+    }
+
+    fn recon_options(follow_redirects: usize) -> ReconOptions {
+        ReconOptions {
+            query_known_fqdns: true,
+            only_changed: false,
+            connect_mode: ConnectMode::Ip,
+            compare: false,
+            sni: true,
+            anonymize_headers: false,
+            method: Method::Get,
+            max_body_bytes: 1024,
+            follow_redirects,
+            favicon: false,
+            waf_signatures: WafSignatures::builtin(),
+            status_classifier: StatusClassifier::new(Vec::new()),
+            asn_lookup: None,
+            credentials: Vec::new(),
+            ports: Vec::new(),
+            retries: 0,
+            retry_backoff: Duration::ZERO,
+        }
+    }
+
+    /// Reads the head of a request from `stream` and answers it with status 200 and `body`
+    async fn respond_ok(mut stream: impl AsyncRead + AsyncWrite + Unpin, body: &str) {
+        if read_request_head(&mut stream).await.is_none() {
+            return;
+        }
+        let _ = stream.write_all(ok_response(body).as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+
+    /// Accepts TLS connections with a self-signed certificate for `names`, offering the
+    /// application protocols `alpn` in order of preference
+    fn tls_acceptor(names: &[&str], alpn: &[&[u8]]) -> TlsAcceptor {
+        let CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                PrivateKeyDer::Pkcs8(key_pair.serialize_der().into()),
+            )
+            .unwrap();
+        config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// Serves HTTPS on `listener` with a self-signed certificate for `names`. Each request is
+    /// answered with status 200 and the name the client sent via SNI, or 'none', as the body
+    async fn serve_tls(listener: TcpListener, names: &[&str]) {
+        let acceptor = tls_acceptor(names, &[]);
+
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let sni = stream
+                    .get_ref()
+                    .1
+                    .server_name()
+                    .unwrap_or("none")
+                    .to_string();
+                respond_ok(stream, &sni).await;
+            });
+        }
+    }
+
+    /// Serves HTTPS on `listener`, speaking HTTP/2 if the client offers it via ALPN and HTTP/1.1
+    /// otherwise. Each request is answered with status 200
+    async fn serve_alpn(listener: TcpListener) {
+        let acceptor = tls_acceptor(&["www.example.com"], &[b"h2", b"http/1.1"]);
+
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                if stream.get_ref().1.alpn_protocol() != Some(b"h2") {
+                    respond_ok(stream, "ok").await;
+                    return;
+                }
+                let Ok(mut connection) = h2::server::handshake(stream).await else {
+                    return;
+                };
+                while let Some(Ok((_, mut respond))) = connection.accept().await {
+                    let response = http::Response::builder().status(200).body(()).unwrap();
+                    let _ = respond.send_response(response, true);
+                }
+            });
+        }
+    }
+
+    /// Stalls the first `failures` connections on `listener` until the client gives up, and
+    /// answers the requests of all later ones with status 200. Counts the connections
+    async fn serve_flaky(listener: TcpListener, failures: usize, connections: Arc<AtomicUsize>) {
+        while let Ok((stream, _)) = listener.accept().await {
+            if connections.fetch_add(1, Ordering::SeqCst) < failures {
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    drop(stream);
+                });
+            } else {
+                tokio::spawn(respond_ok(stream, "ok"));
+            }
+        }
+    }
+
+    /// Probes 'www.example.com' via the stub on `port` of localhost
+    async fn probe_stub(port: u16, opts: &ReconOptions) -> ProbeResult {
+        probe_stub_with(client_pool(), port, opts).await
+    }
+
+    async fn probe_stub_with(clients: ClientPool, port: u16, opts: &ReconOptions) -> ProbeResult {
+        let (client, fallback) = clients.next();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{port}/")).unwrap();
+
+        probe(
+            client,
+            fallback,
+            &fqdn,
+            &ip,
+            url,
+            ConnectMode::Ip,
+            opts,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Probes 'www.example.com' via HTTPS on `port` of localhost
+    async fn probe_https(clients: &ClientPool, port: u16) -> ProbeResult {
+        let (client, fallback) = clients.next();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+        let url = Url::parse(&format!("https://127.0.0.1:{port}/")).unwrap();
+
+        probe(
+            client,
+            fallback,
+            &fqdn,
+            &ip,
+            url,
+            ConnectMode::Ip,
+            &recon_options(0),
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Redirects '/' to '/final' on the FQDN and answers '/final' with status 200. Reports the
+    /// path and the Host header of each request
+    async fn serve_redirect(
+        listener: TcpListener,
+        sender: mpsc::UnboundedSender<(String, String)>,
+    ) {
+        let port = listener.local_addr().unwrap().port();
+        serve(listener, move |request| {
+            let path = request.split_whitespace().nth(1).unwrap().to_string();
+            let host = request
+                .lines()
+                .find_map(|line| line.strip_prefix("host: "))
+                .unwrap_or_default()
+                .to_string();
+            let response = if path == "/" {
+                format!(
+                    "HTTP/1.1 302 Found\r\nlocation: http://www.example.com:{port}/final\r\ncontent-length: 0\r\n\r\n"
+                )
+            } else {
+                ok_response("ok")
+            };
+            let _ = sender.send((path, host));
+            response
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn redirects_are_followed_and_recorded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        tokio::spawn(serve_redirect(listener, sender));
+
+        let clients = client_pool();
+        let (client, fallback) = clients.next();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{port}/")).unwrap();
+        let opts = recon_options(3);
+        let result = probe(
+            client,
+            fallback,
+            &fqdn,
+            &ip,
+            url,
+            ConnectMode::Ip,
+            &opts,
+            None,
+        )
+        .await
+        .unwrap();
+        let record = recon_record(&fqdn, &result, 1);
+
+        let final_url = format!("http://www.example.com:{port}/final");
+        assert_eq!(record.response_status, 200);
+        assert_eq!(record.url, format!("http://127.0.0.1:{port}/"));
+        assert_eq!(record.final_url.as_ref(), Some(&final_url));
+        assert_eq!(record.redirect_chain, [final_url]);
+        // Both hops are sent to the probed IP address with the FQDN as the Host header
+        let host = format!("www.example.com:{port}");
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            ("/".to_string(), host.clone())
+        );
+        assert_eq!(receiver.recv().await.unwrap(), ("/final".to_string(), host));
+    }
+
+    #[tokio::test]
+    async fn the_requested_url_is_final_without_redirects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        tokio::spawn(serve_redirect(listener, sender));
+
+        let clients = client_pool();
+        let (client, fallback) = clients.next();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{port}/")).unwrap();
+        let opts = recon_options(0);
+        let result = probe(
+            client,
+            fallback,
+            &fqdn,
+            &ip,
+            url,
+            ConnectMode::Ip,
+            &opts,
+            None,
+        )
+        .await
+        .unwrap();
+        let record = recon_record(&fqdn, &result, 1);
+
+        assert_eq!(record.response_status, 302);
+        assert!(record.redirect_chain.is_empty());
+        assert_eq!(record.final_url, Some(format!("http://127.0.0.1:{port}/")));
+    }
+
+    #[tokio::test]
+    async fn small_bodies_are_captured_fully() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |_| {
+            "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello".to_string()
+        }));
+
+        let result = probe_stub(port, &recon_options(0)).await;
+
+        assert_eq!(result.body.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(result.body_truncated, Some(false));
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_truncated_at_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |_| {
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: 4096\r\n\r\n{}",
+                "a".repeat(4096)
+            )
+        }));
+
+        let result = probe_stub(port, &recon_options(0)).await;
+
+        assert_eq!(result.body, Some(vec![b'a'; 1024]));
+        assert_eq!(result.body_truncated, Some(true));
+        assert_eq!(result.content_length, Some(4096));
+    }
+
+    #[tokio::test]
+    async fn endless_bodies_stop_being_read_at_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sent_tx, sent_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n")
+                .await;
+            // Streams chunks of 512 bytes until the client hangs up
+            let chunk = format!("200\r\n{}\r\n", "a".repeat(512));
+            let mut sent = 0_usize;
+            while stream.write_all(chunk.as_bytes()).await.is_ok() {
+                sent += 512;
+            }
+            let _ = sent_tx.send(sent);
+        });
+
+        let result = probe_stub(port, &recon_options(0)).await;
+        let sent = tokio::time::timeout(Duration::from_secs(5), sent_rx)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(result.body, Some(vec![b'a'; 1024]));
+        assert_eq!(result.body_truncated, Some(true));
+        assert_eq!(result.content_length, None);
+        // Only what fits into the socket buffers was sent before the connection was closed
+        assert!(sent < 64 * 1024 * 1024, "{sent} bytes were sent");
+    }
+
+    #[tokio::test]
+    async fn head_requests_capture_no_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |request| {
+            if request.starts_with("HEAD / ") {
+                "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n".to_string()
+            } else {
+                "HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\n\r\n".to_string()
+            }
+        }));
+
+        let opts = ReconOptions {
+            method: Method::Head,
+            ..recon_options(0)
+        };
+        let result = probe_stub(port, &opts).await;
+
+        assert_eq!(result.response_status, 200);
+        assert_eq!(result.body, None);
+        assert_eq!(result.body_truncated, None);
+    }
+
+    #[tokio::test]
+    async fn the_certificate_details_are_parsed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_tls(listener, &["www.example.com", "example.com"]));
+
+        let clients = ClientPool::new(
+            ClientSettings {
+                accept_invalid_certs: true,
+                ..client_settings()
+            },
+            vec![None],
+        )
+        .unwrap();
+        let result = probe_https(&clients, port).await;
+
+        assert_eq!(result.response_status, 200);
+        // The self-signed certificate is only accepted by the fallback client
+        assert_eq!(result.tls_valid, Some(false));
+        let certificate = result.certificate.unwrap();
+        assert_eq!(certificate.sans, ["www.example.com", "example.com"]);
+        assert!(certificate.not_before < certificate.not_after);
+    }
+
+    #[tokio::test]
+    async fn the_fqdn_is_sent_via_sni_in_fqdn_mode() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_tls(listener, &["www.example.com"]));
+
+        let clients = ClientPool::new(
+            ClientSettings {
+                accept_invalid_certs: true,
+                ..client_settings()
+            },
+            vec![None],
+        )
+        .unwrap();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+        // The pinned client resolves the FQDN to localhost, standing in for the DNS server
+        let (client, fallback) = clients.pinned(&fqdn, ip).unwrap();
+        let url = Url::parse(&format!("https://www.example.com:{port}/")).unwrap();
+        let result = probe(
+            &client,
+            fallback.as_ref(),
+            &fqdn,
+            &ip,
+            url,
+            ConnectMode::Fqdn,
+            &recon_options(0),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.response_status, 200);
+        assert_eq!(result.body.as_deref(), Some(&b"www.example.com"[..]));
+    }
+
+    #[tokio::test]
+    async fn self_signed_certificates_are_rejected_by_default() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_tls(listener, &["www.example.com"]));
+
+        let result = probe_https(&client_pool(), port).await;
+
+        assert_eq!(result.response_status, 0);
+        assert_eq!(result.error_kind, Some(ErrorKind::Tls));
+        assert!(result.certificate.is_none());
+    }
+
+    #[tokio::test]
+    async fn the_timeout_fires_when_the_response_stalls() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_flaky(listener, 1, Arc::default()));
+
+        // The connection is established right away, so only the overall timeout applies
+        let clients = ClientPool::new(
+            ClientSettings {
+                timeout: Duration::from_millis(300),
+                connect_timeout: Duration::from_secs(5),
+                ..client_settings()
+            },
+            vec![None],
+        )
+        .unwrap();
+        let start = Instant::now();
+        let result = probe_stub_with(clients, port, &recon_options(0)).await;
+
+        assert_eq!(result.response_status, 0);
+        assert_eq!(result.error_kind, Some(ErrorKind::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried_until_the_request_succeeds() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_flaky(listener, 2, connections.clone()));
+
+        let clients = ClientPool::new(
+            ClientSettings {
+                timeout: Duration::from_millis(200),
+                ..client_settings()
+            },
+            vec![None],
+        )
+        .unwrap();
+        let opts = ReconOptions {
+            retries: 2,
+            retry_backoff: Duration::from_millis(10),
+            ..recon_options(0)
+        };
+        let result = probe_stub_with(clients, port, &opts).await;
+
+        assert_eq!(result.response_status, 200);
+        assert_eq!(result.body.as_deref(), Some(&b"ok"[..]));
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn requests_fail_once_the_retries_are_exhausted() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_flaky(listener, 2, connections.clone()));
+
+        let clients = ClientPool::new(
+            ClientSettings {
+                timeout: Duration::from_millis(200),
+                ..client_settings()
+            },
+            vec![None],
+        )
+        .unwrap();
+        let opts = ReconOptions {
+            retries: 1,
+            retry_backoff: Duration::from_millis(10),
+            ..recon_options(0)
+        };
+        let result = probe_stub_with(clients, port, &opts).await;
+
+        assert_eq!(result.response_status, 0);
+        assert_eq!(result.error_kind, Some(ErrorKind::Timeout));
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn the_latency_of_the_response_is_recorded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    respond_ok(stream, "ok").await;
+                });
+            }
+        });
+
+        let result = probe_stub(port, &recon_options(0)).await;
+        let record = recon_record(&Fqdn::from_str("www.example.com").unwrap(), &result, 1);
+
+        let latency_ms = record.latency_ms.unwrap();
+        assert!((50..5000).contains(&latency_ms), "{latency_ms}ms");
+    }
+
+    #[tokio::test]
+    async fn json_output_round_trips_the_header_map() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |_| {
+            "HTTP/1.1 200 OK\r\nserver: stub\r\nset-cookie: session=secret\r\nx-tag: a\r\nx-tag: b\r\ncontent-length: 0\r\n\r\n"
+                .to_string()
+        }));
+        let opts = ReconOptions {
+            anonymize_headers: true,
+            ..recon_options(0)
+        };
+        let result = probe_stub(port, &opts).await;
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+        let ip = "127.0.0.1".parse().unwrap();
+
+        let dir = temp_dir();
+        let path = dir.path().join("results.json");
+        let output = Output::new(
+            Writer::new(
+                OutputFormat::Json,
+                ResultWriter::new(true, Some(&path), false).await.unwrap(),
+            ),
+            None,
+        );
+        output
+            .write(&ip, &result, &recon_record(&fqdn, &result, 1))
+            .await
+            .unwrap();
+        output.finish().await.unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let parsed: Vec<ParsedOutput> = serde_json::from_str(&contents).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].fqdn, "www.example.com");
+        assert_eq!(parsed[0].ip, ip);
+        assert_eq!(parsed[0].response_status, 200);
+        assert_eq!(parsed[0].headers, result.headers.unwrap().0);
+        assert_eq!(parsed[0].headers["set-cookie"], ["session="]);
+        assert_eq!(parsed[0].headers["x-tag"], ["a", "b"]);
+    }
+
+    #[test]
+    fn cookie_values_are_only_removed_when_anonymizing() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::SET_COOKIE,
+            HeaderValue::from_static("session=secret; Path=/; HttpOnly"),
+        );
+        headers.insert(reqwest::header::SERVER, HeaderValue::from_static("nginx"));
+
+        let anonymized = HttpHeaders::new(&headers, true).0;
+        let raw = HttpHeaders::new(&headers, false).0;
+
+        assert_eq!(anonymized["set-cookie"], ["session=; HttpOnly; Path=/"]);
+        assert_eq!(raw["set-cookie"], ["session=secret; Path=/; HttpOnly"]);
+        assert_eq!(anonymized["server"], raw["server"]);
+    }
+
+    #[test]
+    fn malformed_cookies_are_redacted_when_anonymizing() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::SET_COOKIE,
+            HeaderValue::from_static("secret; Path=/"),
+        );
+
+        assert_eq!(
+            HttpHeaders::new(&headers, true).0["set-cookie"],
+            [REDACTED_COOKIE]
+        );
+        assert_eq!(
+            HttpHeaders::new(&headers, false).0["set-cookie"],
+            ["secret; Path=/"]
+        );
+    }
+
+    #[test]
+    fn oversized_header_maps_are_encoded_without_loss() {
+        let mut headers = HeaderMap::new();
+        for i in 0..40 {
+            headers.append(
+                reqwest::header::SET_COOKIE,
+                HeaderValue::from_str(&format!("cookie{i}={}", "x".repeat(2048))).unwrap(),
+            );
+        }
+        let headers = HttpHeaders::new(&headers, false);
+
+        let encoded = headers.to_string();
+        assert!(encoded.len() > 64 * 1024);
+        let decoded = base64ct::Base64::decode_vec(&encoded).unwrap();
+        let decoded: HashMap<String, Vec<String>> = serde_json::from_slice(&decoded).unwrap();
+        assert_eq!(decoded, headers.0);
+        assert_eq!(decoded["set-cookie"].len(), 40);
+    }
+
+    #[test]
+    fn content_lengths_are_only_recorded_if_valid() {
+        let mut present = HeaderMap::new();
+        present.insert(
+            reqwest::header::CONTENT_LENGTH,
+            HeaderValue::from_static("1234"),
+        );
+        let mut malformed = HeaderMap::new();
+        malformed.insert(
+            reqwest::header::CONTENT_LENGTH,
+            HeaderValue::from_static("12ab"),
+        );
+        let mut negative = HeaderMap::new();
+        negative.insert(
+            reqwest::header::CONTENT_LENGTH,
+            HeaderValue::from_static("-1"),
+        );
+
+        assert_eq!(parse_content_length(&present), Some(1234));
+        assert_eq!(parse_content_length(&HeaderMap::new()), None);
+        assert_eq!(parse_content_length(&malformed), None);
+        assert_eq!(parse_content_length(&negative), None);
+    }
+
+    #[tokio::test]
+    async fn the_content_type_is_recorded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |_| {
+            "HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=utf-8\r\ncontent-length: 5\r\n\r\nhello"
+                .to_string()
+        }));
+
+        let result = probe_stub(port, &recon_options(0)).await;
+
+        assert_eq!(
+            result.content_type.as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(result.content_length, Some(5));
+    }
+
+    #[tokio::test]
+    async fn the_negotiated_http_version_is_recorded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve_alpn(listener));
+
+        let mut versions = Vec::new();
+        for http1_only in [false, true] {
+            let clients = ClientPool::new(
+                ClientSettings {
+                    accept_invalid_certs: true,
+                    http1_only,
+                    ..client_settings()
+                },
+                vec![None],
+            )
+            .unwrap();
+            let result = probe_https(&clients, port).await;
+
+            assert_eq!(result.response_status, 200);
+            let fqdn = Fqdn::from_str("www.example.com").unwrap();
+            let record = recon_record(&fqdn, &result, 1);
+            versions.push(record.http_version.unwrap());
+        }
+
+        assert_eq!(versions, ["HTTP/2.0", "HTTP/1.1"]);
+    }
+
+    /// Probes 'www.example.com' via the stub on `port` of localhost in '--only-changed' mode and
+    /// stores the result in the recon database
+    async fn rescan(pg_pool: &PgPool, port: u16) {
+        let opts = ReconOptions {
+            only_changed: true,
+            ports: vec![PortSpec {
+                port,
+                scheme: Scheme::Http,
+            }],
+            ..recon_options(0)
+        };
+        let output = Output::new(
+            Writer::new(
+                OutputFormat::Ndjson,
+                ResultWriter::new(true, None, false).await.unwrap(),
+            ),
+            None,
+        );
+        let db_writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Abort,
+        );
+
+        recon_http(
+            Some(Arc::new(pg_pool.clone())),
+            Some(&db_writer),
+            None,
+            &Stats::new(false),
+            Arc::new(client_pool()),
+            Arc::new(HostLimiter::new(None, None)),
+            Arc::new(output),
+            Arc::new(Fqdn::from_str("www.example.com").unwrap()),
+            Arc::new("127.0.0.1".parse().unwrap()),
+            Arc::new(opts),
+        )
+        .await
+        .unwrap();
+        db_writer.finish().await.unwrap();
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn only_changed_results_are_stored_as_new_versions(pg_pool: PgPool) {
+        let title = Arc::new(std::sync::Mutex::new("Welcome"));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, {
+            let title = title.clone();
+            move |_| {
+                let body = format!("<title>{}</title>", title.lock().unwrap());
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+            }
+        }));
+        let versions = || async {
+            query_as::<_, (i32, Option<String>)>(
+                r#"SELECT version, title FROM "http-recon" ORDER BY version"#,
+            )
+            .fetch_all(&pg_pool)
+            .await
+            .unwrap()
+        };
+
+        rescan(&pg_pool, port).await;
+        assert_eq!(versions().await, [(1, Some("Welcome".to_string()))]);
+
+        rescan(&pg_pool, port).await;
+        assert_eq!(versions().await, [(1, Some("Welcome".to_string()))]);
+
+        *title.lock().unwrap() = "Maintenance";
+        rescan(&pg_pool, port).await;
+        assert_eq!(
+            versions().await,
+            [
+                (1, Some("Welcome".to_string())),
+                (2, Some("Maintenance".to_string()))
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn differing_responses_are_flagged_as_mismatches(pg_pool: PgPool) {
+        // The stub acts as the proxy of all requests, such that it sees the absolute URL of each
+        // request and can tell requests to the IP address from requests to the FQDN
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |request| {
+            let (status, title) = if request.starts_with("GET http://www.example.com") {
+                ("403 Forbidden", "Edge")
+            } else {
+                ("200 OK", "Origin")
+            };
+            let body = format!("<title>{title}</title>");
+            format!(
+                "HTTP/1.1 {status}\r\ncontent-type: text/html\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            )
+        }));
+        let proxy = reqwest::Proxy::http(format!("http://127.0.0.1:{port}")).unwrap();
+        let clients = ClientPool::new(client_settings(), vec![Some(proxy)]).unwrap();
+        let opts = ReconOptions {
+            compare: true,
+            ports: vec![PortSpec {
+                port,
+                scheme: Scheme::Http,
+            }],
+            ..recon_options(0)
+        };
+        let output = Output::new(
+            Writer::new(
+                OutputFormat::Ndjson,
+                ResultWriter::new(true, None, false).await.unwrap(),
+            ),
+            None,
+        );
+        let db_writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Abort,
+        );
+
+        recon_http(
+            None,
+            Some(&db_writer),
+            None,
+            &Stats::new(false),
+            Arc::new(clients),
+            Arc::new(HostLimiter::new(None, None)),
+            Arc::new(output),
+            Arc::new(Fqdn::from_str("www.example.com").unwrap()),
+            Arc::new("127.0.0.1".parse().unwrap()),
+            Arc::new(opts),
+        )
+        .await
+        .unwrap();
+        db_writer.finish().await.unwrap();
+
+        let (status, title, mismatch, direct_status, direct_title) =
+            query_as::<_, (i16, Option<String>, Option<bool>, Option<i64>, Option<String>)>(
+                r#"SELECT "response-status", title, mismatch, ("direct-result"->>'response-status')::bigint, "direct-result"->>'title' FROM "http-recon""#,
+            )
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap();
+        assert_eq!((status, title.as_deref()), (200, Some("Origin")));
+        assert_eq!(mismatch, Some(true));
+        assert_eq!(
+            (direct_status, direct_title.as_deref()),
+            (Some(403), Some("Edge"))
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn known_fqdns_are_found_by_port(pg_pool: PgPool) {
+        sqlx::query(
+            r#"INSERT INTO "https-recon" (fqdn, url, "response-status", domain, port) VALUES ('www.example.com', 'https://192.0.2.1/', 200, 'example.com', 443)"#,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let fqdn = Fqdn::from_str("www.example.com").unwrap();
+
+        assert!(is_fqdn_in_http_recon_db(&pg_pool, &fqdn, &"443:https".parse().unwrap()).await);
+        assert!(!is_fqdn_in_http_recon_db(&pg_pool, &fqdn, &"8443:https".parse().unwrap()).await);
+        assert!(!is_fqdn_in_http_recon_db(&pg_pool, &fqdn, &"443:http".parse().unwrap()).await);
+    }
+}
@@ -1,55 +1,83 @@
 use std::{
-    collections::HashMap,
-    fmt::Display,
-    net::{AddrParseError, IpAddr},
+    net::{IpAddr, SocketAddr},
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
     pin::pin,
+    process::ExitCode,
     str::FromStr,
     sync::Arc,
     time::Duration,
 };
 
-use clap::Parser;
-use cookie::Cookie;
-use futures::{FutureExt, StreamExt};
-use grimoire::{create_recon_db_pool, Fqdn, ParseFqdnError};
+use anyhow::bail;
+use clap::{Parser, ValueEnum};
+use futures::{future, stream::BoxStream, StreamExt};
+use grimoire::{
+    build_info,
+    cidr::reverse_lookup,
+    cli::{
+        parse_with_config, AsnArgs, CidrArgs, ConfigArgs, LogArgs, OutputArgs, ReconDbArgs,
+        RejectArgs, RuntimeArgs, SeedArgs, ShuffleArgs,
+    },
+    exit::{report_with, ExitStatus, EXIT_STATUS_HELP},
+    open_input, shutdown_on_ctrl_c, Fqdn, ParseReport,
+};
+use hickory_resolver::TokioAsyncResolver;
 use itertools::Itertools;
-use reqwest::{header::HeaderMap, redirect::Policy, Proxy, Url};
+use metrics::{describe_counter, describe_gauge};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Proxy,
+};
 use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use sqlx::{query, query_as, query_scalar, PgPool};
-use thiserror::Error;
-use tokio::io::stdin;
+use serde::Deserialize;
+use tokio::io::AsyncRead;
 use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
-use tracing::{debug, error, info, warn};
-use tracing_subscriber::EnvFilter;
+use tracing::{debug, info, warn};
 
-const MAX_HEADER_BUFFER_SIZE: usize = 1024 * 64;
+use http_recon::{
+    auth::{load_credentials, BasicAuth},
+    checkpoint::Checkpoint,
+    classify::{ClassRule, StatusClassifier},
+    client_pool::{ClientPool, ClientSettings},
+    dedup::Deduplicator,
+    host_limit::HostLimiter,
+    jitter::JitteredRateLimiter,
+    output::{Output, StatusFilter},
+    recon_http,
+    robots::RobotsCache,
+    stats::Stats,
+    waf::WafSignatures,
+    writer::{OnDbError, ReconDbWriter},
+    ConnectMode, Error, Method, PortSpec, ReconOptions,
+};
 
 /// Perform mass HTTP(s) connection attempts in order to reconnoiter an entire domain
 #[derive(Debug, Parser)]
+#[command(version, long_version = build_info(), after_help = EXIT_STATUS_HELP)]
 struct Args {
-    /// The IPv4 or IPv6 address or the host name of the recon database service
-    #[arg(long, default_value = "localhost", env = "RECON_DB_HOST")]
-    recon_db_host: String,
-    /// The username used for authenticating with the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_USERNAME")]
-    recon_db_username: String,
-    /// The password used for authenticating with the recon database service
-    #[arg(long, env = "RECON_DB_PASSWORD")]
-    recon_db_password: Option<String>,
-    /// The database to connect to when using the recon database service
-    #[arg(long, default_value = "recon", env = "RECON_DB_DATABASE")]
-    recon_db_database: String,
-    /// If enabled, store the results in the recon database
-    #[arg(short, long)]
-    enable_db_storage: bool,
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    recon_db: ReconDbArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    runtime: RuntimeArgs,
     /// If enabled, run queries again even if the result is known. Ignored when results are not
     /// stored in the recon database
     #[arg(long)]
     query_known_fqdns: bool,
-    /// Optionally proxy the HTTP(s) requests
-    #[arg(short, long, env = "PROXY")]
-    proxy: Option<String>,
+    /// If enabled, probe known targets again and only report and store results whose status,
+    /// title or key headers differ from the latest stored result. Changed results are stored as a
+    /// new version alongside the previous ones
+    #[arg(long, requires = "enable_db_storage")]
+    only_changed: bool,
+    /// Optionally proxy the HTTP(s) requests. If specified multiple times, requests are distributed
+    /// across all proxies in round-robin order
+    #[arg(short, long = "proxy", env = "PROXY", value_parser = parse_proxy)]
+    proxies: Vec<Proxy>,
     /// Define the user agent header used during HTTP(s) requests
     #[arg(
         short,
@@ -58,376 +86,1239 @@ struct Args {
         default_value = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36"
     )]
     user_agent: String,
-    /// Define the total request timeout in seconds
+    /// Define the total request timeout in seconds, which covers connecting, sending the request and
+    /// receiving the response
     #[arg(short, long, default_value_t = 10_u64)]
     timeout_secs: u64,
+    /// Define the connect timeout in seconds, so that unresponsive hosts are skipped quickly. The
+    /// total request timeout still applies, so it takes effect only if it is the shorter of the two
+    #[arg(long, default_value_t = 5_u64)]
+    connect_timeout_secs: u64,
     /// Define the number of requests performed per minute
     #[arg(short, long, default_value_t = 60_usize)]
     requests_per_minute: usize,
     /// Define the maximum number of requests that can be accumulated
-    #[arg(short, long, default_value_t = 600_usize)]
+    #[arg(long, default_value_t = 600_usize)]
     request_max_budget: usize,
-    /// When connecting to HTTPS services, accept invalid certificates
-    #[arg(short, long, default_value_t = true)]
-    accept_invalid_certs: bool,
-    /// Disable output to stdout
+    /// Refill the request budget every this many seconds, with the share of the requests per minute
+    /// that falls on the interval. The requests per minute must be evenly divisible into such
+    /// shares
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 60_u64,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    rate_interval_secs: u64,
+    /// Delay each request by a random fraction, between 0 and 1, of the average interval between
+    /// requests, so that the request cadence is less regular. The average rate is unaffected
+    #[arg(long, value_name = "FRACTION", value_parser = parse_jitter)]
+    jitter: Option<f64>,
+    /// When connecting to HTTPS services, accept invalid certificates. Certificates are validated
+    /// first regardless, and responses received over an invalid certificate are marked as such
     #[arg(short, long)]
-    quiet: bool,
-}
-
-#[derive(Debug, Default)]
-struct CountPair {
-    http_count: Option<i64>,
-    https_count: Option<i64>,
+    accept_invalid_certs: bool,
+    /// Read the pairs of FQDN and IP address from this file instead of Stdin. Use '-' to read from
+    /// Stdin explicitly
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+    #[command(flatten)]
+    cidr: CidrArgs,
+    #[command(flatten)]
+    rejects: RejectArgs,
+    #[command(flatten)]
+    shuffle: ShuffleArgs,
+    #[command(flatten)]
+    seed: SeedArgs,
+    /// Stop reading the input after this many lines have been parsed as targets. Lines skipped via
+    /// the checkpoint are not counted
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// The format of the input. In 'ndjson' format, each line holds a JSON object with an 'fqdn'
+    /// field and either an 'ip' field or an 'ips' list, in which case each address is probed
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = InputFormat::Text,
+        conflicts_with = "networks"
+    )]
+    input_format: InputFormat,
+    /// The character separating the FQDN and the IP address on each line of the input. If a line
+    /// does not contain it, a tab or a comma is tried instead
+    #[arg(long, default_value_t = ' ')]
+    delimiter: char,
+    /// Record the progress through the input in this file. If it exists, the lines processed by a
+    /// previous run are skipped, provided the input did not change in the meantime. Lines only
+    /// count as processed once their results are stored. Not available with '--cidr', whose hosts
+    /// are looked up in no fixed order
+    #[arg(long, value_name = "PATH", conflicts_with = "networks")]
+    checkpoint: Option<PathBuf>,
+    #[command(flatten)]
+    output: OutputArgs,
+    /// Store and output the raw header values, including the values of cookies, instead of
+    /// anonymizing them
+    #[arg(long)]
+    no_anonymize: bool,
+    /// Only write results to stdout whose status matches this comma-separated list of status codes
+    /// and ranges, e.g. '200,301-399,401'. Connection failures are represented by the status 0.
+    /// All results are still stored in the recon database
+    #[arg(long, value_name = "STATUSES")]
+    status_filter: Option<StatusFilter>,
+    /// Assign these statuses to a class instead of the default one, e.g. 'live=200-299,404'. The
+    /// classes are 'live', 'redirect', 'auth-required', 'forbidden', 'error' and 'dead'. May be
+    /// specified multiple times, in which case the first matching one applies. In the config file,
+    /// a 'status_classes' table mapping classes to statuses may be given instead
+    #[arg(long = "status-class", value_name = "CLASS=STATUSES")]
+    status_classes: Vec<ClassRule>,
+    #[command(flatten)]
+    asn: AsnArgs,
+    /// Whether to connect to the IP address of each target and send the FQDN as the Host header, or
+    /// to connect to the FQDN itself, which is resolved via DNS and used for SNI. In 'fqdn' mode,
+    /// lines holding only an FQDN are accepted as well
+    #[arg(long, value_enum, default_value_t = ConnectMode::Ip)]
+    connect_mode: ConnectMode,
+    /// Probe each port a second time by connecting to the FQDN, and record whether the status,
+    /// title or key headers differ from the response received via the IP address. Mismatches
+    /// indicate virtual hosts or origin servers behind a CDN
+    #[arg(long, conflicts_with = "connect_mode")]
+    compare: bool,
+    /// When connecting to IP addresses via HTTPS, send the IP address via SNI instead of the FQDN.
+    /// By default, a dedicated client that resolves the FQDN to the IP address is built for each
+    /// such target, so that virtual hosts present the right certificate
+    #[arg(long)]
+    no_sni: bool,
+    /// The HTTP method used to probe each target
+    #[arg(short, long, value_enum, default_value_t = Method::Head)]
+    method: Method,
+    /// When using the GET method, capture at most this many bytes of each response body. The
+    /// body is read in chunks and the connection is dropped once the limit is exceeded, in which
+    /// case the body is recorded as truncated. The title of HTML pages is extracted from the
+    /// captured body
+    #[arg(long, value_name = "BYTES", default_value_t = 64 * 1024)]
+    max_body_bytes: usize,
+    /// Only use HTTP/1.x, instead of negotiating the HTTP version via ALPN
+    #[arg(long)]
+    http1_only: bool,
+    /// Fetch the favicon of each target and record its hash, as used by Shodan for fingerprinting
+    #[arg(long)]
+    favicon: bool,
+    /// Label responses with the CDN or WAF detected by the signatures in this JSON file instead of
+    /// the built-in signatures
+    #[arg(long, value_name = "PATH")]
+    waf_signatures: Option<PathBuf>,
+    /// Follow at most this many redirects and record the redirect chain
+    #[arg(long, value_name = "N", default_value_t = 0_usize)]
+    follow_redirects: usize,
+    /// When a target responds with 401, retry the request with this user name and password via
+    /// basic authentication and record the status of each attempt. May be specified multiple
+    /// times. Only use on targets you are authorized to assess
+    #[arg(long = "auth-basic", value_name = "USER:PASS")]
+    auth_basic: Vec<BasicAuth>,
+    /// Like '--auth-basic', but send this token via bearer authentication. The results label tokens
+    /// by their position instead of revealing them
+    #[arg(long = "auth-bearer", value_name = "TOKEN")]
+    auth_bearer: Vec<String>,
+    /// Read further credentials from this file, one per line as either 'basic USER:PASS' or
+    /// 'bearer TOKEN', so that they do not show up in the process arguments
+    #[arg(long, value_name = "PATH")]
+    auth_file: Option<PathBuf>,
+    /// Add a custom header to every request. May be specified multiple times
+    #[arg(short = 'H', long = "header", value_name = "NAME: VALUE", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Probe this port using the given scheme, either 'http' or 'https'. May be specified multiple
+    /// times
+    #[arg(
+        long = "port",
+        value_name = "PORT:SCHEME",
+        default_values = ["80:http", "443:https"]
+    )]
+    ports: Vec<PortSpec>,
+    /// Retry requests that fail due to connection errors or timeouts at most this many times
+    #[arg(long, default_value_t = 0_u32)]
+    retries: u32,
+    /// The delay in milliseconds before the first retry, doubled for every subsequent retry
+    #[arg(long, default_value_t = 500_u64)]
+    retry_backoff_ms: u64,
+    /// Skip pairs of FQDN and IP address that were already seen during this run
+    #[arg(long)]
+    dedup: bool,
+    /// Bound the memory used for deduplication by using a Bloom filter sized for this many pairs.
+    /// A small fraction of unseen pairs may be skipped
+    #[arg(long, value_name = "N", requires = "dedup")]
+    dedup_capacity: Option<NonZeroUsize>,
+    /// The maximum number of targets that may be probed at the same time
+    #[arg(short, long, env = "HTTP_CONCURRENCY", default_value = "50")]
+    concurrency: NonZeroUsize,
+    /// The maximum number of results stored in the recon database with a single transaction
+    #[arg(long, default_value = "100")]
+    batch_size: NonZeroUsize,
+    /// If storing results in the recon database keeps failing, continue the run and only write
+    /// the results to the output, instead of aborting
+    #[arg(long, requires = "enable_db_storage")]
+    no_db_on_error: bool,
+    /// With '--no-db-on-error', retry storing a batch this many times before giving up on the
+    /// recon database
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 3_u32,
+        requires = "no_db_on_error"
+    )]
+    db_retries: u32,
+    /// The maximum number of targets that may be probed at the same time on any single IP address
+    #[arg(long)]
+    per_host_concurrency: Option<NonZeroUsize>,
+    /// The maximum number of requests per minute sent to any single IP address, in addition to the
+    /// global rate limit. Each probed port and favicon counts as one request
+    #[arg(long, value_name = "N")]
+    per_host_rpm: Option<NonZeroU32>,
+    /// Only parse the input and report how many lines are valid pairs of FQDN and IP address,
+    /// without sending any requests or accessing the recon database
+    #[arg(long)]
+    dry_run: bool,
+    /// Fetch the robots.txt of each IP address and port before probing it, and skip the URLs it
+    /// disallows for the configured user agent
+    #[arg(long)]
+    respect_robots: bool,
+    /// Serve Prometheus metrics on this address, e.g. '127.0.0.1:9100'
+    #[arg(long, value_name = "HOST:PORT")]
+    metrics_addr: Option<SocketAddr>,
+    /// Include the minimum, median, 90th and 99th percentile and maximum latency of all responses
+    /// in the summary
+    #[arg(long)]
+    timing_stats: bool,
 }
 
-#[tracing::instrument(skip(pg_pool))]
-async fn is_fqdn_in_http_recon_db(pg_pool: &PgPool, fqdn: &Fqdn) -> (bool, bool) {
-    let counts = query_as!(
-        CountPair,
-        r#"
-        SELECT
-            (SELECT COUNT(*) FROM "http-recon" WHERE "fqdn" = $1) AS http_count,
-            (SELECT COUNT(*) FROM "https-recon" WHERE "fqdn" = $1) AS https_count;
-        "#,
-        fqdn.to_string(),
-    )
-    .fetch_one(pg_pool)
-    .await
-    .unwrap_or_default();
-
-    (
-        counts.http_count.unwrap_or(0) != 0,
-        counts.https_count.unwrap_or(0) != 0,
-    )
+/// The format of the lines of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    /// An FQDN and an IP address separated by the delimiter
+    Text,
+    /// A JSON object holding the FQDN and one or more IP addresses
+    Ndjson,
 }
 
-#[tracing::instrument(skip(pg_pool, headers))]
-async fn submit_http_recon_results(
-    pg_pool: &PgPool,
-    fqdn: &Fqdn,
-    url: &Url,
-    response_status: u16,
-    headers: Option<&AnonymizedHttpHeaders>,
-) -> anyhow::Result<()> {
-    let recon_db_entry_count = query_scalar!(
-        r#"SELECT COUNT(*) FROM "http-recon" WHERE "fqdn" = $1"#,
-        fqdn.to_string(),
-    )
-    .fetch_one(pg_pool)
-    .await?
-    .map(|c| c as usize)
-    .unwrap_or(0_usize);
-
-    if recon_db_entry_count > 0 {
-        info!("'{fqdn}' already exists in the recon database");
-        return Ok(());
-    }
+/// Serves the Prometheus metrics on the given address
+fn install_metrics_exporter(addr: SocketAddr) -> anyhow::Result<()> {
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .install()?;
 
-    query!(
-        r#"INSERT INTO "http-recon" (id, fqdn, url, "response-status", headers, domain) VALUES (DEFAULT, $1, $2, $3, $4, $5)"#,
-        fqdn.to_string(),
-        url.to_string(),
-        response_status as i32,
-        headers
-            .and_then(|h| serde_json::to_value(h).map_err(|e| error!("{}", e)).ok())
-            .unwrap_or(serde_json::json!({})),
-        fqdn.domain(),
-    )
-    .execute(pg_pool)
-    .await?;
+    describe_counter!(
+        "http_recon_processed_total",
+        "The number of targets that were probed"
+    );
+    describe_counter!(
+        "http_recon_succeeded_total",
+        "The number of probed URLs that returned a response"
+    );
+    describe_counter!(
+        "http_recon_failed_total",
+        "The number of probed URLs that did not return a response"
+    );
+    describe_counter!(
+        "http_recon_skipped_known_total",
+        "The number of URLs skipped because they are already in the recon database"
+    );
+    describe_counter!(
+        "http_recon_skipped_robots_total",
+        "The number of URLs skipped because they are disallowed by robots.txt"
+    );
+    describe_counter!(
+        "http_recon_unchanged_total",
+        "The number of URLs whose result did not change since the last run"
+    );
+    describe_gauge!(
+        "http_recon_in_flight",
+        "The number of targets currently being probed"
+    );
 
     Ok(())
 }
 
-#[tracing::instrument(skip(pg_pool, headers))]
-async fn submit_https_recon_results(
-    pg_pool: &PgPool,
-    fqdn: &Fqdn,
-    url: &Url,
-    response_status: u16,
-    headers: Option<&AnonymizedHttpHeaders>,
-) -> anyhow::Result<()> {
-    let recon_db_entry_count = query_scalar!(
-        r#"SELECT COUNT(*) FROM "https-recon" WHERE "fqdn" = $1"#,
-        fqdn.to_string(),
-    )
-    .fetch_one(pg_pool)
-    .await?
-    .map(|c| c as usize)
-    .unwrap_or(0_usize);
+/// The delimiters tried if a line does not contain the configured delimiter
+const FALLBACK_DELIMITERS: [char; 2] = ['\t', ','];
 
-    if recon_db_entry_count > 0 {
-        info!("'{fqdn}' already exists in the recon database");
-        return Ok(());
+/// Parses a line of the input, which holds an FQDN and an IP address separated by `delimiter`, or
+/// by one of the fallback delimiters if the line does not contain it. Empty fields are ignored, so
+/// that repeated delimiters are tolerated. When connecting to FQDNs, the IP address may be omitted
+fn parse_line(
+    line: &str,
+    delimiter: char,
+    connect_mode: ConnectMode,
+) -> Result<(Fqdn, Option<IpAddr>), Error> {
+    let split = |delimiter: char| -> Vec<&str> {
+        line.split(delimiter)
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect()
+    };
+
+    let mut fields = split(delimiter);
+    if fields.len() == 1 {
+        if let Some(fallback) = FALLBACK_DELIMITERS
+            .into_iter()
+            .filter(|fallback| *fallback != delimiter)
+            .map(split)
+            .find(|fields| fields.len() > 1)
+        {
+            fields = fallback;
+        }
     }
 
-    query!(
-        r#"INSERT INTO "https-recon" (id, fqdn, url, "response-status", headers, domain) VALUES (DEFAULT, $1, $2, $3, $4, $5)"#,
-        fqdn.to_string(),
-        url.to_string(),
-        response_status as i32,
-        headers
-            .and_then(|h| serde_json::to_value(h).map_err(|e| error!("{}", e)).ok())
-            .unwrap_or(serde_json::json!({})),
-        fqdn.domain(),
-    )
-    .execute(pg_pool)
-    .await?;
+    match fields.as_slice() {
+        [fqdn_str, ip_addr_str] => Ok((
+            Fqdn::from_str(fqdn_str)?,
+            Some(IpAddr::from_str(ip_addr_str)?),
+        )),
+        [fqdn_str] if connect_mode == ConnectMode::Fqdn => Ok((Fqdn::from_str(fqdn_str)?, None)),
+        [] | [_] => Err(Error::InputSplit),
+        fields => Err(Error::InputFields(fields.len())),
+    }
+}
 
-    Ok(())
+/// A line of the input in 'ndjson' format
+#[derive(Debug, Deserialize)]
+struct JsonTarget {
+    fqdn: String,
+    ip: Option<IpAddr>,
+    #[serde(default)]
+    ips: Vec<IpAddr>,
 }
 
-#[tracing::instrument(skip(pg_pool, client))]
-async fn recon_http(
-    pg_pool: Option<Arc<PgPool>>,
-    client: Arc<ClientWithMiddleware>,
-    fqdn: Arc<Fqdn>,
-    ip: Arc<IpAddr>,
-    query_known_fqdns: bool,
-    quiet: bool,
-) -> anyhow::Result<()> {
-    let (skip_http_recon, skip_https_recon) = if let Some(recon_pg_pool) = &pg_pool {
-        is_fqdn_in_http_recon_db(recon_pg_pool, &fqdn).await
-    } else {
-        (false, false)
-    };
+/// Parses a line of the input in 'ndjson' format. The addresses of the 'ip' field and the 'ips'
+/// list are combined. When connecting to FQDNs, both may be omitted
+fn parse_json_line(line: &str, connect_mode: ConnectMode) -> Result<(Fqdn, Vec<IpAddr>), Error> {
+    let target: JsonTarget = serde_json::from_str(line)?;
+    let fqdn = Fqdn::from_str(&target.fqdn)?;
+    let ip_addrs: Vec<IpAddr> = target.ip.into_iter().chain(target.ips).unique().collect();
+    if ip_addrs.is_empty() && connect_mode == ConnectMode::Ip {
+        return Err(Error::JsonAddress);
+    }
 
-    if query_known_fqdns || !skip_http_recon {
-        let url = Url::parse(&format!("http://{ip}"))?;
-        let request = client
-            .head(url.clone())
-            .header(reqwest::header::HOST, fqdn.to_string())
-            .build()?;
+    Ok((fqdn, ip_addrs))
+}
 
-        match client.execute(request).await {
-            Ok(response) => {
-                let response_status = response.status().as_u16();
-                let headers = AnonymizedHttpHeaders::from(response.headers());
+/// Parses a line of the input in the given format into an FQDN and its IP addresses, which are
+/// empty if the FQDN is to be resolved
+fn parse_target(
+    line: &str,
+    input_format: InputFormat,
+    delimiter: char,
+    connect_mode: ConnectMode,
+) -> Result<(Fqdn, Vec<IpAddr>), Error> {
+    match input_format {
+        InputFormat::Text => parse_line(line, delimiter, connect_mode)
+            .map(|(fqdn, ip_addr)| (fqdn, ip_addr.into_iter().collect())),
+        InputFormat::Ndjson => parse_json_line(line, connect_mode),
+    }
+}
 
-                if !quiet {
-                    println!("{fqdn} {ip} {url} {response_status} {headers}");
-                }
+/// Resolves the first IP address of `fqdn`, for targets given without one
+#[tracing::instrument]
+async fn resolve(fqdn: &Fqdn) -> Option<IpAddr> {
+    tokio::net::lookup_host(format!("{fqdn}:0"))
+        .await
+        .map_err(|e| warn!("Cannot resolve '{}': {}", fqdn, e))
+        .ok()?
+        .map(|addr| addr.ip())
+        .next()
+}
 
-                if let Some(recon_pg_pool) = &pg_pool {
-                    submit_http_recon_results(
-                        recon_pg_pool,
-                        &fqdn,
-                        &url,
-                        response_status,
-                        Some(&headers),
-                    )
-                    .await?;
-                }
-            }
-            Err(e) => {
-                debug!("Error when sending a request to '{}': {}", &url, e);
-                if let Some(recon_pg_pool) = &pg_pool {
-                    submit_http_recon_results(recon_pg_pool, &fqdn, &url, 0, None).await?;
-                }
-            }
+/// Parses the input just like a regular run, counting the valid and rejected lines
+async fn dry_run(
+    input: impl AsyncRead + Unpin,
+    input_format: InputFormat,
+    delimiter: char,
+    connect_mode: ConnectMode,
+) -> ParseReport {
+    let mut report = ParseReport::default();
+    let mut lines = FramedRead::new(input, LinesCodec::new());
+    while let Some(line_result) = lines.next().await {
+        match line_result
+            .map_err(Error::from)
+            .and_then(|line| parse_target(&line, input_format, delimiter, connect_mode))
+        {
+            Ok(_) => report.record_valid(),
+            Err(e) => report.record_rejected(e),
         }
     }
 
-    if query_known_fqdns || !skip_https_recon {
-        let url = Url::parse(&format!("https://{ip}"))?;
-        let request = client
-            .head(url.clone())
-            .header(reqwest::header::HOST, fqdn.to_string())
-            .build()?;
+    report
+}
 
-        match client.execute(request).await {
-            Ok(response) => {
-                let response_status = response.status().as_u16();
-                let headers = AnonymizedHttpHeaders::from(response.headers());
+fn parse_proxy(s: &str) -> Result<Proxy, reqwest::Error> {
+    Proxy::all(s)
+}
 
-                if !quiet {
-                    println!("{fqdn} {ip} {url} {response_status} {headers}");
-                }
+fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), Error> {
+    let (name, value) = s.split_once(':').ok_or(Error::HeaderSplit)?;
 
-                if let Some(recon_pg_pool) = &pg_pool {
-                    submit_https_recon_results(
-                        recon_pg_pool,
-                        &fqdn,
-                        &url,
-                        response_status,
-                        Some(&headers),
-                    )
-                    .await?;
-                }
-            }
-            Err(e) => {
-                debug!("Error when sending a request to '{}': {}", &url, e);
-                if let Some(recon_pg_pool) = &pg_pool {
-                    submit_https_recon_results(recon_pg_pool, &fqdn, &url, 0, None).await?;
-                }
-            }
-        }
+    Ok((
+        HeaderName::from_str(name.trim())?,
+        HeaderValue::from_str(value.trim())?,
+    ))
+}
+
+fn parse_jitter(s: &str) -> Result<f64, Error> {
+    let jitter = f64::from_str(s)?;
+    if !(0.0..=1.0).contains(&jitter) {
+        return Err(Error::JitterRange);
     }
-    Ok(())
+
+    Ok(jitter)
 }
 
-#[derive(Debug, serde::Serialize)]
-#[serde(transparent)]
-struct AnonymizedHttpHeaders(HashMap<String, Vec<String>>);
-
-impl<'a> From<&'a HeaderMap> for AnonymizedHttpHeaders {
-    #[tracing::instrument(skip_all)]
-    fn from(value: &'a HeaderMap) -> Self {
-        let mut map = HashMap::default();
-        let groups = value.iter().chunk_by(|(header, _)| *header);
-        for (header, group) in groups.into_iter() {
-            map.insert(
-                header.to_string(),
-                group
-                    .map(|(_, value)| {
-                        let utf8_value = String::from_utf8_lossy(value.as_bytes());
-                        if header == reqwest::header::SET_COOKIE {
-                            let mut cookie =
-                                Cookie::parse(utf8_value).expect("when parsing a cookie");
-                            cookie.set_value("");
-                            cookie.to_string()
-                        } else {
-                            utf8_value.to_string()
-                        }
-                    })
-                    .collect(),
-            );
-        }
+/// Determines the number of requests added to the budget per refill interval, such that the rate is
+/// maintained
+fn rate_limiter_refill(
+    requests_per_minute: usize,
+    interval_secs: u64,
+    max_budget: usize,
+) -> anyhow::Result<usize> {
+    let requests = requests_per_minute as u64 * interval_secs;
+    if requests == 0 || !requests.is_multiple_of(60) {
+        bail!(
+            "{} requests per minute cannot be spread evenly over intervals of {} seconds",
+            requests_per_minute,
+            interval_secs
+        );
+    }
 
-        Self(map)
+    let refill = (requests / 60) as usize;
+    if refill > max_budget {
+        bail!(
+            "The request budget of {} cannot hold the {} requests added every {} seconds",
+            max_budget,
+            refill,
+            interval_secs
+        );
     }
+
+    Ok(refill)
 }
 
-impl Display for AnonymizedHttpHeaders {
-    #[tracing::instrument(skip_all)]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut output_buf = [0_u8; MAX_HEADER_BUFFER_SIZE];
-        let map_str = serde_json::to_string(&self).map_err(|e| {
-            error!("serializing the header map to JSON: {}", e);
-            std::fmt::Error
-        })?;
-        let mut encoder = match base64ct::Encoder::<base64ct::Base64>::new(&mut output_buf) {
-            Ok(encoder) => encoder,
-            Err(e) => {
-                error!("creating the base64 encoder: {}", e);
-                return write!(f, "...");
-            }
-        };
-        if let Err(e) = encoder.encode(map_str.as_bytes()) {
-            error!("encoding the header map as base64-encoded JSON: {}", e);
-            return write!(f, "...");
-        }
-        let encoded_string = match encoder.finish() {
-            Ok(encoded_string) => encoded_string,
-            Err(e) => {
-                error!("finishing the encoder job: {}", e);
-                return write!(f, "...");
-            }
-        };
-        write!(f, "{}", encoded_string)
-    }
+fn main() -> ExitCode {
+    let args: Args = parse_with_config();
+    report_with(
+        args.runtime
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(run(args))),
+        classify_requests,
+    )
 }
 
-#[derive(Debug, Error)]
-enum Error {
-    #[error("Cannot split each line of the input exactly once with a whitespace")]
-    InputSplit,
-    #[error(transparent)]
-    Codec(#[from] LinesCodecError),
-    #[error(transparent)]
-    Fqdn(#[from] ParseFqdnError),
-    #[error(transparent)]
-    IpAddr(#[from] AddrParseError),
+/// Classifies the failed requests, which are not classified where they occur, as network errors
+fn classify_requests(error: &anyhow::Error) -> Option<ExitStatus> {
+    error
+        .chain()
+        .any(|e| e.is::<reqwest::Error>() || e.is::<reqwest_middleware::Error>())
+        .then_some(ExitStatus::Network)
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(std::io::stderr)
-        .init();
+async fn run(args: Args) -> anyhow::Result<()> {
+    args.log.init();
+
+    if args.dry_run {
+        let input = open_input(args.input.as_deref()).await?;
+        println!(
+            "{}",
+            dry_run(input, args.input_format, args.delimiter, args.connect_mode).await
+        );
+
+        return Ok(());
+    }
 
-    debug!("Parsing command line arguments");
-    let args = Args::parse();
+    let hosts = args.cidr.expand()?;
+    let shutdown = shutdown_on_ctrl_c();
 
-    let recon_pg_pool = if args.enable_db_storage {
+    if let Some(metrics_addr) = args.metrics_addr {
+        debug!("Serving Prometheus metrics on {metrics_addr}");
+        install_metrics_exporter(metrics_addr)?;
+    }
+
+    let recon_pg_pool = if args.recon_db.enable_db_storage {
         debug!("Establishing a connection to the recon database");
-        Some(Arc::new(
-            create_recon_db_pool(
-                &args.recon_db_host,
-                &args.recon_db_username,
-                args.recon_db_password.as_deref(),
-                &args.recon_db_database,
-            )
-            .await?,
-        ))
+        Some(Arc::new(args.recon_db.connect().await?))
     } else {
         None
     };
 
     debug!("Creating the rate limiter");
+    let interval = Duration::from_secs(args.rate_interval_secs);
+    let refill = rate_limiter_refill(
+        args.requests_per_minute,
+        args.rate_interval_secs,
+        args.request_max_budget,
+    )?;
     let limiter = RateLimiter::builder()
         .initial(0)
-        .refill(args.requests_per_minute)
-        .interval(Duration::from_secs(60))
+        .refill(refill)
+        .interval(interval)
         .max(args.request_max_budget)
         .build();
 
-    debug!("Creating the reqwest HTTP client");
-    let client = if let Some(proxy) = args.proxy {
-        reqwest::ClientBuilder::default().proxy(Proxy::all(proxy)?)
+    // The jitter, the deduplicator and the order of the input derive their generators from the
+    // same seed
+    let mut rng = args.seed.rng();
+
+    // The jitter is relative to the average interval between two requests
+    let max_delay = match args.jitter {
+        Some(jitter) => interval.mul_f64(jitter / refill as f64),
+        None => Duration::ZERO,
+    };
+
+    debug!("Creating the rate limiting middleware shared by all HTTP clients");
+    let rate_limiter = Arc::new(reqwest_ratelimit::all(JitteredRateLimiter::new(
+        Arc::new(limiter),
+        max_delay,
+        SeedArgs::fork(&mut rng),
+    )));
+
+    debug!("Creating one reqwest HTTP client per proxy");
+    let proxies = if args.proxies.is_empty() {
+        vec![None]
     } else {
-        reqwest::ClientBuilder::default()
-    }
-    .danger_accept_invalid_certs(args.accept_invalid_certs)
-    .user_agent(&args.user_agent)
-    .redirect(Policy::none())
-    .timeout(Duration::from_secs(args.timeout_secs))
-    .build()?;
-
-    debug!("Wrapping the HTTP client to enable rate limiting");
-    let client = Arc::new(
-        ClientBuilder::new(client)
-            .with(reqwest_leaky_bucket::rate_limit_all(limiter))
-            .build(),
-    );
+        args.proxies.into_iter().map(Some).collect()
+    };
+    let settings = ClientSettings {
+        user_agent: args.user_agent.clone(),
+        default_headers: args.headers.into_iter().collect(),
+        timeout: Duration::from_secs(args.timeout_secs),
+        connect_timeout: Duration::from_secs(args.connect_timeout_secs),
+        http1_only: args.http1_only,
+        accept_invalid_certs: args.accept_invalid_certs,
+        rate_limiter,
+    };
+    let clients = Arc::new(ClientPool::new(settings, proxies)?);
+
+    let host_limiter = Arc::new(HostLimiter::new(
+        args.per_host_concurrency,
+        args.per_host_rpm,
+    ));
+    let output = Arc::new(Output::new(args.output.writer().await?, args.status_filter));
+    let deduplicator = args
+        .dedup
+        .then(|| Deduplicator::new(args.dedup_capacity, SeedArgs::fork(&mut rng)));
+    let deduplicator = &deduplicator;
+
+    let mut checkpoint = match args.checkpoint {
+        Some(path) => Some(Checkpoint::load(path).await?),
+        None => None,
+    };
+    let input_format = args.input_format;
+    let delimiter = args.delimiter;
+    let connect_mode = args.connect_mode;
+    let limit = args.limit;
+    let resumed_offset = checkpoint.as_ref().map_or(0, Checkpoint::resumed_offset);
 
-    debug!("Creating a stream from Stdin, decoded as lines, and parsed as pairs FQDNs and IPs");
-    info!("Lines that don't parse as pairs of FQDN and IP address are silently ignored");
-    let query_known_fqdns = args.query_known_fqdns;
-    let mut data_stream = pin!(FramedRead::new(stdin(), LinesCodec::new())
-        .filter_map(|line_result| async move { line_result.map_err(|e| warn!("{e}")).ok() })
-        .filter_map(|line| async move {
-            line.split_once(' ')
-                .ok_or(Error::InputSplit)
-                .and_then(|(fqdn_str, ip_addr_str)| {
-                    let fqdn = Arc::new(Fqdn::from_str(fqdn_str)?);
-                    let ip_addr = Arc::new(IpAddr::from_str(ip_addr_str)?);
-
-                    Ok((fqdn, ip_addr))
-                })
-                .map_err(|e| warn!("{e}"))
-                .ok()
-        })
-        .flat_map_unordered(None, |(fqdn, ip_addr)| {
-            Box::pin(
-                recon_http(
-                    recon_pg_pool.clone(),
-                    client.clone(),
-                    fqdn.clone(),
-                    ip_addr.clone(),
-                    query_known_fqdns,
-                    args.quiet,
+    let mut lines: BoxStream<'static, Result<String, LinesCodecError>> = match hosts {
+        Some(hosts) => {
+            info!("Looking up the names of the network hosts instead of reading the input");
+            let resolver = Arc::new(TokioAsyncResolver::tokio_from_system_conf()?);
+            reverse_lookup(resolver, hosts, args.concurrency.get())
+                .map(move |(fqdn, ip)| Ok(format!("{fqdn}{delimiter}{ip}")))
+                .boxed()
+        }
+        None => {
+            let input = open_input(args.input.as_deref()).await?;
+            FramedRead::new(input, LinesCodec::new()).boxed()
+        }
+    };
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.skip_processed(&mut lines).await?;
+    }
+
+    debug!("Creating a stream from the input, decoded as lines, and parsed as pairs FQDNs and IPs");
+    if !args.rejects.strict_input {
+        info!("Lines that don't parse as pairs of FQDN and IP address are ignored");
+    }
+    let rejects = &args.rejects.rejects().await?;
+    let waf_signatures = match &args.waf_signatures {
+        Some(path) => WafSignatures::load(path).await?,
+        None => WafSignatures::builtin(),
+    };
+    let opts = Arc::new(ReconOptions {
+        query_known_fqdns: args.query_known_fqdns || args.only_changed,
+        only_changed: args.only_changed,
+        connect_mode: args.connect_mode,
+        compare: args.compare,
+        sni: !args.no_sni,
+        anonymize_headers: !args.no_anonymize,
+        method: args.method,
+        max_body_bytes: args.max_body_bytes,
+        follow_redirects: args.follow_redirects,
+        favicon: args.favicon,
+        waf_signatures,
+        status_classifier: StatusClassifier::new(args.status_classes),
+        asn_lookup: args.asn.lookup()?.map(Arc::new),
+        credentials: load_credentials(args.auth_basic, args.auth_bearer, args.auth_file.as_deref())
+            .await?,
+        ports: args.ports,
+        retries: args.retries,
+        retry_backoff: Duration::from_millis(args.retry_backoff_ms),
+    });
+    let robots = args
+        .respect_robots
+        .then(|| RobotsCache::new(args.user_agent.clone()));
+    let robots = robots.as_ref();
+    let db_writer = recon_pg_pool.as_deref().map(|pg_pool| {
+        let on_error = if args.no_db_on_error {
+            OnDbError::Degrade {
+                retries: args.db_retries,
+            }
+        } else {
+            OnDbError::Abort
+        };
+        ReconDbWriter::spawn(pg_pool.clone(), args.batch_size, on_error)
+    });
+    let stats = &Stats::new(args.timing_stats);
+    {
+        let db_writer = db_writer.as_ref();
+        let targets = lines
+            .take_until(shutdown.cancelled())
+            .take_until(rejects.aborted())
+            .enumerate()
+            .then(|(index, line_result)| async move {
+                let line_number = resumed_offset + index + 1;
+                let (line, parsed) = match line_result {
+                    Ok(line) => match parse_target(&line, input_format, delimiter, connect_mode) {
+                        Ok(parsed) => (Some(line), Some(parsed)),
+                        Err(e) => {
+                            rejects.reject(line_number, Some(&line), e).await;
+                            (Some(line), None)
+                        }
+                    },
+                    Err(e) => {
+                        rejects.reject(line_number, None, e).await;
+                        (None, None)
+                    }
+                };
+                (index, line, parsed)
+            })
+            .scan(0_usize, |parsed_count, (index, line, parsed)| {
+                // The stream ends at the first parsed line beyond the limit
+                if parsed.is_some() {
+                    if limit.is_some_and(|limit| *parsed_count >= limit) {
+                        return future::ready(None);
+                    }
+                    *parsed_count += 1;
+                }
+                future::ready(Some((index, line, parsed)))
+            });
+        // Randomizing reads ahead, so lines that are held back are dropped on shutdown as well.
+        // The checkpoint tracks the lines by their index and thus copes with any order
+        let mut data_stream = pin!(args
+            .shuffle
+            .apply(targets, SeedArgs::fork(&mut rng))
+            .take_until(shutdown.cancelled())
+            .map(|(index, line, parsed)| {
+                let recon_pg_pool = recon_pg_pool.clone();
+                let clients = clients.clone();
+                let host_limiter = host_limiter.clone();
+                let output = output.clone();
+                let opts = opts.clone();
+
+                async move {
+                    // A line may hold several IP addresses, which are probed one after another
+                    let mut targets = Vec::new();
+                    if let Some((fqdn, mut ip_addrs)) = parsed {
+                        if ip_addrs.is_empty() {
+                            ip_addrs.extend(resolve(&fqdn).await);
+                        }
+                        let fqdn = Arc::new(fqdn);
+                        targets.extend(
+                            ip_addrs
+                                .into_iter()
+                                .filter(|ip_addr| {
+                                    deduplicator.as_ref().is_none_or(|deduplicator| {
+                                        deduplicator.insert(&fqdn, ip_addr)
+                                    })
+                                })
+                                .map(|ip_addr| (fqdn.clone(), ip_addr)),
+                        );
+                    }
+
+                    for (fqdn, ip_addr) in targets {
+                        recon_http(
+                            recon_pg_pool.clone(),
+                            db_writer,
+                            robots,
+                            stats,
+                            clients.clone(),
+                            host_limiter.clone(),
+                            output.clone(),
+                            fqdn,
+                            Arc::new(ip_addr),
+                            opts.clone(),
+                        )
+                        .await?;
+                        stats.record_processed();
+                    }
+
+                    anyhow::Ok((resumed_offset + index, line))
+                }
+            })
+            .buffer_unordered(args.concurrency.get()));
+
+        info!("Starting HTTP(s) recon");
+        while let Some(http_recon_result) = data_stream.next().await {
+            let (index, line) = http_recon_result?;
+            if let Some(checkpoint) = &mut checkpoint {
+                // The line is done once all results submitted so far, including its own, are stored
+                checkpoint.complete(index, line, db_writer.map_or(0, ReconDbWriter::submitted));
+                checkpoint
+                    .commit(db_writer.map_or(0, ReconDbWriter::committed))
+                    .await?;
+            }
+        }
+    }
+
+    if let Some(db_writer) = db_writer {
+        db_writer.finish().await?;
+    }
+    output.finish().await?;
+    if let Some(checkpoint) = &mut checkpoint {
+        checkpoint.commit(u64::MAX).await?;
+        checkpoint.save().await?;
+    }
+
+    if shutdown.is_cancelled() {
+        eprintln!("Interrupted after probing {} targets", stats.processed());
+    }
+    eprintln!("{stats}");
+    rejects.finish().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context as _;
+    use clap::CommandFactory;
+    use grimoire::exit::RunError;
+    use grimoire_test::{
+        http::{read_request_head, serve},
+        temp_dir,
+    };
+    use serde_json::Value;
+    use tokio::{io::AsyncWriteExt, net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    /// Starts a stub on localhost that answers each request with status 200 and sends the head of
+    /// each request, lowercased, to the returned receiver
+    async fn spawn_stub() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, move |request| {
+            let _ = sender.send(request.to_ascii_lowercase());
+            "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".to_string()
+        }));
+
+        (port, receiver)
+    }
+
+    /// Runs http-recon with `args` on the lines of `input`, and returns the results it writes in
+    /// NDJSON format
+    async fn run_on(input: &str, args: &[&str]) -> Vec<Value> {
+        let dir = temp_dir();
+        let input_path = dir.path().join("input.txt");
+        let output_path = dir.path().join("results.ndjson");
+        std::fs::write(&input_path, input).unwrap();
+
+        let args = Args::try_parse_from(
+            [
+                "http-recon",
+                "--quiet",
+                "--output",
+                "ndjson",
+                "--output-file",
+                output_path.to_str().unwrap(),
+                "--input",
+                input_path.to_str().unwrap(),
+                "--requests-per-minute",
+                "6000",
+                "--rate-interval-secs",
+                "1",
+            ]
+            .iter()
+            .chain(args),
+        )
+        .unwrap();
+        run(args).await.unwrap();
+
+        let output = std::fs::read_to_string(&output_path).unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn checkpoints_cannot_be_combined_with_networks() {
+        let result = Args::try_parse_from([
+            "http-recon",
+            "--checkpoint",
+            "checkpoint.json",
+            "--cidr",
+            "192.0.2.0/24",
+        ]);
+
+        assert_eq!(
+            result.unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn request_max_budget_has_no_short_flag() {
+        let command = Args::command();
+        let budget = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == "request_max_budget")
+            .unwrap();
+
+        assert_eq!(budget.get_short(), None);
+        assert_eq!(budget.get_long(), Some("request-max-budget"));
+    }
+
+    #[test]
+    fn the_refill_spreads_the_rate_over_the_interval() {
+        assert_eq!(rate_limiter_refill(60, 60, 600).unwrap(), 60);
+        assert_eq!(rate_limiter_refill(60, 10, 600).unwrap(), 10);
+        assert_eq!(rate_limiter_refill(6000, 1, 600).unwrap(), 100);
+        // 90 requests per minute are 1.5 requests per second
+        assert!(rate_limiter_refill(90, 1, 600).is_err());
+        assert!(rate_limiter_refill(0, 60, 600).is_err());
+        // The 600 requests added every minute exceed the budget
+        assert!(rate_limiter_refill(600, 60, 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn the_effective_rate_matches_the_refill_per_interval() {
+        use std::time::Instant;
+
+        // 600 requests per minute, refilled every second
+        let interval = Duration::from_secs(1);
+        let refill = rate_limiter_refill(600, 1, 600).unwrap();
+        let limiter = RateLimiter::builder()
+            .initial(0)
+            .refill(refill)
+            .interval(interval)
+            .max(600)
+            .build();
+
+        let start = Instant::now();
+        limiter.acquire(2 * refill).await;
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= interval * 2, "{elapsed:?}");
+        assert!(
+            elapsed < interval * 2 + Duration::from_millis(500),
+            "{elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_certificates_are_rejected_by_default() {
+        assert!(
+            !Args::try_parse_from(["http-recon"])
+                .unwrap()
+                .accept_invalid_certs
+        );
+        assert!(
+            Args::try_parse_from(["http-recon", "--accept-invalid-certs"])
+                .unwrap()
+                .accept_invalid_certs
+        );
+    }
+
+    /// The FQDN and the IP address of a line, as strings
+    fn parsed(line: &str, delimiter: char) -> Result<(String, Option<IpAddr>), Error> {
+        parse_line(line, delimiter, ConnectMode::Ip).map(|(fqdn, ip)| (fqdn.to_string(), ip))
+    }
+
+    #[test]
+    fn lines_are_split_by_the_delimiter() {
+        let expected = ("www.example.com".to_string(), "192.0.2.1".parse().ok());
+
+        assert_eq!(parsed("www.example.com 192.0.2.1", ' ').unwrap(), expected);
+        assert_eq!(
+            parsed("www.example.com   192.0.2.1", ' ').unwrap(),
+            expected
+        );
+        assert_eq!(parsed("www.example.com\t192.0.2.1", ' ').unwrap(), expected);
+        assert_eq!(parsed("www.example.com,192.0.2.1", ' ').unwrap(), expected);
+        assert_eq!(parsed("www.example.com;192.0.2.1", ';').unwrap(), expected);
+        assert_eq!(parsed("www.example.com, 192.0.2.1", ',').unwrap(), expected);
+    }
+
+    #[test]
+    fn lines_with_the_wrong_number_of_fields_are_rejected() {
+        assert!(matches!(
+            parsed("www.example.com", ' '),
+            Err(Error::InputSplit)
+        ));
+        assert!(matches!(
+            parsed("www.example.com 192.0.2.1 80", ' '),
+            Err(Error::InputFields(3))
+        ));
+        assert!(matches!(
+            parsed("www.example.com\t192.0.2.1\t80", ' '),
+            Err(Error::InputFields(3))
+        ));
+        assert_eq!(
+            parse_line("www.example.com", ' ', ConnectMode::Fqdn)
+                .unwrap()
+                .1,
+            None
+        );
+    }
+
+    #[test]
+    fn json_lines_combine_the_ip_and_the_ips() {
+        let (fqdn, ip_addrs) = parse_json_line(
+            r#"{"fqdn": "www.example.com", "ip": "192.0.2.1", "ips": ["192.0.2.1", "192.0.2.2"]}"#,
+            ConnectMode::Ip,
+        )
+        .unwrap();
+
+        assert_eq!(fqdn.to_string(), "www.example.com");
+        assert_eq!(
+            ip_addrs,
+            [
+                "192.0.2.1".parse::<IpAddr>().unwrap(),
+                "192.0.2.2".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_json_lines_are_rejected() {
+        assert!(matches!(
+            parse_json_line("www.example.com 192.0.2.1", ConnectMode::Ip),
+            Err(Error::Json(_))
+        ));
+        assert!(matches!(
+            parse_json_line(r#"{"fqdn": "www.example.com"}"#, ConnectMode::Ip),
+            Err(Error::JsonAddress)
+        ));
+        assert!(parse_json_line(r#"{"fqdn": "www.example.com"}"#, ConnectMode::Fqdn).is_ok());
+    }
+
+    #[tokio::test]
+    async fn each_ip_of_a_json_line_is_probed() {
+        // Listens on all addresses, such that both loopback addresses reach the stub
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |_| {
+            "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".to_string()
+        }));
+
+        let results = run_on(
+            r#"{"fqdn": "www.example.com", "ips": ["127.0.0.1", "127.0.0.2"]}"#,
+            &[
+                "--input-format",
+                "ndjson",
+                "--port",
+                &format!("{port}:http"),
+            ],
+        )
+        .await;
+
+        let mut probed: Vec<_> = results
+            .iter()
+            .map(|result| {
+                (
+                    result["ip"].as_str().unwrap().to_string(),
+                    result["response-status"].as_u64().unwrap(),
                 )
-                .into_stream(),
+            })
+            .collect();
+        probed.sort();
+        assert_eq!(
+            probed,
+            [
+                ("127.0.0.1".to_string(), 200),
+                ("127.0.0.2".to_string(), 200)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_runs_count_valid_and_rejected_lines() {
+        let input = "www.example.com 192.0.2.1\n\
+                     mail.example.com,192.0.2.2\n\
+                     www.example.org\n\
+                     www.example.net 192.0.2\n\
+                     www..example.com 192.0.2.3\n";
+
+        let report = dry_run(input.as_bytes(), InputFormat::Text, ' ', ConnectMode::Ip).await;
+
+        assert_eq!(report.valid(), 2);
+        assert_eq!(report.rejected(), 3);
+    }
+
+    #[tokio::test]
+    async fn custom_headers_are_sent_with_each_request() {
+        let (port, mut requests) = spawn_stub().await;
+
+        let results = run_on(
+            "www.example.com 127.0.0.1\n",
+            &["-H", "X-Scan-Id: 42", "--port", &format!("{port}:http")],
+        )
+        .await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["response-status"], 200);
+        let request = requests.recv().await.unwrap();
+        assert!(request.contains("\r\nx-scan-id: 42\r\n"), "{request}");
+    }
+
+    #[tokio::test]
+    async fn only_matching_statuses_are_written() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |request| {
+            let status = if request.to_ascii_lowercase().contains("host: missing.") {
+                "404 Not Found"
+            } else {
+                "200 OK"
+            };
+            format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\n\r\n")
+        }));
+
+        // Nothing listens on 127.0.0.2, such that the connection fails with status 0
+        let results = run_on(
+            "www.example.com 127.0.0.1\nmissing.example.com 127.0.0.1\nwww.example.org 127.0.0.2\n",
+            &[
+                "--port",
+                &format!("{port}:http"),
+                "--status-filter",
+                "0,200-299",
+            ],
+        )
+        .await;
+
+        let mut written: Vec<_> = results
+            .iter()
+            .map(|result| {
+                (
+                    result["fqdn"].as_str().unwrap(),
+                    result["response-status"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        written.sort();
+        assert_eq!(written, [("www.example.com", 200), ("www.example.org", 0)]);
+    }
+
+    #[tokio::test]
+    async fn only_the_first_parsed_lines_are_probed_up_to_the_limit() {
+        let (port, _requests) = spawn_stub().await;
+        // Rejected lines do not count towards the limit
+        let input = "not a line\n\
+                     www0.example.com 127.0.0.1\n\
+                     www1.example.com 127.0.0.1\n\
+                     www2.example.com 127.0.0.1\n\
+                     www3.example.com 127.0.0.1\n";
+
+        let results = run_on(input, &["--limit", "2", "--port", &format!("{port}:http")]).await;
+
+        let mut fqdns: Vec<_> = results
+            .iter()
+            .map(|result| result["fqdn"].as_str().unwrap().to_string())
+            .collect();
+        fqdns.sort();
+        assert_eq!(fqdns, ["www0.example.com", "www1.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn non_standard_ports_are_probed() {
+        let (port, _requests) = spawn_stub().await;
+
+        let results = run_on(
+            "www.example.com 127.0.0.1\n",
+            &["--port", &format!("{port}:http")],
+        )
+        .await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["port"], port);
+        assert_eq!(results[0]["url"], format!("http://127.0.0.1:{port}/"));
+        assert_eq!(results[0]["response-status"], 200);
+    }
+
+    #[tokio::test]
+    async fn urls_disallowed_by_robots_txt_are_skipped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, mut requests) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, move |request: &str| {
+            let request_line = request.lines().next().unwrap_or_default().to_string();
+            let body = if request_line.starts_with("GET /robots.txt ") {
+                "User-agent: *\nDisallow: /\n"
+            } else {
+                ""
+            };
+            let _ = sender.send(request_line);
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
             )
         }));
 
-    info!("Starting HTTP(s) recon");
-    while let Some(http_recon_result) = data_stream.next().await {
-        http_recon_result?;
+        let results = run_on(
+            "www.example.com 127.0.0.1\nmail.example.com 127.0.0.1\n",
+            &["--respect-robots", "--port", &format!("{port}:http")],
+        )
+        .await;
+
+        assert!(results.is_empty());
+        // The rules are cached per IP address and port, so the second name is skipped right away
+        assert_eq!(requests.recv().await.unwrap(), "GET /robots.txt HTTP/1.1");
+        assert!(requests.try_recv().is_err());
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn credentials_are_retried_after_a_401() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, |request| {
+            // 'user:secret' in base64
+            let status = if request
+                .to_ascii_lowercase()
+                .contains("\r\nauthorization: basic dxnlcjpzzwnyzxq=\r\n")
+            {
+                "200 OK"
+            } else {
+                "401 Unauthorized"
+            };
+            format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\n\r\n")
+        }));
+
+        let results = run_on(
+            "www.example.com 127.0.0.1\n",
+            &[
+                "--auth-basic",
+                "user:wrong",
+                "--auth-basic",
+                "user:secret",
+                "--port",
+                &format!("{port}:http"),
+            ],
+        )
+        .await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["response-status"], 401);
+        assert_eq!(
+            results[0]["auth-attempts"],
+            serde_json::json!([
+                {"credential": "basic:user", "status": 401},
+                {"credential": "basic:user", "status": 200},
+            ])
+        );
+    }
+
+    /// Starts an HTTPS stub on localhost with a self-signed certificate, which answers with status
+    /// 200 if 'www.example.com' was sent via SNI and with status 421 otherwise, as virtual hosts do
+    async fn spawn_vhost_stub() -> u16 {
+        use rcgen::CertifiedKey;
+        use tokio_rustls::{
+            rustls::{crypto::ring, pki_types::PrivateKeyDer, ServerConfig},
+            TlsAcceptor,
+        };
+
+        let CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["www.example.com".to_string()]).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                PrivateKeyDer::Pkcs8(key_pair.serialize_der().into()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let response = match stream.get_ref().1.server_name() {
+                        Some("www.example.com") => "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n",
+                        _ => "HTTP/1.1 421 Misdirected Request\r\ncontent-length: 0\r\n\r\n",
+                    };
+                    if read_request_head(&mut stream).await.is_none() {
+                        return;
+                    }
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn the_fqdn_is_sent_via_sni_when_connecting_to_the_ip_address() {
+        let port = spawn_vhost_stub().await;
+        let args = ["--accept-invalid-certs", "--port", &format!("{port}:https")];
+
+        let with_sni = run_on("www.example.com 127.0.0.1\n", &args).await;
+        let without_sni = run_on(
+            "www.example.com 127.0.0.1\n",
+            &[&args[..], &["--no-sni"]].concat(),
+        )
+        .await;
+
+        assert_eq!(with_sni.len(), 1);
+        assert_eq!(with_sni[0]["response-status"], 200);
+        assert_eq!(without_sni.len(), 1);
+        assert_eq!(without_sni[0]["response-status"], 421);
+    }
+
+    #[test]
+    fn ports_require_a_known_scheme() {
+        assert!(Args::try_parse_from(["http-recon", "--port", "8443:https"]).is_ok());
+        assert!(Args::try_parse_from(["http-recon", "--port", "8443:ftp"]).is_err());
+        assert!(Args::try_parse_from(["http-recon", "--port", "8443"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_network_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let error = reqwest::get(format!("http://127.0.0.1:{port}/"))
+            .await
+            .context("Cannot fetch the robots.txt")
+            .unwrap_err();
+
+        assert_eq!(
+            RunError::classify_with(error, classify_requests).status(),
+            ExitStatus::Network
+        );
+        assert_eq!(
+            RunError::classify_with(anyhow::anyhow!("unexpected"), classify_requests).status(),
+            ExitStatus::Failure
+        );
+    }
 }
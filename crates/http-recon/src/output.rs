@@ -0,0 +1,227 @@
+use std::{net::IpAddr, ops::RangeInclusive, str::FromStr};
+
+use grimoire::{
+    output::{OutputFormat, Writer},
+    records::HttpReconRecord,
+    Fqdn,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::{compare::Comparison, favicon::Favicon, tls::TlsCertificate, Error, ProbeResult};
+
+/// A set of status codes and ranges of status codes
+#[derive(Debug, Clone)]
+pub struct StatusFilter(Vec<RangeInclusive<u16>>);
+
+impl StatusFilter {
+    pub fn matches(&self, status: u16) -> bool {
+        self.0.iter().any(|range| range.contains(&status))
+    }
+}
+
+impl FromStr for StatusFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+        for item in s.split(',') {
+            let range = match item.split_once('-') {
+                Some((low, high)) => low.trim().parse()?..=high.trim().parse()?,
+                None => {
+                    let status = item.trim().parse()?;
+                    status..=status
+                }
+            };
+            if range.is_empty() {
+                return Err(Error::StatusRange);
+            }
+            ranges.push(range);
+        }
+
+        Ok(StatusFilter(ranges))
+    }
+}
+
+/// Builds the record of a result, which is written to the output and stored alike. In '--compare'
+/// mode, the record of connecting to the FQDN is embedded as the direct result
+pub fn recon_record(fqdn: &Fqdn, result: &ProbeResult, version: i32) -> HttpReconRecord {
+    HttpReconRecord {
+        fqdn: fqdn.to_string(),
+        domain: fqdn.domain(),
+        url: result.url.to_string(),
+        port: result.url.port_or_known_default().unwrap_or_default(),
+        response_status: result.response_status,
+        class: result.class.as_str().to_string(),
+        error_kind: result.error_kind.map(|kind| kind.as_str().to_string()),
+        http_version: result.http_version.map(|v| format!("{v:?}")),
+        latency_ms: result
+            .latency
+            .map(|l| i32::try_from(l.as_millis()).unwrap_or(i32::MAX)),
+        // The requested URL is final unless a redirect was followed
+        final_url: Some(
+            result
+                .redirect_chain
+                .last()
+                .unwrap_or(&result.url)
+                .to_string(),
+        ),
+        redirect_chain: result
+            .redirect_chain
+            .iter()
+            .map(|u| u.to_string())
+            .collect(),
+        headers: result
+            .headers
+            .as_ref()
+            .and_then(|h| serde_json::to_value(h).map_err(|e| error!("{}", e)).ok())
+            .unwrap_or(serde_json::json!({})),
+        security_flags: result
+            .security_flags
+            .as_ref()
+            .and_then(|f| serde_json::to_value(f).map_err(|e| error!("{}", e)).ok()),
+        content_length: result.content_length.map(|l| l as i64),
+        content_type: result.content_type.clone(),
+        body: result.body.clone(),
+        body_truncated: result.body_truncated,
+        title: result.title.clone(),
+        waf: result.waf.clone(),
+        tls_valid: result.tls_valid,
+        version,
+        mismatch: result.comparison.as_ref().map(Comparison::is_mismatch),
+        direct_result: result.comparison.as_ref().and_then(|comparison| {
+            serde_json::to_value(recon_record(fqdn, &comparison.direct, version))
+                .map_err(|e| error!("{}", e))
+                .ok()
+        }),
+        asn: result.asn.as_ref().map(|asn| asn.asn),
+        as_org: result.asn.as_ref().and_then(|asn| asn.org.clone()),
+        auth_attempts: auth_attempts(result),
+        updated_at: OffsetDateTime::now_utc(),
+    }
+}
+
+/// Serializes the outcome of retrying with each credential, if any
+fn auth_attempts(result: &ProbeResult) -> Option<serde_json::Value> {
+    if result.auth_attempts.is_empty() {
+        return None;
+    }
+
+    serde_json::to_value(&result.auth_attempts)
+        .map_err(|e| error!("{}", e))
+        .ok()
+}
+
+/// The record of a probed URL as written in the structured output formats, i.e. the record that
+/// is stored along with the IP address and the details that are stored in tables of their own
+#[derive(Debug, Serialize)]
+struct HttpReconOutput<'a> {
+    #[serde(flatten)]
+    record: &'a HttpReconRecord,
+    ip: IpAddr,
+    certificate: Option<&'a TlsCertificate>,
+    favicon: Option<&'a Favicon>,
+    /// How the response received via the FQDN differs, only present in '--compare' mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    differences: Option<&'a [String]>,
+}
+
+/// Writes HTTP(s) recon results to stdout and the output file in the selected format. In the text
+/// format, each line contains the status and the base64-encoded JSON header map of a probed URL,
+/// and certificates and favicons are written on separate lines. The other formats contain one entry per probed
+/// URL
+#[derive(Debug)]
+pub struct Output {
+    writer: Writer,
+    status_filter: Option<StatusFilter>,
+}
+
+impl Output {
+    pub fn new(writer: Writer, status_filter: Option<StatusFilter>) -> Self {
+        Output {
+            writer,
+            status_filter,
+        }
+    }
+
+    /// Writes the result of probing a single URL along with its `record`. Without a status filter,
+    /// failed requests are not written
+    pub async fn write(
+        &self,
+        ip: &IpAddr,
+        result: &ProbeResult,
+        record: &HttpReconRecord,
+    ) -> anyhow::Result<()> {
+        let fqdn = &record.fqdn;
+        let status_matches = match &self.status_filter {
+            Some(status_filter) => status_filter.matches(result.response_status),
+            None => result.response_status != 0,
+        };
+        if !status_matches {
+            return Ok(());
+        }
+
+        if self.writer.format() == OutputFormat::Text {
+            self.writer
+                .write_line(&format!("{fqdn} {ip} {result}"))
+                .await?;
+            if let Some(certificate) = &result.certificate {
+                self.writer
+                    .write_line(&format!("{fqdn} {ip} {certificate}"))
+                    .await?;
+            }
+            if let Some(favicon) = &result.favicon {
+                self.writer
+                    .write_line(&format!("{fqdn} {ip} {favicon}"))
+                    .await?;
+            }
+        } else {
+            self.writer
+                .write_record(&HttpReconOutput {
+                    record,
+                    ip: *ip,
+                    certificate: result.certificate.as_ref(),
+                    favicon: result.favicon.as_ref(),
+                    differences: result
+                        .comparison
+                        .as_ref()
+                        .map(|comparison| comparison.differences.as_slice()),
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Completes the output and flushes the output file
+    pub async fn finish(&self) -> anyhow::Result<()> {
+        self.writer.finish().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_filters_accept_codes_and_ranges() {
+        let filter: StatusFilter = "0, 200,301-399".parse().unwrap();
+
+        assert!(filter.matches(0));
+        assert!(filter.matches(200));
+        assert!(filter.matches(301));
+        assert!(filter.matches(399));
+        assert!(!filter.matches(201));
+        assert!(!filter.matches(404));
+    }
+
+    #[test]
+    fn malformed_status_filters_are_rejected() {
+        assert!("399-301".parse::<StatusFilter>().is_err());
+        assert!("200,ok".parse::<StatusFilter>().is_err());
+        assert!("".parse::<StatusFilter>().is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use grimoire::Fqdn;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::{host_header, read_body_prefix};
+
+/// Only this many bytes of a robots.txt are parsed, which RFC 9309 requires of crawlers at least
+const MAX_ROBOTS_BYTES: usize = 500 * 1024;
+
+/// A group of a robots.txt file, consisting of the user agents it applies to and its rules
+#[derive(Debug, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<(String, bool)>,
+}
+
+/// The rules of a robots.txt file that apply to this crawler, as pairs of a path prefix and whether
+/// paths with that prefix are allowed. Wildcards within paths are not supported
+#[derive(Debug, Default)]
+pub struct RobotsRules {
+    rules: Vec<(String, bool)>,
+}
+
+impl RobotsRules {
+    pub fn allow_all() -> Self {
+        RobotsRules::default()
+    }
+
+    pub fn disallow_all() -> Self {
+        RobotsRules {
+            rules: vec![("/".to_string(), false)],
+        }
+    }
+
+    /// Parses a robots.txt file and keeps the rules of the groups that name a product token
+    /// contained in `user_agent`. If there are none, the rules of the '*' groups are kept instead
+    pub fn parse(contents: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_rules = true;
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user agent lines share the rules that follow them
+                    if in_rules {
+                        groups.push(Group::default());
+                        in_rules = false;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    // An empty rule matches nothing
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push((value.to_string(), key == "allow"));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let user_agent = user_agent.to_ascii_lowercase();
+        let specific: Vec<_> = groups
+            .iter()
+            .filter(|group| {
+                group
+                    .user_agents
+                    .iter()
+                    .any(|agent| agent != "*" && user_agent.contains(agent.as_str()))
+            })
+            .flat_map(|group| group.rules.iter().cloned())
+            .collect();
+        let rules = if specific.is_empty() {
+            groups
+                .iter()
+                .filter(|group| group.user_agents.iter().any(|agent| agent == "*"))
+                .flat_map(|group| group.rules.iter().cloned())
+                .collect()
+        } else {
+            specific
+        };
+
+        RobotsRules { rules }
+    }
+
+    /// Determines whether `path` may be crawled. The longest matching rule takes precedence, and
+    /// allow rules win over disallow rules of the same length
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, allowed)| (prefix.len(), *allowed))
+            .is_none_or(|(_, allowed)| *allowed)
+    }
+}
+
+/// The rules of each IP address and port, which are parsed once by the first request to either
+type RulesByHost = HashMap<(IpAddr, u16), Arc<OnceCell<RobotsRules>>>;
+
+/// Fetches the robots.txt of each IP address and port at most once and caches the parsed rules
+#[derive(Debug)]
+pub struct RobotsCache {
+    user_agent: String,
+    rules: Mutex<RulesByHost>,
+}
+
+impl RobotsCache {
+    pub fn new(user_agent: String) -> Self {
+        RobotsCache {
+            user_agent,
+            rules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Determines whether `url` may be probed on behalf of `fqdn`, fetching the robots.txt from
+    /// `ip` on first use
+    pub async fn is_allowed(
+        &self,
+        client: &ClientWithMiddleware,
+        fqdn: &Fqdn,
+        ip: IpAddr,
+        url: &Url,
+    ) -> bool {
+        let rules = self
+            .rules
+            .lock()
+            .expect("the robots cache lock is never poisoned")
+            .entry((ip, url.port_or_known_default().unwrap_or_default()))
+            .or_default()
+            .clone();
+
+        rules
+            .get_or_init(|| self.fetch(client, fqdn, url))
+            .await
+            .is_allowed(url.path())
+    }
+
+    /// Fetches and parses the robots.txt of the origin of `url`. As per RFC 9309, a missing file
+    /// allows everything, whereas an unreachable server or a server error disallows everything
+    #[tracing::instrument(skip(self, client))]
+    async fn fetch(&self, client: &ClientWithMiddleware, fqdn: &Fqdn, url: &Url) -> RobotsRules {
+        let Ok(robots_url) = url.join("/robots.txt") else {
+            return RobotsRules::allow_all();
+        };
+
+        let response = client
+            .get(robots_url)
+            .header(reqwest::header::HOST, host_header(fqdn, url))
+            .send()
+            .await;
+        match response {
+            Ok(mut response) if response.status().is_success() => {
+                match read_body_prefix(&mut response, MAX_ROBOTS_BYTES).await {
+                    Ok(contents) => RobotsRules::parse(
+                        &String::from_utf8_lossy(&contents.data),
+                        &self.user_agent,
+                    ),
+                    Err(e) => {
+                        debug!("Error when reading the robots.txt of '{}': {}", url, e);
+                        RobotsRules::disallow_all()
+                    }
+                }
+            }
+            Ok(response) if response.status().is_server_error() => RobotsRules::disallow_all(),
+            Ok(_) => RobotsRules::allow_all(),
+            Err(e) => {
+                debug!("Error when fetching the robots.txt of '{}': {}", url, e);
+                RobotsRules::disallow_all()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+# Crawlers in general may visit everything but the admin pages
+User-agent: *
+Disallow: /admin
+Allow: /admin/public
+
+User-agent: http-recon
+User-agent: other-bot
+Disallow: /   # except for the start page
+Disallow:
+";
+
+    #[test]
+    fn the_groups_of_the_user_agent_take_precedence() {
+        let rules = RobotsRules::parse(ROBOTS, "Mozilla/5.0 (compatible; HTTP-Recon/0.1)");
+
+        assert!(!rules.is_allowed("/"));
+        assert!(!rules.is_allowed("/index.html"));
+    }
+
+    #[test]
+    fn other_user_agents_follow_the_wildcard_group() {
+        let rules = RobotsRules::parse(ROBOTS, "curl/8.0");
+
+        assert!(rules.is_allowed("/"));
+        assert!(!rules.is_allowed("/admin/users"));
+        assert!(rules.is_allowed("/admin/public/logo.png"));
+    }
+
+    #[test]
+    fn the_longest_matching_rule_wins() {
+        let rules = RobotsRules::parse(
+            "User-agent: *\nDisallow: /a\nAllow: /a/b\nDisallow: /a/b/c\nAllow: /x\nDisallow: /x\n",
+            "curl/8.0",
+        );
+
+        assert!(!rules.is_allowed("/a"));
+        assert!(rules.is_allowed("/a/b"));
+        assert!(!rules.is_allowed("/a/b/c/d"));
+        assert!(rules.is_allowed("/x"));
+        assert!(rules.is_allowed("/z"));
+    }
+
+    #[test]
+    fn files_without_rules_allow_everything() {
+        let rules = RobotsRules::parse("Sitemap: https://www.example.com/sitemap.xml\n", "curl");
+
+        assert!(rules.is_allowed("/"));
+        assert!(RobotsRules::allow_all().is_allowed("/"));
+        assert!(!RobotsRules::disallow_all().is_allowed("/"));
+    }
+}
@@ -0,0 +1,112 @@
+use reqwest::header::{
+    HeaderMap, CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS,
+};
+use serde::Serialize;
+
+/// HSTS policies with a `max-age` below one year are considered weak
+const HSTS_MIN_MAX_AGE: u64 = 31_536_000;
+
+/// Summarizes which hardening headers are present in a response
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SecurityFlags {
+    pub strict_transport_security: bool,
+    pub hsts_max_age: Option<u64>,
+    pub hsts_max_age_too_short: bool,
+    pub content_security_policy: bool,
+    pub x_frame_options: bool,
+    /// Only set if the header carries the value `nosniff`
+    pub x_content_type_options: bool,
+}
+
+impl<'a> From<&'a HeaderMap> for SecurityFlags {
+    #[tracing::instrument(skip_all)]
+    fn from(headers: &'a HeaderMap) -> Self {
+        let hsts_max_age = headers
+            .get(STRICT_TRANSPORT_SECURITY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_hsts_max_age);
+
+        SecurityFlags {
+            strict_transport_security: headers.contains_key(STRICT_TRANSPORT_SECURITY),
+            hsts_max_age,
+            hsts_max_age_too_short: hsts_max_age.is_some_and(|max_age| max_age < HSTS_MIN_MAX_AGE),
+            content_security_policy: headers.contains_key(CONTENT_SECURITY_POLICY),
+            x_frame_options: headers.contains_key(X_FRAME_OPTIONS),
+            x_content_type_options: headers
+                .get(X_CONTENT_TYPE_OPTIONS)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case("nosniff")),
+        }
+    }
+}
+
+/// Extracts the `max-age` directive from the value of a `Strict-Transport-Security` header
+fn parse_hsts_max_age(value: &str) -> Option<u64> {
+    value.split(';').find_map(|directive| {
+        let (name, value) = directive.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("max-age") {
+            value.trim().trim_matches('"').parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn hardened_responses_set_all_flags() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static("max-age=63072000; includeSubDomains; preload"),
+        );
+        headers.insert(
+            CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("default-src 'self'"),
+        );
+        headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+
+        assert_eq!(
+            SecurityFlags::from(&headers),
+            SecurityFlags {
+                strict_transport_security: true,
+                hsts_max_age: Some(63072000),
+                hsts_max_age_too_short: false,
+                content_security_policy: true,
+                x_frame_options: true,
+                x_content_type_options: true,
+            }
+        );
+    }
+
+    #[test]
+    fn bare_responses_set_no_flags() {
+        assert_eq!(
+            SecurityFlags::from(&HeaderMap::new()),
+            SecurityFlags::default()
+        );
+    }
+
+    #[test]
+    fn short_hsts_policies_are_flagged() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static("max-age=\"3600\""),
+        );
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("sniff"));
+        let flags = SecurityFlags::from(&headers);
+
+        assert!(flags.strict_transport_security);
+        assert_eq!(flags.hsts_max_age, Some(3600));
+        assert!(flags.hsts_max_age_too_short);
+        assert!(!flags.x_content_type_options);
+    }
+}
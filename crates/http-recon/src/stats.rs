@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use hdrhistogram::Histogram;
+use metrics::counter;
+
+/// Latencies above one hour are recorded as one hour
+const MAX_LATENCY_MS: u64 = 60 * 60 * 1000;
+
+/// The percentiles of the latencies listed in the summary
+const LATENCY_QUANTILES: [(&str, f64); 3] = [("median", 0.5), ("p90", 0.9), ("p99", 0.99)];
+
+/// Counts the outcomes of a run, for the summary printed on completion. Every outcome is also
+/// recorded as a Prometheus metric
+#[derive(Debug, Default)]
+pub struct Stats {
+    processed: AtomicUsize,
+    connected: AtomicUsize,
+    failed: AtomicUsize,
+    skipped_known: AtomicUsize,
+    skipped_robots: AtomicUsize,
+    unchanged: AtomicUsize,
+    mismatched: AtomicUsize,
+    authenticated: AtomicUsize,
+    stored: AtomicUsize,
+    /// The number of probed URLs per response status
+    statuses: Mutex<BTreeMap<u16, usize>>,
+    /// The latencies of all responses in milliseconds, if timing stats are enabled
+    latencies: Option<Mutex<Histogram<u64>>>,
+}
+
+impl Stats {
+    /// Creates the stats, which also collect the latencies of all responses if `timing` is set
+    pub fn new(timing: bool) -> Self {
+        Stats {
+            latencies: timing.then(|| {
+                Mutex::new(
+                    Histogram::new_with_bounds(1, MAX_LATENCY_MS, 3)
+                        .expect("the latency bounds are valid"),
+                )
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// The number of probed URLs that returned a response
+    pub fn connected(&self) -> usize {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn record_processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_processed_total").increment(1);
+    }
+
+    /// Records the outcome of probing a single URL, where a status of zero denotes a failure
+    pub fn record_probe(&self, status: u16) {
+        if status != 0 {
+            self.connected.fetch_add(1, Ordering::Relaxed);
+            counter!("http_recon_succeeded_total").increment(1);
+
+            let mut statuses = self.statuses.lock().expect("locking the statuses");
+            *statuses.entry(status).or_default() += 1;
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            counter!("http_recon_failed_total").increment(1);
+        }
+    }
+
+    pub fn record_skipped_known(&self) {
+        self.skipped_known.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_skipped_known_total").increment(1);
+    }
+
+    pub fn record_skipped_robots(&self) {
+        self.skipped_robots.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_skipped_robots_total").increment(1);
+    }
+
+    pub fn record_unchanged(&self) {
+        self.unchanged.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_unchanged_total").increment(1);
+    }
+
+    pub fn record_mismatch(&self) {
+        self.mismatched.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_mismatch_total").increment(1);
+    }
+
+    pub fn record_authenticated(&self) {
+        self.authenticated.fetch_add(1, Ordering::Relaxed);
+        counter!("http_recon_authenticated_total").increment(1);
+    }
+
+    pub fn record_stored(&self) {
+        self.stored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the latency of a response, unless timing stats are disabled
+    pub fn record_latency(&self, latency: Duration) {
+        if let Some(latencies) = &self.latencies {
+            let latency_ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+            latencies
+                .lock()
+                .expect("locking the latencies")
+                .saturating_record(latency_ms);
+        }
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Processed:     {}", self.processed())?;
+        writeln!(
+            f,
+            "Connected:     {}",
+            self.connected.load(Ordering::Relaxed)
+        )?;
+        let statuses = self.statuses.lock().map_err(|_| std::fmt::Error)?;
+        for (status, count) in statuses.iter() {
+            writeln!(f, "  {status:<12}{count}")?;
+        }
+        writeln!(f, "Failed:        {}", self.failed.load(Ordering::Relaxed))?;
+        writeln!(
+            f,
+            "Skipped known: {}",
+            self.skipped_known.load(Ordering::Relaxed)
+        )?;
+        writeln!(
+            f,
+            "Skipped robots: {}",
+            self.skipped_robots.load(Ordering::Relaxed)
+        )?;
+        writeln!(
+            f,
+            "Unchanged:     {}",
+            self.unchanged.load(Ordering::Relaxed)
+        )?;
+        writeln!(
+            f,
+            "Mismatched:    {}",
+            self.mismatched.load(Ordering::Relaxed)
+        )?;
+        writeln!(
+            f,
+            "Authenticated: {}",
+            self.authenticated.load(Ordering::Relaxed)
+        )?;
+        write!(f, "Stored:        {}", self.stored.load(Ordering::Relaxed))?;
+        if let Some(latencies) = &self.latencies {
+            let latencies = latencies.lock().map_err(|_| std::fmt::Error)?;
+            if !latencies.is_empty() {
+                writeln!(f)?;
+                writeln!(f, "Latency:")?;
+                writeln!(f, "  {:<12}{}ms", "min", latencies.min())?;
+                for (label, quantile) in LATENCY_QUANTILES {
+                    let latency = latencies.value_at_quantile(quantile);
+                    writeln!(f, "  {label:<12}{latency}ms")?;
+                }
+                write!(f, "  {:<12}{}ms", "max", latencies.max())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_summary_counts_each_status() {
+        let stats = Stats::new(false);
+        for status in [200, 404, 200, 0, 301] {
+            stats.record_probe(status);
+            stats.record_processed();
+        }
+        stats.record_skipped_known();
+
+        assert_eq!(stats.processed(), 5);
+        assert_eq!(stats.connected(), 4);
+        assert_eq!(
+            stats.to_string(),
+            "Processed:     5\n\
+             Connected:     4\n  \
+               200         2\n  \
+               301         1\n  \
+               404         1\n\
+             Failed:        1\n\
+             Skipped known: 1\n\
+             Skipped robots: 0\n\
+             Unchanged:     0\n\
+             Mismatched:    0\n\
+             Authenticated: 0\n\
+             Stored:        0"
+        );
+    }
+
+    #[test]
+    fn the_latency_percentiles_are_summarized() {
+        let stats = Stats::new(true);
+        // Recorded out of order, to show that the order does not matter
+        for latency_ms in (1..=100).rev() {
+            stats.record_latency(Duration::from_millis(latency_ms));
+        }
+
+        let summary = stats.to_string();
+        let latencies = summary.split_once("Latency:\n").unwrap().1;
+        assert_eq!(
+            latencies,
+            "  min         1ms\n  \
+               median      50ms\n  \
+               p90         90ms\n  \
+               p99         99ms\n  \
+               max         100ms"
+        );
+    }
+
+    #[test]
+    fn latencies_are_only_summarized_if_enabled() {
+        let stats = Stats::new(false);
+        stats.record_latency(Duration::from_millis(20));
+
+        assert!(!stats.to_string().contains("Latency:"));
+        assert!(!Stats::new(true).to_string().contains("Latency:"));
+    }
+}
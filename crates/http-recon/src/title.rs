@@ -0,0 +1,110 @@
+use encoding_rs::{Encoding, UTF_8};
+
+/// Titles longer than this many characters are truncated
+const MAX_TITLE_CHARS: usize = 256;
+
+/// Extracts the contents of the `<title>` element from an HTML response body, decoded using the
+/// charset of the `Content-Type` header and falling back to UTF-8. Whitespace, including line
+/// breaks, is collapsed into single spaces. Returns `None` for responses that are not HTML or have
+/// no title
+pub fn extract_title(body: &[u8], content_type: Option<&str>) -> Option<String> {
+    let content_type = content_type?.to_ascii_lowercase();
+    let (mime_type, params) = content_type
+        .split_once(';')
+        .unwrap_or((content_type.as_str(), ""));
+    if !matches!(mime_type.trim(), "text/html" | "application/xhtml+xml") {
+        return None;
+    }
+
+    let encoding = params
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("charset="))
+        .find_map(|charset| Encoding::for_label(charset.trim_matches('"').as_bytes()))
+        .unwrap_or(UTF_8);
+    let (html, _, _) = encoding.decode(body);
+
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase.find("<title")?;
+    let start = start + lowercase[start..].find('>')? + 1;
+    let end = start
+        + lowercase[start..]
+            .find("</title")
+            .unwrap_or(lowercase.len() - start);
+
+    let title = decode_entities(
+        &html[start..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    if title.is_empty() {
+        return None;
+    }
+
+    Some(title.chars().take(MAX_TITLE_CHARS).collect())
+}
+
+/// Replaces the most common named character references
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_title_is_extracted() {
+        let body = b"<!DOCTYPE html><html><head><TITLE>Welcome to nginx!</TITLE></head></html>";
+
+        assert_eq!(
+            extract_title(body, Some("text/html")).as_deref(),
+            Some("Welcome to nginx!")
+        );
+    }
+
+    #[test]
+    fn multi_line_titles_are_collapsed() {
+        let body = b"<html><head><title lang=\"en\">\n    Sign in &amp;\n    Register\n</title>";
+
+        assert_eq!(
+            extract_title(body, Some("text/html; charset=utf-8")).as_deref(),
+            Some("Sign in & Register")
+        );
+    }
+
+    #[test]
+    fn the_charset_of_the_content_type_is_respected() {
+        // 'Übersicht' encoded as ISO-8859-1
+        let body = b"<title>\xdcbersicht</title>";
+
+        assert_eq!(
+            extract_title(body, Some("text/html; charset=\"iso-8859-1\"")).as_deref(),
+            Some("Übersicht")
+        );
+    }
+
+    #[test]
+    fn long_titles_are_truncated() {
+        let body = format!("<title>{}</title>", "a".repeat(1000));
+
+        let title = extract_title(body.as_bytes(), Some("text/html")).unwrap();
+
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+    }
+
+    #[test]
+    fn non_html_responses_have_no_title() {
+        let body = b"<title>Not a page</title>";
+
+        assert_eq!(extract_title(body, Some("application/json")), None);
+        assert_eq!(extract_title(body, None), None);
+        assert_eq!(extract_title(b"<html></html>", Some("text/html")), None);
+        assert_eq!(extract_title(b"<title> </title>", Some("text/html")), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fmt::Display, net::IpAddr};
+
+use anyhow::anyhow;
+use grimoire::Fqdn;
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::{query, types::ipnetwork::IpNetwork, PgConnection};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use x509_parser::{extensions::GeneralName, parse_x509_certificate};
+
+/// The details of the certificate presented by an HTTPS service
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsCertificate {
+    pub subject_cn: Option<String>,
+    pub sans: Vec<String>,
+    pub issuer: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub not_before: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub not_after: OffsetDateTime,
+    pub serial: String,
+}
+
+impl TlsCertificate {
+    /// Parses a DER-encoded X.509 certificate
+    pub fn from_der(der: &[u8]) -> anyhow::Result<Self> {
+        let (_, cert) = parse_x509_certificate(der)
+            .map_err(|e| anyhow!("parsing the peer certificate: {e}"))?;
+
+        let subject_cn = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(|cn| cn.to_string());
+
+        let sans = cert
+            .subject_alternative_name()?
+            .map(|ext| {
+                ext.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(dns_name) => Some(dns_name.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(TlsCertificate {
+            subject_cn,
+            sans,
+            issuer: cert.issuer().to_string(),
+            not_before: cert.validity().not_before.to_datetime(),
+            not_after: cert.validity().not_after.to_datetime(),
+            serial: cert.raw_serial_as_string(),
+        })
+    }
+}
+
+impl Display for TlsCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.subject_cn.as_deref().unwrap_or("-"),
+            self.sans.iter().join(","),
+            self.not_before
+                .format(&Rfc3339)
+                .map_err(|_| std::fmt::Error)?,
+            self.not_after
+                .format(&Rfc3339)
+                .map_err(|_| std::fmt::Error)?,
+            self.serial,
+        )
+    }
+}
+
+#[tracing::instrument(skip(conn, cert))]
+pub async fn submit_tls_recon_results(
+    conn: &mut PgConnection,
+    fqdn: &Fqdn,
+    ip: &IpAddr,
+    cert: &TlsCertificate,
+) -> anyhow::Result<()> {
+    query!(
+        r#"
+        INSERT INTO "tls-recon" (id, domain, fqdn, ip, "subject-cn", sans, issuer, "not-before", "not-after", serial)
+        VALUES (DEFAULT, $1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT ON CONSTRAINT "tls-recon_pkey" DO
+        UPDATE SET "subject-cn" = EXCLUDED."subject-cn", sans = EXCLUDED.sans, issuer = EXCLUDED.issuer, "not-before" = EXCLUDED."not-before", "not-after" = EXCLUDED."not-after", serial = EXCLUDED.serial
+        "#,
+        fqdn.domain(),
+        fqdn.to_string(),
+        IpNetwork::from(*ip),
+        cert.subject_cn.as_deref(),
+        &cert.sans,
+        &cert.issuer,
+        cert.not_before,
+        cert.not_after,
+        &cert.serial,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+/// The built-in signatures as triples of the product name, the header name and an optional
+/// substring of the header value
+const BUILTIN_SIGNATURES: &[(&str, &str, Option<&str>)] = &[
+    ("Cloudflare", "cf-ray", None),
+    ("Cloudflare", "server", Some("cloudflare")),
+    ("Akamai", "x-akamai-*", None),
+    ("Akamai", "server", Some("akamaighost")),
+    ("Amazon CloudFront", "x-amz-cf-id", None),
+    ("Amazon CloudFront", "via", Some("cloudfront")),
+    ("Fastly", "x-fastly-request-id", None),
+    ("Fastly", "x-served-by", Some("cache-")),
+    ("Azure Front Door", "x-azure-ref", None),
+    ("Imperva", "x-iinfo", None),
+    ("Imperva", "x-cdn", Some("imperva")),
+    ("Sucuri", "x-sucuri-id", None),
+    ("Sucuri", "server", Some("sucuri")),
+    ("F5 BIG-IP", "server", Some("big-ip")),
+    ("Varnish", "via", Some("varnish")),
+];
+
+/// Identifies a CDN or WAF by a response header. A header name ending in '*' matches all headers
+/// with that prefix
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WafSignature {
+    pub name: String,
+    pub header: String,
+    /// If set, the header value must contain this string, ignoring case
+    #[serde(default)]
+    pub value_contains: Option<String>,
+}
+
+impl WafSignature {
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        let header = self.header.to_ascii_lowercase();
+        headers
+            .iter()
+            .filter(|(name, _)| match header.strip_suffix('*') {
+                Some(prefix) => name.as_str().starts_with(prefix),
+                None => name.as_str() == header,
+            })
+            .any(|(_, value)| {
+                self.value_contains.as_ref().is_none_or(|needle| {
+                    String::from_utf8_lossy(value.as_bytes())
+                        .to_ascii_lowercase()
+                        .contains(&needle.to_ascii_lowercase())
+                })
+            })
+    }
+}
+
+/// The signatures used to label responses with the CDN or WAF they were served by
+#[derive(Debug, Clone)]
+pub struct WafSignatures(Vec<WafSignature>);
+
+impl WafSignatures {
+    pub fn builtin() -> Self {
+        WafSignatures(
+            BUILTIN_SIGNATURES
+                .iter()
+                .map(|(name, header, value_contains)| WafSignature {
+                    name: name.to_string(),
+                    header: header.to_string(),
+                    value_contains: value_contains.map(|v| v.to_string()),
+                })
+                .collect(),
+        )
+    }
+
+    /// Loads the signatures from a JSON file holding an array of objects with the keys 'name',
+    /// 'header' and optionally 'value_contains'. They replace the built-in signatures
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = tokio::fs::read(path).await?;
+        let signatures = serde_json::from_slice(&contents).map_err(|e| {
+            anyhow::anyhow!(
+                "Cannot parse the WAF signatures '{}': {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        Ok(WafSignatures(signatures))
+    }
+
+    /// Returns the name of the first CDN or WAF whose signature matches the response headers
+    pub fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        self.0
+            .iter()
+            .find(|signature| signature.matches(headers))
+            .map(|signature| signature.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grimoire_test::temp_dir;
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn cloudflare_is_detected() {
+        let signatures = WafSignatures::builtin();
+
+        assert_eq!(
+            signatures
+                .detect(&headers(&[
+                    ("server", "cloudflare"),
+                    ("cf-ray", "8a1b2c3d4e5f6789-FRA"),
+                ]))
+                .as_deref(),
+            Some("Cloudflare")
+        );
+        assert_eq!(
+            signatures
+                .detect(&headers(&[("server", "CloudFlare")]))
+                .as_deref(),
+            Some("Cloudflare")
+        );
+    }
+
+    #[test]
+    fn header_prefixes_are_matched() {
+        assert_eq!(
+            WafSignatures::builtin()
+                .detect(&headers(&[("x-akamai-transformed", "9 - 0 pmb=mRUM,1")]))
+                .as_deref(),
+            Some("Akamai")
+        );
+    }
+
+    #[test]
+    fn plain_nginx_is_not_labeled() {
+        assert_eq!(
+            WafSignatures::builtin().detect(&headers(&[
+                ("server", "nginx/1.25.3"),
+                ("content-type", "text/html"),
+            ])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn loaded_signatures_replace_the_builtin_ones() {
+        let dir = temp_dir();
+        let path = dir.path().join("waf-signatures.json");
+        std::fs::write(
+            &path,
+            r#"[{"name": "Internal", "header": "server", "value_contains": "nginx"}]"#,
+        )
+        .unwrap();
+
+        let signatures = WafSignatures::load(&path).await.unwrap();
+
+        assert_eq!(
+            signatures
+                .detect(&headers(&[("server", "nginx/1.25.3")]))
+                .as_deref(),
+            Some("Internal")
+        );
+        assert_eq!(signatures.detect(&headers(&[("cf-ray", "8a1b")])), None);
+    }
+}
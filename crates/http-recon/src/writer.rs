@@ -0,0 +1,550 @@
+use std::{
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use grimoire::{records::HttpReconRecord, Fqdn};
+use sqlx::{query_scalar, PgConnection, PgPool};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    favicon::submit_favicon_recon_results, tls::submit_tls_recon_results, ProbeResult, Scheme,
+};
+
+/// Partial batches are flushed at least this often, so that results are not held back when
+/// probes complete slowly
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The delay before retrying a batch that could not be stored, multiplied by the number of the
+/// attempt
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// What happens when a batch cannot be stored
+#[derive(Debug, Clone, Copy)]
+pub enum OnDbError {
+    /// Stop the run with the error
+    Abort,
+    /// Retry the batch up to this many times. If it still cannot be stored, stop storing results
+    /// for the rest of the run
+    Degrade { retries: u32 },
+}
+
+/// The result of probing a single port of a target, as queued for storage
+#[derive(Debug)]
+pub struct ReconRecord {
+    pub fqdn: Arc<Fqdn>,
+    pub ip: IpAddr,
+    pub scheme: Scheme,
+    /// The row of the 'http-recon' or 'https-recon' table, as written to the output
+    pub row: HttpReconRecord,
+    /// The result the row was built from, whose certificate and favicon are stored separately
+    pub result: ProbeResult,
+}
+
+/// Stores HTTP(s) recon results in the recon database from a background task, so that database
+/// round-trips are kept out of the request path. Results are written in batches, each within a
+/// single transaction. Results are stored in the order they are submitted, so that the number of
+/// committed results tells which submitted results are safely stored
+#[derive(Debug)]
+pub struct ReconDbWriter {
+    sender: mpsc::Sender<ReconRecord>,
+    task: JoinHandle<anyhow::Result<()>>,
+    degraded: Arc<AtomicBool>,
+    submitted: AtomicU64,
+    /// The number of results whose batch was committed, or discarded once degraded
+    committed: Arc<AtomicU64>,
+}
+
+impl ReconDbWriter {
+    pub fn spawn(pg_pool: PgPool, batch_size: NonZeroUsize, on_error: OnDbError) -> Self {
+        let (sender, receiver) = mpsc::channel(batch_size.get());
+        let degraded = Arc::new(AtomicBool::new(false));
+        let committed = Arc::new(AtomicU64::new(0));
+        let task = tokio::spawn(run(
+            pg_pool,
+            receiver,
+            batch_size.get(),
+            on_error,
+            degraded.clone(),
+            committed.clone(),
+        ));
+
+        ReconDbWriter {
+            sender,
+            task,
+            degraded,
+            submitted: AtomicU64::new(0),
+            committed,
+        }
+    }
+
+    /// Whether results are no longer stored, because the recon database became unavailable
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// The number of results submitted so far, including those still being queued. Once as many
+    /// results are committed, all results whose submission completed before are stored
+    pub fn submitted(&self) -> u64 {
+        self.submitted.load(Ordering::SeqCst)
+    }
+
+    /// The number of results that are done with, i.e. stored or discarded because the writer is
+    /// degraded. These are always the first ones submitted
+    pub fn committed(&self) -> u64 {
+        self.committed.load(Ordering::SeqCst)
+    }
+
+    /// Queues a result. If the queue is full, this waits until the background task catches up
+    pub async fn submit(&self, record: ReconRecord) -> anyhow::Result<()> {
+        // Counted before it is queued, such that the count covers any result queued earlier
+        self.submitted.fetch_add(1, Ordering::SeqCst);
+        self.sender
+            .send(record)
+            .await
+            .map_err(|_| anyhow!("the recon database writer has stopped"))
+    }
+
+    /// Stores all queued results and waits for the background task to complete
+    pub async fn finish(self) -> anyhow::Result<()> {
+        drop(self.sender);
+        self.task.await?
+    }
+}
+
+async fn run(
+    pg_pool: PgPool,
+    mut receiver: mpsc::Receiver<ReconRecord>,
+    batch_size: usize,
+    on_error: OnDbError,
+    degraded: Arc<AtomicBool>,
+    committed: Arc<AtomicU64>,
+) -> anyhow::Result<()> {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            record = receiver.recv() => match record {
+                Some(record) => {
+                    batch.push(record);
+                    if batch.len() >= batch_size {
+                        flush(&pg_pool, &mut batch, on_error, &degraded, &committed).await?;
+                    }
+                }
+                None => break,
+            },
+            _ = flush_interval.tick() => {
+                if !batch.is_empty() {
+                    flush(&pg_pool, &mut batch, on_error, &degraded, &committed).await?;
+                }
+            }
+        }
+    }
+
+    if !batch.is_empty() {
+        flush(&pg_pool, &mut batch, on_error, &degraded, &committed).await?;
+    }
+
+    Ok(())
+}
+
+/// Stores and clears the batch, retrying as configured, and counts its results as committed.
+/// Once degraded, batches are discarded
+async fn flush(
+    pg_pool: &PgPool,
+    batch: &mut Vec<ReconRecord>,
+    on_error: OnDbError,
+    degraded: &AtomicBool,
+    committed: &AtomicU64,
+) -> anyhow::Result<()> {
+    let count = batch.len() as u64;
+    let retries = match on_error {
+        OnDbError::Abort => {
+            submit_batch(pg_pool, batch).await?;
+            committed.fetch_add(count, Ordering::SeqCst);
+            return Ok(());
+        }
+        OnDbError::Degrade { retries } => retries,
+    };
+    if degraded.load(Ordering::Relaxed) {
+        batch.clear();
+        committed.fetch_add(count, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    let mut attempt = 0;
+    loop {
+        match submit_batch(pg_pool, batch).await {
+            Ok(()) => {
+                committed.fetch_add(count, Ordering::SeqCst);
+                return Ok(());
+            }
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                warn!("Cannot store a batch of results, retry {attempt} of {retries}: {e}");
+                tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+            }
+            Err(e) => {
+                error!(
+                    "The recon database is unavailable, {} results and all further results are NOT stored, but only written to the output: {}",
+                    batch.len(),
+                    e
+                );
+                degraded.store(true, Ordering::Relaxed);
+                batch.clear();
+                committed.fetch_add(count, Ordering::SeqCst);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Stores the batch within a single transaction and clears it once committed
+#[tracing::instrument(skip_all, fields(batch_size = batch.len()))]
+async fn submit_batch(pg_pool: &PgPool, batch: &mut Vec<ReconRecord>) -> anyhow::Result<()> {
+    debug!("Flushing a batch of HTTP(s) recon results");
+
+    let mut tx = pg_pool.begin().await?;
+    for record in batch.iter() {
+        match record.scheme {
+            Scheme::Http => submit_http_recon_results(&mut tx, record).await?,
+            Scheme::Https => submit_https_recon_results(&mut tx, record).await?,
+        }
+
+        if let Some(certificate) = &record.result.certificate {
+            submit_tls_recon_results(&mut tx, &record.fqdn, &record.ip, certificate).await?;
+        }
+        if let Some(favicon) = &record.result.favicon {
+            submit_favicon_recon_results(&mut tx, &record.fqdn, &record.ip, favicon).await?;
+        }
+    }
+    tx.commit().await?;
+    batch.clear();
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(fqdn = %record.fqdn, port = record.row.port))]
+async fn submit_http_recon_results(
+    conn: &mut PgConnection,
+    record: &ReconRecord,
+) -> anyhow::Result<()> {
+    let row = &record.row;
+    let inserted = query_scalar!(
+        r#"INSERT INTO "http-recon" (id, fqdn, url, "response-status", headers, domain, body, "body-truncated", "final-url", "redirect-chain", "latency-ms", port, "security-flags", "content-length", "content-type", "http-version", title, waf, version, mismatch, "direct-result", class, asn, "as-org", "error-kind", "auth-attempts") VALUES (DEFAULT, $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25) ON CONFLICT ON CONSTRAINT "http-recon_pkey" DO UPDATE SET updated_at = now() RETURNING (xmax = 0) AS "inserted!""#,
+        row.fqdn,
+        row.url,
+        row.response_status as i32,
+        row.headers,
+        row.domain,
+        row.body.as_deref(),
+        row.body_truncated,
+        row.final_url,
+        &row.redirect_chain,
+        row.latency_ms,
+        row.port as i32,
+        row.security_flags,
+        row.content_length,
+        row.content_type,
+        row.http_version,
+        row.title,
+        row.waf,
+        row.version,
+        row.mismatch,
+        row.direct_result,
+        row.class,
+        row.asn.map(i64::from),
+        row.as_org,
+        row.error_kind,
+        row.auth_attempts,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !inserted {
+        info!(
+            "Version {} of '{}' on port {} already exists in the recon database",
+            row.version, row.fqdn, row.port
+        );
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(fqdn = %record.fqdn, port = record.row.port))]
+async fn submit_https_recon_results(
+    conn: &mut PgConnection,
+    record: &ReconRecord,
+) -> anyhow::Result<()> {
+    let row = &record.row;
+    let inserted = query_scalar!(
+        r#"INSERT INTO "https-recon" (id, fqdn, url, "response-status", headers, domain, body, "body-truncated", "final-url", "redirect-chain", "latency-ms", port, "security-flags", "content-length", "content-type", "http-version", "tls-valid", title, waf, version, mismatch, "direct-result", class, asn, "as-org", "error-kind", "auth-attempts") VALUES (DEFAULT, $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26) ON CONFLICT ON CONSTRAINT "https-recon_pkey" DO UPDATE SET updated_at = now() RETURNING (xmax = 0) AS "inserted!""#,
+        row.fqdn,
+        row.url,
+        row.response_status as i32,
+        row.headers,
+        row.domain,
+        row.body.as_deref(),
+        row.body_truncated,
+        row.final_url,
+        &row.redirect_chain,
+        row.latency_ms,
+        row.port as i32,
+        row.security_flags,
+        row.content_length,
+        row.content_type,
+        row.http_version,
+        row.tls_valid,
+        row.title,
+        row.waf,
+        row.version,
+        row.mismatch,
+        row.direct_result,
+        row.class,
+        row.asn.map(i64::from),
+        row.as_org,
+        row.error_kind,
+        row.auth_attempts,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !inserted {
+        info!(
+            "Version {} of '{}' on port {} already exists in the recon database",
+            row.version, row.fqdn, row.port
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use reqwest::Url;
+    use sqlx::query_as;
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::{classify::StatusClass, output::recon_record};
+
+    /// The record of a failed request to `fqdn` via 192.0.2.1
+    fn record(fqdn: &str, scheme: Scheme) -> ReconRecord {
+        let fqdn = Arc::new(Fqdn::from_str(fqdn).unwrap());
+        let result = ProbeResult {
+            url: Url::parse(&format!("{scheme}://192.0.2.1/")).unwrap(),
+            response_status: 0,
+            class: StatusClass::Dead,
+            error_kind: None,
+            http_version: None,
+            latency: None,
+            headers: None,
+            security_flags: None,
+            content_length: None,
+            content_type: None,
+            body: None,
+            body_truncated: None,
+            title: None,
+            waf: None,
+            redirect_chain: Vec::new(),
+            certificate: None,
+            tls_valid: None,
+            favicon: None,
+            comparison: None,
+            asn: None,
+            auth_attempts: Vec::new(),
+        };
+
+        ReconRecord {
+            row: recon_record(&fqdn, &result, 1),
+            fqdn,
+            ip: "192.0.2.1".parse().unwrap(),
+            scheme,
+            result,
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn queued_records_are_stored_after_the_producer_finishes(pg_pool: PgPool) {
+        let writer = Arc::new(ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(4).unwrap(),
+            OnDbError::Abort,
+        ));
+
+        let producer = tokio::spawn({
+            let writer = writer.clone();
+            async move {
+                for i in 0..25 {
+                    let scheme = if i % 2 == 0 {
+                        Scheme::Http
+                    } else {
+                        Scheme::Https
+                    };
+                    writer
+                        .submit(record(&format!("host{i}.example.com"), scheme))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        producer.await.unwrap();
+        Arc::into_inner(writer).unwrap().finish().await.unwrap();
+
+        let (http, https): (i64, i64) = query_as(
+            r#"SELECT (SELECT COUNT(*) FROM "http-recon"), (SELECT COUNT(*) FROM "https-recon")"#,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!((http, https), (13, 12));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn submitted_records_count_as_committed_once_stored(pg_pool: PgPool) {
+        let writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(2).unwrap(),
+            OnDbError::Abort,
+        );
+
+        writer
+            .submit(record("www.example.com", Scheme::Http))
+            .await
+            .unwrap();
+        writer
+            .submit(record("mail.example.com", Scheme::Http))
+            .await
+            .unwrap();
+        assert_eq!(writer.submitted(), 2);
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while writer.committed() < 2 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let stored: i64 = query_scalar(r#"SELECT COUNT(*) FROM "http-recon""#)
+            .fetch_one(&pg_pool)
+            .await
+            .unwrap();
+        assert_eq!((writer.committed(), stored), (2, 2));
+        writer.finish().await.unwrap();
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn duplicate_records_are_stored_once(pg_pool: PgPool) {
+        let writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Abort,
+        );
+        for _ in 0..3 {
+            writer
+                .submit(record("www.example.com", Scheme::Http))
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let rows: Vec<(String, i32, i32)> =
+            query_as(r#"SELECT fqdn, port, version FROM "http-recon""#)
+                .fetch_all(&pg_pool)
+                .await
+                .unwrap();
+        assert_eq!(rows, [("www.example.com".to_string(), 80, 1)]);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn re_inserting_a_result_advances_updated_at(pg_pool: PgPool) {
+        let updated_at = || async {
+            query_scalar::<_, OffsetDateTime>(r#"SELECT updated_at FROM "https-recon""#)
+                .fetch_one(&pg_pool)
+                .await
+                .unwrap()
+        };
+
+        let writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Abort,
+        );
+        writer
+            .submit(record("www.example.com", Scheme::Https))
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+        let first = updated_at().await;
+
+        let writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Abort,
+        );
+        writer
+            .submit(record("www.example.com", Scheme::Https))
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+        assert!(updated_at().await > first);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn the_run_continues_once_the_recon_db_goes_away(pg_pool: PgPool) {
+        let writer = ReconDbWriter::spawn(
+            pg_pool.clone(),
+            NonZeroUsize::new(1).unwrap(),
+            OnDbError::Degrade { retries: 0 },
+        );
+        writer
+            .submit(record("host0.example.com", Scheme::Http))
+            .await
+            .unwrap();
+        let stored = async {
+            loop {
+                let count: i64 = query_scalar(r#"SELECT COUNT(*) FROM "http-recon""#)
+                    .fetch_one(&pg_pool)
+                    .await
+                    .unwrap();
+                if count == 1 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), stored)
+            .await
+            .unwrap();
+        assert!(!writer.is_degraded());
+
+        // Closing the pool fails all further queries, just like an outage of the database
+        pg_pool.close().await;
+        for i in 1..10 {
+            writer
+                .submit(record(&format!("host{i}.example.com"), Scheme::Http))
+                .await
+                .unwrap();
+        }
+        let degraded = async {
+            while !writer.is_degraded() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), degraded)
+            .await
+            .unwrap();
+        writer
+            .submit(record("host10.example.com", Scheme::Http))
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+    }
+}
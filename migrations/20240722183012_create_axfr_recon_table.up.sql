@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "axfr-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, "record-type" varchar(16) NOT NULL, ttl integer NOT NULL, value text NOT NULL, PRIMARY KEY (fqdn, "record-type", value));
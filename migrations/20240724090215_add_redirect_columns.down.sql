@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE "http-recon" DROP COLUMN "final-url", DROP COLUMN "redirect-chain";
+ALTER TABLE "https-recon" DROP COLUMN "final-url", DROP COLUMN "redirect-chain";
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "final-url" text, ADD COLUMN "redirect-chain" text[] NOT NULL DEFAULT '{}';
+ALTER TABLE "https-recon" ADD COLUMN "final-url" text, ADD COLUMN "redirect-chain" text[] NOT NULL DEFAULT '{}';
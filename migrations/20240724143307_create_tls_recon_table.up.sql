@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "tls-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, ip inet NOT NULL, "subject-cn" text, sans text[] NOT NULL DEFAULT '{}', issuer text NOT NULL, "not-before" timestamptz NOT NULL, "not-after" timestamptz NOT NULL, serial text NOT NULL, PRIMARY KEY (fqdn, ip));
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE "http-recon" DROP COLUMN "latency-ms";
+ALTER TABLE "https-recon" DROP COLUMN "latency-ms";
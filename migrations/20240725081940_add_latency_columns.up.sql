@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "latency-ms" integer;
+ALTER TABLE "https-recon" ADD COLUMN "latency-ms" integer;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DELETE FROM "http-recon" WHERE port <> 80;
+ALTER TABLE "http-recon" DROP CONSTRAINT "http-recon_pkey", ADD CONSTRAINT "http-recon_pkey" PRIMARY KEY (fqdn), DROP COLUMN port;
+DELETE FROM "https-recon" WHERE port <> 443;
+ALTER TABLE "https-recon" DROP CONSTRAINT "https-recon_pkey", ADD CONSTRAINT "https-recon_pkey" PRIMARY KEY (fqdn), DROP COLUMN port;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN port integer NOT NULL DEFAULT 80;
+ALTER TABLE "http-recon" ALTER COLUMN port DROP DEFAULT, DROP CONSTRAINT "http-recon_pkey", ADD CONSTRAINT "http-recon_pkey" PRIMARY KEY (fqdn, port);
+ALTER TABLE "https-recon" ADD COLUMN port integer NOT NULL DEFAULT 443;
+ALTER TABLE "https-recon" ALTER COLUMN port DROP DEFAULT, DROP CONSTRAINT "https-recon_pkey", ADD CONSTRAINT "https-recon_pkey" PRIMARY KEY (fqdn, port);
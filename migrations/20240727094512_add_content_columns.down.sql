@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE "http-recon" DROP COLUMN "content-length", DROP COLUMN "content-type";
+ALTER TABLE "https-recon" DROP COLUMN "content-length", DROP COLUMN "content-type";
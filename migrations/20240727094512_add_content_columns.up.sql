@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "content-length" bigint, ADD COLUMN "content-type" text;
+ALTER TABLE "https-recon" ADD COLUMN "content-length" bigint, ADD COLUMN "content-type" text;
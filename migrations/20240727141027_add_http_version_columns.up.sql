@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "http-version" varchar(16);
+ALTER TABLE "https-recon" ADD COLUMN "http-version" varchar(16);
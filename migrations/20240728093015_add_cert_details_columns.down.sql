@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE "cert-recon" DROP COLUMN issuer, DROP COLUMN "not-before", DROP COLUMN "not-after";
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "cert-recon" ADD COLUMN issuer text, ADD COLUMN "not-before" timestamptz, ADD COLUMN "not-after" timestamptz;
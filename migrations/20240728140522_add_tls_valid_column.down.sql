@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE "https-recon" DROP COLUMN "tls-valid";
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "https-recon" ADD COLUMN "tls-valid" boolean;
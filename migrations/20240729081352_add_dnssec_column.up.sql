@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "dns-recon" ADD COLUMN dnssec varchar(16);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "caa-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, flags smallint NOT NULL, tag varchar(32) NOT NULL, value text NOT NULL, PRIMARY KEY (fqdn, tag, value));
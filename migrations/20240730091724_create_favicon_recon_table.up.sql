@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "favicon-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, ip inet NOT NULL, url text NOT NULL, hash integer NOT NULL, PRIMARY KEY (fqdn, ip));
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN title text;
+ALTER TABLE "https-recon" ADD COLUMN title text;
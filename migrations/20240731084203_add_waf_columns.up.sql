@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN waf varchar(64);
+ALTER TABLE "https-recon" ADD COLUMN waf varchar(64);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+-- The original prefixes are not restored, since host addresses are valid either way
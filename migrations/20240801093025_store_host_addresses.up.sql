@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- Addresses used to be stored with a /32 prefix regardless of their family, which corrupts IPv6
+-- addresses. host() strips the prefix, so that the cast yields the full host prefix
+UPDATE "dns-recon"
+SET ips = ARRAY(SELECT DISTINCT host(ip)::inet FROM UNNEST(ips) AS ip)
+WHERE EXISTS (
+    SELECT 1 FROM UNNEST(ips) AS ip
+    WHERE masklen(ip) <> CASE family(ip) WHEN 4 THEN 32 ELSE 128 END
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "srv-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, priority integer NOT NULL, weight integer NOT NULL, port integer NOT NULL, target varchar(256) NOT NULL, PRIMARY KEY (fqdn, target, port));
@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE TABLE "mx-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, preference integer NOT NULL, exchange varchar(256) NOT NULL, PRIMARY KEY (fqdn, exchange));
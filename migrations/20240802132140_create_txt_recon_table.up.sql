@@ -0,0 +1,4 @@
+-- Add up migration script here
+CREATE TABLE "txt-recon" (id SERIAL PRIMARY KEY, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, value bytea NOT NULL);
+-- TXT values may be too long to be indexed directly
+CREATE UNIQUE INDEX "txt-recon_fqdn_value_idx" ON "txt-recon" (fqdn, md5(value));
@@ -0,0 +1,3 @@
+-- Add up migration script here
+CREATE TABLE "ns-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, nameserver varchar(256) NOT NULL, ips inet[] NOT NULL, PRIMARY KEY (fqdn, nameserver));
+CREATE TABLE "soa-recon" (id SERIAL, domain varchar(256) NOT NULL, fqdn varchar(256) NOT NULL, mname varchar(256) NOT NULL, rname varchar(256) NOT NULL, serial bigint NOT NULL, refresh integer NOT NULL, retry integer NOT NULL, expire integer NOT NULL, minimum bigint NOT NULL, PRIMARY KEY (fqdn));
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DELETE FROM "http-recon" WHERE version <> 1;
+ALTER TABLE "http-recon" DROP CONSTRAINT "http-recon_pkey", ADD CONSTRAINT "http-recon_pkey" PRIMARY KEY (fqdn, port), DROP COLUMN version, DROP COLUMN created_at;
+DELETE FROM "https-recon" WHERE version <> 1;
+ALTER TABLE "https-recon" DROP CONSTRAINT "https-recon_pkey", ADD CONSTRAINT "https-recon_pkey" PRIMARY KEY (fqdn, port), DROP COLUMN version, DROP COLUMN created_at;
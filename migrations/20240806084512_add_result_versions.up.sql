@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN version integer NOT NULL DEFAULT 1, ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE "http-recon" DROP CONSTRAINT "http-recon_pkey", ADD CONSTRAINT "http-recon_pkey" PRIMARY KEY (fqdn, port, version);
+ALTER TABLE "https-recon" ADD COLUMN version integer NOT NULL DEFAULT 1, ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE "https-recon" DROP CONSTRAINT "https-recon_pkey", ADD CONSTRAINT "https-recon_pkey" PRIMARY KEY (fqdn, port, version);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE "dns-recon" DROP COLUMN created_at, DROP COLUMN updated_at;
+ALTER TABLE "http-recon" DROP COLUMN updated_at;
+ALTER TABLE "https-recon" DROP COLUMN updated_at;
+ALTER TABLE "cert-recon" DROP COLUMN created_at, DROP COLUMN updated_at;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE "dns-recon" ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(), ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE "http-recon" ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE "https-recon" ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE "cert-recon" ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(), ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE "dns-recon" DROP COLUMN "name-server", DROP COLUMN "query-ms";
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "dns-recon" ADD COLUMN "name-server" varchar(64), ADD COLUMN "query-ms" integer;
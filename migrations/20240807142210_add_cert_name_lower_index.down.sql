@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX "cert-recon_cert-name_lower_key";
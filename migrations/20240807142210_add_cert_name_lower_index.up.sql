@@ -0,0 +1,5 @@
+-- Add up migration script here
+DELETE FROM "cert-recon" AS a USING "cert-recon" AS b
+WHERE lower(rtrim(a."cert-name", '.')) = lower(rtrim(b."cert-name", '.')) AND a.id > b.id;
+UPDATE "cert-recon" SET "cert-name" = rtrim("cert-name", '.') WHERE "cert-name" LIKE '%.';
+CREATE UNIQUE INDEX "cert-recon_cert-name_lower_key" ON "cert-recon" (lower("cert-name"));
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "body-truncated" boolean;
+ALTER TABLE "https-recon" ADD COLUMN "body-truncated" boolean;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE "http-recon" DROP COLUMN mismatch, DROP COLUMN "direct-result";
+ALTER TABLE "https-recon" DROP COLUMN mismatch, DROP COLUMN "direct-result";
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN mismatch boolean, ADD COLUMN "direct-result" jsonb;
+ALTER TABLE "https-recon" ADD COLUMN mismatch boolean, ADD COLUMN "direct-result" jsonb;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE "dns-recon" DROP COLUMN "asn-info";
+ALTER TABLE "http-recon" DROP COLUMN asn, DROP COLUMN "as-org";
+ALTER TABLE "https-recon" DROP COLUMN asn, DROP COLUMN "as-org";
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE "dns-recon" ADD COLUMN "asn-info" jsonb;
+ALTER TABLE "http-recon" ADD COLUMN asn bigint, ADD COLUMN "as-org" text;
+ALTER TABLE "https-recon" ADD COLUMN asn bigint, ADD COLUMN "as-org" text;
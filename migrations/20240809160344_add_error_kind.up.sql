@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE "http-recon" ADD COLUMN "error-kind" varchar(16);
+ALTER TABLE "https-recon" ADD COLUMN "error-kind" varchar(16);
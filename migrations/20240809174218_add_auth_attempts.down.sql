@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE "http-recon" DROP COLUMN "auth-attempts";
+ALTER TABLE "https-recon" DROP COLUMN "auth-attempts";
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE "dns-recon" DROP COLUMN "system-ips", DROP COLUMN divergent;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "dns-recon" ADD COLUMN "system-ips" inet[], ADD COLUMN divergent boolean;